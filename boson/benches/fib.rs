@@ -0,0 +1,123 @@
+/*
+    Benchmarks of the VM on the fib examples, run with `cargo bench`.
+    Every program is evaluated a few times and the best and mean times
+    are reported, parsing and compiling are part of the measurement.
+*/
+
+extern crate boson;
+
+use boson::api::BosonLang;
+
+use std::time::Duration;
+use std::time::Instant;
+
+const ROUNDS: usize = 5;
+
+// examples/fib.np, with a larger input:
+const FIB_RECURSIVE: &str = "
+func fib(N) {
+    if (N == 0) {
+        return 0;
+    }
+
+    if (N == 1) {
+        return 1;
+    }
+
+    return fib(N - 1) + fib(N - 2);
+}
+
+fib(22);
+";
+
+// examples/fib_iter.np, repeated:
+const FIB_ITER: &str = "
+const N = 90;
+var n1 = 0;
+var round = 0;
+
+while (round < 3000) {
+    var n0 = 0;
+    var n2 = 0;
+    var idx = 2;
+    n1 = 1;
+
+    while (idx <= N) {
+        n2 = n0 + n1;
+        n0 = n1;
+        n1 = n2;
+        idx = idx + 1;
+    }
+
+    round = round + 1;
+}
+
+n1;
+";
+
+// examples/fib_dynamic.np, repeated with a fresh memory every round:
+const FIB_DYNAMIC: &str = "
+var N = 100;
+var memory = create_array(N, 0);
+
+func fib(n) {
+    if (n == 1) {
+        return 1;
+    }
+
+    if (n == 0) {
+        return 0;
+    }
+
+    var left_result = 0;
+    var right_result = 0;
+
+    if (memory[n - 1] != 0) {
+        left_result = memory[n - 1];
+    } else {
+        left_result = fib(n - 1);
+        memory[n - 1] = left_result;
+    }
+
+    if (memory[n - 2] != 0) {
+        right_result = memory[n - 2];
+    }
+
+    return left_result + right_result;
+}
+
+var result = 0;
+var round = 0;
+while (round < 1000) {
+    memory = create_array(N, 0);
+    result = fib(90);
+    round = round + 1;
+}
+
+result;
+";
+
+fn run_bench(name: &str, source: &str) {
+    let mut times = vec![];
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        let result = BosonLang::eval_buffer(source.as_bytes().to_vec());
+        times.push(start.elapsed());
+        assert!(result.is_some(), "{} failed", name);
+    }
+
+    let best = times.iter().min().unwrap();
+    let mean = times.iter().sum::<Duration>() / ROUNDS as u32;
+    println!(
+        "{:<12} best: {:>8.2} ms  mean: {:>8.2} ms",
+        name,
+        best.as_secs_f64() * 1000.0,
+        mean.as_secs_f64() * 1000.0
+    );
+}
+
+fn main() {
+    run_bench("fib", FIB_RECURSIVE);
+    run_bench("fib_iter", FIB_ITER);
+    run_bench("fib_dynamic", FIB_DYNAMIC);
+}
@@ -4,15 +4,18 @@ use crate::compiler::loader::BytecodeWriter;
 use crate::compiler::BytecodeCompiler;
 use crate::compiler::BytecodeDecompiler;
 use crate::compiler::CompiledBytecode;
+use crate::config::VMConfig;
 use crate::lexer::LexerAPI;
 use crate::parser::debug::ParserError;
 use crate::parser::Parser;
-use crate::types::object::Object;
+use crate::types::object::Value;
 use crate::vm::errors::VMError;
+use crate::vm::limits::CancelHandle;
+use crate::vm::limits::ExecutionLimits;
 use crate::vm::BosonVM;
 use std::env::Vars;
 use std::fmt;
-use std::rc::Rc;
+use std::time::Duration;
 
 pub mod native;
 
@@ -22,11 +25,41 @@ pub enum PlatformKind {
     WebAssembly,
 }
 
+// where process.spawn() starts the command and what it gives it:
+#[derive(Debug, Clone, Default)]
+pub struct ProcessOptions {
+    pub cwd: Option<String>,
+    pub env: Vec<(String, String)>,
+    // start from an empty environment instead of the one of the VM:
+    pub clear_env: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessStream {
+    Stdout,
+    Stderr,
+}
+
+/*
+    A process started by the platform. The output streams are read in
+    chunks as they arrive: read() waits up to the timeout and returns an
+    empty chunk if nothing arrived, none once the stream is closed.
+*/
+pub trait ChildProcess: Send {
+    fn pid(&self) -> u32;
+    fn read(&mut self, stream: ProcessStream, timeout: Duration) -> Result<Option<Vec<u8>>, String>;
+    fn write(&mut self, data: &Vec<u8>) -> Result<usize, String>;
+    fn close_stdin(&mut self);
+    // the exit code once the process is done, -1 if a signal stopped it:
+    fn try_wait(&mut self) -> Result<Option<i32>, String>;
+    fn kill(&mut self) -> Result<(), String>;
+}
+
 pub struct Platform {
     pub platform_type: PlatformKind,
     pub print: fn(fmt_string: &String),
-    pub exec: fn(args: &Vec<Rc<Object>>) -> Result<(i32, Vec<u8>), String>,
-    pub get_args: fn() -> Vec<Rc<Object>>,
+    pub exec: fn(args: &Vec<Value>) -> Result<(i32, Vec<u8>), String>,
+    pub get_args: fn() -> Vec<Value>,
     pub get_env: fn(name: &String) -> Result<String, String>,
     pub get_envs: fn() -> Vars,
     pub get_unix_time: fn() -> Result<f64, String>,
@@ -37,10 +70,15 @@ pub struct Platform {
         fn(path: String, start: Option<u64>, n_b: Option<u64>) -> Result<(Vec<u8>, u64), String>,
     pub fwrite: fn(path: String, data: &Vec<u8>) -> Result<u64, String>,
     pub fappend: fn(path: String, data: &Vec<u8>) -> Result<u64, String>,
-    pub finfo: fn(path: String) -> Result<Rc<Object>, String>,
+    pub finfo: fn(path: String) -> Result<Value, String>,
     pub stdin_read: fn() -> Result<Vec<u8>, String>,
     pub stdout_write: fn(data: &Vec<u8>) -> Result<usize, String>,
     pub read_line: fn(display: Option<String>) -> Result<String, String>,
+    pub spawn_process: fn(
+        cmd: &String,
+        args: &Vec<String>,
+        opts: &ProcessOptions,
+    ) -> Result<Box<dyn ChildProcess>, String>,
 }
 
 impl fmt::Debug for Platform {
@@ -56,6 +94,11 @@ pub struct BosonLang {
     pub compiler: BytecodeCompiler,
     pub vm: Option<BosonVM>,
     pub platform: Platform,
+    pub config: VMConfig,
+    pub cancel: CancelHandle,
+    // limits inherited from an enclosing VM, used instead of new limits
+    // from the config:
+    pub limits: Option<ExecutionLimits>,
 }
 
 #[derive(Debug)]
@@ -85,6 +128,7 @@ impl BosonLang {
             stdin_read: native::stdin_read,
             stdout_write: native::stdout_write,
             read_line: native::read_line,
+            spawn_process: native::spawn_process,
         };
     }
 
@@ -98,6 +142,9 @@ impl BosonLang {
             compiler: compiler,
             vm: None,
             platform: BosonLang::prepare_native_platform(),
+            config: VMConfig::new(),
+            cancel: CancelHandle::new(),
+            limits: None,
         };
     }
 
@@ -111,6 +158,9 @@ impl BosonLang {
             compiler: compiler,
             vm: None,
             platform: BosonLang::prepare_native_platform(),
+            config: VMConfig::new(),
+            cancel: CancelHandle::new(),
+            limits: None,
         };
     }
 
@@ -127,10 +177,24 @@ impl BosonLang {
             return Err(ErrorKind::CompileError(compiler_result.unwrap_err()));
         }
 
+        self.__display_warnings();
+
         let bytecode = compiler_result.unwrap();
         return Ok(bytecode);
     }
 
+    fn __display_warnings(&self) {
+        // warnings go to stderr, so they do not mix with the output of the program:
+        for warning in &self.compiler.warnings {
+            let (line, column) = self.parser.lexer.get_line_and_column(warning.pos);
+            eprintln!("Compiler Warning:");
+            eprintln!(
+                "{:?}: {}, at line {}, column {}",
+                warning.t, warning.message, line, column
+            );
+        }
+    }
+
     fn __display_error(&mut self, error: ErrorKind) {
         match error {
             ErrorKind::ParserError(_) => {
@@ -157,25 +221,57 @@ impl BosonLang {
         self.print_globals();
     }
 
+    // enables or disables the peephole optimizer of the compiler:
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.compiler.optimize = optimize;
+    }
+
+    // sets the limits of the VM, takes effect on the next evaluation:
+    pub fn set_config(&mut self, config: VMConfig) {
+        self.compiler.max_globals = config.global_pool_size;
+        self.config = config;
+    }
+
+    // the handle aborts the evaluation from another thread:
+    pub fn cancel_handle(&self) -> CancelHandle {
+        return self.cancel.clone();
+    }
+
+    // runs under the limits of an enclosing VM, like eval() does:
+    pub fn set_limits(&mut self, limits: ExecutionLimits) {
+        self.cancel = limits.cancel.clone();
+        self.limits = Some(limits);
+    }
+
     pub fn update(&mut self, new_buffer: Vec<u8>) {
         self.parser.reset_errors();
         self.parser.lexer = LexerAPI::new_from_buffer(new_buffer);
     }
 
-    pub fn eval_buffer(buffer: Vec<u8>) -> Option<Rc<Object>> {
+    pub fn eval_buffer(buffer: Vec<u8>) -> Option<Value> {
         let mut lang = BosonLang::new_from_buffer(buffer);
         let result = lang.eval_state();
         return result;
     }
 
-    pub fn eval_file(filename: String) -> Option<Rc<Object>> {
+    pub fn eval_file(filename: String) -> Option<Value> {
+        return BosonLang::eval_file_with(filename, VMConfig::new());
+    }
+
+    pub fn eval_file_with(filename: String, config: VMConfig) -> Option<Value> {
         let mut lang = BosonLang::new_from_file(filename);
+        lang.set_config(config);
         let result = lang.eval_state();
         return result;
     }
 
     pub fn disasm_file(filename: String) -> Option<String> {
+        return BosonLang::disasm_file_with(filename, true);
+    }
+
+    pub fn disasm_file_with(filename: String, optimize: bool) -> Option<String> {
         let mut lang = BosonLang::new_from_file(filename);
+        lang.set_optimize(optimize);
         let result = lang.disasm_state();
         return result;
     }
@@ -186,7 +282,7 @@ impl BosonLang {
         return result;
     }
 
-    pub fn eval_state(&mut self) -> Option<Rc<Object>> {
+    pub fn eval_state(&mut self) -> Option<Value> {
         let bytecode = self.__get_bytecode();
         if bytecode.is_err() {
             self.__display_error(bytecode.unwrap_err());
@@ -194,13 +290,21 @@ impl BosonLang {
         }
 
         if self.vm.is_none() {
-            self.vm = Some(BosonVM::new(&bytecode.unwrap()));
+            self.vm = Some(BosonVM::new(&bytecode.unwrap(), &self.config));
         } else {
             self.vm = Some(BosonVM::new_state(
                 &bytecode.unwrap(),
                 self.vm.as_mut().unwrap().globals.clone(),
+                &self.config,
             ));
         }
+
+        let limits = match self.limits.as_ref() {
+            Some(limits) => limits.clone(),
+            None => ExecutionLimits::new(&self.config, self.cancel.clone()),
+        };
+        self.vm.as_mut().unwrap().threads.limits = limits;
+
         let result = self
             .vm
             .as_mut()
@@ -260,9 +364,14 @@ impl BosonLang {
     }
 
     pub fn save_bytecode_from_file(fname: String) -> Option<usize> {
+        return BosonLang::save_bytecode_from_file_with(fname, true);
+    }
+
+    pub fn save_bytecode_from_file_with(fname: String, optimize: bool) -> Option<usize> {
         let mut bytecode_fname = String::from(&fname);
         bytecode_fname.push_str(".b");
         let mut lang = BosonLang::new_from_file(fname);
+        lang.set_optimize(optimize);
         return lang.__save_bytecode(bytecode_fname);
     }
 
@@ -279,7 +388,20 @@ impl BosonLang {
         return Some(disasm);
     }
 
-    pub fn eval_bytecode(fname: String) -> Option<Rc<Object>> {
+    pub fn eval_bytecode(fname: String) -> Option<Value> {
+        return BosonLang::eval_bytecode_with(fname, VMConfig::new());
+    }
+
+    pub fn eval_bytecode_with(fname: String, config: VMConfig) -> Option<Value> {
+        let limits = ExecutionLimits::new(&config, CancelHandle::new());
+        return BosonLang::eval_bytecode_limited(fname, config, limits);
+    }
+
+    pub fn eval_bytecode_limited(
+        fname: String,
+        config: VMConfig,
+        limits: ExecutionLimits,
+    ) -> Option<Value> {
         let mut loader = BytecodeLoader::new(fname);
         let result = loader.load_bytecode();
         if result.is_err() {
@@ -288,7 +410,8 @@ impl BosonLang {
         }
 
         // create VM and run:
-        let mut boson_vm = BosonVM::new(&result.unwrap());
+        let mut boson_vm = BosonVM::new(&result.unwrap(), &config);
+        boson_vm.threads.limits = limits;
         let platform = BosonLang::prepare_native_platform();
 
         let result = boson_vm.eval_bytecode(&platform, true, false);
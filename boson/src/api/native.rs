@@ -1,11 +1,19 @@
+use crate::api::ChildProcess;
+use crate::api::ProcessOptions;
+use crate::api::ProcessStream;
 use crate::types::hash;
 use crate::types::object;
 
 use std::env;
 use std::fs;
 use std::io;
+use std::process::Child;
+use std::process::ChildStdin;
 use std::process::Command;
-use std::rc::Rc;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
@@ -17,6 +25,7 @@ use std::cell::RefCell;
 
 use hash::HashTable;
 use object::Object;
+use object::Value;
 use std::collections::HashMap;
 use std::thread;
 use std::time::Duration;
@@ -41,7 +50,7 @@ pub fn print(st: &String) {
     print!("{}", st);
 }
 
-pub fn exec(args: &Vec<Rc<Object>>) -> Result<(i32, Vec<u8>), String> {
+pub fn exec(args: &Vec<Value>) -> Result<(i32, Vec<u8>), String> {
     let mut command = Command::new(args[0].as_ref().describe());
     for idx in 1..args.len() {
         command.arg(args[idx].as_ref().describe());
@@ -69,13 +78,15 @@ pub fn exec(args: &Vec<Rc<Object>>) -> Result<(i32, Vec<u8>), String> {
     return Ok((exit_code, output_data));
 }
 
-pub fn get_args() -> Vec<Rc<Object>> {
+pub fn get_args() -> Vec<Value> {
     let mut cmd_args = env::args();
     // skip the binary name
     cmd_args.next();
 
     // get a vector slice starting from index 1:
-    let arg_str_objects: Vec<Rc<Object>> = cmd_args.map(|arg| Rc::new(Object::Str(arg))).collect();
+    let arg_str_objects: Vec<Value> = cmd_args
+        .map(|arg| Value::new(Object::Str(Box::new(arg))))
+        .collect();
     return arg_str_objects;
 }
 
@@ -237,7 +248,7 @@ pub fn fappend(path: String, data: &Vec<u8>) -> Result<u64, String> {
 }
 
 // Returns the size of a file.
-pub fn finfo(path: String) -> Result<Rc<Object>, String> {
+pub fn finfo(path: String) -> Result<Value, String> {
     let m_result = fs::metadata(path);
     if m_result.is_err() {
         return Err(format!("{}", m_result.unwrap_err()));
@@ -248,27 +259,27 @@ pub fn finfo(path: String) -> Result<Rc<Object>, String> {
     let mut h_map = HashMap::new();
 
     h_map.insert(
-        Rc::new(Object::Str(format!("is_file"))),
-        Rc::new(Object::Bool(metadata.is_file())),
+        Value::new(Object::Str(Box::new(format!("is_file")))),
+        Value::new(Object::Bool(metadata.is_file())),
     );
     h_map.insert(
-        Rc::new(Object::Str(format!("is_dir"))),
-        Rc::new(Object::Bool(metadata.is_dir())),
+        Value::new(Object::Str(Box::new(format!("is_dir")))),
+        Value::new(Object::Bool(metadata.is_dir())),
     );
     h_map.insert(
-        Rc::new(Object::Str(format!("size"))),
-        Rc::new(Object::Int(metadata.len() as i64)),
+        Value::new(Object::Str(Box::new(format!("size")))),
+        Value::new(Object::Int(metadata.len() as i64)),
     );
     h_map.insert(
-        Rc::new(Object::Str(format!("is_read_only"))),
-        Rc::new(Object::Bool(metadata.permissions().readonly())),
+        Value::new(Object::Str(Box::new(format!("is_read_only")))),
+        Value::new(Object::Bool(metadata.permissions().readonly())),
     );
 
     if let Some(created) = metadata.created().ok() {
         if let Some(time_f) = created.duration_since(SystemTime::UNIX_EPOCH).ok() {
             h_map.insert(
-                Rc::new(Object::Str(format!("created_at"))),
-                Rc::new(Object::Float(time_f.as_secs_f64())),
+                Value::new(Object::Str(Box::new(format!("created_at")))),
+                Value::new(Object::Float(time_f.as_secs_f64())),
             );
         }
     }
@@ -276,8 +287,8 @@ pub fn finfo(path: String) -> Result<Rc<Object>, String> {
     if let Some(access) = metadata.accessed().ok() {
         if let Some(time_f) = access.duration_since(SystemTime::UNIX_EPOCH).ok() {
             h_map.insert(
-                Rc::new(Object::Str(format!("accessed_at"))),
-                Rc::new(Object::Float(time_f.as_secs_f64())),
+                Value::new(Object::Str(Box::new(format!("accessed_at")))),
+                Value::new(Object::Float(time_f.as_secs_f64())),
             );
         }
     }
@@ -287,7 +298,7 @@ pub fn finfo(path: String) -> Result<Rc<Object>, String> {
         entries: h_map,
     };
 
-    return Ok(Rc::new(Object::HashTable(RefCell::new(h_table))));
+    return Ok(Value::new(Object::HashTable(Box::new(RefCell::new(h_table)))));
 }
 
 // Read stdin input:
@@ -334,3 +345,130 @@ pub fn stdout_write(data: &Vec<u8>) -> Result<usize, String> {
 
     return Ok(data.len());
 }
+
+/*
+    A process started with piped stdin, stdout and stderr. A thread reads
+    each output stream and hands the chunks over as they arrive, so reads
+    can wait with a timeout and never block on a full pipe.
+*/
+pub struct NativeProcess {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: Receiver<Vec<u8>>,
+    stderr: Receiver<Vec<u8>>,
+}
+
+fn pump<R: Read + Send + 'static>(mut reader: R) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match reader.read(&mut chunk) {
+                Ok(0) | Err(_) => return,
+                Ok(n) => {
+                    if sender.send(chunk[..n].to_vec()).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    return receiver;
+}
+
+pub fn spawn_process(
+    cmd: &String,
+    args: &Vec<String>,
+    opts: &ProcessOptions,
+) -> Result<Box<dyn ChildProcess>, String> {
+    let mut command = Command::new(cmd);
+    command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    if opts.cwd.is_some() {
+        command.current_dir(opts.cwd.as_ref().unwrap());
+    }
+
+    if opts.clear_env {
+        command.env_clear();
+    }
+
+    for (name, value) in &opts.env {
+        command.env(name, value);
+    }
+
+    let spawn_result = command.spawn();
+    if spawn_result.is_err() {
+        return Err(format!("Sub Command Error: {}", spawn_result.unwrap_err()));
+    }
+
+    let mut child = spawn_result.unwrap();
+    let stdin = child.stdin.take();
+    let stdout = pump(child.stdout.take().unwrap());
+    let stderr = pump(child.stderr.take().unwrap());
+
+    return Ok(Box::new(NativeProcess {
+        child: child,
+        stdin: stdin,
+        stdout: stdout,
+        stderr: stderr,
+    }));
+}
+
+impl ChildProcess for NativeProcess {
+    fn pid(&self) -> u32 {
+        return self.child.id();
+    }
+
+    fn read(&mut self, stream: ProcessStream, timeout: Duration) -> Result<Option<Vec<u8>>, String> {
+        let receiver = match stream {
+            ProcessStream::Stdout => &self.stdout,
+            ProcessStream::Stderr => &self.stderr,
+        };
+
+        match receiver.recv_timeout(timeout) {
+            Ok(chunk) => return Ok(Some(chunk)),
+            Err(RecvTimeoutError::Timeout) => return Ok(Some(vec![])),
+            Err(RecvTimeoutError::Disconnected) => return Ok(None),
+        }
+    }
+
+    fn write(&mut self, data: &Vec<u8>) -> Result<usize, String> {
+        if self.stdin.is_none() {
+            return Err("The stdin of the process is closed".to_string());
+        }
+
+        let result = self.stdin.as_mut().unwrap().write_all(data);
+        if result.is_err() {
+            return Err(format!("{}", result.unwrap_err()));
+        }
+
+        return Ok(data.len());
+    }
+
+    fn close_stdin(&mut self) {
+        self.stdin = None;
+    }
+
+    fn try_wait(&mut self) -> Result<Option<i32>, String> {
+        match self.child.try_wait() {
+            Ok(Some(status)) => return Ok(Some(status.code().unwrap_or(-1))),
+            Ok(None) => return Ok(None),
+            Err(error) => return Err(format!("{}", error)),
+        }
+    }
+
+    fn kill(&mut self) -> Result<(), String> {
+        // a process that is done already cannot be killed:
+        let result = self.child.kill();
+        if result.is_err() && self.child.try_wait().unwrap_or(None).is_none() {
+            return Err(format!("{}", result.unwrap_err()));
+        }
+
+        return Ok(());
+    }
+}
@@ -7,10 +7,19 @@ fn info() {
     println!("boson-compile v0.1.0");
     println!("Boson is an educational general purpose programming language written in Rust.");
     println!("This binary compiles boson program files and saves the bytecode.");
+    println!("Usage: boson-compile [-O0] file-name, -O0 disables the optimizer.");
 }
 
 pub fn main() {
-    let args: Vec<String> = args().collect();
+    let mut args: Vec<String> = args().collect();
+
+    // optimizer switch:
+    let mut optimize = true;
+    if args.len() > 1 && args[1] == "-O0" {
+        optimize = false;
+        args.remove(1);
+    }
+
     if args.len() == 1 {
         info();
         return;
@@ -23,7 +32,7 @@ pub fn main() {
     }
 
     // run compiler:
-    let ret = BosonLang::save_bytecode_from_file(f_name.clone());
+    let ret = BosonLang::save_bytecode_from_file_with(f_name.clone(), optimize);
     if ret.is_some() {
         println!("Wrote {} bytes.", ret.unwrap());
         process::exit(0);
@@ -5,11 +5,19 @@ use std::env::args;
 fn info() {
     println!("boson-dis v0.1.0");
     println!("Boson is an educational general purpose programming language written in Rust.");
-    println!("This binary generates bytecode and displays it. Usage: boson-dis file-name");
+    println!("This binary generates bytecode and displays it. Usage: boson-dis [-O0] file-name");
 }
 
 pub fn main() {
-    let args: Vec<String> = args().collect();
+    let mut args: Vec<String> = args().collect();
+
+    // optimizer switch:
+    let mut optimize = true;
+    if args.len() > 1 && args[1] == "-O0" {
+        optimize = false;
+        args.remove(1);
+    }
+
     if args.len() == 1 {
         info();
         return;
@@ -28,7 +36,7 @@ pub fn main() {
         }
     } else {
         // run evaluator:
-        let result = BosonLang::disasm_file(f_name.clone());
+        let result = BosonLang::disasm_file_with(f_name.clone(), optimize);
         if result.is_some() {
             println!("{}", result.unwrap());
         }
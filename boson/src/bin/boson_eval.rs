@@ -1,4 +1,5 @@
 use boson::api::BosonLang;
+use boson::config::VMConfig;
 
 use std::env::args;
 use std::process;
@@ -6,11 +7,49 @@ use std::process;
 fn info() {
     println!("boson-eval v0.1.0");
     println!("Boson is an educational general purpose programming language written in Rust.");
-    println!("This binary evaluates program files. Usage: boson-eval file-name");
+    println!("This binary evaluates program files.");
+    println!("Usage: boson-eval [--config=file] [--option=value ...] file-name");
+    println!("Options: --frame-stack-size, --data-stack-size, --global-pool-size, --enable-concurrency,");
+    println!("         --max-instructions, --timeout-ms, --max-heap-bytes, --gc-threshold");
 }
 
 pub fn main() {
-    let args: Vec<String> = args().collect();
+    let mut args: Vec<String> = args().collect();
+
+    // VM limits, the options are applied in the order they are given:
+    let mut config = VMConfig::new();
+    let mut flags = vec![];
+    while args.len() > 1 && args[1].starts_with("--") {
+        flags.push(args.remove(1));
+    }
+
+    for flag in flags {
+        let option = flag.trim_start_matches("--");
+        let sep = option.find('=');
+        if sep.is_none() {
+            println!("Invalid option {}, expected --option=value", flag);
+            process::exit(-1);
+        }
+
+        let (key, value) = option.split_at(sep.unwrap());
+        let value = &value[1..];
+        if key == "config" {
+            let loaded = VMConfig::load_from_file(value);
+            if loaded.is_err() {
+                println!("Config Error: {}", loaded.unwrap_err());
+                process::exit(-1);
+            }
+            config = loaded.unwrap();
+            continue;
+        }
+
+        let result = config.set(key, value);
+        if result.is_err() {
+            println!("Config Error: {}", result.unwrap_err());
+            process::exit(-1);
+        }
+    }
+
     if args.len() == 1 {
         info();
         return;
@@ -24,13 +63,13 @@ pub fn main() {
 
     // run evaluator:
     if f_name.ends_with(".b") {
-        let ret = BosonLang::eval_bytecode(f_name.clone());
+        let ret = BosonLang::eval_bytecode_with(f_name.clone(), config);
         if ret.is_some() {
             process::exit(0);
         }
         process::exit(-1);
     } else {
-        let ret = BosonLang::eval_file(f_name.clone());
+        let ret = BosonLang::eval_file_with(f_name.clone(), config);
         if ret.is_some() {
             process::exit(0);
         }
@@ -3,13 +3,14 @@ extern crate boson;
 
 use boson::api::BosonLang;
 use boson::types::object::Object;
+use boson::types::object::Value;
 
 use std::rc::Rc;
 
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
-fn display_result(obj: Rc<Object>) {
+fn display_result(obj: Value) {
     match obj.as_ref() {
         Object::Noval => {},
         _ => println!("{}", obj.as_ref().describe())
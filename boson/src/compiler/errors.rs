@@ -12,6 +12,7 @@ pub enum CompilerErrorKind {
     BytecodeError,
     InvalidBreak,
     InvalidContinue,
+    OperandOverflow,
 }
 
 #[derive(Debug, Clone)]
@@ -41,3 +42,35 @@ impl fmt::Display for CompileError {
         )
     }
 }
+
+#[derive(Debug, Clone)]
+pub enum CompilerWarningKind {
+    ShadowedSymbol,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompileWarning {
+    pub t: CompilerWarningKind,
+    pub message: String,
+    pub pos: usize,
+}
+
+impl CompileWarning {
+    pub fn new(message: String, t: CompilerWarningKind, pos: usize) -> CompileWarning {
+        return CompileWarning {
+            message: message,
+            t: t,
+            pos: pos,
+        };
+    }
+}
+
+impl fmt::Display for CompileWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Type={:?} Position={} Reason={}",
+            self.t, self.pos, self.message
+        )
+    }
+}
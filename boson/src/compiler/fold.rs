@@ -0,0 +1,142 @@
+/*
+    Constant folding: evaluates the expressions that only depend on literals
+    and known constants at compile time. The VM's ALU is used for the
+    evaluation, so a folded expression gives the same result as the
+    instructions it replaces. Expressions that fail (division by zero,
+    overflow, type errors) are left as they are and fail at runtime.
+*/
+
+
+use crate::compiler::symtab;
+use crate::parser::ast;
+use crate::parser::exp;
+use crate::types::object::Object;
+use crate::types::object::Value;
+use crate::vm::alu;
+use crate::vm::errors::ISAError;
+
+use alu::Arithmetic;
+use alu::Bitwise;
+use alu::Comparision;
+use alu::Logical;
+use symtab::SymbolTable;
+
+pub struct ConstantFolder {}
+
+impl ConstantFolder {
+    // only immutable values can be folded or inlined:
+    pub fn is_foldable(obj: &Object) -> bool {
+        match obj {
+            Object::Int(_)
+            | Object::Float(_)
+            | Object::Bool(_)
+            | Object::Char(_)
+            | Object::Str(_)
+            | Object::Byte(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn fold_expression(
+        expr: &ast::ExpressionKind,
+        symbol_table: &SymbolTable,
+    ) -> Option<Value> {
+        match expr {
+            ast::ExpressionKind::Literal(lt) => return ConstantFolder::fold_literal(lt),
+            ast::ExpressionKind::Boolean(b) => return Some(Value::new(Object::Bool(*b))),
+            ast::ExpressionKind::Identifier(id) => {
+                let symbol = symbol_table.lookup_symbol(&id.name);
+                if symbol.is_none() {
+                    return None;
+                }
+
+                return symbol.unwrap().value.clone();
+            }
+            ast::ExpressionKind::Prefix(prefix) => {
+                return ConstantFolder::fold_prefix(prefix, symbol_table);
+            }
+            ast::ExpressionKind::Infix(infix) => {
+                return ConstantFolder::fold_infix(infix, symbol_table);
+            }
+            _ => return None,
+        }
+    }
+
+    fn fold_literal(literal: &ast::LiteralKind) -> Option<Value> {
+        let obj = match literal {
+            ast::LiteralKind::Int(i) => Object::Int(*i),
+            ast::LiteralKind::Float(f) => Object::Float(*f),
+            ast::LiteralKind::Char(c) => Object::Char(*c),
+            ast::LiteralKind::Str(st) => Object::Str(Box::new(st.clone())),
+            ast::LiteralKind::Bool(b) => Object::Bool(*b),
+            _ => return None,
+        };
+
+        return Some(Value::new(obj));
+    }
+
+    fn fold_prefix(prefix: &ast::PrefixType, symbol_table: &SymbolTable) -> Option<Value> {
+        let operand = ConstantFolder::fold_expression(&prefix.expression, symbol_table);
+        if operand.is_none() {
+            return None;
+        }
+
+        let obj = operand.unwrap();
+        let result = match prefix.prefix {
+            exp::PrefixExpKind::Neg => Bitwise::not(&obj),
+            exp::PrefixExpKind::Not => Logical::not(&obj),
+            _ => return None,
+        };
+
+        return ConstantFolder::get_folded(result);
+    }
+
+    fn fold_infix(infix: &ast::InfixType, symbol_table: &SymbolTable) -> Option<Value> {
+        let left_res = ConstantFolder::fold_expression(&infix.expression_left, symbol_table);
+        if left_res.is_none() {
+            return None;
+        }
+
+        let right_res = ConstantFolder::fold_expression(&infix.expression_right, symbol_table);
+        if right_res.is_none() {
+            return None;
+        }
+
+        let left = left_res.unwrap();
+        let right = right_res.unwrap();
+
+        let result = match infix.infix {
+            exp::InfixExpKind::Plus => Arithmetic::add(&left, &right),
+            exp::InfixExpKind::Minus => Arithmetic::sub(&left, &right),
+            exp::InfixExpKind::Mul => Arithmetic::mul(&left, &right),
+            exp::InfixExpKind::Div => Arithmetic::div(&left, &right),
+            exp::InfixExpKind::Mod => Arithmetic::modulus(&left, &right),
+            exp::InfixExpKind::And => Bitwise::and(&left, &right),
+            exp::InfixExpKind::Or => Bitwise::or(&left, &right),
+            exp::InfixExpKind::LesserThan => Comparision::lt(&left, &right),
+            exp::InfixExpKind::LesserThanEqual => Comparision::lte(&left, &right),
+            exp::InfixExpKind::GreaterThan => Comparision::gt(&left, &right),
+            exp::InfixExpKind::GreaterThanEqual => Comparision::gte(&left, &right),
+            exp::InfixExpKind::EEqual => Comparision::eq(&left, &right),
+            exp::InfixExpKind::NotEqual => Comparision::neq(&left, &right),
+            exp::InfixExpKind::LogicalOr => Logical::or(&left, &right),
+            exp::InfixExpKind::LogicalAnd => Logical::and(&left, &right),
+            _ => return None,
+        };
+
+        return ConstantFolder::get_folded(result);
+    }
+
+    fn get_folded(result: Result<Value, ISAError>) -> Option<Value> {
+        if result.is_err() {
+            return None;
+        }
+
+        let obj = result.unwrap();
+        if !ConstantFolder::is_foldable(&obj) {
+            return None;
+        }
+
+        return Some(obj);
+    }
+}
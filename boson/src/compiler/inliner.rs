@@ -0,0 +1,123 @@
+/*
+    Function inliner: small leaf functions are copied into the caller instead
+    of being called. The parameters and locals of the function are moved to
+    slots of the caller (globals at the top-level), so the copied instructions
+    are renumbered and their jumps relocated to the call site.
+*/
+
+use crate::compiler::optimizer::PeepholeOptimizer;
+use crate::compiler::symtab::ScopeKind;
+use crate::compiler::symtab::Symbol;
+use crate::compiler::CompiledInstructions;
+use crate::isa::InstructionKind;
+use crate::types::subroutine::Subroutine;
+
+use std::rc::Rc;
+
+// size of the largest function body that is inlined, in bytes.
+pub const INLINE_MAX_SIZE: usize = 64;
+
+pub struct FunctionInliner {}
+
+impl FunctionInliner {
+    // a function can be inlined if it is small, does not call other functions
+    // or create closures and returns only at its end.
+    pub fn can_inline(subroutine: &Subroutine) -> bool {
+        let bytecode = subroutine.get_bytecode();
+        if bytecode.len() == 0 || bytecode.len() > INLINE_MAX_SIZE {
+            return false;
+        }
+
+        let decoded = PeepholeOptimizer::decode(bytecode);
+        let last = decoded.len() - 1;
+
+        for (idx, inst) in decoded.iter().enumerate() {
+            match inst.kind {
+                InstructionKind::IRet | InstructionKind::IRetVal => {
+                    if idx != last {
+                        return false;
+                    }
+                }
+                InstructionKind::ICall
+                | InstructionKind::ITailCall
+                | InstructionKind::ICallThread
+                | InstructionKind::ICallAsync
+                | InstructionKind::ICallAttr
+                | InstructionKind::IClosure
+                | InstructionKind::ILoadFree
+                | InstructionKind::IStoreFree
+                | InstructionKind::ICaptureLocal
+                | InstructionKind::ICaptureFree
+                | InstructionKind::IDefer
+                | InstructionKind::IRaise
+                | InstructionKind::IPushExcHandle
+                | InstructionKind::IPopExcHandle => return false,
+                _ => {}
+            }
+        }
+
+        return decoded[last].kind == InstructionKind::IRet
+            || decoded[last].kind == InstructionKind::IRetVal;
+    }
+
+    // copies the body of the function to be placed at position `base` of
+    // the caller, `slots` has the symbol of the caller for every local slot
+    // of the function. The value returned stays on the stack, a function
+    // without a return value leaves the constant `noval_idx`.
+    pub fn inline(
+        subroutine: &Subroutine,
+        base: usize,
+        slots: &Vec<Rc<Symbol>>,
+        noval_idx: usize,
+    ) -> CompiledInstructions {
+        let mut decoded = PeepholeOptimizer::decode(subroutine.get_bytecode());
+
+        for inst in decoded.iter_mut() {
+            let is_global = |slot: usize| slots[slot].scope == ScopeKind::Global;
+            match inst.kind {
+                InstructionKind::ILoadLocal => {
+                    if is_global(inst.operands[0]) {
+                        inst.kind = InstructionKind::ILoadGlobal;
+                    }
+                    inst.operands[0] = slots[inst.operands[0]].pos;
+                }
+                InstructionKind::IStoreLocal => {
+                    if is_global(inst.operands[0]) {
+                        inst.kind = InstructionKind::IStoreGlobal;
+                    }
+                    inst.operands[0] = slots[inst.operands[0]].pos;
+                }
+                InstructionKind::IReleaseLocal => {
+                    if is_global(inst.operands[0]) {
+                        inst.kind = InstructionKind::IReleaseGlobal;
+                    }
+                    inst.operands[0] = slots[inst.operands[0]].pos;
+                }
+                InstructionKind::IIncrLocal => {
+                    if is_global(inst.operands[0]) {
+                        inst.kind = InstructionKind::IIncrGlobal;
+                    }
+                    inst.operands[0] = slots[inst.operands[0]].pos;
+                }
+                InstructionKind::IAddLocal => {
+                    if is_global(inst.operands[0]) {
+                        inst.kind = InstructionKind::IAddGlobal;
+                    }
+                    inst.operands[0] = slots[inst.operands[0]].pos;
+                }
+                // the return value is already on the stack, jumps to the
+                // return land on the instruction that replaces it.
+                InstructionKind::IRetVal => {
+                    inst.kind = InstructionKind::INoOp;
+                }
+                InstructionKind::IRet => {
+                    inst.kind = InstructionKind::IConstant;
+                    inst.operands = vec![noval_idx];
+                }
+                _ => {}
+            }
+        }
+
+        return PeepholeOptimizer::encode(&decoded, base);
+    }
+}
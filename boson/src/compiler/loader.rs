@@ -9,6 +9,7 @@ use crate::compiler::symtab::ConstantPool;
 use crate::compiler::CompiledBytecode;
 use crate::compiler::CompiledInstructions;
 use crate::types::object::Object;
+use crate::types::object::Value;
 use crate::types::subroutine::Subroutine;
 
 use std::collections::HashMap;
@@ -517,23 +518,23 @@ impl BytecodeLoader {
         let bin_pool = &self.bin[self.bin_pool_start..];
 
         let mut cp = vec![];
-        cp.resize(self.n_data_items - self.n_sub_items, Rc::new(Object::Noval));
+        cp.resize(self.n_data_items - self.n_sub_items, Value::new(Object::Noval));
         let mut instructions = vec![];
         // iterate over data pool:
         for (const_idx, data_item) in &self.data_table {
             let base_data_item: &DataIndexItem = &data_item[0];
             match base_data_item.t_code {
                 TypeCode::NONE => {
-                    cp[*const_idx as usize] = Rc::new(Object::Noval);
+                    cp[*const_idx as usize] = Value::new(Object::Noval);
                 }
                 TypeCode::CHAR => {
                     let data = bin_pool[base_data_item.start as usize];
-                    cp[*const_idx as usize] = Rc::new(Object::Char(data as char));
+                    cp[*const_idx as usize] = Value::new(Object::Char(data as char));
                 }
                 TypeCode::BOOL => {
                     let data = bin_pool[base_data_item.start as usize];
                     cp[*const_idx as usize] =
-                        Rc::new(Object::Bool(if data != 0u8 { true } else { false }));
+                        Value::new(Object::Bool(if data != 0u8 { true } else { false }));
                 }
                 TypeCode::STR => {
                     let str_slice =
@@ -543,7 +544,8 @@ impl BytecodeLoader {
                         return Err(format!("Invalid utf-8 string {:?}", str_slice));
                     }
 
-                    cp[*const_idx as usize] = Rc::new(Object::Str(string_res.unwrap()));
+                    cp[*const_idx as usize] =
+                        Value::new(Object::Str(Box::new(string_res.unwrap())));
                 }
                 TypeCode::INT => {
                     let b_slice =
@@ -553,7 +555,7 @@ impl BytecodeLoader {
                         return Err(result.unwrap_err());
                     }
 
-                    cp[*const_idx as usize] = Rc::new(Object::Int(result.unwrap()));
+                    cp[*const_idx as usize] = Value::new(Object::Int(result.unwrap()));
                 }
                 TypeCode::FLOAT => {
                     let b_slice =
@@ -563,7 +565,7 @@ impl BytecodeLoader {
                         return Err(result.unwrap_err());
                     }
 
-                    cp[*const_idx as usize] = Rc::new(Object::Float(result.unwrap()));
+                    cp[*const_idx as usize] = Value::new(Object::Float(result.unwrap()));
                 }
                 TypeCode::SUBROUTINE => {
                     let subroutine_item_res = self.subroutine_table.get(const_idx);
@@ -593,27 +595,23 @@ impl BytecodeLoader {
                         instructions = bytecode_vector;
                     } else {
                         // child function
-                        let subroutine_obj = Subroutine {
-                            name: sub_name_res.unwrap(),
-                            bytecode: bytecode_vector,
-                            num_locals: subroutine_item.n_locals as usize,
-                            num_parameters: subroutine_item.n_params as usize,
-                            is_local_scope: subroutine_item.is_local,
-                        };
+                        let subroutine_obj = Subroutine::new(
+                            sub_name_res.unwrap(),
+                            bytecode_vector,
+                            subroutine_item.n_locals as usize,
+                            subroutine_item.n_params as usize,
+                            subroutine_item.is_local,
+                        );
 
                         cp[*const_idx as usize] =
-                            Rc::new(Object::Subroutine(Rc::new(subroutine_obj)));
+                            Value::new(Object::Subroutine(Rc::new(subroutine_obj)));
                     }
                 }
             }
         }
 
-        let n_objs = cp.len();
         return Ok(CompiledBytecode {
-            constant_pool: ConstantPool {
-                objects: cp,
-                size: n_objs,
-            },
+            constant_pool: ConstantPool::new_from_objects(cp),
             instructions,
         });
     }
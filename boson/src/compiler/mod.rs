@@ -1,17 +1,27 @@
+use std::collections::HashMap;
 use std::rc::Rc;
 
 pub mod errors;
+pub mod fold;
+pub mod inliner;
 pub mod loader;
+pub mod optimizer;
 pub mod symtab;
 
+use crate::config::GLOBAL_POOL_SIZE;
 use crate::isa;
 use crate::parser::ast;
 use crate::parser::exp;
+use crate::types::builtins::BuiltinKind;
 use crate::types::object::Object;
+use crate::types::object::Value;
+use crate::types::shell;
 use crate::types::subroutine::Subroutine;
 
 use isa::InstructionPacker;
+use inliner::FunctionInliner;
 use isa::Operands;
+use optimizer::PeepholeOptimizer;
 use symtab::ConstantPool;
 
 pub type CompiledInstructions = Vec<u8>;
@@ -56,6 +66,13 @@ impl ProgramScope {
         self.size += instructions.len();
     }
 
+    // removes the instructions from `pos` to the end and returns them.
+    fn take_instructions(&mut self, pos: usize) -> CompiledInstructions {
+        let taken = self.instructions.split_off(pos);
+        self.size = pos;
+        return taken;
+    }
+
     fn get_size(&self) -> usize {
         self.size
     }
@@ -109,12 +126,51 @@ pub struct BytecodeCompiler {
     loop_ctls: Vec<LoopControl>,
     n_lambdas: usize,
     attr_ctls: Vec<usize>,
+    n_defers: usize,
+    // label of the next loop, set by the labelled statement:
+    pending_label: Option<String>,
+    pub warnings: Vec<errors::CompileWarning>,
+    // top-level functions declared before the program is compiled, so
+    // they can call each other regardless of the order of definition.
+    hoisted: Vec<String>,
+    // functions that can be inlined, by the position of their global const:
+    inline_candidates: HashMap<usize, Rc<Subroutine>>,
+    // global slots of the functions inlined at the top level, shared by
+    // all the call sites as global slots are never re-used:
+    inline_globals: Vec<Rc<symtab::Symbol>>,
+    // the last function compiled and its number of free variables:
+    last_function: Option<(Rc<Subroutine>, usize)>,
+    // runs the peephole optimizer and the function inliner:
+    pub optimize: bool,
+    // number of globals the VM can hold, see VMConfig:
+    pub max_globals: usize,
+    // the open try and catch blocks, calls inside them are not tail calls:
+    try_scopes: Vec<TryScope>,
+    // source position of the statement being compiled, for the warnings:
+    statement_pos: usize,
+    // operands that could not be encoded, reported when the compilation ends
+    operand_error: Option<errors::CompileError>,
+}
+
+// a try or catch block being compiled, the finally block of its statement
+// runs before a return, break or continue leaves it:
+#[derive(Clone)]
+struct TryScope {
+    scope_index: usize,
+    // number of blocks around the try statement:
+    block_depth: usize,
+    final_block: Option<ast::BlockStatement>,
 }
 
 struct LoopControl {
     loop_start_pos: usize,
     pos_after_loop: usize,
     break_pos: Vec<usize>,
+    // blocks opened after this depth are closed by break and continue.
+    block_depth: usize,
+    label: Option<String>,
+    continue_pos: Vec<usize>,
+    continue_after_body: bool,
 }
 
 impl BytecodeCompiler {
@@ -132,6 +188,18 @@ impl BytecodeCompiler {
             loop_ctls: vec![],
             n_lambdas: 0,
             attr_ctls: vec![],
+            n_defers: 0,
+            pending_label: None,
+            warnings: vec![],
+            hoisted: vec![],
+            inline_candidates: HashMap::new(),
+            inline_globals: vec![],
+            last_function: None,
+            optimize: true,
+            max_globals: GLOBAL_POOL_SIZE,
+            try_scopes: vec![],
+            statement_pos: 0,
+            operand_error: None,
         };
     }
 
@@ -149,15 +217,42 @@ impl BytecodeCompiler {
             loop_ctls: vec![],
             n_lambdas: 0,
             attr_ctls: vec![],
+            n_defers: 0,
+            pending_label: None,
+            warnings: vec![],
+            hoisted: vec![],
+            inline_candidates: HashMap::new(),
+            inline_globals: vec![],
+            last_function: None,
+            optimize: true,
+            max_globals: GLOBAL_POOL_SIZE,
+            try_scopes: vec![],
+            statement_pos: 0,
+            operand_error: None,
         };
     }
 
     #[allow(dead_code)]
     fn register_constant(&mut self, obj: Object) -> usize {
-        self.constant_pool.set_object(Rc::new(obj))
+        self.constant_pool.set_object(Value::new(obj))
+    }
+
+    fn check_operands(&mut self, inst: &isa::InstructionKind, operands: &Operands) {
+        if self.operand_error.is_none() && !InstructionPacker::can_encode(operands) {
+            self.operand_error = Some(errors::CompileError::new(
+                format!(
+                    "Operands {:?} of {} are too large",
+                    operands,
+                    inst.as_string()
+                ),
+                errors::CompilerErrorKind::OperandOverflow,
+                0,
+            ));
+        }
     }
 
     fn save(&mut self, inst: isa::InstructionKind, operands: &Operands) -> usize {
+        self.check_operands(&inst, operands);
         let coded_stmt = InstructionPacker::encode_instruction(inst.clone(), operands);
         let current_pos = self.scopes[self.scope_index].get_size();
         self.scopes[self.scope_index].push_compiled_instructions(&coded_stmt);
@@ -166,6 +261,17 @@ impl BytecodeCompiler {
         return current_pos;
     }
 
+    // saves a jump whose target is not known yet, it is encoded wide so
+    // any target fits when it is replaced.
+    fn save_jump_placeholder(&mut self, inst: isa::InstructionKind) -> usize {
+        let coded_stmt = InstructionPacker::encode_wide_instruction(inst.clone(), &vec![0]);
+        let current_pos = self.scopes[self.scope_index].get_size();
+        self.scopes[self.scope_index].push_compiled_instructions(&coded_stmt);
+        self.scopes[self.scope_index].set_last(inst, current_pos);
+
+        return current_pos;
+    }
+
     #[allow(dead_code)]
     fn enter_scope(&mut self) {
         let new_scope = ProgramScope::new_scope();
@@ -193,10 +299,101 @@ impl BytecodeCompiler {
         return Ok(instructions);
     }
 
+    fn enter_block(&mut self) {
+        self.symbol_table.enter_block();
+        self.save(isa::InstructionKind::IBlockStart, &vec![]);
+    }
+
+    fn exit_block(&mut self) {
+        let declared = self.symbol_table.exit_block();
+        self.release_symbols(&declared);
+        self.save(isa::InstructionKind::IBlockEnd, &vec![]);
+    }
+
+    fn release_symbols(&mut self, symbols: &Vec<Rc<symtab::Symbol>>) {
+        for sym in symbols {
+            match sym.scope {
+                symtab::ScopeKind::Global => {
+                    self.save(isa::InstructionKind::IReleaseGlobal, &vec![sym.pos]);
+                }
+                symtab::ScopeKind::Local => {
+                    self.save(isa::InstructionKind::IReleaseLocal, &vec![sym.pos]);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // releases and closes all the blocks opened inside the current loop,
+    // used before break and continue jump out of them.
+    fn unwind_loop_blocks(&mut self, block_depth: usize) {
+        let blocks = self.symbol_table.get_block_symbols(block_depth);
+        for declared in &blocks {
+            self.release_symbols(declared);
+            self.save(isa::InstructionKind::IBlockEnd, &vec![]);
+        }
+    }
+
+    // registers a new name in the current block, names from the outer blocks
+    // and scopes can be shadowed, this raises a warning.
+    fn declare_symbol(
+        &mut self,
+        name: &str,
+        is_const: bool,
+    ) -> Result<Rc<symtab::Symbol>, errors::CompileError> {
+        return self.declare_symbol_with_value(name, is_const, None);
+    }
+
+    fn declare_symbol_with_value(
+        &mut self,
+        name: &str,
+        is_const: bool,
+        value: Option<Value>,
+    ) -> Result<Rc<symtab::Symbol>, errors::CompileError> {
+        if self.symbol_table.is_declared_in_scope(name) {
+            return Err(errors::CompileError::new(
+                format!("{} already declared", name),
+                errors::CompilerErrorKind::SymbolAlreadyExist,
+                0,
+            ));
+        }
+
+        let existing = self.symbol_table.lookup_symbol(name);
+        if existing.is_some() {
+            let existing_sym = existing.unwrap();
+            if existing_sym.scope == symtab::ScopeKind::Builtin {
+                return Err(errors::CompileError::new(
+                    format!("Cannot assign {} as built-in.", name),
+                    errors::CompilerErrorKind::BuiltinAssignment,
+                    0,
+                ));
+            }
+
+            self.warnings.push(errors::CompileWarning::new(
+                format!("{} shadows a previous declaration", name),
+                errors::CompilerWarningKind::ShadowedSymbol,
+                self.statement_pos,
+            ));
+        }
+
+        let symbol = self
+            .symbol_table
+            .insert_new_symbol_with_value(name, is_const, value);
+        if symbol.scope == symtab::ScopeKind::Global && symbol.pos >= self.max_globals {
+            return Err(errors::CompileError::new(
+                format!("Too many globals, {} does not fit", name),
+                errors::CompilerErrorKind::OperandOverflow,
+                0,
+            ));
+        }
+
+        return Ok(symbol);
+    }
+
     fn compile_literal(&mut self, literal: &ast::LiteralKind) -> Option<errors::CompileError> {
         match literal {
             ast::LiteralKind::Str(st) => {
-                let idx = self.register_constant(Object::Str(st.to_string()));
+                let idx = self.register_constant(Object::Str(Box::new(st.to_string())));
 
                 self.save(isa::InstructionKind::IConstant, &vec![idx]);
             }
@@ -258,7 +455,7 @@ impl BytecodeCompiler {
         }
 
         self.save(isa::InstructionKind::ILNot, &vec![]);
-        let not_jmp_pos = self.save(isa::InstructionKind::INotJump, &vec![0]);
+        let not_jmp_pos = self.save_jump_placeholder(isa::InstructionKind::INotJump);
 
         // load panic expression:
         error = self.compile_expression(&node.fail_expr);
@@ -290,7 +487,7 @@ impl BytecodeCompiler {
         }
 
         // add a not jump, this will be replaced to either else or NoOp
-        let jump_instr_pos = self.save(isa::InstructionKind::INotJump, &vec![0]);
+        let jump_instr_pos = self.save_jump_placeholder(isa::InstructionKind::INotJump);
 
         // compile the if block:
         error = self.compile_block_statement(&node.main_block);
@@ -319,7 +516,7 @@ impl BytecodeCompiler {
 
         // it has an else statement:
         // add a JUMP in if statement:
-        let after_if_pos = self.save(isa::InstructionKind::IJump, &vec![0]);
+        let after_if_pos = self.save_jump_placeholder(isa::InstructionKind::IJump);
 
         let else_pos = self.save(isa::InstructionKind::INoOp, &vec![]);
         error = self.replace_instruction_operands(
@@ -358,15 +555,36 @@ impl BytecodeCompiler {
         if node.expression.is_some() {
             // compile the return expression
             let exp = &node.expression.as_ref().unwrap();
-            let error = self.compile_expression(exp);
+
+            // a call in tail position can re-use the frame of the function:
+            let error = match exp {
+                ast::ExpressionKind::Call(ct)
+                    if self.scope_index != 0
+                        && self.try_scopes.last().map(|t| t.scope_index) != Some(self.scope_index) =>
+                {
+                    self.compile_call(ct, true)
+                }
+                _ => self.compile_expression(exp),
+            };
 
             if error.is_some() {
                 return error;
             }
 
+            // the value is returned after the finally blocks ran:
+            let error = self.compile_finally_exits(None);
+            if error.is_some() {
+                return error;
+            }
+
             self.save(isa::InstructionKind::IRetVal, &vec![]);
             return None;
         } else {
+            let error = self.compile_finally_exits(None);
+            if error.is_some() {
+                return error;
+            }
+
             self.save(isa::InstructionKind::IRet, &vec![]);
             return None;
         }
@@ -377,27 +595,31 @@ impl BytecodeCompiler {
         node: &ast::FunctionType,
         is_lambda: bool,
     ) -> Option<errors::CompileError> {
-        // check function name:
-        let resolve_result = self.symbol_table.resolve_symbol(&node.name);
-        if resolve_result.is_some() {
-            return Some(errors::CompileError::new(
-                format!("Name {} already defined", &node.name),
-                errors::CompilerErrorKind::SymbolAlreadyExist,
-                0,
-            ));
-        }
-
         // store
         let mut sym_res: Option<Rc<symtab::Symbol>> = None;
-        if !is_lambda {
-            sym_res = Some(self.symbol_table.insert_new_symbol(&node.name, true));
+        let hoisted_pos = self.hoisted.iter().position(|name| *name == node.name);
+        if !is_lambda
+            && self.scope_index == 0
+            && self.symbol_table.get_block_depth() == 0
+            && hoisted_pos.is_some()
+        {
+            self.hoisted.remove(hoisted_pos.unwrap());
+            sym_res = self.symbol_table.lookup_symbol(&node.name);
+        } else if !is_lambda {
+            let declared = self.declare_symbol(&node.name, true);
+            if declared.is_err() {
+                return Some(declared.unwrap_err());
+            }
+
+            sym_res = Some(declared.unwrap());
         }
 
-        // enter the scope:
+        // enter the scope, loops of the enclosing scope are not visible:
         self.enter_scope();
+        let outer_loop_ctls = std::mem::take(&mut self.loop_ctls);
+        let outer_label = self.pending_label.take();
 
         let args = &node.parameters;
-        let error: Option<errors::CompileError>;
 
         for arg in args {
             match arg {
@@ -415,59 +637,77 @@ impl BytecodeCompiler {
             }
         }
 
+        // the body shares the scope of the parameters, the frame is
+        // cleared on return so no block markers are needed:
         let func_block = &node.body;
-        error = self.compile_block_statement(func_block);
-        if error.is_some() {
-            return error;
+        for (idx, stmt) in func_block.statements.iter().enumerate() {
+            self.set_statement_pos(func_block, idx);
+            let error = self.compile_statement(&stmt);
+            if error.is_some() {
+                return error;
+            }
         }
 
         // check if there is a return statement at last:
         let n_statements = func_block.statements.len();
         let last_stmt = &func_block.statements[n_statements - 1];
 
-        if !is_lambda {
-            match last_stmt {
-                ast::StatementKind::Return(_) => {}
-                _ => {
+        match last_stmt {
+            ast::StatementKind::Return(_) => {}
+            _ => {
+                if !is_lambda {
                     // append a return void statement
                     self.save(isa::InstructionKind::IRet, &vec![]);
+                } else {
+                    self.save(isa::InstructionKind::IRetVal, &vec![]);
                 }
             }
-        } else {
-            self.save(isa::InstructionKind::IRetVal, &vec![]);
         }
 
         let free_symbols = self.symbol_table.get_free_symbols();
-        let n_locals = self.symbol_table.n_items;
+        let n_locals = self.symbol_table.max_items;
+        self.loop_ctls = outer_loop_ctls;
+        self.pending_label = outer_label;
 
         let compiled_result = self.exit_scope();
         if compiled_result.is_err() {
             return Some(compiled_result.unwrap_err());
         }
 
-        let compiled_func = compiled_result.unwrap();
+        let mut compiled_func = compiled_result.unwrap();
+        if self.optimize {
+            compiled_func = PeepholeOptimizer::optimize(&compiled_func, &self.constant_pool);
+        } else {
+            compiled_func = PeepholeOptimizer::relax(&compiled_func);
+        }
 
         for sym in &free_symbols {
             match sym.scope {
                 symtab::ScopeKind::Local => {
-                    self.save(isa::InstructionKind::ILoadLocal, &vec![sym.pos]);
+                    self.save(isa::InstructionKind::ICaptureLocal, &vec![sym.pos]);
                 }
                 symtab::ScopeKind::Free => {
-                    self.save(isa::InstructionKind::ILoadGlobal, &vec![sym.pos]);
+                    self.save(isa::InstructionKind::ICaptureFree, &vec![sym.pos]);
                 }
                 _ => {}
             }
         }
 
-        let compiled_func_type = Subroutine {
-            name: node.name.clone(),
-            bytecode: compiled_func,
-            num_locals: n_locals,
-            num_parameters: args.len(),
-            is_local_scope: false,
-        };
+        let compiled_func_type = Subroutine::new(
+            node.name.clone(),
+            compiled_func,
+            n_locals,
+            args.len(),
+            false,
+        );
+
+        let subroutine = Rc::new(compiled_func_type);
+        self.last_function = Some((Rc::clone(&subroutine), free_symbols.len()));
+        if sym_res.is_some() && free_symbols.len() == 0 {
+            self.register_inline_candidate(sym_res.as_ref().unwrap(), &subroutine);
+        }
 
-        let func_object = Object::Subroutine(Rc::new(compiled_func_type));
+        let func_object = Object::Subroutine(subroutine);
 
         // register the sub-routine:
         let func_idx = self.register_constant(func_object);
@@ -522,17 +762,12 @@ impl BytecodeCompiler {
 
         match node {
             ast::ExpressionKind::Identifier(id) => {
-                // check if it's constant:
-                let resolved_sym = self.symbol_table.resolve_symbol(&id.name);
-                if resolved_sym.is_some() && resolved_sym.unwrap().is_const {
-                    return Some(errors::CompileError::new(
-                        format!("Cannot assign to constant {}", id.name),
-                        errors::CompilerErrorKind::ConstantAssignment,
-                        0,
-                    ));
+                let declared = self.declare_symbol(&id.name, false);
+                if declared.is_err() {
+                    return Some(declared.unwrap_err());
                 }
 
-                registered_element = self.symbol_table.insert_new_symbol(&id.name, false)
+                registered_element = declared.unwrap();
             }
             _ => {
                 return Some(errors::CompileError::new(
@@ -566,6 +801,9 @@ impl BytecodeCompiler {
     fn compile_for_loop(&mut self, node: &ast::ForLoopType) -> Option<errors::CompileError> {
         let iter_exp = &node.iter;
 
+        // the loop block holds the iterator and the loop variable:
+        self.enter_block();
+
         // compile:
         let mut error = self.compile_expression(&iter_exp);
         if error.is_some() {
@@ -577,6 +815,10 @@ impl BytecodeCompiler {
             loop_start_pos: current_pos.clone(),
             pos_after_loop: 0,
             break_pos: vec![],
+            block_depth: self.symbol_table.get_block_depth(),
+            label: self.pending_label.take(),
+            continue_pos: vec![],
+            continue_after_body: false,
         };
 
         self.loop_ctls.push(new_loop_ctl);
@@ -587,7 +829,7 @@ impl BytecodeCompiler {
 
         // Perform iteration, replace the iterator end later
         self.save(isa::InstructionKind::IIter, &vec![]);
-        let loop_start = self.save(isa::InstructionKind::IIterNext, &vec![0]);
+        let loop_start = self.save_jump_placeholder(isa::InstructionKind::IIterNext);
         self.loop_ctls[current_loop_ctl].loop_start_pos = loop_start;
 
         // load the iter variable:
@@ -635,12 +877,16 @@ impl BytecodeCompiler {
 
         // pop the loop control:
         self.loop_ctls.pop();
+        self.exit_block();
         return None;
     }
 
     fn compile_feach_stmt(&mut self, node: &ast::ForEachType) -> Option<errors::CompileError> {
         let iter_exp = &node.iterator_exp;
 
+        // the loop block holds the iterator and the loop variables:
+        self.enter_block();
+
         // compile:
         let mut error = self.compile_expression(&iter_exp);
         if error.is_some() {
@@ -652,6 +898,10 @@ impl BytecodeCompiler {
             loop_start_pos: current_pos.clone(),
             pos_after_loop: 0,
             break_pos: vec![],
+            block_depth: self.symbol_table.get_block_depth(),
+            label: self.pending_label.take(),
+            continue_pos: vec![],
+            continue_after_body: false,
         };
 
         self.loop_ctls.push(new_loop_ctl);
@@ -662,7 +912,7 @@ impl BytecodeCompiler {
         let index = &node.index;
 
         self.save(isa::InstructionKind::IIter, &vec![]);
-        let loop_start = self.save(isa::InstructionKind::IEnumNext, &vec![0]);
+        let loop_start = self.save_jump_placeholder(isa::InstructionKind::IEnumNext);
         self.loop_ctls[current_loop_ctl].loop_start_pos = loop_start;
 
         error = self.register_loop_var(index);
@@ -714,6 +964,7 @@ impl BytecodeCompiler {
 
         // pop the loop control:
         self.loop_ctls.pop();
+        self.exit_block();
         return None;
     }
 
@@ -723,6 +974,13 @@ impl BytecodeCompiler {
         check_const: bool,
     ) -> Option<errors::CompileError> {
         let id_name = &idt.name;
+
+        // constants known at compile time are inlined, this also avoids
+        // capturing them in closures:
+        if !check_const && self.compile_folded(&ast::ExpressionKind::Identifier(idt.clone())) {
+            return None;
+        }
+
         // resolve it
         let resolve_result = self.symbol_table.resolve_symbol(id_name);
         if resolve_result.is_none() {
@@ -873,7 +1131,7 @@ impl BytecodeCompiler {
 
         // substitute all the resovlers as constant pools:
         for attr in &resolver.child_attrs {
-            let idx = self.register_constant(Object::Str(attr.clone()));
+            let idx = self.register_constant(Object::Str(Box::new(attr.clone())));
             self.save(isa::InstructionKind::IConstant, &vec![idx]);
         }
 
@@ -969,6 +1227,9 @@ impl BytecodeCompiler {
                     &vec![resolved_symbol.pos],
                 );
             }
+            symtab::ScopeKind::Free => {
+                self.save(isa::InstructionKind::IStoreFree, &vec![resolved_symbol.pos]);
+            }
             _ => {
                 return Some(errors::CompileError::new(
                     format!("Invalid assignment {}", id.name),
@@ -1134,6 +1395,23 @@ impl BytecodeCompiler {
     }
 
     fn compile_shell_expr(&mut self, node: &ast::ShellType) -> Option<errors::CompileError> {
+        // $$ "cat {file}" is a template, the values of the placeholders are quoted:
+        if node.is_template {
+            match node.shell.as_ref() {
+                ast::ExpressionKind::Literal(ast::LiteralKind::Str(template)) => {
+                    let names = shell::placeholders(template);
+                    return self.compile_shell_template(template, names, node.is_raw);
+                }
+                _ => {
+                    return Some(errors::CompileError::new(
+                        "A shell template must be a string literal".to_string(),
+                        errors::CompilerErrorKind::InvalidOperand,
+                        0,
+                    ));
+                }
+            }
+        }
+
         // compile the expression
         let error = self.compile_expression(&node.shell);
         if error.is_some() {
@@ -1149,6 +1427,71 @@ impl BytecodeCompiler {
         return None;
     }
 
+    // $$ "cat {file}" is compiled as sh("cat {file}", {"file": file}):
+    fn compile_shell_template(
+        &mut self,
+        template: &String,
+        names: Vec<String>,
+        is_raw: bool,
+    ) -> Option<errors::CompileError> {
+        for name in &names {
+            if self.symbol_table.resolve_symbol(name).is_none() {
+                return Some(errors::CompileError::new(
+                    format!("Unknown placeholder {{{}}} in the shell template {}", name, template),
+                    errors::CompilerErrorKind::UnresolvedSymbol,
+                    0,
+                ));
+            }
+        }
+
+        let pairs = names
+            .into_iter()
+            .map(|name| {
+                (
+                    ast::ExpressionKind::Literal(ast::LiteralKind::Str(name.clone())),
+                    ast::ExpressionKind::Identifier(ast::IdentifierType { name: name, t: None }),
+                )
+            })
+            .collect();
+
+        let args = vec![
+            ast::ExpressionKind::Literal(ast::LiteralKind::Str(template.clone())),
+            ast::ExpressionKind::Literal(ast::LiteralKind::HashTable(ast::HashTableType {
+                pairs: pairs,
+            })),
+        ];
+
+        for arg in &args {
+            let error = self.compile_expression(arg);
+            if error.is_some() {
+                return error;
+            }
+        }
+
+        // the builtin is loaded by its index, a symbol named sh does not hide it:
+        let builtin = if is_raw {
+            BuiltinKind::ShellRaw
+        } else {
+            BuiltinKind::Shell
+        };
+        self.save(isa::InstructionKind::ILoadBuiltIn, &vec![builtin as usize]);
+        self.save(isa::InstructionKind::ICall, &vec![args.len()]);
+        return None;
+    }
+
+    // emits the value of the expression as a constant if it can be
+    // evaluated at compile time.
+    fn compile_folded(&mut self, expression: &ast::ExpressionKind) -> bool {
+        let folded = fold::ConstantFolder::fold_expression(expression, &self.symbol_table);
+        if folded.is_none() {
+            return false;
+        }
+
+        let idx = self.constant_pool.set_object(folded.unwrap());
+        self.save(isa::InstructionKind::IConstant, &vec![idx]);
+        return true;
+    }
+
     #[allow(unused_variables)]
     fn compile_expression(
         &mut self,
@@ -1168,10 +1511,16 @@ impl BytecodeCompiler {
                 return result;
             }
             ast::ExpressionKind::Infix(expr) => {
+                if self.compile_folded(expression) {
+                    return None;
+                }
                 let result = self.compile_infix_expression(&expr);
                 return result;
             }
             ast::ExpressionKind::Prefix(expr) => {
+                if self.compile_folded(expression) {
+                    return None;
+                }
                 let result = self.compile_prefix_expression(&expr);
                 return result;
             }
@@ -1180,7 +1529,7 @@ impl BytecodeCompiler {
                 return result;
             }
             ast::ExpressionKind::Call(ct) => {
-                let result = self.compile_call(&ct);
+                let result = self.compile_call(&ct, false);
                 return result;
             }
             ast::ExpressionKind::Shell(sh) => {
@@ -1206,20 +1555,40 @@ impl BytecodeCompiler {
 
     fn compile_const_declr(&mut self, stmt: &ast::ConstType) -> Option<errors::CompileError> {
         let var_name = &stmt.identifier.name;
-        // resolve the name:
-        let resolve_result = self.symbol_table.resolve_symbol(&var_name);
-        if resolve_result.is_some() {
-            return Some(errors::CompileError::new(
-                format!("{} already declared", var_name),
-                errors::CompilerErrorKind::SymbolAlreadyExist,
-                0,
-            ));
+
+        // constants with a value known at compile time are inlined:
+        let mut value: Option<Value> = None;
+        if stmt.expression.is_some() {
+            value = fold::ConstantFolder::fold_expression(
+                stmt.expression.as_ref().unwrap(),
+                &self.symbol_table,
+            );
+        }
+
+        let declared = self.declare_symbol_with_value(&var_name, true, value);
+        if declared.is_err() {
+            return Some(declared.unwrap_err());
         }
 
-        let registered_symbol = self.symbol_table.insert_new_symbol(&var_name, true);
+        let registered_symbol = declared.unwrap();
 
         if stmt.expression.is_some() {
-            self.compile_expression(stmt.expression.as_ref().unwrap());
+            self.last_function = None;
+            let expr = stmt.expression.as_ref().unwrap();
+            let error = self.compile_expression(expr);
+            if error.is_some() {
+                return error;
+            }
+
+            // constant lambdas can be inlined like functions:
+            if let ast::ExpressionKind::Lambda(_) = expr {
+                if self.last_function.is_some() {
+                    let (subroutine, n_free) = self.last_function.clone().unwrap();
+                    if n_free == 0 {
+                        self.register_inline_candidate(&registered_symbol, &subroutine);
+                    }
+                }
+            }
         }
 
         match registered_symbol.scope {
@@ -1292,20 +1661,18 @@ impl BytecodeCompiler {
 
     fn compile_variable_declr(&mut self, stmt: &ast::LetType) -> Option<errors::CompileError> {
         let var_name = &stmt.identifier.name;
-        // resolve the name:
-        let resolve_result = self.symbol_table.resolve_symbol(&var_name);
-        if resolve_result.is_some() {
-            return Some(errors::CompileError::new(
-                format!("{} already declared", var_name),
-                errors::CompilerErrorKind::SymbolAlreadyExist,
-                0,
-            ));
+        let declared = self.declare_symbol(&var_name, false);
+        if declared.is_err() {
+            return Some(declared.unwrap_err());
         }
 
-        let registered_symbol = self.symbol_table.insert_new_symbol(&var_name, false);
+        let registered_symbol = declared.unwrap();
 
         if stmt.expression.is_some() {
-            self.compile_expression(stmt.expression.as_ref().unwrap());
+            let error = self.compile_expression(stmt.expression.as_ref().unwrap());
+            if error.is_some() {
+                return error;
+            }
         } else {
             // register a noval:
             let no_val = self.register_constant(Object::Noval);
@@ -1362,8 +1729,23 @@ impl BytecodeCompiler {
             ));
         }
 
-        // substitute:
-        let compiled_opcode = InstructionPacker::encode_instruction(inst, &operands);
+        // substitute, the replacement must keep the width of the instruction:
+        self.check_operands(&inst, operands);
+        let is_wide =
+            self.scopes[scope_idx].instructions[*pos] == isa::InstructionKind::IWide as u8;
+        if !is_wide && InstructionPacker::needs_wide(&inst, operands) {
+            return Some(errors::CompileError::new(
+                format!("Operands {:?} of {} do not fit", operands, inst.as_string()),
+                errors::CompilerErrorKind::OperandOverflow,
+                0,
+            ));
+        }
+
+        let compiled_opcode = if is_wide {
+            InstructionPacker::encode_wide_instruction(inst, &operands)
+        } else {
+            InstructionPacker::encode_instruction(inst, &operands)
+        };
 
         for idx in 0..compiled_opcode.len() {
             self.scopes[scope_idx].instructions[*pos + idx] = compiled_opcode[idx];
@@ -1372,8 +1754,45 @@ impl BytecodeCompiler {
         return None;
     }
 
-    fn compile_call(&mut self, node: &ast::CallType) -> Option<errors::CompileError> {
+    fn compile_call(
+        &mut self,
+        node: &ast::CallType,
+        is_tail: bool,
+    ) -> Option<errors::CompileError> {
         let args = &node.arguments;
+        let fn_expr = &node.function;
+
+        // constant functions and lambdas called right away can be inlined:
+        let mut inlined: Option<Rc<Subroutine>> = None;
+        let mut closure_code: Option<CompiledInstructions> = None;
+        if self.optimize && !node.is_thread && !node.is_async {
+            match fn_expr.as_ref() {
+                ast::ExpressionKind::Identifier(id) => {
+                    inlined = self.get_inline_candidate(&id.name);
+                }
+                ast::ExpressionKind::Lambda(lm) => {
+                    // the closure is compiled first and moved after the arguments:
+                    let start = self.scopes[self.scope_index].get_size();
+                    self.last_function = None;
+                    let error = self.compile_lambda(lm);
+                    if error.is_some() {
+                        return error;
+                    }
+
+                    closure_code = Some(self.scopes[self.scope_index].take_instructions(start));
+                    let (subroutine, n_free) = self.last_function.clone().unwrap();
+                    if n_free == 0 && FunctionInliner::can_inline(&subroutine) {
+                        inlined = Some(subroutine);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // calls with a wrong number of arguments fail at runtime:
+        if inlined.is_some() && inlined.as_ref().unwrap().num_parameters != args.len() {
+            inlined = None;
+        }
 
         // compile all arguments:
         for idx in 0..args.len() {
@@ -1386,9 +1805,10 @@ impl BytecodeCompiler {
             }
         }
 
-        // resolve function name:
-        
-        let fn_expr = &node.function;
+        if inlined.is_some() {
+            self.compile_inlined(&inlined.unwrap());
+            return None;
+        }
 
         // check if it's a attribute call:
         let mut is_attr = false;
@@ -1400,9 +1820,13 @@ impl BytecodeCompiler {
             _ => {}
         }
 
-        let error = self.compile_expression(fn_expr);
-        if error.is_some() {
-            return error;
+        if closure_code.is_some() {
+            self.scopes[self.scope_index].push_compiled_instructions(&closure_code.unwrap());
+        } else {
+            let error = self.compile_expression(fn_expr);
+            if error.is_some() {
+                return error;
+            }
         }
 
         if is_attr {
@@ -1414,6 +1838,8 @@ impl BytecodeCompiler {
             self.save(isa::InstructionKind::ICallThread, &vec![args.len()]);
         } else if node.is_async {
             self.save(isa::InstructionKind::ICallAsync, &vec![args.len()]);
+        } else if is_tail {
+            self.save(isa::InstructionKind::ITailCall, &vec![args.len()]);
         } else {
             self.save(isa::InstructionKind::ICall, &vec![args.len()]);
         }
@@ -1421,38 +1847,514 @@ impl BytecodeCompiler {
         return None;
     }
 
-    fn compile_break_stmt(&mut self) -> Option<errors::CompileError> {
+    fn register_inline_candidate(
+        &mut self,
+        symbol: &Rc<symtab::Symbol>,
+        subroutine: &Rc<Subroutine>,
+    ) {
+        if !self.optimize || !symbol.is_const || symbol.scope != symtab::ScopeKind::Global {
+            return;
+        }
+
+        if FunctionInliner::can_inline(subroutine) {
+            self.inline_candidates
+                .insert(symbol.pos, Rc::clone(subroutine));
+        }
+    }
+
+    fn get_inline_candidate(&self, name: &str) -> Option<Rc<Subroutine>> {
+        let symbol = self.symbol_table.lookup_symbol(name);
+        if symbol.is_none() {
+            return None;
+        }
+
+        let sym = symbol.unwrap();
+        if !sym.is_const || sym.scope != symtab::ScopeKind::Global {
+            return None;
+        }
+
+        return self.inline_candidates.get(&sym.pos).cloned();
+    }
+
+    // places the body of the function at the call site, the arguments are
+    // on the stack and are stored in the slots of the parameters.
+    fn compile_inlined(&mut self, subroutine: &Rc<Subroutine>) {
+        let is_global = self.symbol_table.level == 0;
+        if is_global {
+            // the missing slots are taken once, their names go away with the block:
+            self.symbol_table.enter_block();
+            while self.inline_globals.len() < subroutine.num_locals {
+                let name = format!("#inline_{}", self.inline_globals.len());
+                let slot = self.symbol_table.insert_new_symbol(&name, false);
+                self.inline_globals.push(slot);
+            }
+            self.symbol_table.exit_block();
+        }
+
+        self.symbol_table.enter_block();
+
+        let mut slots = vec![];
+        for idx in 0..subroutine.num_locals {
+            if is_global {
+                slots.push(Rc::clone(&self.inline_globals[idx]));
+                continue;
+            }
+
+            let name = format!("#inline_{}", idx);
+            slots.push(self.symbol_table.insert_new_symbol(&name, false));
+        }
+
+        for idx in (0..subroutine.num_parameters).rev() {
+            let slot = &slots[idx];
+            match slot.scope {
+                symtab::ScopeKind::Global => {
+                    self.save(isa::InstructionKind::IStoreGlobal, &vec![slot.pos]);
+                }
+                _ => {
+                    self.save(isa::InstructionKind::IStoreLocal, &vec![slot.pos]);
+                }
+            }
+        }
+
+        let noval_idx = self.register_constant(Object::Noval);
+        let base = self.scopes[self.scope_index].get_size();
+        let inlined = FunctionInliner::inline(subroutine, base, &slots, noval_idx);
+        self.scopes[self.scope_index].push_compiled_instructions(&inlined);
+
+        // the slots are released, the value returned stays on the stack:
+        self.symbol_table.exit_block();
+        self.release_symbols(&slots);
+    }
+
+    // runs the finally blocks of the try statements a return, or a break or
+    // continue to the loop at `loop_depth`, leaves, the innermost first. The
+    // handler of each block is dropped before, so an error of a finally
+    // block is not caught by its own try statement.
+    fn compile_finally_exits(&mut self, loop_depth: Option<usize>) -> Option<errors::CompileError> {
+        let mut idx = self.try_scopes.len();
+        while idx > 0 {
+            let try_scope = self.try_scopes[idx - 1].clone();
+            if try_scope.scope_index != self.scope_index
+                || (loop_depth.is_some() && try_scope.block_depth < loop_depth.unwrap())
+            {
+                break;
+            }
+
+            idx -= 1;
+            self.save(isa::InstructionKind::IPopExcHandle, &vec![]);
+            if try_scope.final_block.is_none() {
+                continue;
+            }
+
+            // the block is compiled as if it followed the try statement: the
+            // names, loops and try blocks opened after it are not visible.
+            let hidden_tries = self.try_scopes.split_off(idx);
+            let n_loops = self
+                .loop_ctls
+                .iter()
+                .take_while(|ctl| ctl.block_depth <= try_scope.block_depth)
+                .count();
+            let hidden_loops = self.loop_ctls.split_off(n_loops);
+            let hidden_blocks = self.symbol_table.hide_blocks(try_scope.block_depth);
+            let n_warnings = self.warnings.len();
+            let statement_pos = self.statement_pos;
+
+            let error = self.compile_block_statement(try_scope.final_block.as_ref().unwrap());
+
+            // its warnings are given when it is compiled after the catch block:
+            self.warnings.truncate(n_warnings);
+            self.statement_pos = statement_pos;
+            self.symbol_table.restore_blocks(hidden_blocks);
+            self.loop_ctls.extend(hidden_loops);
+            self.try_scopes.extend(hidden_tries);
+            if error.is_some() {
+                return error;
+            }
+        }
+
+        return None;
+    }
+
+    // finds the loop targeted by break or continue, the innermost loop if
+    // there is no label.
+    fn find_loop_ctl(&self, label: &Option<String>) -> Option<usize> {
         let n_loop_ctls = self.loop_ctls.len();
-        if n_loop_ctls == 0 {
+        if label.is_none() {
+            if n_loop_ctls == 0 {
+                return None;
+            }
+
+            return Some(n_loop_ctls - 1);
+        }
+
+        for idx in (0..n_loop_ctls).rev() {
+            if &self.loop_ctls[idx].label == label {
+                return Some(idx);
+            }
+        }
+
+        return None;
+    }
+
+    fn compile_break_stmt(&mut self, label: &Option<String>) -> Option<errors::CompileError> {
+        let loop_idx = self.find_loop_ctl(label);
+        if loop_idx.is_none() {
+            let message = match label {
+                Some(name) => format!("break to unknown loop label {}", name),
+                None => "break encountered outside loop".to_string(),
+            };
+
             return Some(errors::CompileError::new(
-                "break encountered outside loop".to_string(),
+                message,
                 errors::CompilerErrorKind::InvalidBreak,
                 0,
             ));
         }
 
-        self.save(isa::InstructionKind::IBlockEnd, &vec![]);
-        let break_pos = self.save(isa::InstructionKind::IJump, &vec![0]);
+        let idx = loop_idx.unwrap();
+        let block_depth = self.loop_ctls[idx].block_depth;
+        let error = self.compile_finally_exits(Some(block_depth));
+        if error.is_some() {
+            return error;
+        }
+
+        self.unwind_loop_blocks(block_depth);
+        let break_pos = self.save_jump_placeholder(isa::InstructionKind::IJump);
 
-        self.loop_ctls[n_loop_ctls - 1].break_pos.push(break_pos);
+        self.loop_ctls[idx].break_pos.push(break_pos);
         return None;
     }
 
-    fn compile_continue_stmt(&mut self) -> Option<errors::CompileError> {
-        let n_loop_ctls = self.loop_ctls.len();
-        if n_loop_ctls == 0 {
+    fn compile_continue_stmt(&mut self, label: &Option<String>) -> Option<errors::CompileError> {
+        let loop_idx = self.find_loop_ctl(label);
+        if loop_idx.is_none() {
+            let message = match label {
+                Some(name) => format!("continue to unknown loop label {}", name),
+                None => "continue encountered outside loop".to_string(),
+            };
+
             return Some(errors::CompileError::new(
-                "continue encountered outside loop".to_string(),
+                message,
                 errors::CompilerErrorKind::InvalidContinue,
                 0,
             ));
         }
 
-        let jump_pos = self.loop_ctls[n_loop_ctls - 1].loop_start_pos;
+        let idx = loop_idx.unwrap();
+        let block_depth = self.loop_ctls[idx].block_depth;
+        let error = self.compile_finally_exits(Some(block_depth));
+        if error.is_some() {
+            return error;
+        }
 
-        self.save(isa::InstructionKind::IBlockEnd, &vec![]);
-        self.save(isa::InstructionKind::IJump, &vec![jump_pos]);
+        self.unwind_loop_blocks(block_depth);
+
+        // do-while loops check the condition after the body, the jump
+        // is replaced once the condition is compiled:
+        if self.loop_ctls[idx].continue_after_body {
+            let continue_pos = self.save_jump_placeholder(isa::InstructionKind::IJump);
+            self.loop_ctls[idx].continue_pos.push(continue_pos);
+        } else {
+            let jump_pos = self.loop_ctls[idx].loop_start_pos;
+            self.save(isa::InstructionKind::IJump, &vec![jump_pos]);
+        }
+
+        return None;
+    }
+
+    fn compile_do_while_loop(&mut self, node: &ast::WhileLoopType) -> Option<errors::CompileError> {
+        let current_pos = self.scopes[self.scope_index].get_size();
+        let new_loop_ctl = LoopControl {
+            loop_start_pos: current_pos.clone(),
+            pos_after_loop: 0,
+            break_pos: vec![],
+            block_depth: self.symbol_table.get_block_depth(),
+            label: self.pending_label.take(),
+            continue_pos: vec![],
+            continue_after_body: true,
+        };
+
+        self.loop_ctls.push(new_loop_ctl);
+        let current_loop_ctl = self.loop_ctls.len() - 1;
+
+        // compile the loop body first:
+        let block_error = self.compile_block_statement(&node.loop_block);
+        if block_error.is_some() {
+            return block_error;
+        }
+
+        // compile the loop expression, continue jumps here:
+        let cond_pos = self.save(isa::InstructionKind::INoOp, &vec![]);
+        let expr_error = self.compile_expression(&node.target_expr);
+        if expr_error.is_some() {
+            return expr_error;
+        }
+
+        let jump_inst_pos = self.save_jump_placeholder(isa::InstructionKind::INotJump);
+        self.save(isa::InstructionKind::IJump, &vec![current_pos]);
+        let pos_after_loop = self.save(isa::InstructionKind::INoOp, &vec![]);
+        self.loop_ctls[current_loop_ctl].pos_after_loop = pos_after_loop;
+
+        let mut error = self.replace_instruction_operands(
+            self.scope_index,
+            isa::InstructionKind::INotJump,
+            &vec![pos_after_loop],
+            &jump_inst_pos,
+        );
+
+        if error.is_some() {
+            return error;
+        }
+
+        // replace all break and continue instructions:
+        for idx in 0..self.loop_ctls[current_loop_ctl].break_pos.len() {
+            let pos = self.loop_ctls[current_loop_ctl].break_pos[idx];
+            error = self.replace_instruction_operands(
+                self.scope_index,
+                isa::InstructionKind::IJump,
+                &vec![pos_after_loop],
+                &pos,
+            );
+            if error.is_some() {
+                return error;
+            }
+        }
+
+        for idx in 0..self.loop_ctls[current_loop_ctl].continue_pos.len() {
+            let pos = self.loop_ctls[current_loop_ctl].continue_pos[idx];
+            error = self.replace_instruction_operands(
+                self.scope_index,
+                isa::InstructionKind::IJump,
+                &vec![cond_pos],
+                &pos,
+            );
+            if error.is_some() {
+                return error;
+            }
+        }
+
+        self.loop_ctls.pop();
+        return None;
+    }
+
+    fn compile_labelled_stmt(&mut self, node: &ast::LabelledType) -> Option<errors::CompileError> {
+        for loop_ctl in &self.loop_ctls {
+            if loop_ctl.label.as_ref() == Some(&node.label) {
+                return Some(errors::CompileError::new(
+                    format!("Loop label {} already in use", node.label),
+                    errors::CompilerErrorKind::SymbolAlreadyExist,
+                    0,
+                ));
+            }
+        }
+
+        match node.statement.as_ref() {
+            ast::StatementKind::While(_)
+            | ast::StatementKind::DoWhile(_)
+            | ast::StatementKind::For(_)
+            | ast::StatementKind::ForEach(_) => {}
+            _ => {
+                return Some(errors::CompileError::new(
+                    format!("Label {} must be attached to a loop", node.label),
+                    errors::CompilerErrorKind::InvalidOperand,
+                    0,
+                ));
+            }
+        }
+
+        // the loop picks up the label when it registers its loop control:
+        self.pending_label = Some(node.label.clone());
+        return self.compile_statement(&node.statement);
+    }
+
+    fn compile_defer_stmt(&mut self, node: &ast::DeferType) -> Option<errors::CompileError> {
+        if self.scope_index == 0 {
+            return Some(errors::CompileError::new(
+                "defer encountered outside function".to_string(),
+                errors::CompilerErrorKind::InvalidScope,
+                0,
+            ));
+        }
+
+        // the deferred statement is compiled as a closure, the VM calls it
+        // when the function returns:
+        let func_type = ast::FunctionType {
+            name: format!("defer_{}", self.n_defers),
+            parameters: vec![],
+            body: ast::BlockStatement {
+                statements: vec![
+                    node.statement.as_ref().clone(),
+                    ast::StatementKind::Return(ast::ReturnType { expression: None }),
+                ],
+                pos: vec![],
+            },
+            return_type: None,
+        };
+
+        self.n_defers += 1;
+        let error = self.compile_function(&func_type, true);
+        if error.is_some() {
+            return error;
+        }
+
+        self.save(isa::InstructionKind::IDefer, &vec![]);
+        return None;
+    }
+
+    fn compile_try_stmt(&mut self, node: &ast::TryCatchType) -> Option<errors::CompileError> {
+        let exception_name = match node.exception_ident.as_ref() {
+            ast::ExpressionKind::Identifier(id) => id.name.clone(),
+            _ => {
+                return Some(errors::CompileError::new(
+                    "catch expects an identifier".to_string(),
+                    errors::CompilerErrorKind::InvalidOperand,
+                    0,
+                ))
+            }
+        };
+
+        // the handler is pushed inside the try block, leaving the block
+        // by its end, break or continue drops it:
+        let first_local = self.symbol_table.n_items;
+        let try_pos = self.statement_pos;
+        let try_scope = TryScope {
+            scope_index: self.scope_index,
+            block_depth: self.symbol_table.get_block_depth(),
+            final_block: node.final_block.clone(),
+        };
+        self.try_scopes.push(try_scope.clone());
+        self.enter_block();
+        let handler_pos = self.save_jump_placeholder(isa::InstructionKind::IPushExcHandle);
+        for (idx, stmt) in node.try_block.statements.iter().enumerate() {
+            self.set_statement_pos(&node.try_block, idx);
+            let error = self.compile_statement(&stmt);
+            if error.is_some() {
+                return error;
+            }
+        }
+        self.exit_block();
+        self.try_scopes.pop();
+        let skip_catch_pos = self.save_jump_placeholder(isa::InstructionKind::IJump);
+
+        // the catch block starts with the exception on the stack, it is
+        // stored before the block starts so the block does not keep it:
+        let catch_pos = self.scopes[self.scope_index].get_size();
+        let mut error = self.replace_instruction_operands(
+            self.scope_index,
+            isa::InstructionKind::IPushExcHandle,
+            &vec![catch_pos],
+            &handler_pos,
+        );
+        if error.is_some() {
+            return error;
+        }
+
+        // the locals of the try block were not released, closures created
+        // in it keep their last values:
+        if self.scope_index != 0 {
+            for pos in first_local..self.symbol_table.max_items {
+                self.save(isa::InstructionKind::IReleaseLocal, &vec![pos]);
+            }
+        }
+
+        self.statement_pos = try_pos;
+        self.symbol_table.enter_block();
+        let declared = self.declare_symbol(&exception_name, false);
+        if declared.is_err() {
+            return Some(declared.unwrap_err());
+        }
+
+        let symbol = declared.unwrap();
+        match symbol.scope {
+            symtab::ScopeKind::Global => {
+                self.save(isa::InstructionKind::IStoreGlobal, &vec![symbol.pos]);
+            }
+            _ => {
+                self.save(isa::InstructionKind::IStoreLocal, &vec![symbol.pos]);
+            }
+        }
+        self.save(isa::InstructionKind::IBlockStart, &vec![]);
+
+        // errors raised by the catch block run the finally block before
+        // they are raised again:
+        let mut rethrow_handler_pos = None;
+        if node.final_block.is_some() {
+            self.try_scopes.push(try_scope);
+            let pos = self.save_jump_placeholder(isa::InstructionKind::IPushExcHandle);
+            rethrow_handler_pos = Some(pos);
+        }
+
+        for (idx, stmt) in node.catch_block.statements.iter().enumerate() {
+            self.set_statement_pos(&node.catch_block, idx);
+            let error = self.compile_statement(&stmt);
+            if error.is_some() {
+                return error;
+            }
+        }
+        self.exit_block();
+
+        // the try and catch blocks continue after the catch block, or at
+        // the finally block:
+        let mut continue_pos = self.scopes[self.scope_index].get_size();
+        if rethrow_handler_pos.is_some() {
+            self.try_scopes.pop();
+            let skip_rethrow_pos = self.save_jump_placeholder(isa::InstructionKind::IJump);
+
+            let rethrow_pos = self.scopes[self.scope_index].get_size();
+            error = self.replace_instruction_operands(
+                self.scope_index,
+                isa::InstructionKind::IPushExcHandle,
+                &vec![rethrow_pos],
+                &rethrow_handler_pos.unwrap(),
+            );
+            if error.is_some() {
+                return error;
+            }
+
+            let final_block = node.final_block.as_ref().unwrap();
+            error = self.compile_block_statement(final_block);
+            if error.is_some() {
+                return error;
+            }
+            self.save(isa::InstructionKind::IRaise, &vec![]);
 
+            continue_pos = self.scopes[self.scope_index].get_size();
+            error = self.replace_instruction_operands(
+                self.scope_index,
+                isa::InstructionKind::IJump,
+                &vec![continue_pos],
+                &skip_rethrow_pos,
+            );
+            if error.is_some() {
+                return error;
+            }
+
+            error = self.compile_block_statement(final_block);
+            if error.is_some() {
+                return error;
+            }
+        }
+
+        // the jump target must be an instruction:
+        if rethrow_handler_pos.is_none() {
+            continue_pos = self.save(isa::InstructionKind::INoOp, &vec![]);
+        }
+
+        return self.replace_instruction_operands(
+            self.scope_index,
+            isa::InstructionKind::IJump,
+            &vec![continue_pos],
+            &skip_catch_pos,
+        );
+    }
+
+    fn compile_throw_stmt(&mut self, node: &ast::ThrowType) -> Option<errors::CompileError> {
+        let error = self.compile_expression(&node.expression);
+        if error.is_some() {
+            return error;
+        }
+
+        self.save(isa::InstructionKind::IRaise, &vec![]);
         return None;
     }
 
@@ -1463,6 +2365,10 @@ impl BytecodeCompiler {
             loop_start_pos: current_pos.clone(),
             pos_after_loop: 0,
             break_pos: vec![],
+            block_depth: self.symbol_table.get_block_depth(),
+            label: self.pending_label.take(),
+            continue_pos: vec![],
+            continue_after_body: false,
         };
 
         self.loop_ctls.push(new_loop_ctl);
@@ -1475,7 +2381,7 @@ impl BytecodeCompiler {
         }
 
         // append a jump statement if loop fails:
-        let jump_inst_pos = self.save(isa::InstructionKind::INotJump, &vec![0]);
+        let jump_inst_pos = self.save_jump_placeholder(isa::InstructionKind::INotJump);
 
         // compile loop block statement:
         let block_error = self.compile_block_statement(&node.loop_block);
@@ -1525,14 +2431,19 @@ impl BytecodeCompiler {
             ast::StatementKind::Var(node) => self.compile_variable_declr(&node),
             ast::StatementKind::Const(node) => self.compile_const_declr(&node),
             ast::StatementKind::While(node) => self.compile_while_loop(&node),
-            ast::StatementKind::Break => self.compile_break_stmt(),
-            ast::StatementKind::Continue => self.compile_continue_stmt(),
+            ast::StatementKind::Break(label) => self.compile_break_stmt(&label),
+            ast::StatementKind::Continue(label) => self.compile_continue_stmt(&label),
+            ast::StatementKind::DoWhile(node) => self.compile_do_while_loop(&node),
+            ast::StatementKind::Labelled(node) => self.compile_labelled_stmt(&node),
+            ast::StatementKind::Defer(node) => self.compile_defer_stmt(&node),
             ast::StatementKind::If(node) => self.compile_if_statement(&node),
             ast::StatementKind::Assert(node) => self.compile_assert_statement(&node),
             ast::StatementKind::For(node) => self.compile_for_loop(&node),
             ast::StatementKind::Function(node) => self.compile_function(&node, false),
             ast::StatementKind::Return(node) => self.compile_return_stmt(&node),
             ast::StatementKind::ForEach(node) => self.compile_feach_stmt(&node),
+            ast::StatementKind::TryCatch(node) => self.compile_try_stmt(&node),
+            ast::StatementKind::Throw(node) => self.compile_throw_stmt(&node),
             _ => {
                 return Some(errors::CompileError::new(
                     "Not yet implemented".to_string(),
@@ -1550,28 +2461,45 @@ impl BytecodeCompiler {
         return None;
     }
 
+    // blocks built by the compiler have no positions, they keep the last one:
+    fn set_statement_pos(&mut self, block: &ast::BlockStatement, idx: usize) {
+        if idx < block.pos.len() {
+            self.statement_pos = block.pos[idx];
+        }
+    }
+
     fn compile_block_statement(
         &mut self,
         node: &ast::BlockStatement,
     ) -> Option<errors::CompileError> {
-        self.save(isa::InstructionKind::IBlockStart, &vec![]);
+        self.enter_block();
 
-        for stmt in &node.statements {
+        let outer_pos = self.statement_pos;
+        for (idx, stmt) in node.statements.iter().enumerate() {
+            self.set_statement_pos(&node, idx);
             let error = self.compile_statement(&stmt);
             if error.is_some() {
                 return error;
             }
         }
 
-        self.save(isa::InstructionKind::IBlockEnd, &vec![]);
+        self.statement_pos = outer_pos;
+        self.exit_block();
 
         return None;
     }
 
     fn get_bytecode(&self) -> CompiledBytecode {
+        let mut instructions = self.scopes[self.scope_index].get_instructions().clone();
+        if self.optimize {
+            instructions = PeepholeOptimizer::optimize(&instructions, &self.constant_pool);
+        } else {
+            instructions = PeepholeOptimizer::relax(&instructions);
+        }
+
         return CompiledBytecode {
             constant_pool: self.constant_pool.clone(),
-            instructions: self.scopes[self.scope_index].get_instructions().clone(),
+            instructions: instructions,
         };
     }
 
@@ -1580,19 +2508,43 @@ impl BytecodeCompiler {
         program_ast: &ast::Program,
     ) -> Result<CompiledBytecode, errors::CompileError> {
         let statements = &program_ast.statements;
+
+        // declare the top-level functions first:
+        self.hoisted.clear();
         for stmt in statements {
+            if let ast::StatementKind::Function(func) = stmt {
+                let declared = self.declare_symbol(&func.name, true);
+                if declared.is_err() {
+                    return Err(declared.unwrap_err());
+                }
+                self.hoisted.push(func.name.clone());
+            }
+        }
+
+        for (idx, stmt) in statements.iter().enumerate() {
+            self.set_statement_pos(program_ast, idx);
             let error = self.compile_statement(&stmt);
             if error.is_some() {
+                // drop the blocks left open by the failed statement:
+                while self.symbol_table.get_block_depth() != 0 {
+                    self.symbol_table.exit_block();
+                }
+
                 let unwrapped_error = error.unwrap();
                 return Err(unwrapped_error);
             }
         }
 
+        if self.operand_error.is_some() {
+            return Err(self.operand_error.take().unwrap());
+        }
+
         return Ok(self.get_bytecode());
     }
 
     pub fn clear_previous(&mut self) {
         self.scopes[self.scope_index].instructions.clear();
+        self.warnings.clear();
     }
 }
 
@@ -1606,11 +2558,11 @@ impl BytecodeDecompiler {
         let mut idx = 0;
 
         while idx < length {
-            let op = instructions[idx];
-            let op_kind: isa::InstructionKind = unsafe { ::std::mem::transmute(op) };
-            let (operands, next_offset) =
-                InstructionPacker::decode_instruction(&op_kind, &instructions[idx + 1..]);
+            let (op_kind, operands, next_offset) = InstructionPacker::decode_at(instructions, idx);
             decoded_string.push_str(&format!("{:0>8x} ", idx));
+            if instructions[idx] == isa::InstructionKind::IWide as u8 {
+                decoded_string.push_str("IWide ");
+            }
             decoded_string.push_str(&op_kind.disasm_instruction(&operands));
             decoded_string.push('\n');
 
@@ -0,0 +1,476 @@
+/*
+    Peephole optimizer: rewrites the compiled instructions of a function
+    (or the top-level program) after it is compiled. The instructions are
+    decoded into a list, jump operands are turned into indices of that list,
+    so instructions can be removed or rewritten without breaking the jumps,
+    and the list is encoded back once no more rewrites apply.
+*/
+
+use crate::compiler::symtab::ConstantPool;
+use crate::compiler::CompiledInstructions;
+use crate::isa;
+use crate::types::object::Object;
+
+use isa::InstructionKind;
+use isa::InstructionPacker;
+use isa::Operands;
+
+// number of times the rewrites are repeated, each round can expose
+// more dead code and jumps to thread.
+const MAX_PASSES: usize = 8;
+
+#[derive(Debug, Clone)]
+pub struct DecodedInstruction {
+    pub kind: InstructionKind,
+    pub operands: Operands,
+    pub removed: bool,
+}
+
+pub struct PeepholeOptimizer {}
+
+impl PeepholeOptimizer {
+    pub fn optimize(
+        instructions: &CompiledInstructions,
+        constants: &ConstantPool,
+    ) -> CompiledInstructions {
+        let mut decoded = PeepholeOptimizer::decode(instructions);
+
+        for _ in 0..MAX_PASSES {
+            let mut changed = false;
+            changed |= PeepholeOptimizer::thread_jumps(&mut decoded);
+            changed |= PeepholeOptimizer::fold_constant_branches(&mut decoded, constants);
+            changed |= PeepholeOptimizer::remove_no_ops(&mut decoded);
+            changed |= PeepholeOptimizer::remove_dead_code(&mut decoded);
+
+            decoded = PeepholeOptimizer::compact(decoded);
+            if !changed {
+                break;
+            }
+        }
+
+        // superinstructions are formed once the other rewrites are done:
+        if PeepholeOptimizer::fuse_instructions(&mut decoded) {
+            decoded = PeepholeOptimizer::compact(decoded);
+        }
+
+        return PeepholeOptimizer::encode(&decoded, 0);
+    }
+
+    // re-encodes the instructions without rewriting them, so the jumps
+    // that were compiled wide take the narrow form when their target fits.
+    pub fn relax(instructions: &CompiledInstructions) -> CompiledInstructions {
+        let decoded = PeepholeOptimizer::decode(instructions);
+        return PeepholeOptimizer::encode(&decoded, 0);
+    }
+
+    // instructions whose first operand is a position in the instructions:
+    pub fn is_jump(kind: &InstructionKind) -> bool {
+        return kind.is_jump();
+    }
+
+    // instructions that never continue with the next instruction:
+    fn is_terminator(kind: &InstructionKind) -> bool {
+        match kind {
+            InstructionKind::IJump | InstructionKind::IRet | InstructionKind::IRetVal => true,
+            _ => false,
+        }
+    }
+
+    pub fn decode(instructions: &CompiledInstructions) -> Vec<DecodedInstruction> {
+        let mut decoded = vec![];
+        let mut positions = vec![];
+
+        let mut idx = 0;
+        while idx < instructions.len() {
+            let (op_kind, operands, next_offset) = InstructionPacker::decode_at(instructions, idx);
+
+            positions.push(idx);
+            decoded.push(DecodedInstruction {
+                kind: op_kind,
+                operands: operands,
+                removed: false,
+            });
+
+            idx = idx + next_offset + 1;
+        }
+
+        // jump positions to instruction indices, a jump to the end of the
+        // instructions is mapped to the length of the list.
+        for inst in decoded.iter_mut() {
+            if PeepholeOptimizer::is_jump(&inst.kind) {
+                let target = inst.operands[0];
+                let index = match positions.binary_search(&target) {
+                    Ok(index) => index,
+                    Err(index) => index,
+                };
+                inst.operands[0] = index;
+            }
+        }
+
+        return decoded;
+    }
+
+    // encodes the instructions to be placed at position `base`.
+    pub fn encode(decoded: &Vec<DecodedInstruction>, base: usize) -> CompiledInstructions {
+        // compute the new position of every instruction, a jump becomes
+        // wide when its target is too far, which moves the instructions
+        // after it, so this is repeated until the positions are stable.
+        let mut wide = vec![false; decoded.len()];
+        let mut positions = vec![];
+        loop {
+            positions.clear();
+            let mut size = base;
+            for (idx, inst) in decoded.iter().enumerate() {
+                positions.push(size);
+                size += if wide[idx] {
+                    2 + 4 * inst.operands.len()
+                } else {
+                    InstructionPacker::get_encoded_size(&inst.kind, &inst.operands)
+                };
+            }
+            positions.push(size);
+
+            let mut changed = false;
+            for (idx, inst) in decoded.iter().enumerate() {
+                if wide[idx] || !PeepholeOptimizer::is_jump(&inst.kind) {
+                    continue;
+                }
+
+                let mut operands = inst.operands.clone();
+                operands[0] = positions[operands[0]];
+                if InstructionPacker::needs_wide(&inst.kind, &operands) {
+                    wide[idx] = true;
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        // jumps to the end of the instructions need an instruction to land on:
+        let jumps_to_end = decoded.iter().any(|inst| {
+            PeepholeOptimizer::is_jump(&inst.kind) && inst.operands[0] == decoded.len()
+        });
+
+        let mut instructions = vec![];
+        for (idx, inst) in decoded.iter().enumerate() {
+            let mut operands = inst.operands.clone();
+            if PeepholeOptimizer::is_jump(&inst.kind) {
+                operands[0] = positions[operands[0]];
+            }
+
+            let encoded = if wide[idx] {
+                InstructionPacker::encode_wide_instruction(inst.kind.clone(), &operands)
+            } else {
+                InstructionPacker::encode_instruction(inst.kind.clone(), &operands)
+            };
+            instructions.extend_from_slice(&encoded);
+        }
+
+        if jumps_to_end {
+            instructions.push(InstructionKind::INoOp as u8);
+        }
+
+        return instructions;
+    }
+
+    // drops the removed instructions, jumps to a removed instruction
+    // land on the next instruction that is kept.
+    fn compact(decoded: Vec<DecodedInstruction>) -> Vec<DecodedInstruction> {
+        let mut new_index = vec![];
+        let mut n_kept = 0;
+        for inst in &decoded {
+            new_index.push(n_kept);
+            if !inst.removed {
+                n_kept += 1;
+            }
+        }
+        new_index.push(n_kept);
+
+        let mut compacted = vec![];
+        for mut inst in decoded.into_iter() {
+            if inst.removed {
+                continue;
+            }
+
+            if PeepholeOptimizer::is_jump(&inst.kind) {
+                inst.operands[0] = new_index[inst.operands[0]];
+            }
+            compacted.push(inst);
+        }
+
+        return compacted;
+    }
+
+    fn get_jump_targets(decoded: &Vec<DecodedInstruction>) -> Vec<bool> {
+        let mut targets = vec![false; decoded.len() + 1];
+        for inst in decoded {
+            if !inst.removed && PeepholeOptimizer::is_jump(&inst.kind) {
+                targets[inst.operands[0]] = true;
+            }
+        }
+
+        return targets;
+    }
+
+    // jumps to an unconditional jump go to its target directly, and an
+    // unconditional jump to a return is replaced by the return.
+    fn thread_jumps(decoded: &mut Vec<DecodedInstruction>) -> bool {
+        let mut changed = false;
+
+        for idx in 0..decoded.len() {
+            if !PeepholeOptimizer::is_jump(&decoded[idx].kind) {
+                continue;
+            }
+
+            let mut target = decoded[idx].operands[0];
+            let mut visited = vec![];
+            while target < decoded.len()
+                && decoded[target].kind == InstructionKind::IJump
+                && !visited.contains(&target)
+            {
+                visited.push(target);
+                target = decoded[target].operands[0];
+            }
+
+            if target != decoded[idx].operands[0] {
+                decoded[idx].operands[0] = target;
+                changed = true;
+            }
+
+            if decoded[idx].kind == InstructionKind::IJump && target < decoded.len() {
+                let target_kind = decoded[target].kind.clone();
+                if target_kind == InstructionKind::IRet || target_kind == InstructionKind::IRetVal {
+                    decoded[idx].kind = target_kind;
+                    decoded[idx].operands = vec![];
+                    changed = true;
+                }
+            }
+        }
+
+        return changed;
+    }
+
+    // a conditional jump on a constant either always jumps or never does,
+    // this is the case for `while (true)` and for conditions on constants.
+    fn fold_constant_branches(
+        decoded: &mut Vec<DecodedInstruction>,
+        constants: &ConstantPool,
+    ) -> bool {
+        let mut changed = false;
+        let targets = PeepholeOptimizer::get_jump_targets(decoded);
+
+        for idx in 1..decoded.len() {
+            if decoded[idx].kind != InstructionKind::INotJump || targets[idx] {
+                continue;
+            }
+
+            let prev = &decoded[idx - 1];
+            if prev.removed || prev.kind != InstructionKind::IConstant {
+                continue;
+            }
+
+            let constant = constants.get_object(prev.operands[0]);
+            if constant.is_none() {
+                continue;
+            }
+
+            let is_true = match constant.unwrap().as_ref() {
+                Object::Noval => false,
+                Object::Bool(b) => *b,
+                Object::Int(i) => *i != 0,
+                Object::Str(st) => **st != "",
+                _ => continue,
+            };
+
+            decoded[idx - 1].removed = true;
+            if is_true {
+                decoded[idx].removed = true;
+            } else {
+                decoded[idx].kind = InstructionKind::IJump;
+            }
+            changed = true;
+        }
+
+        return changed;
+    }
+
+    // removes INoOp, jumps to the next instruction and empty blocks.
+    fn remove_no_ops(decoded: &mut Vec<DecodedInstruction>) -> bool {
+        let mut changed = false;
+        let targets = PeepholeOptimizer::get_jump_targets(decoded);
+
+        for idx in 0..decoded.len() {
+            if decoded[idx].removed {
+                continue;
+            }
+
+            match decoded[idx].kind {
+                InstructionKind::INoOp => {
+                    decoded[idx].removed = true;
+                    changed = true;
+                }
+                InstructionKind::IJump => {
+                    if decoded[idx].operands[0] == idx + 1 {
+                        decoded[idx].removed = true;
+                        changed = true;
+                    }
+                }
+                InstructionKind::IBlockStart => {
+                    if idx + 1 < decoded.len()
+                        && decoded[idx + 1].kind == InstructionKind::IBlockEnd
+                        && !decoded[idx + 1].removed
+                        && !targets[idx + 1]
+                    {
+                        decoded[idx].removed = true;
+                        decoded[idx + 1].removed = true;
+                        changed = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        return changed;
+    }
+
+    // removes the instructions that cannot be reached from the first one,
+    // like the code after a return or a break.
+    fn remove_dead_code(decoded: &mut Vec<DecodedInstruction>) -> bool {
+        let mut reachable = vec![false; decoded.len()];
+        let mut pending = vec![0];
+
+        while !pending.is_empty() {
+            let idx = pending.pop().unwrap();
+            if idx >= decoded.len() || reachable[idx] {
+                continue;
+            }
+
+            reachable[idx] = true;
+            let inst = &decoded[idx];
+            if PeepholeOptimizer::is_jump(&inst.kind) {
+                pending.push(inst.operands[0]);
+            }
+
+            if !PeepholeOptimizer::is_terminator(&inst.kind) {
+                pending.push(idx + 1);
+            }
+        }
+
+        let mut changed = false;
+        for idx in 0..decoded.len() {
+            if !reachable[idx] && !decoded[idx].removed {
+                decoded[idx].removed = true;
+                changed = true;
+            }
+        }
+
+        return changed;
+    }
+
+    fn is_comparison(kind: &InstructionKind) -> bool {
+        match kind {
+            InstructionKind::ILLt
+            | InstructionKind::ILLTe
+            | InstructionKind::ILGt
+            | InstructionKind::ILGte
+            | InstructionKind::ILEq
+            | InstructionKind::ILNe => true,
+            _ => false,
+        }
+    }
+
+    // a sequence of `length` instructions at `idx` can be fused if no jump
+    // lands in the middle of it.
+    fn can_fuse(
+        decoded: &Vec<DecodedInstruction>,
+        targets: &Vec<bool>,
+        idx: usize,
+        length: usize,
+    ) -> bool {
+        if idx + length > decoded.len() {
+            return false;
+        }
+
+        return (idx + 1..idx + length).all(|pos| !targets[pos]);
+    }
+
+    // replaces common sequences by superinstructions:
+    // x = x + c => IIncrLocal/IIncrGlobal, load and add => IAddLocal/IAddGlobal
+    // and a comparison followed by INotJump => ICmpNotJump.
+    fn fuse_instructions(decoded: &mut Vec<DecodedInstruction>) -> bool {
+        let mut changed = false;
+        let targets = PeepholeOptimizer::get_jump_targets(decoded);
+
+        let mut idx = 0;
+        while idx < decoded.len() {
+            let kind = decoded[idx].kind.clone();
+            let is_local = kind == InstructionKind::ILoadLocal;
+            let is_global = kind == InstructionKind::ILoadGlobal;
+
+            if (is_local || is_global) && PeepholeOptimizer::can_fuse(decoded, &targets, idx, 4) {
+                let store = if is_local {
+                    InstructionKind::IStoreLocal
+                } else {
+                    InstructionKind::IStoreGlobal
+                };
+
+                if decoded[idx + 1].kind == InstructionKind::IConstant
+                    && decoded[idx + 2].kind == InstructionKind::IAdd
+                    && decoded[idx + 3].kind == store
+                    && decoded[idx + 3].operands[0] == decoded[idx].operands[0]
+                {
+                    decoded[idx].kind = if is_local {
+                        InstructionKind::IIncrLocal
+                    } else {
+                        InstructionKind::IIncrGlobal
+                    };
+                    decoded[idx].operands =
+                        vec![decoded[idx].operands[0], decoded[idx + 1].operands[0]];
+                    for pos in idx + 1..idx + 4 {
+                        decoded[pos].removed = true;
+                    }
+
+                    changed = true;
+                    idx += 4;
+                    continue;
+                }
+            }
+
+            if (is_local || is_global)
+                && PeepholeOptimizer::can_fuse(decoded, &targets, idx, 2)
+                && decoded[idx + 1].kind == InstructionKind::IAdd
+            {
+                decoded[idx].kind = if is_local {
+                    InstructionKind::IAddLocal
+                } else {
+                    InstructionKind::IAddGlobal
+                };
+                decoded[idx + 1].removed = true;
+
+                changed = true;
+                idx += 2;
+                continue;
+            }
+
+            if PeepholeOptimizer::is_comparison(&kind)
+                && PeepholeOptimizer::can_fuse(decoded, &targets, idx, 2)
+                && decoded[idx + 1].kind == InstructionKind::INotJump
+            {
+                decoded[idx].operands = vec![decoded[idx + 1].operands[0], kind as usize];
+                decoded[idx].kind = InstructionKind::ICmpNotJump;
+                decoded[idx + 1].removed = true;
+
+                changed = true;
+                idx += 2;
+                continue;
+            }
+
+            idx += 1;
+        }
+
+        return changed;
+    }
+}
@@ -5,6 +5,7 @@ use std::rc::Rc;
 
 use builtins::BuiltinKind;
 use object::Object;
+use object::Value;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ScopeKind {
@@ -20,17 +21,37 @@ pub struct Symbol {
     pub pos: usize,
     pub is_const: bool,
     pub scope: ScopeKind,
+    // number of lexical blocks enclosing the declaration, 0 for
+    // function parameters and top-level names.
+    pub depth: usize,
+    // compile-time value of a constant, if it is known, uses of such
+    // constants are replaced with the value.
+    pub value: Option<Value>,
 }
 
 pub type SymbolsMap = HashMap<String, Rc<Symbol>>;
 
+// A lexical block inside a function (or the top-level program).
+// Names declared inside the block are removed when the block is closed,
+// and the names they shadowed are put back.
+#[derive(Debug, Clone)]
+pub struct BlockScope {
+    pub declared: Vec<Rc<Symbol>>,
+    pub shadowed: Vec<(String, Rc<Symbol>)>,
+    pub start_items: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct SymbolTable {
     pub parent: Option<Box<SymbolTable>>,
     pub symbols: SymbolsMap,
     pub n_items: usize,
+    // high-water mark of n_items, local slots are re-used once a block is
+    // closed, so this is the number of slots the frame has to reserve.
+    pub max_items: usize,
     pub free_symbols: Vec<Rc<Symbol>>,
     pub level: usize,
+    pub blocks: Vec<BlockScope>,
 }
 
 impl SymbolTable {
@@ -39,8 +60,10 @@ impl SymbolTable {
             parent: None,
             symbols: HashMap::new(),
             n_items: 0,
+            max_items: 0,
             free_symbols: vec![],
             level: 0,
+            blocks: vec![],
         };
     }
 
@@ -51,8 +74,10 @@ impl SymbolTable {
             parent: boxed_table,
             symbols: HashMap::new(),
             n_items: 0,
+            max_items: 0,
             free_symbols: vec![],
             level: new_level,
+            blocks: vec![],
         };
     }
 
@@ -64,6 +89,8 @@ impl SymbolTable {
             pos: self.free_symbols.len() - 1,
             is_const: symbol.is_const,
             scope: ScopeKind::Free,
+            depth: 0,
+            value: symbol.value.clone(),
         };
 
         let ref_c_new_symbol = Rc::new(new_symbol);
@@ -81,11 +108,15 @@ impl SymbolTable {
                 pos: self.n_items,
                 is_const: true,
                 scope: ScopeKind::Builtin,
+                depth: 0,
+                value: None,
             };
 
             self.symbols.insert(name.clone(), Rc::new(builtin_symbol));
             self.n_items += 1;
         }
+
+        self.max_items = self.n_items;
     }
 
     pub fn get_symbol(&self, name: &String) -> Option<Rc<Symbol>> {
@@ -94,6 +125,15 @@ impl SymbolTable {
     }
 
     pub fn insert_new_symbol(&mut self, name: &str, is_const: bool) -> Rc<Symbol> {
+        return self.insert_new_symbol_with_value(name, is_const, None);
+    }
+
+    pub fn insert_new_symbol_with_value(
+        &mut self,
+        name: &str,
+        is_const: bool,
+        value: Option<Value>,
+    ) -> Rc<Symbol> {
         // select scope
         let current_scope = if self.level == 0 {
             ScopeKind::Global
@@ -107,34 +147,155 @@ impl SymbolTable {
             pos: self.n_items,
             is_const: is_const,
             scope: current_scope,
+            depth: self.blocks.len(),
+            value: value,
         };
 
         let ref_counted_symbol = Rc::new(symbol);
         // enter into the hash map:
-        self.symbols
+        let previous = self
+            .symbols
             .insert(name.to_string(), Rc::clone(&ref_counted_symbol));
+
+        // remember the declaration, so it can be dropped when the block ends:
+        if self.blocks.len() != 0 {
+            let block = self.blocks.last_mut().unwrap();
+            if previous.is_some() {
+                block.shadowed.push((name.to_string(), previous.unwrap()));
+            }
+            block.declared.push(Rc::clone(&ref_counted_symbol));
+        }
+
         self.n_items += 1;
+        if self.n_items > self.max_items {
+            self.max_items = self.n_items;
+        }
+
         return ref_counted_symbol;
     }
 
-    pub fn resolve_symbol(&mut self, name: &str) -> Option<Rc<Symbol>> {
-        let result = self.__resolve(name);
+    pub fn enter_block(&mut self) {
+        self.blocks.push(BlockScope {
+            declared: vec![],
+            shadowed: vec![],
+            start_items: self.n_items,
+        });
+    }
 
-        if result.is_some() {
-            let (unwrapped_sym, is_parent) = result.unwrap();
+    // closes the innermost block and returns the symbols declared in it.
+    pub fn exit_block(&mut self) -> Vec<Rc<Symbol>> {
+        let block_res = self.blocks.pop();
+        if block_res.is_none() {
+            return vec![];
+        }
 
-            if !is_parent {
-                return Some(Rc::clone(&unwrapped_sym));
+        let block = block_res.unwrap();
+        for sym in &block.declared {
+            self.symbols.remove(&sym.name);
+        }
+
+        for (name, sym) in block.shadowed.into_iter().rev() {
+            self.symbols.insert(name, sym);
+        }
+
+        // local slots of the block can be re-used by the next block,
+        // global slots are never re-used as they outlive the frame.
+        if self.level != 0 {
+            self.n_items = block.start_items;
+        }
+
+        return block.declared;
+    }
+
+    // hides the names declared in the blocks from depth `from`, until the
+    // blocks are put back by restore_blocks. Their slots stay taken.
+    pub fn hide_blocks(&mut self, from: usize) -> Vec<BlockScope> {
+        let hidden = self.blocks.split_off(from);
+        for block in hidden.iter().rev() {
+            for sym in &block.declared {
+                self.symbols.remove(&sym.name);
+            }
+
+            for (name, sym) in block.shadowed.iter().rev() {
+                self.symbols.insert(name.clone(), Rc::clone(sym));
             }
+        }
+
+        return hidden;
+    }
+
+    pub fn restore_blocks(&mut self, blocks: Vec<BlockScope>) {
+        for block in blocks {
+            for sym in &block.declared {
+                self.symbols.insert(sym.name.clone(), Rc::clone(sym));
+            }
+            self.blocks.push(block);
+        }
+    }
+
+    pub fn get_block_depth(&self) -> usize {
+        return self.blocks.len();
+    }
+
+    // returns the symbols declared in the blocks starting from depth `from`,
+    // innermost block first.
+    pub fn get_block_symbols(&self, from: usize) -> Vec<Vec<Rc<Symbol>>> {
+        let mut symbols = vec![];
+        for idx in (from..self.blocks.len()).rev() {
+            symbols.push(self.blocks[idx].declared.clone());
+        }
+
+        return symbols;
+    }
+
+    // checks if the name is declared in the innermost scope, redeclaring such
+    // a name is an error while declaring a name from an outer scope shadows it.
+    pub fn is_declared_in_scope(&self, name: &str) -> bool {
+        if self.blocks.len() != 0 {
+            let block = self.blocks.last().unwrap();
+            return block.declared.iter().any(|sym| sym.name == name);
+        }
+
+        match self.symbols.get(name) {
+            Some(sym) => sym.scope != ScopeKind::Free,
+            None => false,
+        }
+    }
+
+    pub fn resolve_symbol(&mut self, name: &str) -> Option<Rc<Symbol>> {
+        let result = self.get_symbol(&name.to_string());
+        if result.is_some() {
+            return result;
+        }
+
+        if self.level == 0 || self.parent.is_none() {
+            return None;
+        }
+
+        // resolve in the enclosing scope first, so every function in between
+        // captures the variable and can pass it down to the nested closure.
+        let parent_result = self.parent.as_mut().unwrap().resolve_symbol(name);
+        if parent_result.is_none() {
+            return None;
+        }
 
-            match unwrapped_sym.scope {
-                ScopeKind::Global | ScopeKind::Builtin => return Some(Rc::clone(&unwrapped_sym)),
-                ScopeKind::Local | ScopeKind::Free => {
-                    let free = self.insert_free_symbol(&unwrapped_sym);
-                    return Some(free);
-                }
+        let unwrapped_sym = parent_result.unwrap();
+        match unwrapped_sym.scope {
+            ScopeKind::Global | ScopeKind::Builtin => return Some(unwrapped_sym),
+            ScopeKind::Local | ScopeKind::Free => {
+                let free = self.insert_free_symbol(&unwrapped_sym);
+                return Some(free);
             }
         }
+    }
+
+    // resolves the name without capturing it as a free symbol.
+    pub fn lookup_symbol(&self, name: &str) -> Option<Rc<Symbol>> {
+        let result = self.__resolve(name);
+        if result.is_some() {
+            let (unwrapped_sym, _) = result.unwrap();
+            return Some(unwrapped_sym);
+        }
 
         return None;
     }
@@ -167,8 +328,11 @@ impl SymbolTable {
 // constant pool: This stores all the literal objects that will be referenced by the VM
 #[derive(Debug, Clone)]
 pub struct ConstantPool {
-    pub objects: Vec<Rc<Object>>,
+    pub objects: Vec<Value>,
     pub size: usize,
+    // positions of the scalar constants, so the same literal used many times
+    // occupies only one slot in the pool.
+    index: HashMap<String, usize>,
 }
 
 impl ConstantPool {
@@ -176,17 +340,59 @@ impl ConstantPool {
         return ConstantPool {
             objects: vec![],
             size: 0,
+            index: HashMap::new(),
         };
     }
 
-    pub fn get_object(&self, idx: usize) -> Option<Rc<Object>> {
+    pub fn new_from_objects(objects: Vec<Value>) -> ConstantPool {
+        let mut pool = ConstantPool::new();
+        for object in objects {
+            pool.objects.push(object.clone());
+            pool.size += 1;
+
+            let key = ConstantPool::get_key(&object);
+            if key.is_some() {
+                pool.index.entry(key.unwrap()).or_insert(pool.size - 1);
+            }
+        }
+
+        return pool;
+    }
+
+    // only immutable scalars are shared, the debug representation
+    // keeps the type, so 1 and 1.0 are not merged.
+    fn get_key(object: &Object) -> Option<String> {
+        match object {
+            Object::Noval
+            | Object::Int(_)
+            | Object::Float(_)
+            | Object::Bool(_)
+            | Object::Char(_)
+            | Object::Str(_)
+            | Object::Byte(_) => return Some(format!("{:?}", object)),
+            _ => return None,
+        }
+    }
+
+    pub fn get_object(&self, idx: usize) -> Option<Value> {
         if idx < self.size {
             return Some(self.objects[idx].clone());
         }
         return None;
     }
 
-    pub fn set_object(&mut self, object: Rc<Object>) -> usize {
+    pub fn set_object(&mut self, object: Value) -> usize {
+        let key = ConstantPool::get_key(&object);
+        if key.is_some() {
+            let key = key.unwrap();
+            let existing = self.index.get(&key);
+            if existing.is_some() {
+                return *existing.unwrap();
+            }
+
+            self.index.insert(key, self.size);
+        }
+
         self.objects.push(object);
         self.size += 1;
         return self.size - 1;
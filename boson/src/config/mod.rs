@@ -1,17 +1,144 @@
-
-// TODO: Move into a configurable format like JSON/TOML later
+use std::fs;
 
 pub const BYTE_ENDIAN: &str = "little";
+
+// default limits of the VM, see VMConfig:
 pub const FRAME_STACK_SIZE: usize = 2048;
 pub const DATA_STACK_SIZE: usize = 20480;
 pub const GLOBAL_POOL_SIZE: usize = 65536;
-
-// static-data-stack:
-// This will allocate data-stack as a static array
-// provides a performance gain as vector resize will be avoided.
-pub const USE_STATIC_DATA_STACK: bool = true;
+// captured variables created between two runs of the cycle collector:
+pub const GC_THRESHOLD: usize = 10000;
 
 // enable-concurrency
 // Enabl-concurrency features, if disabled
 // multi-threading code will run sequentially and join/async will throw errors.
-pub const ENABLE_CONCURRENCY: bool = true;
\ No newline at end of file
+pub const ENABLE_CONCURRENCY: bool = true;
+
+/*
+    VMConfig holds the runtime limits of the VM. It starts with the defaults
+    above and can be loaded from a config file of key-value pairs, both TOML
+    and flat JSON objects are accepted:
+
+        # boson.toml
+        frame_stack_size = 100000
+        data_stack_size = 1000000
+        global_pool_size = 1024
+        enable_concurrency = false
+        max_instructions = 100000000
+        timeout_ms = 5000
+        max_heap_bytes = 67108864
+        gc_threshold = 10000
+
+    or from boson-eval flags like --frame-stack-size=100000.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct VMConfig {
+    pub frame_stack_size: usize,
+    pub data_stack_size: usize,
+    pub global_pool_size: usize,
+    pub enable_concurrency: bool,
+    // optional limits of an evaluation, new threads start with the
+    // budget that is left and the same deadline:
+    pub max_instructions: Option<u64>,
+    pub timeout_ms: Option<u64>,
+    // approximate cap on the strings, arrays, hash tables and buffers
+    // of every thread, exceeding it raises a MemoryError:
+    pub max_heap_bytes: Option<usize>,
+    pub gc_threshold: usize,
+}
+
+impl VMConfig {
+    pub fn new() -> VMConfig {
+        return VMConfig {
+            frame_stack_size: FRAME_STACK_SIZE,
+            data_stack_size: DATA_STACK_SIZE,
+            global_pool_size: GLOBAL_POOL_SIZE,
+            enable_concurrency: ENABLE_CONCURRENCY,
+            max_instructions: None,
+            timeout_ms: None,
+            max_heap_bytes: None,
+            gc_threshold: GC_THRESHOLD,
+        };
+    }
+
+    // sets a single option, dashes and underscores are the same in keys.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let key = key.trim().replace("-", "_");
+        let value = value.trim();
+
+        if key == "enable_concurrency" {
+            match value {
+                "true" => self.enable_concurrency = true,
+                "false" => self.enable_concurrency = false,
+                _ => return Err(format!("{} expects true or false, got {}", key, value)),
+            }
+            return Ok(());
+        }
+
+        let size = match value.replace("_", "").parse::<usize>() {
+            Ok(size) if size > 0 => size,
+            _ => return Err(format!("{} expects a positive integer, got {}", key, value)),
+        };
+
+        match key.as_str() {
+            "frame_stack_size" => self.frame_stack_size = size,
+            "data_stack_size" => self.data_stack_size = size,
+            "global_pool_size" => self.global_pool_size = size,
+            "max_instructions" => self.max_instructions = Some(size as u64),
+            "timeout_ms" => self.timeout_ms = Some(size as u64),
+            "max_heap_bytes" => self.max_heap_bytes = Some(size),
+            "gc_threshold" => self.gc_threshold = size,
+            _ => return Err(format!("Unknown config option {}", key)),
+        }
+
+        return Ok(());
+    }
+
+    pub fn parse(source: &str) -> Result<VMConfig, String> {
+        let mut config = VMConfig::new();
+
+        for (idx, raw_line) in source.lines().enumerate() {
+            // values are plain numbers and booleans, so # always starts a comment:
+            let line = raw_line.split('#').next().unwrap().trim();
+            // TOML tables carry no options:
+            if line.starts_with('[') {
+                continue;
+            }
+
+            // JSON braces can share the line with the pairs, which are
+            // separated by commas, as in {"max_heap_bytes": 1000}:
+            let line = line.trim_start_matches('{').trim_end_matches('}');
+            for pair in line.split(',') {
+                let pair = pair.trim();
+                if pair.is_empty() {
+                    continue;
+                }
+
+                let sep = pair.find(&['=', ':'][..]);
+                if sep.is_none() {
+                    return Err(format!("line {}: expected key = value", idx + 1));
+                }
+
+                let (key, value) = pair.split_at(sep.unwrap());
+                let key = key.trim().trim_matches('"');
+                let value = value[1..].trim().trim_matches('"');
+
+                let result = config.set(key, value);
+                if result.is_err() {
+                    return Err(format!("line {}: {}", idx + 1, result.unwrap_err()));
+                }
+            }
+        }
+
+        return Ok(config);
+    }
+
+    pub fn load_from_file(path: &str) -> Result<VMConfig, String> {
+        let source = fs::read_to_string(path);
+        if source.is_err() {
+            return Err(format!("Cannot read config {}: {}", path, source.unwrap_err()));
+        }
+
+        return VMConfig::parse(&source.unwrap());
+    }
+}
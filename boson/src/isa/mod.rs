@@ -79,8 +79,13 @@ pub enum InstructionKind {
     IRet,
     IRetVal,
 
+    // register a closure to run when the function returns
+    IDefer,
+
     // Call
     ICall,
+    // call in tail position, re-uses the frame of the caller
+    ITailCall,
     ICallThread,
     ICallAsync,
 
@@ -92,6 +97,9 @@ pub enum InstructionKind {
 
     // Free
     ILoadFree,
+    IStoreFree,
+    ICaptureLocal,
+    ICaptureFree,
 
     // Data ops:
     IRegArray,
@@ -114,6 +122,10 @@ pub enum InstructionKind {
     IBlockStart,
     IBlockEnd,
 
+    // release block-local variables:
+    IReleaseLocal,
+    IReleaseGlobal,
+
     // Array and Hash
     IArray,
     IHash,
@@ -130,11 +142,37 @@ pub enum InstructionKind {
     ISetAttr,
     IGetAttr,
     ICallAttr,
+
+    // superinstructions, emitted by the optimizer for common sequences:
+    // x = x + constant
+    IIncrLocal,
+    IIncrGlobal,
+    // adds a variable to the top of the stack
+    IAddLocal,
+    IAddGlobal,
+    // comparison followed by INotJump, the comparison is an operand
+    ICmpNotJump,
+
+    // prefix, the operands of the next instruction are 4 bytes wide
+    IWide,
 }
 
 
 pub type Operands = Vec<usize>;
 
+// largest operand of an instruction with the IWide prefix:
+pub const WIDE_OPERAND_MAX: usize = u32::MAX as usize;
+
+// an instruction decoded ahead of execution, jump operands are indices
+// of the decoded instructions instead of byte offsets.
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub kind: InstructionKind,
+    pub operands: [usize; 2],
+}
+
+pub type DecodedInstructions = Vec<Instruction>;
+
 pub struct InstructionPacker {}
 
 impl InstructionPacker {
@@ -160,7 +198,60 @@ impl InstructionPacker {
         }
     }
 
+    fn unpack_32(operand: u32) -> [u8; 4] {
+        if BYTE_ENDIAN != "big" {
+            return operand.to_le_bytes();
+        }
+
+        return operand.to_be_bytes();
+    }
+
+    fn pack_u32(bytes: &[u8]) -> u32 {
+        let mut packed = [0u8; 4];
+        packed.copy_from_slice(&bytes[0..4]);
+        if BYTE_ENDIAN != "big" {
+            return u32::from_le_bytes(packed);
+        }
+
+        return u32::from_be_bytes(packed);
+    }
+
+    // the operands that do not fit in their width need the IWide prefix:
+    pub fn needs_wide(instruction: &InstructionKind, operands: &Operands) -> bool {
+        let operand_sizes = instruction.get_encoding_width();
+        for idx in 0..operands.len() {
+            let max = match operand_sizes[idx] {
+                1 => u8::MAX as usize,
+                2 => u16::MAX as usize,
+                _ => WIDE_OPERAND_MAX,
+            };
+
+            if operands[idx] > max {
+                return true;
+            }
+        }
+
+        return false;
+    }
+
+    // operands larger than the wide encoding cannot be encoded at all:
+    pub fn can_encode(operands: &Operands) -> bool {
+        return operands.iter().all(|op| *op <= WIDE_OPERAND_MAX);
+    }
+
+    pub fn get_encoded_size(instruction: &InstructionKind, operands: &Operands) -> usize {
+        if InstructionPacker::needs_wide(instruction, operands) {
+            return 2 + 4 * operands.len();
+        }
+
+        return 1 + instruction.get_encoding_width().iter().sum::<u8>() as usize;
+    }
+
     pub fn encode_instruction(instruction: InstructionKind, operands: &Operands) -> Vec<u8> {
+        if InstructionPacker::needs_wide(&instruction, operands) {
+            return InstructionPacker::encode_wide_instruction(instruction, operands);
+        }
+
         let operand_sizes = instruction.get_encoding_width();
         let mut statement = Vec::new();
 
@@ -181,6 +272,20 @@ impl InstructionPacker {
         return statement;
     }
 
+    // encodes the instruction with the IWide prefix regardless of the
+    // operands, used for jumps whose target is patched later.
+    pub fn encode_wide_instruction(instruction: InstructionKind, operands: &Operands) -> Vec<u8> {
+        let mut statement = Vec::new();
+
+        statement.push(InstructionKind::IWide as u8);
+        statement.push(instruction as u8);
+        for operand in operands {
+            statement.extend_from_slice(&InstructionPacker::unpack_32(*operand as u32));
+        }
+
+        return statement;
+    }
+
     pub fn decode_instruction(
         instruction: &InstructionKind,
         packed_ops: &[u8],
@@ -203,6 +308,66 @@ impl InstructionPacker {
 
         return (unpacked_stmt, offset);
     }
+
+    // decodes the instruction at `pos`, the offset returned is the number
+    // of bytes after the first one, including the prefixed instruction.
+    pub fn decode_at(instructions: &[u8], pos: usize) -> (InstructionKind, Operands, usize) {
+        let op_kind: InstructionKind = unsafe { ::std::mem::transmute(instructions[pos]) };
+        if op_kind != InstructionKind::IWide {
+            let (operands, offset) =
+                InstructionPacker::decode_instruction(&op_kind, &instructions[pos + 1..]);
+            return (op_kind, operands, offset);
+        }
+
+        let wide_kind: InstructionKind = unsafe { ::std::mem::transmute(instructions[pos + 1]) };
+        let n_operands = wide_kind.get_encoding_width().len();
+        let mut operands = vec![];
+        let mut offset = 2;
+        for _ in 0..n_operands {
+            let value = InstructionPacker::pack_u32(&instructions[pos + offset..]);
+            operands.push(value as usize);
+            offset += 4;
+        }
+
+        return (wide_kind, operands, offset - 1);
+    }
+
+    // decodes the whole bytecode of a function once, so the VM does not
+    // decode (and allocate) operands on every step.
+    pub fn decode_instructions(instructions: &[u8]) -> DecodedInstructions {
+        let mut decoded = vec![];
+        let mut positions = vec![];
+
+        let mut idx = 0;
+        while idx < instructions.len() {
+            let (kind, operands, next_offset) = InstructionPacker::decode_at(instructions, idx);
+            let mut packed = [0; 2];
+            for (op_idx, operand) in operands.iter().enumerate() {
+                packed[op_idx] = *operand;
+            }
+
+            positions.push(idx);
+            decoded.push(Instruction {
+                kind: kind,
+                operands: packed,
+            });
+
+            idx = idx + next_offset + 1;
+        }
+
+        // a jump to a position that is not an instruction (the end of the
+        // bytecode) is mapped past the last instruction.
+        for inst in decoded.iter_mut() {
+            if inst.kind.is_jump() {
+                inst.operands[0] = match positions.binary_search(&inst.operands[0]) {
+                    Ok(index) => index,
+                    Err(_) => positions.len(),
+                };
+            }
+        }
+
+        return decoded;
+    }
 }
 
 impl InstructionKind {
@@ -244,14 +409,21 @@ impl InstructionKind {
             InstructionKind::IIter => "IIter".to_string(),
             InstructionKind::IBlockEnd => "IBlockEnd".to_string(),
             InstructionKind::IBlockStart => "IBlockStart".to_string(),
+            InstructionKind::IReleaseLocal => "IReleaseLocal".to_string(),
+            InstructionKind::IReleaseGlobal => "IReleaseGlobal".to_string(),
             InstructionKind::IArray => "IArray".to_string(),
             InstructionKind::IHash => "IHash".to_string(),
             InstructionKind::ILoadBuiltIn => "ILoadBuiltIn".to_string(),
             InstructionKind::ICall => "ICall".to_string(),
+            InstructionKind::ITailCall => "ITailCall".to_string(),
             InstructionKind::ILoadFree => "ILoadFree".to_string(),
+            InstructionKind::IStoreFree => "IStoreFree".to_string(),
+            InstructionKind::ICaptureLocal => "ICaptureLocal".to_string(),
+            InstructionKind::ICaptureFree => "ICaptureFree".to_string(),
             InstructionKind::IClosure => "IClosure".to_string(),
             InstructionKind::IRet => "IRet".to_string(),
             InstructionKind::IRetVal => "IRetVal".to_string(),
+            InstructionKind::IDefer => "IDefer".to_string(),
             InstructionKind::IGetIndex => "IGetIndex".to_string(),
             InstructionKind::ISetIndex => "ISetIndex".to_string(),
             InstructionKind::IIterNext => "IIterNext".to_string(),
@@ -266,6 +438,12 @@ impl InstructionKind {
             InstructionKind::ISetAttr => "ISetAttr".to_string(),
             InstructionKind::IGetAttr => "IGetAttr".to_string(),
             InstructionKind::ICallAttr => "ICallAttr".to_string(),
+            InstructionKind::IIncrLocal => "IIncrLocal".to_string(),
+            InstructionKind::IIncrGlobal => "IIncrGlobal".to_string(),
+            InstructionKind::IAddLocal => "IAddLocal".to_string(),
+            InstructionKind::IAddGlobal => "IAddGlobal".to_string(),
+            InstructionKind::ICmpNotJump => "ICmpNotJump".to_string(),
+            InstructionKind::IWide => "IWide".to_string(),
             _ => "invalid".to_string(),
         }
     }
@@ -275,6 +453,7 @@ impl InstructionKind {
         match self {
             InstructionKind::IStoreGlobal
             | InstructionKind::ILoadGlobal
+            | InstructionKind::IReleaseGlobal
             | InstructionKind::IConstant
             | InstructionKind::IJump
             | InstructionKind::INotJump
@@ -284,8 +463,11 @@ impl InstructionKind {
             | InstructionKind::IArray
             | InstructionKind::ILoadBuiltIn
             | InstructionKind::ICall
+            | InstructionKind::ITailCall
             | InstructionKind::ICallThread
             | InstructionKind::ILoadFree
+            | InstructionKind::IStoreFree
+            | InstructionKind::ICaptureFree
             | InstructionKind::IPushExcHandle
             | InstructionKind::ICallAsync => vec![2],
             | InstructionKind::ISetAttr => vec![2],
@@ -319,6 +501,7 @@ impl InstructionKind {
             | InstructionKind::IBlockStart
             | InstructionKind::IRetVal
             | InstructionKind::IRet
+            | InstructionKind::IDefer
             | InstructionKind::IGetIndex
             | InstructionKind::ISetIndex
             | InstructionKind::IRaise
@@ -327,15 +510,36 @@ impl InstructionKind {
             | InstructionKind::IPopExcHandle => vec![],
 
             InstructionKind::IClosure
-            | InstructionKind::ICallAttr => vec![2, 2],
+            | InstructionKind::ICallAttr
+            | InstructionKind::IIncrGlobal => vec![2, 2],
+
+            InstructionKind::IIncrLocal => vec![1, 2],
+            InstructionKind::ICmpNotJump => vec![2, 1],
+            InstructionKind::IAddGlobal => vec![2],
 
             InstructionKind::IStoreLocal
-            | InstructionKind::ILoadLocal => vec![1],
+            | InstructionKind::ILoadLocal
+            | InstructionKind::IReleaseLocal
+            | InstructionKind::ICaptureLocal
+            | InstructionKind::IAddLocal => vec![1],
 
             _ => vec![],
         }
     }
 
+    // instructions whose first operand is a position in the instructions:
+    pub fn is_jump(&self) -> bool {
+        match self {
+            InstructionKind::IJump
+            | InstructionKind::INotJump
+            | InstructionKind::IIterNext
+            | InstructionKind::IEnumNext
+            | InstructionKind::ICmpNotJump
+            | InstructionKind::IPushExcHandle => true,
+            _ => false,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn disasm_instruction(&self, operands: &Operands) -> String {
         let op_strings: Vec<String> = operands.into_iter().map(|op| format!("{:x}", op)).collect();
@@ -16,7 +16,7 @@ pub const SYMBOLS: &'static [&'static str] = &[
 pub const KEYWORDS: &'static [&'static str] = &[
     "invalid", "if", "else", "while", "for", "break", "continue", "const", "var", "none", "func",
     "return", "try", "catch", "finally", "rethrow", "throw", "as", "true", "false", "foreach",
-    "in", "use", "pure", "lambda", "assert", "thread", "async"
+    "in", "use", "pure", "lambda", "assert", "thread", "async", "do", "defer"
 ];
 
 #[allow(dead_code)]
@@ -96,6 +96,8 @@ pub enum KeywordKind {
     KAssert = 25,
     KThread = 26,
     KAsync = 27,
+    KDo = 28,
+    KDefer = 29,
 }
 
 #[allow(dead_code)]
@@ -385,6 +387,8 @@ impl ProgramLexer {
             "assert" => TokenKind::Keyword(KeywordKind::KAssert),
             "thread" => TokenKind::Keyword(KeywordKind::KThread),
             "async" => TokenKind::Keyword(KeywordKind::KAsync),
+            "do" => TokenKind::Keyword(KeywordKind::KDo),
+            "defer" => TokenKind::Keyword(KeywordKind::KDefer),
             _ => TokenKind::Identifier(id_string),
         };
 
@@ -794,6 +798,30 @@ impl LexerAPI {
         sym1 == (&sym2)
     }
 
+    // the line and the column of the position, both counted from 1. The
+    // position of a token is where the lexer started to skip the whitespace
+    // before it, the whitespace and the comments are skipped here too:
+    pub fn get_line_and_column(&self, pos: usize) -> (usize, usize) {
+        let buffer = &self.lexer.buffer.buffer;
+        let mut end = pos.min(self.lexer.buffer.buffer_size);
+        while end < self.lexer.buffer.buffer_size {
+            match buffer[end] {
+                b' ' | b'\t' | b'\r' | b'\n' => end += 1,
+                b'#' => {
+                    while end < self.lexer.buffer.buffer_size && buffer[end] != b'\n' {
+                        end += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        let before = &buffer[..end];
+        let line = before.iter().filter(|ch| **ch == b'\n').count() + 1;
+        let line_start = before.iter().rposition(|ch| *ch == b'\n').map_or(0, |idx| idx + 1);
+        return (line, end - line_start + 1);
+    }
+
     pub fn get_line_by_pos(&mut self, pos: usize) -> (String, usize, usize) {
         let mut back_iter = pos;
         let mut front_iter = pos;
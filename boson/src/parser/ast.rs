@@ -13,6 +13,8 @@ pub struct Node {
 pub struct ShellType {
     pub shell: Box<ExpressionKind>,
     pub is_raw: bool,
+    // $$ "cat {file}" quotes the values of its placeholders:
+    pub is_template: bool,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -89,6 +91,17 @@ pub struct WhileLoopType {
     pub loop_block: BlockStatement,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct LabelledType {
+    pub label: String,
+    pub statement: Box<StatementKind>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DeferType {
+    pub statement: Box<StatementKind>,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct AssertType {
     pub target_expr: Box<ExpressionKind>,
@@ -179,8 +192,8 @@ pub enum ExpressionKind {
 #[derive(Debug, PartialEq, Clone)]
 pub enum StatementKind {
     Empty,
-    Break,
-    Continue,
+    Break(Option<String>),
+    Continue(Option<String>),
     Var(LetType),
     Const(ConstType),
     Return(ReturnType),
@@ -190,6 +203,9 @@ pub enum StatementKind {
     Function(FunctionType),
     For(ForLoopType),
     While(WhileLoopType),
+    DoWhile(WhileLoopType),
+    Labelled(LabelledType),
+    Defer(DeferType),
     Assert(AssertType),
     If(IfElseType),
     ForEach(ForEachType),
@@ -68,10 +68,12 @@ impl Parser {
         self.lexer.iterate();
 
         // parse the first statement:
+        let mut stmt_pos = self.lexer.get_current_token().pos;
         match self.parse_statement() {
             Ok(stmt) => {
                 if !self.is_empty_statement(&stmt) {
                     block_statement.statements.push(stmt);
+                    block_statement.pos.push(stmt_pos);
                 }
             }
             Err(error) => return Err(error),
@@ -86,10 +88,12 @@ impl Parser {
                 continue;
             }
 
+            stmt_pos = self.lexer.get_current_token().pos;
             match self.parse_statement() {
                 Ok(stmt) => {
                     if !self.is_empty_statement(&stmt) {
                         block_statement.statements.push(stmt);
+                        block_statement.pos.push(stmt_pos);
                     }
                     self.lexer.iterate();
                 }
@@ -140,6 +144,12 @@ impl Parser {
     fn parse_shell_expression(&mut self) -> Result<ast::ExpressionKind, ParserError> {
         self.lexer.iterate();
 
+        let mut is_template = false;
+        if self.current_symbol_is(SymbolKind::SDollar) {
+            is_template = true;
+            self.lexer.iterate();
+        }
+
         let mut is_raw = false;
         if self.current_symbol_is(SymbolKind::SDot) {
             is_raw = true;
@@ -155,6 +165,7 @@ impl Parser {
         return Ok(ast::ExpressionKind::Shell(ast::ShellType {
             shell: Box::new(right_expr_res.unwrap()),
             is_raw,
+            is_template,
         }));
     }
 
@@ -474,6 +485,92 @@ impl Parser {
         }
     }
 
+    fn parse_do_while_statement(&mut self) -> Result<ast::StatementKind, ParserError> {
+        if !self.next_symbol_is(SymbolKind::SLBrace) {
+            return Err(self.new_invalid_token_err(String::from("Invalid syntax")));
+        }
+
+        let block_result = self.parse_block_statement();
+        if block_result.is_err() {
+            return Err(block_result.unwrap_err());
+        }
+
+        if !self.next_keyword_is(KeywordKind::KWhile) {
+            return Err(self.new_invalid_token_err(String::from("Expected while after do block")));
+        }
+
+        self.lexer.iterate();
+        if !self.next_symbol_is(SymbolKind::SLParen) {
+            return Err(self.new_invalid_token_err(String::from("Invalid syntax")));
+        }
+
+        self.lexer.iterate();
+        self.lexer.iterate();
+
+        let expr_result = self.parse_expression(ExpOrder::Zero);
+        if expr_result.is_err() {
+            return Err(expr_result.unwrap_err());
+        }
+
+        if !self.next_symbol_is(SymbolKind::SRparen) {
+            return Err(self.new_invalid_token_err(String::from("Invalid syntax")));
+        }
+
+        self.lexer.iterate();
+        // the trailing ; is optional:
+        self.is_terminated();
+
+        return Ok(ast::StatementKind::DoWhile(ast::WhileLoopType {
+            target_expr: Box::new(expr_result.unwrap()),
+            loop_block: block_result.unwrap(),
+        }));
+    }
+
+    fn parse_jump_label(&mut self) -> Option<String> {
+        let next_token = self.lexer.get_next_token();
+        match next_token.token {
+            TokenKind::Identifier(label) => {
+                self.lexer.iterate();
+                return Some(label);
+            }
+            _ => return None,
+        }
+    }
+
+    fn parse_labelled_statement(&mut self) -> Result<ast::StatementKind, ParserError> {
+        let label_result = self.get_identifier();
+        if label_result.is_err() {
+            return Err(label_result.unwrap_err());
+        }
+
+        // skip the ':'
+        self.lexer.iterate();
+        self.lexer.iterate();
+
+        let stmt_result = self.parse_statement();
+        if stmt_result.is_err() {
+            return Err(stmt_result.unwrap_err());
+        }
+
+        return Ok(ast::StatementKind::Labelled(ast::LabelledType {
+            label: label_result.unwrap(),
+            statement: Box::new(stmt_result.unwrap()),
+        }));
+    }
+
+    fn parse_defer_statement(&mut self) -> Result<ast::StatementKind, ParserError> {
+        self.lexer.iterate();
+
+        let stmt_result = self.parse_statement();
+        if stmt_result.is_err() {
+            return Err(stmt_result.unwrap_err());
+        }
+
+        return Ok(ast::StatementKind::Defer(ast::DeferType {
+            statement: Box::new(stmt_result.unwrap()),
+        }));
+    }
+
     fn parse_lambda_exp(&mut self) -> Result<ast::ExpressionKind, ParserError> {
         if self.next_symbol_is(SymbolKind::SSemiColon) {
             return Err(self.new_invalid_token_err(String::from("Invalid syntax")));
@@ -1255,16 +1352,18 @@ impl Parser {
 
         match current_token.token {
             TokenKind::Keyword(KeywordKind::KBreak) => {
+                let label = self.parse_jump_label();
                 if self.is_terminated() {
-                    return Ok(ast::StatementKind::Break);
+                    return Ok(ast::StatementKind::Break(label));
                 } else {
                     return Err(self.new_invalid_token_err(String::from("Expected ; after break.")));
                 }
             }
 
             TokenKind::Keyword(KeywordKind::KContinue) => {
+                let label = self.parse_jump_label();
                 if self.is_terminated() {
-                    return Ok(ast::StatementKind::Continue);
+                    return Ok(ast::StatementKind::Continue(label));
                 } else {
                     return Err(
                         self.new_invalid_token_err(String::from("Expected ; after continue."))
@@ -1272,6 +1371,26 @@ impl Parser {
                 }
             }
 
+            TokenKind::Identifier(_) if self.next_symbol_is(SymbolKind::SColon) => {
+                return self.parse_labelled_statement();
+            }
+
+            TokenKind::Keyword(KeywordKind::KDo) => {
+                if self.is_terminated() {
+                    return Err(self.new_invalid_token_err(String::from("Invalid syntax")));
+                } else {
+                    return self.parse_do_while_statement();
+                }
+            }
+
+            TokenKind::Keyword(KeywordKind::KDefer) => {
+                if self.is_terminated() {
+                    return Err(self.new_invalid_token_err(String::from("Invalid syntax")));
+                } else {
+                    return self.parse_defer_statement();
+                }
+            }
+
             TokenKind::Keyword(KeywordKind::KVar) => {
                 if self.is_terminated() {
                     return Err(self.new_invalid_token_err(String::from("Invalid syntax")));
@@ -1386,9 +1505,13 @@ impl Parser {
                 continue;
             }
 
+            let stmt_pos = current_token.pos;
             let stmt_result = self.parse_statement();
             match stmt_result {
-                Ok(stmt) => program.statements.push(stmt),
+                Ok(stmt) => {
+                    program.statements.push(stmt);
+                    program.pos.push(stmt_pos);
+                }
                 Err(error) => self.errors.push(error),
             }
 
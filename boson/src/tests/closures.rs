@@ -0,0 +1,102 @@
+use crate::types;
+
+
+use types::object::Object;
+
+use super::eval;
+
+#[test]
+pub fn counter() {
+    let source = "
+        func counter() {
+            var c = 0;
+            func inc() { c = c + 1; return c; }
+            return inc;
+        }
+        const tick = counter();
+        tick(); tick();
+        tick();
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(3));
+}
+
+#[test]
+pub fn shared_upvalues() {
+    // both closures and the enclosing function see the same variable:
+    let source = "
+        func pair() {
+            var n = 0;
+            func get() { return n; }
+            func set(v) { n = v; }
+            set(5);
+            return [get, set, n];
+        }
+        const p = pair();
+        p[1](p[2] + 37);
+        p[0]();
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(42));
+}
+
+#[test]
+pub fn nested_capture() {
+    let source = "
+        func outer() {
+            var v = 1;
+            func mid() {
+                func inner() { v = v + 10; return v; }
+                return inner;
+            }
+            const f = mid();
+            f();
+            return v;
+        }
+        func fact_of(x) {
+            func fact(n) { if (n == 0) { return 1; } return n * fact(n - 1); }
+            return fact(x);
+        }
+        outer() + fact_of(5);
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(131));
+}
+
+#[test]
+pub fn cycle_collection() {
+    // a recursive inner function captures itself, the cycle outlives the call:
+    let source = "
+        func make(n) {
+            const big = create_array(1000, n);
+            func walk(k) {
+                if (k == 0) { return big; }
+                return walk(k - 1);
+            }
+            return len(walk(3));
+        }
+        var i = 0;
+        while (i < 100) { make(i); i = i + 1; }
+        const before = memory_usage();
+        const collected = gc();
+        [collected >= 100, memory_usage() < before / 10];
+    ";
+    assert_eq!(eval(source).unwrap().describe(), "Array([true, true])");
+
+    // cycles that are still reachable are kept:
+    let source = "
+        func counter() {
+            var n = 0;
+            func inc(k) { n = n + k; if (k > 1) { return inc(k - 1); } return n; }
+            return inc;
+        }
+        const c = counter();
+        const held = [counter()];
+        func inner() {
+            var x = 5;
+            func self_ref(k) { if (k == 0) { return x; } return self_ref(k - 1); }
+            gc();
+            return self_ref(3);
+        }
+        gc();
+        c(3) + held[0](2) + inner();
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(14));
+}
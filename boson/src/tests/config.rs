@@ -0,0 +1,232 @@
+use crate::api;
+use crate::config;
+use crate::types;
+use crate::vm;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use api::BosonLang;
+use config::VMConfig;
+use types::builtins::BuiltinKind;
+use types::hash::HashTable;
+use types::object::Object;
+use types::object::Value;
+use vm::errors::VMError;
+use vm::errors::VMErrorKind;
+use vm::limits::CancelHandle;
+use vm::limits::ExecutionLimits;
+use vm::BosonVM;
+
+fn eval_with(source: &str, config: VMConfig) -> Option<Value> {
+    let mut lang = BosonLang::new_from_buffer(source.as_bytes().to_vec());
+    lang.set_config(config);
+    return lang.eval_state();
+}
+
+// runs the source on a new VM and returns the error of the VM itself:
+fn run_limited(source: &str, config: VMConfig, cancel: CancelHandle) -> Result<Value, VMError> {
+    let mut lang = BosonLang::new_from_buffer(source.as_bytes().to_vec());
+    let program = lang.parser.parse().unwrap();
+    let bytecode = lang.compiler.compile(&program).unwrap();
+
+    let mut vm = BosonVM::new(&bytecode, &config);
+    vm.threads.limits = ExecutionLimits::new(&config, cancel);
+    return vm.eval_bytecode(&lang.platform, true, false);
+}
+
+#[test]
+pub fn parse_config() {
+    let toml = "
+        # limits for deep recursion
+        [vm]
+        frame_stack_size = 100_000 # nested calls
+        data_stack-size = 1000000
+        enable_concurrency = false
+    ";
+    let config = VMConfig::parse(toml).unwrap();
+    assert_eq!(config.frame_stack_size, 100000);
+    assert_eq!(config.data_stack_size, 1000000);
+    assert_eq!(config.global_pool_size, VMConfig::new().global_pool_size);
+    assert_eq!(config.enable_concurrency, false);
+
+    let json = "{
+        \"global_pool_size\": 16,
+        \"enable_concurrency\": true
+    }";
+    let config = VMConfig::parse(json).unwrap();
+    assert_eq!(config.global_pool_size, 16);
+    assert_eq!(config.enable_concurrency, true);
+
+    // a JSON object can be on a single line:
+    let config = VMConfig::parse("{\"max_heap_bytes\": 1000, \"timeout_ms\": 50}").unwrap();
+    assert_eq!(config.max_heap_bytes, Some(1000));
+    assert_eq!(config.timeout_ms, Some(50));
+    assert_eq!(VMConfig::parse("{\"max_heap_bytes\": 1000 \"timeout_ms\": 50}").is_err(), true);
+
+    // unknown options and bad values are reported with their line:
+    assert_eq!(VMConfig::parse("\nstack = 10").unwrap_err(), "line 2: Unknown config option stack");
+    assert_eq!(VMConfig::parse("frame_stack_size = 0").is_err(), true);
+    assert_eq!(VMConfig::parse("enable_concurrency = yes").is_err(), true);
+}
+
+#[test]
+pub fn vm_limits() {
+    let source = "
+        func depth(n) { if (n == 0) { return 0; } return 1 + depth(n - 1); }
+        depth(5000);
+    ";
+    assert_eq!(eval_with(source, VMConfig::new()).is_none(), true);
+
+    let mut config = VMConfig::new();
+    config.frame_stack_size = 10000;
+    assert_eq!(eval_with(source, config).unwrap().as_ref(), &Object::Int(5000));
+
+    // a small global pool is checked by the compiler, the builtins are
+    // numbered before the globals:
+    let mut config = VMConfig::new();
+    config.global_pool_size = BuiltinKind::get_size() + 20;
+    let declare = |n: usize| {
+        let names: Vec<String> = (0..n).map(|i| format!("var g{} = {};", i, i)).collect();
+        return names.join(" ");
+    };
+    assert_eq!(eval_with(&declare(10), config.clone()).is_some(), true);
+    assert_eq!(eval_with(&declare(30), config).is_none(), true);
+
+    // threads are rejected when concurrency is disabled:
+    let mut config = VMConfig::new();
+    config.enable_concurrency = false;
+    let source = "
+        func add(a, b) { return a + b; }
+        const th = thread add(1, 2);
+        wait(th);
+    ";
+    assert_eq!(eval_with(source, VMConfig::new()).unwrap().as_ref(), &Object::Int(3));
+    assert_eq!(eval_with(source, config.clone()).is_none(), true);
+    assert_eq!(eval_with("call_async(lambda x => x, [1]);", config).is_none(), true);
+}
+
+// the files of the heap test are too big to be read:
+fn huge_finfo(_path: String) -> Result<Value, String> {
+    let mut entries = HashMap::new();
+    entries.insert(
+        Value::new(Object::Str(Box::new("size".to_string()))),
+        Value::new(Object::Int(1 << 40)),
+    );
+    let info = HashTable { name: "finfo".to_string(), entries: entries };
+    return Ok(Value::new(Object::HashTable(Box::new(RefCell::new(info)))));
+}
+
+fn unreachable_fread(path: String, _start: Option<u64>, _n: Option<u64>) -> Result<(Vec<u8>, u64), String> {
+    panic!("{} was read past the heap limit", path);
+}
+
+#[test]
+pub fn heap_limit() {
+    let mut config = VMConfig::new();
+    config.max_heap_bytes = Some(1000000);
+
+    // growing a string, allocating an array and filling a table:
+    let source = "
+        var s = \"ab\";
+        var log = \"\";
+        try { while (true) { s = s + s; } } catch e { log = log + e.kind; }
+        try { create_array(100000000); } catch e { log = log + \":\" + e.kind; }
+        var h = {};
+        var i = 0;
+        try { while (true) { h[i] = i; i = i + 1; } } catch e { log = log + \":\" + e.kind; }
+        log;
+    ";
+    let result = run_limited(source, config.clone(), CancelHandle::new());
+    assert_eq!(
+        result.unwrap().as_ref(),
+        &Object::Str(Box::new("MemoryError:MemoryError:MemoryError".to_string()))
+    );
+
+    // uncaught, the error stops the VM:
+    let result = run_limited("create_array(100000000);", config.clone(), CancelHandle::new());
+    assert_eq!(result.unwrap_err().t, VMErrorKind::MemoryError);
+
+    // the usage goes down once the values are gone:
+    let source = "
+        var a = create_array(10000, \"x\");
+        const used = memory_usage();
+        a = 0;
+        used - memory_usage() >= 10000;
+    ";
+    let result = run_limited(source, config.clone(), CancelHandle::new());
+    assert_eq!(result.unwrap().as_ref(), &Object::Bool(true));
+
+    // a read that does not fit is refused before the file is read:
+    let source = "
+        var log = \"\";
+        try { fread(\"big.bin\"); } catch e { log = e.kind; }
+        try { fread(\"big.bin\", 0, 2000000); } catch e { log = log + \":\" + e.kind; }
+        log;
+    ";
+    let mut lang = BosonLang::new_from_buffer(source.as_bytes().to_vec());
+    lang.platform.finfo = huge_finfo;
+    lang.platform.fread = unreachable_fread;
+    lang.set_config(config);
+    assert_eq!(lang.eval_state().unwrap().describe(), "MemoryError:MemoryError");
+}
+
+#[test]
+pub fn execution_limits() {
+    let source = "var i = 0; while (i < 1000) { i = i + 1; } i;";
+    let mut config = VMConfig::new();
+    config.max_instructions = Some(1000000);
+    let result = run_limited(source, config.clone(), CancelHandle::new());
+    assert_eq!(result.unwrap().as_ref(), &Object::Int(1000));
+
+    // the budget stops infinite loops, in the VM and in its threads:
+    config.max_instructions = Some(5000);
+    let result = run_limited("while (true) {}", config.clone(), CancelHandle::new());
+    assert_eq!(result.unwrap_err().t, VMErrorKind::InstructionBudgetExceeded);
+
+    let source = "
+        func spin() { while (true) {} }
+        const th = thread spin();
+        wait(th);
+    ";
+    let error = run_limited(source, config.clone(), CancelHandle::new()).unwrap_err();
+    assert_eq!(error.t, VMErrorKind::InstructionBudgetExceeded);
+
+    // the deadline:
+    let mut config = VMConfig::new();
+    config.timeout_ms = Some(50);
+    let start = Instant::now();
+    let result = run_limited("while (true) {}", config, CancelHandle::new());
+    assert_eq!(result.unwrap_err().t, VMErrorKind::DeadlineExceeded);
+    assert_eq!(start.elapsed() < Duration::from_secs(5), true);
+
+    // a sleep wakes up at the deadline and cannot be caught:
+    let mut config = VMConfig::new();
+    config.timeout_ms = Some(100);
+    let start = Instant::now();
+    let source = "try { sleep_sec(5); } catch e { 1; }";
+    let result = run_limited(source, config, CancelHandle::new());
+    assert_eq!(result.unwrap_err().t, VMErrorKind::DeadlineExceeded);
+    assert_eq!(start.elapsed() < Duration::from_secs(2), true);
+
+    // the host cancels the evaluation from another thread:
+    let mut lang = BosonLang::new_from_buffer("while (true) {}".as_bytes().to_vec());
+    let handle = lang.cancel_handle();
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        handle.cancel();
+    });
+    assert_eq!(lang.eval_state().is_none(), true);
+    canceller.join().unwrap();
+
+    let cancel = CancelHandle::new();
+    cancel.cancel();
+    let result = run_limited("1 + 2;", VMConfig::new(), cancel.clone());
+    assert_eq!(result.unwrap_err().t, VMErrorKind::Cancelled);
+    cancel.reset();
+    let result = run_limited("1 + 2;", VMConfig::new(), cancel);
+    assert_eq!(result.unwrap().as_ref(), &Object::Int(3));
+}
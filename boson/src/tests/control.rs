@@ -0,0 +1,215 @@
+use crate::api;
+use crate::types;
+
+
+use api::BosonLang;
+use types::object::Object;
+
+use super::eval;
+
+#[test]
+pub fn labelled_loops() {
+    let source = "
+        var total = 0;
+        outer: for i in [1, 2, 3] => {
+            for j in [1, 2, 3] => {
+                if (j == 2) { continue outer; }
+                if (i == 3) { break outer; }
+                total = total + i * 10 + j;
+            }
+        }
+        total;
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(32));
+
+    // unknown labels are rejected:
+    assert_eq!(eval("while (true) { break missing; }").is_none(), true);
+}
+
+#[test]
+pub fn do_while() {
+    let source = "
+        var k = 0;
+        var seen = 0;
+        do {
+            k = k + 1;
+            if (k == 2) { continue; }
+            seen = seen + k;
+        } while (k < 4);
+        do { seen = seen + 100; } while (false);
+        seen;
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(108));
+}
+
+#[test]
+pub fn defer_on_return() {
+    let source = "
+        var log = \"\";
+        func work(n) {
+            defer log = log + \"a\";
+            defer log = log + \"b\";
+            if (n > 0) {
+                return n * 2;
+            }
+            log = log + \"c\";
+        }
+        const doubled = work(3);
+        work(0);
+        log + string(doubled);
+    ";
+    assert_eq!(
+        eval(source).unwrap().as_ref(),
+        &Object::Str(Box::new("bacba6".to_string()))
+    );
+}
+
+#[test]
+pub fn defer_on_error() {
+    let mut lang = BosonLang::new_from_buffer(
+        "
+        var log = \"\";
+        func fails() {
+            defer log = log + \"cleanup\";
+            var x = 1 + \"a\";
+        }
+        fails();
+        "
+        .as_bytes()
+        .to_vec(),
+    );
+
+    assert_eq!(lang.eval_state().is_none(), true);
+    let globals = &lang.vm.as_ref().unwrap().globals;
+    let log_sym = lang.compiler.symbol_table.resolve_symbol("log").unwrap();
+    assert_eq!(
+        globals.get(log_sym.pos).unwrap().as_ref(),
+        &Object::Str(Box::new("cleanup".to_string()))
+    );
+}
+
+#[test]
+pub fn tail_calls() {
+    // deeper than the call stack:
+    let source = "
+        func count(n, acc) {
+            if (n == 0) { return acc; }
+            return count(n - 1, acc + 1);
+        }
+        count(100000, 0);
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(100000));
+
+    // mutual recursion, defined in any order:
+    let source = "
+        func is_even(n) { if (n == 0) { return true; } return is_odd(n - 1); }
+        func is_odd(n) { if (n == 0) { return false; } return is_even(n - 1); }
+        is_even(10001);
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Bool(false));
+
+    // calls that are not in tail position still overflow:
+    let source = "
+        func depth(n) { if (n == 0) { return 0; } return 1 + depth(n - 1); }
+        depth(100000);
+    ";
+    assert_eq!(eval(source).is_none(), true);
+
+    // captured locals and deferred closures survive the frame re-use:
+    let source = "
+        var log = \"\";
+        func apply(f, x) { return f(x); }
+        func wrap(n) {
+            var k = n * 2;
+            defer log = log + \"d\";
+            return apply(lambda x => x + k, 1);
+        }
+        func outer(n) { var k = n; return apply(lambda x => x + k, 2); }
+        const a = wrap(10);
+        const b = outer(5);
+        log + string(a) + string(b);
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Str(Box::new("d217".to_string())));
+}
+
+#[test]
+pub fn try_catch() {
+    // runtime errors and thrown values are caught, by the caller too:
+    let source = "
+        var log = \"\";
+        try { 1 / 0; } catch e { log = log + e.kind; }
+        func fails(x) { throw x * 2; }
+        try { fails(21); } catch e { log = log + \":\" + e.message; }
+        log;
+    ";
+    assert_eq!(
+        eval(source).unwrap().as_ref(),
+        &Object::Str(Box::new("DivideByZeroError:42".to_string()))
+    );
+
+    // finally runs on both paths and the error goes on to the outer handler:
+    let source = "
+        var log = \"\";
+        try {
+            try { throw \"x\"; } catch e { log = log + \"a\"; throw e; } finally { log = log + \"f\"; }
+        } catch e {
+            log = log + \"b\";
+        }
+        try { log = log + \"c\"; } catch e {} finally { log = log + \"f\"; }
+        log;
+    ";
+    assert_eq!(
+        eval(source).unwrap().as_ref(),
+        &Object::Str(Box::new("afbcf".to_string()))
+    );
+
+    // break and continue leave the try block:
+    let source = "
+        var n = 0;
+        for i in [1, 2, 3, 4] => {
+            try {
+                if (i == 2) { continue; }
+                if (i == 4) { break; }
+                n = n + i;
+            } catch e {}
+        }
+        n;
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(4));
+
+    // finally runs before return, break and continue leave the try and catch blocks:
+    let source = "
+        var log = \"\";
+        func early(x) {
+            var v = \"outer\";
+            try {
+                try { var v = \"inner\"; if (x == 1) { return 1; } throw x; } catch e { return e.message; } finally { log = log + v; }
+            } catch e {} finally { log = log + \"-\"; }
+            return 0;
+        }
+        const results = [early(1), early(2)];
+        var n = 0;
+        while (n < 10) {
+            n = n + 1;
+            try {
+                if (n == 2) { continue; }
+                if (n == 3) { break; }
+            } catch e {} finally { log = log + string(n); }
+        }
+        for i in [1, 2] => {
+            try { throw i; } catch e { continue; } finally { log = log + \"c\"; }
+        }
+        var failed = \"\";
+        try {
+            try { return 5; } catch e { log = log + \"caught by itself\"; } finally { throw \"from finally\"; }
+        } catch e { failed = e.message; }
+        [results, log, failed];
+    ";
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "Array([Array([1, 2]), outer-outer-123cc, from finally])"
+    );
+
+    // an uncaught throw stops the program:
+    assert_eq!(eval("throw \"uncaught\";").is_none(), true);
+}
@@ -1 +1,17 @@
-pub mod types;
\ No newline at end of file
+pub mod closures;
+pub mod config;
+pub mod control;
+pub mod optimize;
+pub mod process;
+pub mod scopes;
+pub mod tasks;
+pub mod threads;
+pub mod types;
+
+use crate::api::BosonLang;
+use crate::types::object::Value;
+
+// evaluates the source with the default config and gives the last value:
+fn eval(source: &str) -> Option<Value> {
+    return BosonLang::eval_buffer(source.as_bytes().to_vec());
+}
@@ -0,0 +1,292 @@
+use crate::api;
+use crate::compiler;
+use crate::config;
+use crate::lexer;
+use crate::parser;
+use crate::types;
+
+
+use api::BosonLang;
+use config::VMConfig;
+use compiler::BytecodeCompiler;
+use compiler::BytecodeDecompiler;
+use compiler::CompiledBytecode;
+use lexer::LexerAPI;
+use parser::Parser;
+use types::object::Object;
+use types::object::Value;
+
+use super::eval;
+
+fn eval_unoptimized(source: &str) -> Option<Value> {
+    let mut lang = BosonLang::new_from_buffer(source.as_bytes().to_vec());
+    lang.set_optimize(false);
+    return lang.eval_state();
+}
+
+fn compile_with(source: &str, optimize: bool) -> CompiledBytecode {
+    let lexer = LexerAPI::new_from_buffer(source.as_bytes().to_vec());
+    let mut parser = Parser::new_from_lexer(lexer);
+    let program = parser.parse().unwrap();
+
+    let mut compiler = BytecodeCompiler::new();
+    compiler.optimize = optimize;
+    return compiler.compile(&program).unwrap();
+}
+
+fn compile(source: &str) -> CompiledBytecode {
+    return compile_with(source, true);
+}
+
+fn get_function_disasm(bytecode: &CompiledBytecode) -> String {
+    let mut disasm = String::new();
+    for obj in &bytecode.constant_pool.objects {
+        if let Object::Subroutine(sub) = obj.as_ref() {
+            disasm = BytecodeDecompiler::disassemble_function(sub.get_bytecode());
+        }
+    }
+
+    return disasm;
+}
+
+#[test]
+pub fn constant_folding() {
+    let bytecode = compile("var x = 2 * 3 + 4 - 1; var s = \"ab\" + \"cd\"; var b = !(1 > 2);");
+    let disasm = BytecodeDecompiler::disassemble_instructions(&bytecode);
+    assert!(!disasm.contains("IMul"));
+    assert!(!disasm.contains("IAdd"));
+    assert!(!disasm.contains("ILGt"));
+    assert!(!disasm.contains("ILNot"));
+
+    let result = eval("var x = 2 * 3 + 4 - 1; x;");
+    assert_eq!(result.unwrap().as_ref(), &Object::Int(9));
+
+    let result = eval("var s = \"ab\" + \"cd\"; s;");
+    assert_eq!(result.unwrap().as_ref(), &Object::Str(Box::new("abcd".to_string())));
+
+    // errors are left to the runtime:
+    let bytecode = compile("var x = 1 / 0;");
+    let disasm = BytecodeDecompiler::disassemble_instructions(&bytecode);
+    assert!(disasm.contains("IDiv"));
+}
+
+#[test]
+pub fn const_inlining() {
+    let bytecode = compile("const N = 10; func f(x) { return x * N; }");
+    let disasm = get_function_disasm(&bytecode);
+    assert!(disasm.contains("IMul"));
+    assert!(!disasm.contains("ILoadGlobal"));
+
+    let result = eval("const N = 4 * 4; const M = N + 1; func f() { return M * 2; } f();");
+    assert_eq!(result.unwrap().as_ref(), &Object::Int(34));
+
+    let result = eval("var n = 3; const M = n + 1; M;");
+    assert_eq!(result.unwrap().as_ref(), &Object::Int(4));
+}
+
+#[test]
+pub fn constant_pool_dedup() {
+    let bytecode = compile("var a = 1; var b = 1; var c = \"x\"; var d = \"x\"; var e = 1.0;");
+    assert_eq!(bytecode.constant_pool.get_size(), 3);
+}
+
+#[test]
+pub fn peephole_dead_code() {
+    let source = "func f(n) { if (n > 1) { return 1; } else { return 2; } println(\"dead\"); } f(3);";
+    let disasm = get_function_disasm(&compile(source));
+    assert!(!disasm.contains("ILoadBuiltIn"));
+    assert!(!disasm.contains("INoOp"));
+    assert!(!disasm.contains("IJump"));
+
+    let disasm = get_function_disasm(&compile_with(source, false));
+    assert!(disasm.contains("ILoadBuiltIn"));
+
+    let result = eval(source);
+    assert_eq!(result.unwrap().as_ref(), &Object::Int(1));
+}
+
+#[test]
+pub fn peephole_constant_branches() {
+    let source = "const DEBUG = false; var i = 0; \
+        while (true) { i = i + 1; if (i > 3) { break; } if (DEBUG) { i = 100; } } i;";
+
+    let disasm = BytecodeDecompiler::disassemble_instructions(&compile(source));
+    assert!(!disasm.contains("IConstant 64"));
+    assert_eq!(disasm.matches("NotJump").count(), 1);
+
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(4));
+    assert_eq!(eval_unoptimized(source).unwrap().as_ref(), &Object::Int(4));
+}
+
+#[test]
+pub fn peephole_jumps() {
+    let source = "var s = 0; \
+        for i in [0, 1, 2, 3, 4] => { if (i == 1) { continue; } if (i == 3) { continue; } s = s + i; } \
+        do { s = s + 1; } while (s < 10); \
+        var t = 0; \
+        outer: foreach [1, 2, 3], k, x => { for y in [1, 2] => { if (x == 2) { continue outer; } t = t + y; } } \
+        s * 100 + t;";
+
+    let result = eval(source);
+    assert_eq!(result.as_ref().unwrap().as_ref(), eval_unoptimized(source).unwrap().as_ref());
+    assert_eq!(result.unwrap().as_ref(), &Object::Int(1006));
+
+    let optimized = compile(source).instructions.len();
+    let unoptimized = compile_with(source, false).instructions.len();
+    assert!(optimized < unoptimized);
+}
+
+#[test]
+pub fn function_inlining() {
+    let source = "
+        func sq(x) { return x * x; }
+        func clamp(x, lo, hi) { var r = x; if (x < lo) { r = lo; } if (x > hi) { r = hi; } return r; }
+        const dbl = lambda x => x * 2;
+        func log(x) { var y = x; }
+        func work(n) {
+            var total = 0;
+            for i in [1, 2, 3] => { total = total + sq(i) + clamp(i * n, 2, 5); }
+            return total + dbl(n) + (lambda a, b => a - b)(10, 3);
+        }
+        const nothing = log(1);
+        sq(7) * 1000 + clamp(10, 0, 5) * 100 + work(2);
+    ";
+
+    let bytecode = compile(source);
+    let disasm = BytecodeDecompiler::disassemble_instructions(&bytecode);
+    // only the call to work is left:
+    assert_eq!(disasm.matches("ICall").count(), 1);
+    for obj in &bytecode.constant_pool.objects {
+        if let Object::Subroutine(sub) = obj.as_ref() {
+            if sub.name == "work" {
+                let disasm = BytecodeDecompiler::disassemble_function(sub.get_bytecode());
+                assert!(!disasm.contains("ICall"));
+            }
+        }
+    }
+
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(49536));
+    assert_eq!(eval_unoptimized(source).unwrap().as_ref(), &Object::Int(49536));
+
+    // recursive and non-leaf functions are called:
+    let source = "func fact(n) { if (n < 2) { return 1; } return n * fact(n - 1); } fact(5);";
+    let disasm = BytecodeDecompiler::disassemble_instructions(&compile(source));
+    assert!(disasm.contains("ICall"));
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(120));
+
+    // the calls inlined at the top level share their global slots:
+    let mut source = String::from("func add(a, b) { return a + b; } var s = 0;");
+    for i in 0..600 {
+        source.push_str(&format!("s = add(s, {});", i));
+    }
+    source.push_str("s;");
+    let mut config = VMConfig::new();
+    config.global_pool_size = 200;
+    let mut lang = BosonLang::new_from_buffer(source.as_bytes().to_vec());
+    lang.set_config(config);
+    assert_eq!(lang.eval_state().unwrap().as_ref(), &Object::Int(179700));
+}
+
+#[test]
+pub fn wide_operands() {
+    // more than 65535 constants and a jump over more than 64 KB of code:
+    let mut source = String::from("var s = 0; var n = 1; if (n > 0) {");
+    for i in 0..70000 {
+        source.push_str(&format!("s = s + {};", i));
+    }
+    source.push_str("} s;");
+
+    let disasm = BytecodeDecompiler::disassemble_instructions(&compile(&source));
+    assert!(disasm.contains("IWide IIncrGlobal"));
+    assert!(disasm.contains(", 1116f\n"));
+    assert!(disasm.contains("IWide ICmpNotJump"));
+
+    assert_eq!(eval(&source).unwrap().as_ref(), &Object::Int(2449965000));
+    assert_eq!(eval_unoptimized(&source).unwrap().as_ref(), &Object::Int(2449965000));
+
+    // more than 255 locals:
+    let mut source = String::from("func many() {");
+    for i in 0..300 {
+        source.push_str(&format!("var v{} = {};", i, i));
+    }
+    source.push_str("return v0 + v299; } many();");
+    assert_eq!(eval(&source).unwrap().as_ref(), &Object::Int(299));
+}
+
+#[test]
+pub fn too_many_globals() {
+    let mut source = String::new();
+    for i in 0..65537 {
+        source.push_str(&format!("var g{} = 0;", i));
+    }
+
+    let lexer = LexerAPI::new_from_buffer(source.as_bytes().to_vec());
+    let mut parser = Parser::new_from_lexer(lexer);
+    let program = parser.parse().unwrap();
+
+    let result = BytecodeCompiler::new().compile(&program);
+    assert!(result.is_err());
+    match result.unwrap_err().t {
+        compiler::errors::CompilerErrorKind::OperandOverflow => {}
+        kind => panic!("unexpected error {:?}", kind),
+    }
+}
+
+#[test]
+pub fn predecoded_instructions() {
+    let source = "func count(n) { var i = 0; while (i < n) { i = i + 1; } return i; } count(300);";
+    let bytecode = compile_with(source, false);
+    for obj in &bytecode.constant_pool.objects {
+        if let Object::Subroutine(sub) = obj.as_ref() {
+            // jumps point at decoded instructions, not at byte offsets:
+            let instructions = sub.get_instructions();
+            assert!(instructions.len() < sub.get_bytecode().len());
+            for inst in instructions {
+                if inst.kind.is_jump() {
+                    assert!(inst.operands[0] < instructions.len());
+                }
+            }
+        }
+    }
+
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(300));
+}
+
+#[test]
+pub fn superinstructions() {
+    let source = "
+        func count(n, step) {
+            var i = 0;
+            var s = 0;
+            while (i < n) {
+                s = s + step;
+                i = i + 1;
+            }
+            return s;
+        }
+        var f = 0.5;
+        var t = \"a\";
+        var h = 0.5;
+        var j = 0;
+        while (j != 3) { j = j + 1; f = f + 1; t = t + \"b\"; h = h + 0.25; }
+        [count(10, 1), count(4, 0.5), f, t, j, h];
+    ";
+
+    let bytecode = compile(source);
+    let disasm = get_function_disasm(&bytecode);
+    assert!(disasm.contains("IIncrLocal"));
+    assert!(disasm.contains("IAddLocal"));
+    assert!(disasm.contains("ICmpNotJump"));
+    assert!(!disasm.contains("IAdd "));
+
+    let disasm = BytecodeDecompiler::disassemble_instructions(&bytecode);
+    assert!(disasm.contains("IIncrGlobal"));
+
+    let expected = "Array([10, 2, 3.5, abbb, 3, 1.25])";
+    assert_eq!(eval(source).unwrap().as_ref().describe(), expected);
+    assert_eq!(eval_unoptimized(source).unwrap().as_ref().describe(), expected);
+
+    // the overflow of an increment is reported like the one of an add:
+    let source = "var x = 9223372036854775806; var i = 0; while (i < 3) { x = x + 1; i = i + 1; }";
+    assert!(eval(source).is_none());
+}
@@ -0,0 +1,152 @@
+use crate::api;
+
+use std::time::Duration;
+
+use api::BosonLang;
+use api::ChildProcess;
+use api::ProcessOptions;
+use api::ProcessStream;
+
+use super::eval;
+
+// a process of the stub platform, it prints what it gets on stdin back in upper case:
+struct UpperCase {
+    output: Vec<Vec<u8>>,
+    stdin_closed: bool,
+}
+
+impl ChildProcess for UpperCase {
+    fn pid(&self) -> u32 {
+        return 42;
+    }
+
+    fn read(&mut self, stream: ProcessStream, _timeout: Duration) -> Result<Option<Vec<u8>>, String> {
+        if stream == ProcessStream::Stderr || self.output.is_empty() {
+            return Ok(if self.stdin_closed { None } else { Some(vec![]) });
+        }
+        return Ok(Some(self.output.remove(0)));
+    }
+
+    fn write(&mut self, data: &Vec<u8>) -> Result<usize, String> {
+        self.output.push(data.to_ascii_uppercase());
+        return Ok(data.len());
+    }
+
+    fn close_stdin(&mut self) {
+        self.stdin_closed = true;
+    }
+
+    fn try_wait(&mut self) -> Result<Option<i32>, String> {
+        return Ok(if self.stdin_closed { Some(0) } else { None });
+    }
+
+    fn kill(&mut self) -> Result<(), String> {
+        self.stdin_closed = true;
+        return Ok(());
+    }
+}
+
+fn stub_spawn(
+    cmd: &String,
+    args: &Vec<String>,
+    _opts: &ProcessOptions,
+) -> Result<Box<dyn ChildProcess>, String> {
+    if cmd != "upper" {
+        return Err(format!("{} is not allowed", cmd));
+    }
+    let greeting = format!("{} ", args.join(" ")).into_bytes();
+    return Ok(Box::new(UpperCase {
+        output: vec![greeting],
+        stdin_closed: false,
+    }));
+}
+
+#[test]
+pub fn process_pipes() {
+    // stdout and stderr are read as they come, stdin is written while the process runs:
+    let source = "
+        const p = process.spawn(\"sh\", [\"-c\", \"echo out; echo err 1>&2; read x; echo got $x $FOO; exit 3\"], {\"env\": {\"FOO\": 7}});
+        const first = [string(p.read_stdout()), string(p.read_stderr()), p.is_running()];
+        p.write(\"hi\");
+        p.close_stdin();
+        const second = [string(p.read_stdout()), p.read_stdout(), p.wait(), p.exit_code()];
+        const r = process.spawn(\"pwd\", [], {\"cwd\": \"/\"});
+        [first, second, string(r.read_stdout()), r.wait(), p.pid > 0];
+    ";
+
+    let result = eval(source);
+    assert_eq!(
+        result.unwrap().describe(),
+        "Array([Array([out\n, err\n, true]), Array([got hi 7\n, undef, 3, 3]), /\n, 0, true])"
+    );
+}
+
+#[test]
+pub fn process_timeout() {
+    // a process past its timeout is killed, a wait with a timeout gives up first:
+    let source = "
+        const p = process.spawn(\"sleep\", [\"5\"], {\"timeout\": 150});
+        const early = p.wait(10);
+        [early, p.timed_out(), p.wait(), p.timed_out(), p.is_running()];
+    ";
+
+    let started = std::time::Instant::now();
+    let result = eval(source);
+    assert_eq!(result.unwrap().describe(), "Array([undef, false, -1, true, false])");
+    assert!(started.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+pub fn process_stub_platform() {
+    // the host decides how processes run, this one never starts a real process:
+    let source = "
+        const p = process.spawn(\"upper\", [\"hello\", 1]);
+        p.write(\"abc\");
+        const out = [string(p.read_stdout()), string(p.read_stdout()), p.pid];
+        p.close_stdin();
+        var denied = \"\";
+        try { process.spawn(\"rm\", [\"-rf\", \"/\"]); } catch e { denied = string(e); }
+        [out, p.read_stdout(), p.wait(), denied];
+    ";
+
+    let mut lang = BosonLang::new_from_buffer(source.as_bytes().to_vec());
+    lang.platform.spawn_process = stub_spawn;
+    let result = lang.eval_state();
+    assert_eq!(
+        result.unwrap().describe(),
+        "Array([Array([hello 1 , ABC, 42]), undef, 0, AttributeError: rm is not allowed])"
+    );
+}
+
+#[test]
+pub fn shell_templates() {
+    // the values are quoted, so they stay one word whatever they contain:
+    let source = "
+        const file = \"a b; echo injected\";
+        const quote = \"it's\";
+        const r = $$ \"printf '%s|' {file} {quote} 1>&2; exit 2\";
+        const s = sh(\"echo {} {{x}}\", [\"$(whoami)\"]);
+        var failed = \"\";
+        try { sh(\"echo {name}\", [1]); } catch e { failed = string(e); }
+        [r.code, r.stdout, r.stderr, r.cmd, s.stdout, s.cmd, failed];
+    ";
+
+    let result = eval(source);
+    assert_eq!(
+        result.unwrap().describe(),
+        "Array([2, , a b; echo injected|it's|, printf '%s|' 'a b; echo injected' 'it'\\''s' 1>&2; exit 2, \
+         $(whoami) {x}\n, echo '$(whoami)' {x}, \
+         BuiltinFunctionError: The placeholder {name} needs a hash table of values, provided array])"
+    );
+
+    // a plain $ string is not a template, the shell expands it:
+    let result = eval("$ \"echo ${HOME}-{x}\";");
+    let home = std::env::var("HOME").unwrap();
+    assert_eq!(result.unwrap().describe(), format!("Array([0, {}-{{x}}\n])", home));
+
+    // a placeholder must name a variable:
+    let mut lang = BosonLang::new_from_buffer("const r = $$ \"echo {nope}\";".as_bytes().to_vec());
+    let program = lang.parser.parse().unwrap();
+    let error = lang.compiler.compile(&program).unwrap_err();
+    assert_eq!(error.message, "Unknown placeholder {nope} in the shell template echo {nope}");
+}
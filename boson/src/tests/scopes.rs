@@ -0,0 +1,78 @@
+use crate::compiler;
+use crate::lexer;
+use crate::parser;
+use crate::types;
+
+
+use compiler::BytecodeCompiler;
+use lexer::LexerAPI;
+use parser::Parser;
+use types::object::Object;
+
+use super::eval;
+
+fn compile(source: &str) -> BytecodeCompiler {
+    let lexer = LexerAPI::new_from_buffer(source.as_bytes().to_vec());
+    let mut parser = Parser::new_from_lexer(lexer);
+    let program = parser.parse().unwrap();
+
+    let mut compiler = BytecodeCompiler::new();
+    let _ = compiler.compile(&program);
+    return compiler;
+}
+
+#[test]
+pub fn block_shadowing() {
+    let result = eval("var x = 10; if (true) { var x = 20; x = x + 1; } x;");
+    assert_eq!(result.unwrap().as_ref(), &Object::Int(10));
+
+    let result = eval("func f(n) { var s = n; if (true) { var s = 5; } return s; } f(3);");
+    assert_eq!(result.unwrap().as_ref(), &Object::Int(3));
+
+    let compiler = compile("var x = 1; if (true) { var x = 2; }");
+    assert_eq!(compiler.warnings.len(), 1);
+
+    // the warning points at the declaration that shadows:
+    let source = "var x = 1;\nif (true) {\n    # comment\n    var x = 2;\n}";
+    let lexer = LexerAPI::new_from_buffer(source.as_bytes().to_vec());
+    let mut parser = Parser::new_from_lexer(lexer);
+    let program = parser.parse().unwrap();
+    let mut compiler = BytecodeCompiler::new();
+    let _ = compiler.compile(&program);
+    assert_eq!(parser.lexer.get_line_and_column(compiler.warnings[0].pos), (4, 5));
+}
+
+#[test]
+pub fn block_variables_are_released() {
+    // names declared in a block are not visible after it:
+    let mut compiler = compile("if (true) { var y = 1; }");
+    assert_eq!(compiler.symbol_table.resolve_symbol("y").is_none(), true);
+
+    // redeclaring in the same block is still an error:
+    let lexer = LexerAPI::new_from_buffer("if (true) { var y = 1; var y = 2; }".as_bytes().to_vec());
+    let mut parser = Parser::new_from_lexer(lexer);
+    compiler = BytecodeCompiler::new();
+    assert_eq!(compiler.compile(&parser.parse().unwrap()).is_err(), true);
+}
+
+#[test]
+pub fn loop_blocks() {
+    // loop bodies do not leak values on the data stack:
+    let result = eval("var i = 0; while (i < 30000) { i = i + 1; var t = i; } i;");
+    assert_eq!(result.unwrap().as_ref(), &Object::Int(30000));
+
+    let source = "
+        func sum(arr) {
+            var s = 0;
+            for k in arr => {
+                if (k == 2) { var skip = k; continue; }
+                if (k == 4) { break; }
+                s = s + k;
+            }
+            return s;
+        }
+        sum([1, 2, 3, 4, 5]);
+    ";
+    let result = eval(source);
+    assert_eq!(result.unwrap().as_ref(), &Object::Int(4));
+}
@@ -0,0 +1,160 @@
+use crate::api;
+use crate::types;
+
+use std::cell::Cell;
+
+use api::BosonLang;
+use types::object::Value;
+
+use super::eval;
+
+thread_local! {
+    // the clock of the platform in the timer tests, in microseconds:
+    static FAKE_NOW: Cell<u64> = const { Cell::new(0) };
+}
+
+fn fake_unix_time() -> Result<f64, String> {
+    return Ok(FAKE_NOW.with(|now| now.get()) as f64 / 1e6);
+}
+
+// sleeping moves the clock forward at once:
+fn fake_sleep(duration_ms: &f64) {
+    let duration_us = (*duration_ms * 1000.0).ceil().max(1.0) as u64;
+    FAKE_NOW.with(|now| now.set(now.get() + duration_us));
+}
+
+// a sleep that wakes up one millisecond late, like a busy system:
+fn late_sleep(duration_ms: &f64) {
+    fake_sleep(&(*duration_ms + 1.0));
+}
+
+// runs the source with the fake clock, starting at 1000 seconds:
+fn eval_fake_clock(source: &str) -> Option<Value> {
+    FAKE_NOW.with(|now| now.set(1_000_000_000));
+    let mut lang = BosonLang::new_from_buffer(source.as_bytes().to_vec());
+    lang.platform.get_unix_time = fake_unix_time;
+    lang.platform.sleep = fake_sleep;
+    return lang.eval_state();
+}
+
+#[test]
+pub fn tasks_interleave() {
+    // the tasks take their turns at the yield points, in the order they were spawned:
+    let source = "
+        var log = \"\";
+        func worker(name) {
+            var i = 0;
+            while (i < 3) {
+                log = log + name + string(i) + \" \";
+                yield();
+                i = i + 1;
+            }
+            return name;
+        }
+        const a = spawn(worker, [\"a\"]);
+        const b = spawn(worker, [\"b\"], \"second\");
+        const names = [await(a), await(b), b.name, b.id];
+        [names, log];
+    ";
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "Array([Array([a, b, second, 2]), a0 b0 a1 b1 a2 b2 ])"
+    );
+
+    // sleeping tasks wake up in the order of their wake times:
+    let source = "
+        var log = \"\";
+        func nap(ms) { sleep_ms(ms); log = log + string(ms) + \" \"; return ms; }
+        const tasks = [spawn(nap, [60]), spawn(nap, [20]), spawn(nap, [40])];
+        const last = await(tasks[0]);
+        [last, log];
+    ";
+    assert_eq!(eval(source).unwrap().describe(), "Array([60, 20 40 60 ])");
+}
+
+#[test]
+pub fn tasks_channels_and_errors() {
+    // a task waiting on a channel lets the other tasks run:
+    let source = "
+        const ends = channel(1);
+        const tx = ends[0];
+        const rx = ends[1];
+        func producer(n) {
+            var i = 0;
+            while (i < n) { tx.send(i); i = i + 1; }
+            tx.close();
+            return n;
+        }
+        func consumer(n) { var total = 0; for v in rx => { total = total + v; } return total; }
+        const c = spawn(consumer, [0]);
+        const p = spawn(producer, [5]);
+        [await(c), await(p)];
+    ";
+    assert_eq!(eval(source).unwrap().describe(), "Array([10, 5])");
+
+    // the error of a task is thrown by await, the tasks left run after the program:
+    let source = "
+        var log = \"\";
+        func fails(x) { throw \"bad \" + string(x); }
+        func late(x) { log = log + \"late\"; return x; }
+        const f = spawn(fails, [1]);
+        func bad() { const a = []; return a[0]; }
+        try { await(f); } catch e { log = e.kind + \" \" + e.message + \" \"; }
+        try { await(spawn(bad, [])); } catch e { log = log + e.kind + \" \"; }
+        spawn(late, [2]);
+        const done = is_done(f);
+        [done, log];
+    ";
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "Array([true, UserException bad 1 IndexError ])"
+    );
+}
+
+#[test]
+pub fn timers() {
+    // the timers fire in the order of their times, on the clock of the platform:
+    let source = "
+        var log = \"\";
+        var ticks = 0;
+        var iv = 0;
+        const start = unix_time();
+        func stamp(name) {
+            log = log + name + \"@\" + string(int((unix_time() - start) * 1000)) + \" \";
+        }
+        func tick(name) {
+            ticks = ticks + 1;
+            stamp(name);
+            if (ticks == 3) { clear_timer(iv); }
+        }
+        iv = set_interval(tick, 100, [\"tick\"]);
+        set_timeout(stamp, 250, [\"timeout\"]);
+        const cleared = set_timeout(stamp, 120, [\"cleared\"]);
+        set_timeout(stamp, 2000, [\"after\"]);
+        const first_clear = clear_timer(cleared);
+        const second_clear = clear_timer(cleared);
+        sleep_ms(50);
+        stamp(\"main\");
+        sleep_ms(450);
+        [log, first_clear, second_clear];
+    ";
+    assert_eq!(
+        eval_fake_clock(source).unwrap().describe(),
+        "Array([main@50 tick@100 tick@200 timeout@250 tick@300 , true, false])"
+    );
+
+    // the event loop ran until the last timer fired, without waiting for real:
+    let now = FAKE_NOW.with(|now| now.get());
+    assert!(now >= 1_000_000_000 + 2_000_000 && now < 1_000_000_000 + 2_100_000);
+}
+
+#[test]
+pub fn sleep_drift() {
+    // the sleep ends at its deadline however late each of its slices wakes up:
+    FAKE_NOW.with(|now| now.set(1_000_000_000));
+    let source = "const t = unix_time(); sleep_ms(100); sleep_sec(0.2); int((unix_time() - t) * 1000);";
+    let mut lang = BosonLang::new_from_buffer(source.as_bytes().to_vec());
+    lang.platform.get_unix_time = fake_unix_time;
+    lang.platform.sleep = late_sleep;
+    assert_eq!(lang.eval_state().unwrap().describe(), "302");
+}
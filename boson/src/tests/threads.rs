@@ -0,0 +1,359 @@
+use crate::types;
+
+
+use types::object::Object;
+
+use super::eval;
+
+#[test]
+pub fn thread_copies() {
+    // every thread works on its own copy of the arguments and the globals:
+    let source = "
+        var shared = {\"a\": [1, 2, 3], \"n\": 0};
+        func worker(data, k) {
+            var arr = data[\"a\"];
+            arr[0] = k;
+            shared[\"n\"] = k;
+            var s = 0;
+            for x in arr => { s = s + x; }
+            return s;
+        }
+        var handles = create_array(16);
+        var i = 0;
+        while (i < 16) { handles[i] = thread worker(shared, i); i = i + 1; }
+        var total = 0;
+        i = 0;
+        while (i < 16) { total = total + wait(handles[i]); i = i + 1; }
+        [total, shared[\"a\"][0], shared[\"n\"]];
+    ";
+    assert_eq!(eval(source).unwrap().describe(), "Array([200, 1, 0])");
+
+    // closures keep their captured variables, and the cycles through them:
+    let source = "
+        func make_counter(start) {
+            var n = start;
+            func inc(k) { n = n + k; if (k > 1) { return inc(k - 1); } return n; }
+            return inc;
+        }
+        func spawn_counter(s) { return make_counter(s); }
+        func apply(f) { return f(2); }
+        const c = wait(thread spawn_counter(10));
+        const local = make_counter(100);
+        [c(3), c(1), wait(thread apply(local)), local(1)];
+    ";
+    assert_eq!(eval(source).unwrap().describe(), "Array([16, 17, 103, 101])");
+
+    // the local variables of a running function go with the closures that capture them:
+    let source = "
+        func run() {
+            var x = 5;
+            func get() { return x; }
+            func add(y) { return x + y; }
+            const p = pool(1);
+            const submitted = p.submit(add, [2]);
+            const ch = channel();
+            const tx = ch[0];
+            const rx = ch[1];
+            tx.send(get);
+            const received = rx.recv();
+            const results = [
+                await(call_async(get, [])),
+                wait(call_thread(get, [])),
+                parallel_map(add, [1, 2], 2),
+                await(submitted),
+                wait(thread add(3)),
+                received()
+            ];
+            p.close();
+            return results;
+        }
+        run();
+    ";
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "Array([5, 5, Array([6, 7]), 7, 8, 5])"
+    );
+}
+
+#[test]
+pub fn thread_stress() {
+    // many threads at once, each with large nested arguments and results:
+    let source = "
+        func build(n) {
+            var arr = create_array(n, 0);
+            var i = 0;
+            while (i < n) { arr[i] = {\"v\": [i, string(i)]}; i = i + 1; }
+            return arr;
+        }
+        func sum(data, k) {
+            var s = 0;
+            for item in data => { s = s + item[\"v\"][0]; }
+            return [s + k, build(100)];
+        }
+        const data = build(2000);
+        var round = 0;
+        var ok = true;
+        while (round < 4) {
+            var handles = create_array(32);
+            var i = 0;
+            while (i < 32) { handles[i] = thread sum(data, i); i = i + 1; }
+            i = 0;
+            while (i < 32) {
+                const result = wait(handles[i]);
+                ok = ok && result[0] == 1999000 + i && len(result[1]) == 100;
+                i = i + 1;
+            }
+            round = round + 1;
+        }
+        ok;
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Bool(true));
+}
+
+#[test]
+pub fn channels() {
+    // values stream from a producer and the loop ends when it closes:
+    let source = "
+        const ch = channel();
+        const tx = ch[0];
+        const rx = ch[1];
+        func producer(out, n) {
+            var i = 0;
+            while (i < n) { out.send({\"sq\": i * i}); i = i + 1; }
+            out.close();
+            return n;
+        }
+        const p = thread producer(tx, 50);
+        var total = 0;
+        for msg in rx => { total = total + msg[\"sq\"]; }
+        var log = \"\";
+        try { rx.recv(); } catch e { log = e.message; }
+        [total, wait(p), rx.is_closed(), log];
+    ";
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "Array([40425, 50, true, recv() on a closed channel])"
+    );
+
+    // a bounded channel blocks the sender until the consumer catches up:
+    let source = "
+        const ch = channel(2);
+        const tx = ch[0];
+        const rx = ch[1];
+        func consumer(input) {
+            sleep_ms(20);
+            var s = 0;
+            while (true) {
+                const v = input.recv_timeout(200);
+                if (v == none) { return s; }
+                s = s + v;
+            }
+        }
+        const c = thread consumer(rx);
+        var k = 1;
+        while (k <= 100) { tx.send(k); k = k + 1; }
+        [wait(c), rx.try_recv() == none, tx.len()];
+    ";
+    assert_eq!(eval(source).unwrap().describe(), "Array([5050, true, 0])");
+
+    // many producers on one channel:
+    let source = "
+        const ch = channel(8);
+        const rx = ch[1];
+        func producer(out, id) {
+            var i = 0;
+            while (i < 100) { out.send(id); i = i + 1; }
+            return id;
+        }
+        var handles = create_array(8);
+        var i = 0;
+        while (i < 8) { handles[i] = thread producer(ch[0], i); i = i + 1; }
+        var sum = 0;
+        var n = 0;
+        while (n < 800) { sum = sum + rx.recv(); n = n + 1; }
+        i = 0;
+        while (i < 8) { wait(handles[i]); i = i + 1; }
+        sum;
+    ";
+    assert_eq!(eval(source).unwrap().as_ref(), &Object::Int(2800));
+}
+
+#[test]
+pub fn shared_state() {
+    // every worker updates the same objects:
+    let source = "
+        const counter = atomic_int();
+        const seen = shared_map();
+        const total = mutex(0);
+        func bump(v) { if (v == none) { return 1; } return v + 1; }
+        func worker(id, counter, seen, total) {
+            var i = 0;
+            while (i < 200) {
+                counter.incr();
+                seen.update(i % 10, bump);
+                i = i + 1;
+            }
+            total.lock(lambda v => v + id);
+            return id;
+        }
+        var handles = create_array(4);
+        var i = 0;
+        while (i < 4) { handles[i] = thread worker(i + 1, counter, seen, total); i = i + 1; }
+        i = 0;
+        while (i < 4) { wait(handles[i]); i = i + 1; }
+        [counter.get(), seen.get(3), seen.len(), total.get()];
+    ";
+    assert_eq!(eval(source).unwrap().describe(), "Array([800, 80, 10, 10])");
+
+    let source = "
+        const n = atomic_int(5);
+        const m = shared_map();
+        m.set(\"a\", [1, 2]);
+        const lock = mutex(1);
+        var log = \"\";
+        try { m.set([1], 2); } catch e { log = e.message; }
+        try { lock.lock(lambda v => lock.get()); } catch e { log = log + \"; \" + e.message; }
+        [n.add(10), n.compare_and_swap(15, 0), n.swap(7), n.decr(), m.remove(\"a\"),
+         m.contains(\"a\"), lock.is_locked(), log];
+    ";
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "Array([15, true, 0, 6, Array([1, 2]), false, false, \
+         Keys of a shared map must be int, bool, char, string or raw, got array; \
+         AttributeError: Lock is already held by this thread at 0, Instruction: Some(ICallAttr)])"
+    );
+}
+
+#[test]
+pub fn futures() {
+    // the calls run at the same time and the futures are returned at once:
+    let source = "
+        func slow(n) { sleep_ms(n); return n; }
+        const t1 = unix_time();
+        const a = async slow(200);
+        const b = call_async(slow, [200]);
+        const started = is_done(a) || is_done(b);
+        const results = join_all([a, b]);
+        [started, results, unix_time() - t1 < 0.35, await(a), is_done(b)];
+    ";
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "Array([false, Array([200, 200]), true, 200, true])"
+    );
+
+    // select returns the first future to finish:
+    let source = "
+        func slow(n) { sleep_ms(n); return n * 2; }
+        select([async slow(500), async slow(10), async slow(400)]);
+    ";
+    assert_eq!(eval(source).unwrap().describe(), "Array([1, 20])");
+
+    // the error of the call is thrown by await with its own kind:
+    let source = "
+        func fail(n) { throw \"boom \" + string(n); }
+        func divide(n) { return n / 0; }
+        var log = \"\";
+        try { await(async fail(1)); } catch e { log = e.kind + \" \" + e.message; }
+        try { join_all([async fail(2)]); } catch e { log = log + \"; \" + e.kind + \" \" + e.message; }
+        try { select([async divide(3)]); } catch e { log = log + \"; \" + e.kind; }
+        log;
+    ";
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "UserException boom 1; UserException boom 2; DivideByZeroError"
+    );
+}
+
+#[test]
+pub fn thread_management() {
+    // a named thread, waited for with a timeout:
+    let source = "
+        func slow(n) { sleep_ms(n); const me = current_thread(); return me.name; }
+        const t = call_thread(slow, [300], \"sleeper\");
+        const early = wait(t, 20);
+        const alive = is_alive(t);
+        const result = wait(t, 2000);
+        const main = current_thread();
+        [t.name, t.id > 0, early == none, alive, result, is_alive(t), thread_id(), main.name];
+    ";
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "Array([sleeper, true, true, true, sleeper, false, 0, main])"
+    );
+
+    // a cancelled thread gets a ThreadKillError, it can catch it or stop:
+    let source = "
+        func spin(n) {
+            var caught = \"\";
+            try {
+                while (true) { n = n + 1; }
+            } catch e { caught = e.kind; }
+            return caught;
+        }
+        func forever(n) { while (true) { n = n + 1; } }
+        func bad(n) { const a = [1]; return a[n]; }
+        const s = thread spin(1);
+        const f = thread forever(1);
+        sleep_ms(10);
+        cancel(s);
+        cancel(f);
+        var log = \"\";
+        try { wait(f); } catch e { log = e.kind + \" \" + e.message; }
+        try { wait(thread bad(5)); } catch e { log = log + \"; \" + e.kind; }
+        const caught = wait(s);
+        [caught, log, is_alive(f)];
+    ";
+    // wait throws the error of the thread with its own kind:
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "Array([ThreadKillError, ThreadKillError Thread was cancelled; IndexError, false])"
+    );
+}
+
+#[test]
+pub fn thread_pool() {
+    // the calls submitted to a pool run on its workers:
+    let source = "
+        func square(x) { sleep_ms(20); return x * x; }
+        func who(x) { const me = current_thread(); return me.name; }
+        const p = pool(2);
+        const futures = [p.submit(square, [3]), p.submit(square, [4]), p.submit(square, [5])];
+        const results = join_all(futures);
+        const worker = await(p.submit(who, [1]));
+        const on_worker = worker == \"pool-worker-0\" || worker == \"pool-worker-1\";
+        const size = p.size();
+        p.close();
+        var log = \"\";
+        try { p.submit(square, [1]); } catch e { log = e.message; }
+        [results, on_worker, size, p.pending(), log];
+    ";
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "Array([Array([9, 16, 25]), true, 2, 0, submit() on a closed pool])"
+    );
+
+    // parallel_map keeps the order of the elements:
+    let source = "
+        func slow(x) { sleep_ms(40 - x * 10); return x * 2; }
+        [parallel_map(slow, [1, 2, 3, 4], 4), parallel_map(slow, [], 2)];
+    ";
+    assert_eq!(
+        eval(source).unwrap().describe(),
+        "Array([Array([2, 4, 6, 8]), Array([])])"
+    );
+
+    // the workers start with the globals as they are when the pool is created,
+    // packed once for all the jobs:
+    let source = "
+        var scale = 3;
+        func scaled(x) { return x * scale; }
+        const p = pool(2);
+        scale = 5;
+        const first = await(p.submit(scaled, [1]));
+        p.close();
+        const big = create_array(4000, 2);
+        const mapped = parallel_map(scaled, big, 8);
+        [first, len(mapped), mapped[0], mapped[3999]];
+    ";
+    assert_eq!(eval(source).unwrap().describe(), "Array([3, 4000, 10, 10])");
+}
@@ -3,9 +3,9 @@ use crate::types;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::mem;
-use std::rc::Rc;
 
 use types::object::Object;
+use types::object::Value;
 
 #[test]
 pub fn truthy() {
@@ -28,21 +28,21 @@ pub fn truthy() {
     let float_object = Object::Float(1.41);
     assert_eq!(float_object.is_true(), true);
 
-    let string_object = Object::Str("Hi!".to_string());
+    let string_object = Object::Str(Box::new("Hi!".to_string()));
     assert_eq!(string_object.is_true(), true);
 
-    let empty_string = Object::Str("".to_string());
+    let empty_string = Object::Str(Box::new("".to_string()));
     assert_eq!(empty_string.is_true(), false);
     // array, hash table - filled and empty:
     let mut arr_obj = types::array::Array {
-        elements: vec![Rc::new(Object::Int(10))],
+        elements: vec![Value::new(Object::Int(10))],
         name: "test".to_string(),
     };
 
-    assert_eq!(Object::Array(RefCell::new(arr_obj.clone())).is_true(), true);
+    assert_eq!(Object::Array(Box::new(RefCell::new(arr_obj.clone()))).is_true(), true);
 
     arr_obj.elements = vec![];
-    assert_eq!(Object::Array(RefCell::new(arr_obj)).is_true(), false);
+    assert_eq!(Object::Array(Box::new(RefCell::new(arr_obj))).is_true(), false);
 
     // hash table:
     let mut hash_table = types::hash::HashTable {
@@ -50,39 +50,39 @@ pub fn truthy() {
         name: "test".to_string(),
     };
 
-    hash_table.set(Rc::new(Object::Int(10)), Rc::new(Object::Int(20)));
+    hash_table.set(Value::new(Object::Int(10)), Value::new(Object::Int(20)));
     assert_eq!(
-        Object::HashTable(RefCell::new(hash_table.clone())).is_true(),
+        Object::HashTable(Box::new(RefCell::new(hash_table.clone()))).is_true(),
         true
     );
 
     hash_table.entries = HashMap::new();
-    assert_eq!(Object::HashTable(RefCell::new(hash_table)).is_true(), false);
+    assert_eq!(Object::HashTable(Box::new(RefCell::new(hash_table))).is_true(), false);
 }
 
 #[test]
 pub fn indexing() {
     // check indexing - GET and SET
     let array = types::array::Array {
-        elements: vec![Rc::new(Object::Int(10))],
+        elements: vec![Value::new(Object::Int(10))],
         name: "test".to_string(),
     };
 
-    let mut arr_object = Object::Array(RefCell::new(array));
-    let result = arr_object.get_indexed(&Rc::new(Object::Int(0)));
+    let mut arr_object = Object::Array(Box::new(RefCell::new(array)));
+    let result = arr_object.get_indexed(&Value::new(Object::Int(0)));
     assert_eq!(result.is_ok(), true);
     assert_eq!(*result.unwrap().as_ref(), Object::Int(10));
 
     // out of bounds:
-    let result = arr_object.get_indexed(&Rc::new(Object::Int(1)));
+    let result = arr_object.get_indexed(&Value::new(Object::Int(1)));
     assert_eq!(result.is_err(), true);
 
     // set indexed:
-    let result = arr_object.set_indexed(&Rc::new(Object::Int(0)), Rc::new(Object::Int(20)));
+    let result = arr_object.set_indexed(&Value::new(Object::Int(0)), Value::new(Object::Int(20)));
     assert_eq!(result.is_none(), true);
 
     // set indexed out of bounds:
-    let result = arr_object.set_indexed(&Rc::new(Object::Int(1)), Rc::new(Object::Int(20)));
+    let result = arr_object.set_indexed(&Value::new(Object::Int(1)), Value::new(Object::Int(20)));
     assert_eq!(result.is_some(), true);
 
     // hash map set and get operations:
@@ -91,37 +91,37 @@ pub fn indexing() {
         name: "test".to_string(),
     };
 
-    let mut h_obj = Object::HashTable(RefCell::new(hm));
+    let mut h_obj = Object::HashTable(Box::new(RefCell::new(hm)));
 
     // set:
     let result = h_obj.set_indexed(
-        &Rc::new(Object::Str("Age".to_string())),
-        Rc::new(Object::Int(23)),
+        &Value::new(Object::Str(Box::new("Age".to_string()))),
+        Value::new(Object::Int(23)),
     );
     assert_eq!(result.is_none(), true);
 
     // get
-    let result = h_obj.get_indexed(&Rc::new(Object::Str("Age".to_string())));
+    let result = h_obj.get_indexed(&Value::new(Object::Str(Box::new("Age".to_string()))));
     assert_eq!(result.is_ok(), true);
     assert_eq!(*result.unwrap().as_ref(), Object::Int(23));
 
     // get key error
-    let result = h_obj.get_indexed(&Rc::new(Object::Str("NotAge".to_string())));
+    let result = h_obj.get_indexed(&Value::new(Object::Str(Box::new("NotAge".to_string()))));
     assert_eq!(result.is_err(), true);
 
-    let string_obj = Object::Str("Prasanna".to_string());
-    let result = string_obj.get_indexed(&Rc::new(Object::Int(3)));
+    let string_obj = Object::Str(Box::new("Prasanna".to_string()));
+    let result = string_obj.get_indexed(&Value::new(Object::Int(3)));
     assert_eq!(result.is_ok(), true);
     assert_eq!(*result.unwrap().as_ref(), Object::Char('s'));
 
     // out of bounds:
-    let result = string_obj.get_indexed(&Rc::new(Object::Int(30)));
+    let result = string_obj.get_indexed(&Value::new(Object::Int(30)));
     assert_eq!(result.is_ok(), false);
 
     // unsupported object
     assert_eq!(
         Object::Int(20)
-            .get_indexed(&Rc::new(Object::Int(30)))
+            .get_indexed(&Value::new(Object::Int(30)))
             .is_err(),
         true
     );
@@ -138,35 +138,37 @@ pub fn object_equality() {
     assert_eq!(Object::Float(3.144) != Object::Float(3.140), true);
 
     assert_eq!(
-        Object::Str(String::from("Hey String!")) == Object::Str(String::from("Hey String!")),
+        Object::Str(Box::new(String::from("Hey String!")))
+            == Object::Str(Box::new(String::from("Hey String!"))),
         true
     );
 
     assert_eq!(
-        Object::Str(String::from("Hey String!")) != Object::Str(String::from("Heyy String!")),
+        Object::Str(Box::new(String::from("Hey String!")))
+            != Object::Str(Box::new(String::from("Heyy String!"))),
         true
     );
 
     // arrays:
     assert_eq!(
-        Object::Array(RefCell::new(types::array::Array {
-            elements: vec![Rc::new(Object::Int(10))],
+        Object::Array(Box::new(RefCell::new(types::array::Array {
+            elements: vec![Value::new(Object::Int(10))],
             name: "test".to_string(),
-        })) == Object::Array(RefCell::new(types::array::Array {
-            elements: vec![Rc::new(Object::Int(10))],
+        }))) == Object::Array(Box::new(RefCell::new(types::array::Array {
+            elements: vec![Value::new(Object::Int(10))],
             name: "test2".to_string(),
-        })),
+        }))),
         true
     );
 
     assert_eq!(
-        Object::Array(RefCell::new(types::array::Array {
-            elements: vec![Rc::new(Object::Int(10))],
+        Object::Array(Box::new(RefCell::new(types::array::Array {
+            elements: vec![Value::new(Object::Int(10))],
             name: "test".to_string(),
-        })) != Object::Array(RefCell::new(types::array::Array {
-            elements: vec![Rc::new(Object::Int(10)), Rc::new(Object::Int(3))],
+        }))) != Object::Array(Box::new(RefCell::new(types::array::Array {
+            elements: vec![Value::new(Object::Int(10)), Value::new(Object::Int(3))],
             name: "test".to_string(),
-        })),
+        }))),
         true
     );
 }
@@ -224,3 +226,22 @@ pub fn buffer_endian_encoding() {
     assert_eq!(back_conv.is_ok(), true);
     assert_eq!(back_conv.unwrap(), i64_number);
 }
+
+#[test]
+pub fn inline_values() {
+    // primitives are stored inline and copied, other objects are shared:
+    for obj in vec![Object::Noval, Object::Int(7), Object::Float(1.5), Object::Bool(true)] {
+        let value = Value::new(obj.clone());
+        assert!(matches!(value, Value::Inline(_)));
+        assert_eq!(*value.clone(), obj);
+    }
+
+    let value = Value::new(Object::Str(Box::new("shared".to_string())));
+    let copy = value.clone();
+    match (&value, &copy) {
+        (Value::Shared(a), Value::Shared(b)) => assert!(std::rc::Rc::ptr_eq(a, b)),
+        _ => panic!("strings must be shared"),
+    }
+
+    assert_eq!(mem::size_of::<Value>(), 2 * mem::size_of::<u64>());
+}
@@ -1,13 +1,12 @@
 use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::rc::Rc;
 
-use crate::types::object::Object;
+use crate::types::object::Value;
 
 #[derive(Clone, Debug)]
 pub struct Array {
     pub name: String,
-    pub elements: Vec<Rc<Object>>,
+    pub elements: Vec<Value>,
 }
 
 impl Array {
@@ -19,19 +18,19 @@ impl Array {
         return format!("Array([{}])", values.join(", "));
     }
 
-    pub fn get_values_ref(&self) -> &Vec<Rc<Object>> {
+    pub fn get_values_ref(&self) -> &Vec<Value> {
         return &self.elements;
     }
 
-    pub fn get_values(&self) -> Vec<Rc<Object>> {
+    pub fn get_values(&self) -> Vec<Value> {
         return self.elements.clone();
     }
 
-    pub fn get_sliced(&self, start: usize, end: usize) -> Vec<Rc<Object>> {
+    pub fn get_sliced(&self, start: usize, end: usize) -> Vec<Value> {
         return self.elements[start..end].to_vec();
     }
 
-    pub fn set_object(&mut self, pos: usize, obj: Rc<Object>) -> Option<String> {
+    pub fn set_object(&mut self, pos: usize, obj: Value) -> Option<String> {
         if pos >= self.elements.len() {
             return Some(format!("Array index out of range for position {}", pos));
         }
@@ -40,7 +39,7 @@ impl Array {
         return None;
     }
 
-    pub fn get_object(&self, pos: usize) -> Result<Rc<Object>, String> {
+    pub fn get_object(&self, pos: usize) -> Result<Value, String> {
         if pos >= self.elements.len() {
             return Err(format!("Array index out of range for position {}", pos));
         }
@@ -48,7 +47,7 @@ impl Array {
         return Ok(self.elements[pos].clone());
     }
 
-    pub fn get_object_ref(&self, pos: usize) -> Result<&Rc<Object>, String> {
+    pub fn get_object_ref(&self, pos: usize) -> Result<&Value, String> {
         if pos >= self.elements.len() {
             return Err(format!("Array index out of range for position {}", pos));
         }
@@ -3,30 +3,42 @@ use std::collections::HashMap;
 use std::hash::Hash;
 use std::hash::Hasher;
 use std::process;
-use std::rc::Rc;
+use std::time::Duration;
 
 use crate::api;
 use crate::api::BosonLang;
 use crate::compiler;
+use crate::isa::InstructionKind;
 use crate::types::array;
 use crate::types::buffer;
+use crate::types::channel;
+use crate::types::future;
+use crate::types::gc;
 use crate::types::hash;
+use crate::types::heap;
 use crate::types::iter;
-use crate::config;
 use crate::types::object;
+use crate::types::shared;
+use crate::types::shell;
 use crate::vm;
 
 use compiler::symtab::ConstantPool;
+use vm::errors;
 use vm::global::GlobalPool;
 use vm::thread::BosonThreads;
+use vm::tasks::Switch;
+use vm::thread::ThreadCall;
 use vm::thread::ThreadParams;
 use vm::BosonVM;
-use config::ENABLE_CONCURRENCY;
 
 use api::Platform;
 use array::Array;
+use channel::Channel;
+use future::Future;
 use hash::HashTable;
 use object::Object;
+use object::Value;
+use shared::SharedObject;
 
 #[repr(u8)]
 #[derive(PartialEq, Clone, Debug, Eq, Copy)]
@@ -72,11 +84,36 @@ pub enum BuiltinKind {
     SRead,
     Wait,
     BytecodeEval,
+    MemoryUsage,
+    Gc,
+    Channel,
+    SharedMap,
+    AtomicInt,
+    Mutex,
+    Await,
+    JoinAll,
+    Select,
+    IsDone,
+    IsAlive,
+    Cancel,
+    ThreadId,
+    CurrentThread,
+    CallThread,
+    Pool,
+    ParallelMap,
+    Spawn,
+    Yield,
+    SetTimeout,
+    SetInterval,
+    ClearTimer,
+    Process,
+    Shell,
+    ShellRaw,
     EndMark, // the end marker will tell the number of varinats in BuiltinKind, since
              // they are sequential.
 }
 
-fn repr_is_big_endian(args: &Vec<Rc<Object>>) -> bool {
+fn repr_is_big_endian(args: &Vec<Value>) -> bool {
     if args.len() == 2 && args[1].get_type() == "bool" && args[1].is_true() {
         true
     } else {
@@ -84,6 +121,68 @@ fn repr_is_big_endian(args: &Vec<Rc<Object>>) -> bool {
     }
 }
 
+// the size of the file from the platform, if it can tell:
+fn file_size(platform: &Platform, path: &String) -> Option<u64> {
+    let finfo_fn = platform.finfo;
+    let info = finfo_fn(path.clone());
+    if info.is_err() {
+        return None;
+    }
+
+    let info = info.unwrap();
+    let key = Value::new(Object::Str(Box::new("size".to_string())));
+    match info.as_ref() {
+        Object::HashTable(ht) => match ht.borrow().entries.get(&key).map(|size| size.as_ref()) {
+            Some(Object::Int(size)) => return Some(*size as u64),
+            _ => return None,
+        },
+        _ => return None,
+    }
+}
+
+// a sleeping thread wakes up this often to check the limits of the VM:
+const SLEEP_SLICE_MS: f64 = 10.0;
+
+// a task sleeps while the other tasks run, anywhere else the thread sleeps
+// in slices, so a deadline or a cancellation stops it early:
+fn sleep_task(platform: &Platform, th: &mut BosonThreads, duration_ms: f64) -> Result<(), String> {
+    let get_time_fn = platform.get_unix_time;
+    let sleep_fn = platform.sleep;
+
+    let now = get_time_fn();
+    if now.is_err() {
+        // without a clock the sleep cannot be sliced:
+        sleep_fn(&duration_ms);
+        return Ok(());
+    }
+
+    let deadline = now.unwrap() + duration_ms / 1000.0;
+    if th.tasks.switch(Switch::Sleep(deadline)) {
+        return Ok(());
+    }
+
+    // the time left is read from the clock, so the slices do not add up
+    // their scheduling delays:
+    loop {
+        let interruption = th.limits.interrupted(&InstructionKind::ICall);
+        if interruption.is_some() {
+            return Err(interruption.unwrap().message);
+        }
+
+        let now = get_time_fn();
+        if now.is_err() {
+            return Err(now.unwrap_err());
+        }
+
+        let remaining_ms = (deadline - now.unwrap()) * 1000.0;
+        if remaining_ms <= 0.0 {
+            return Ok(());
+        }
+
+        sleep_fn(&remaining_ms.min(SLEEP_SLICE_MS));
+    }
+}
+
 impl BuiltinKind {
     pub fn get_size() -> usize {
         return BuiltinKind::EndMark as usize;
@@ -135,18 +234,43 @@ impl BuiltinKind {
             BuiltinKind::SWrite => "stdout".to_string(),
             BuiltinKind::FRead => "fread".to_string(),
             BuiltinKind::BytecodeEval => "eval_bytecode".to_string(),
+            BuiltinKind::MemoryUsage => "memory_usage".to_string(),
+            BuiltinKind::Gc => "gc".to_string(),
+            BuiltinKind::Channel => "channel".to_string(),
+            BuiltinKind::SharedMap => "shared_map".to_string(),
+            BuiltinKind::AtomicInt => "atomic_int".to_string(),
+            BuiltinKind::Mutex => "mutex".to_string(),
+            BuiltinKind::Await => "await".to_string(),
+            BuiltinKind::JoinAll => "join_all".to_string(),
+            BuiltinKind::Select => "select".to_string(),
+            BuiltinKind::IsDone => "is_done".to_string(),
+            BuiltinKind::IsAlive => "is_alive".to_string(),
+            BuiltinKind::Cancel => "cancel".to_string(),
+            BuiltinKind::ThreadId => "thread_id".to_string(),
+            BuiltinKind::CurrentThread => "current_thread".to_string(),
+            BuiltinKind::CallThread => "call_thread".to_string(),
+            BuiltinKind::Pool => "pool".to_string(),
+            BuiltinKind::ParallelMap => "parallel_map".to_string(),
+            BuiltinKind::Spawn => "spawn".to_string(),
+            BuiltinKind::Yield => "yield".to_string(),
+            BuiltinKind::SetTimeout => "set_timeout".to_string(),
+            BuiltinKind::SetInterval => "set_interval".to_string(),
+            BuiltinKind::ClearTimer => "clear_timer".to_string(),
+            BuiltinKind::Process => "process".to_string(),
+            BuiltinKind::Shell => "sh".to_string(),
+            BuiltinKind::ShellRaw => "sh_raw".to_string(),
             _ => "undef".to_string(),
         }
     }
 
     pub fn exec(
         &self,
-        args: Vec<Rc<Object>>,
+        args: Vec<Value>,
         platform: &Platform,
         gp: &mut GlobalPool,
         c: &mut ConstantPool,
         th: &mut BosonThreads,
-    ) -> Result<Rc<Object>, String> {
+    ) -> Result<Value, String> {
         match self {
             BuiltinKind::Print => {
                 if args.len() == 0 {
@@ -166,7 +290,7 @@ impl BuiltinKind {
                 let print_fn = platform.print;
                 print_fn(&fmt_string);
 
-                return Ok(Rc::new(Object::Noval));
+                return Ok(Value::new(Object::Noval));
             }
 
             BuiltinKind::Println => {
@@ -188,7 +312,7 @@ impl BuiltinKind {
                 let print_fn = platform.print;
                 print_fn(&fmt_string);
 
-                return Ok(Rc::new(Object::Noval));
+                return Ok(Value::new(Object::Noval));
             }
 
             BuiltinKind::Truthy => {
@@ -200,7 +324,7 @@ impl BuiltinKind {
                     ));
                 }
 
-                return Ok(Rc::new(Object::Bool(args[0].as_ref().is_true())));
+                return Ok(Value::new(Object::Bool(args[0].as_ref().is_true())));
             }
 
             BuiltinKind::Length => {
@@ -210,12 +334,12 @@ impl BuiltinKind {
 
                 let obj = args[0].as_ref();
                 match obj {
-                    Object::Str(st) => Ok(Rc::new(Object::Int(st.len() as i64))),
+                    Object::Str(st) => Ok(Value::new(Object::Int(st.len() as i64))),
                     Object::Array(arr) => {
-                        Ok(Rc::new(Object::Int(arr.borrow().elements.len() as i64)))
+                        Ok(Value::new(Object::Int(arr.borrow().elements.len() as i64)))
                     }
                     Object::HashTable(ht) => {
-                        Ok(Rc::new(Object::Int(ht.borrow().entries.len() as i64)))
+                        Ok(Value::new(Object::Int(ht.borrow().entries.len() as i64)))
                     }
                     _ => Err(format!("len() cannot be applied on {}", obj.get_type())),
                 }
@@ -237,10 +361,14 @@ impl BuiltinKind {
                     ));
                 }
 
+                // the evaluated code runs under the limits of the caller:
                 let buffer = obj.describe().as_bytes().to_vec();
-                let result = BosonLang::eval_buffer(buffer);
+                let mut lang = BosonLang::new_from_buffer(buffer);
+                lang.set_config(th.config.clone());
+                lang.set_limits(th.limits.clone());
+                let result = lang.eval_state();
                 if result.is_none() {
-                    return Ok(Rc::new(Object::Noval));
+                    return Ok(Value::new(Object::Noval));
                 }
 
                 return Ok(result.unwrap());
@@ -257,13 +385,13 @@ impl BuiltinKind {
                 let all_builtins = BuiltinKind::get_names();
                 let mut strings = vec![];
                 for name in all_builtins {
-                    strings.push(Rc::new(Object::Str(name.clone())));
+                    strings.push(Value::new(Object::Str(Box::new(name.clone()))));
                 }
 
-                return Ok(Rc::new(Object::Array(RefCell::new(Array {
+                return Ok(Value::new(Object::Array(Box::new(RefCell::new(Array {
                     name: "todo".to_string(),
                     elements: strings,
-                }))));
+                })))));
             }
 
             BuiltinKind::Disasm => {
@@ -286,10 +414,10 @@ impl BuiltinKind {
                 let buffer = obj.describe().as_bytes().to_vec();
                 let output_result = BosonLang::disasm_buffer(buffer);
                 if output_result.is_none() {
-                    return Ok(Rc::new(Object::Noval));
+                    return Ok(Value::new(Object::Noval));
                 }
 
-                return Ok(Rc::new(Object::Str(output_result.unwrap())));
+                return Ok(Value::new(Object::Str(Box::new(output_result.unwrap()))));
             }
 
             BuiltinKind::TimeUnix => {
@@ -308,7 +436,7 @@ impl BuiltinKind {
                 }
 
                 let epoch_time = epoch_time_res.unwrap();
-                return Ok(Rc::new(Object::Float(epoch_time)));
+                return Ok(Value::new(Object::Float(epoch_time)));
             }
 
             BuiltinKind::Args => {
@@ -328,7 +456,7 @@ impl BuiltinKind {
                 };
 
                 args_array.elements = args;
-                return Ok(Rc::new(Object::Array(RefCell::new(args_array))));
+                return Ok(Value::new(Object::Array(Box::new(RefCell::new(args_array)))));
             }
 
             BuiltinKind::Exit => {
@@ -373,11 +501,11 @@ impl BuiltinKind {
                         // default value is provided, return it
                         return Ok(args[1].clone());
                     }
-                    return Ok(Rc::new(Object::Noval));
+                    return Ok(Value::new(Object::Noval));
                 }
 
                 let env_value = env_value_res.unwrap();
-                return Ok(Rc::new(Object::Str(env_value)));
+                return Ok(Value::new(Object::Str(Box::new(env_value))));
             }
 
             BuiltinKind::Envs => {
@@ -395,10 +523,13 @@ impl BuiltinKind {
                     entries: HashMap::new(),
                 };
                 for (key, value) in envs {
-                    env_table.set(Rc::new(Object::Str(key)), Rc::new(Object::Str(value)));
+                    env_table.set(
+                        Value::new(Object::Str(Box::new(key))),
+                        Value::new(Object::Str(Box::new(value))),
+                    );
                 }
 
-                return Ok(Rc::new(Object::HashTable(RefCell::new(env_table))));
+                return Ok(Value::new(Object::HashTable(Box::new(RefCell::new(env_table)))));
             }
 
             BuiltinKind::CreateArray => {
@@ -413,11 +544,18 @@ impl BuiltinKind {
                 match args[0].as_ref() {
                     Object::Int(i) => {
                         let to_fill = if args_len == 1 {
-                            Rc::new(Object::Noval)
+                            Value::new(Object::Noval)
                         } else {
                             args[1].clone()
                         };
 
+                        // the elements are allocated at once, so check they fit first:
+                        let size = (*i as usize).saturating_mul(std::mem::size_of::<Value>());
+                        let reserved = heap::reserve(size);
+                        if reserved.is_err() {
+                            return Err(reserved.unwrap_err());
+                        }
+
                         // create a vector
                         let mut arr_vec = vec![];
                         arr_vec.resize(*i as usize, to_fill);
@@ -427,7 +565,7 @@ impl BuiltinKind {
                             elements: arr_vec,
                         };
 
-                        return Ok(Rc::new(Object::Array(RefCell::new(arr_type))));
+                        return Ok(Value::new(Object::Array(Box::new(RefCell::new(arr_type)))));
                     }
                     _ => {
                         return Err(format!(
@@ -455,21 +593,21 @@ impl BuiltinKind {
                 };
 
                 platform_table.set(
-                    Rc::new(Object::Str("arch".to_string())),
-                    Rc::new(Object::Str(platform_info_vec[0].clone())),
+                    Value::new(Object::Str(Box::new("arch".to_string()))),
+                    Value::new(Object::Str(Box::new(platform_info_vec[0].clone()))),
                 );
 
                 platform_table.set(
-                    Rc::new(Object::Str("family".to_string())),
-                    Rc::new(Object::Str(platform_info_vec[1].clone())),
+                    Value::new(Object::Str(Box::new("family".to_string()))),
+                    Value::new(Object::Str(Box::new(platform_info_vec[1].clone()))),
                 );
 
                 platform_table.set(
-                    Rc::new(Object::Str("os".to_string())),
-                    Rc::new(Object::Str(platform_info_vec[2].clone())),
+                    Value::new(Object::Str(Box::new("os".to_string()))),
+                    Value::new(Object::Str(Box::new(platform_info_vec[2].clone()))),
                 );
 
-                return Ok(Rc::new(Object::HashTable(RefCell::new(platform_table))));
+                return Ok(Value::new(Object::HashTable(Box::new(RefCell::new(platform_table)))));
             }
 
             // Get type:
@@ -482,7 +620,7 @@ impl BuiltinKind {
                 }
 
                 let t_str = args[0].as_ref().get_type();
-                return Ok(Rc::new(Object::Str(t_str)));
+                return Ok(Value::new(Object::Str(Box::new(t_str))));
             }
 
             // Conversion functions:
@@ -501,11 +639,11 @@ impl BuiltinKind {
                             return Err(result_str.unwrap_err());
                         }
 
-                        return Ok(Rc::new(Object::Str(result_str.unwrap())));
+                        return Ok(Value::new(Object::Str(Box::new(result_str.unwrap()))));
                     }
                     _ => {
                         let result_str = args[0].as_ref().describe();
-                        return Ok(Rc::new(Object::Str(result_str)));
+                        return Ok(Value::new(Object::Str(Box::new(result_str))));
                     }
                 }
             }
@@ -520,7 +658,7 @@ impl BuiltinKind {
 
                 match args[0].as_ref() {
                     Object::Int(i) => {
-                        return Ok(Rc::new(Object::Int(*i)));
+                        return Ok(Value::new(Object::Int(*i)));
                     }
 
                     Object::Str(st) => {
@@ -530,13 +668,13 @@ impl BuiltinKind {
                         }
 
                         let result_i64 = result.unwrap();
-                        return Ok(Rc::new(Object::Int(result_i64)));
+                        return Ok(Value::new(Object::Int(result_i64)));
                     }
 
-                    Object::Byte(byte) => return Ok(Rc::new(Object::Int(*byte as i64))),
+                    Object::Byte(byte) => return Ok(Value::new(Object::Int(*byte as i64))),
 
                     Object::Float(f) => {
-                        return Ok(Rc::new(Object::Int(f.round() as i64)));
+                        return Ok(Value::new(Object::Int(f.round() as i64)));
                     }
 
                     Object::ByteBuffer(bytes) => {
@@ -545,16 +683,16 @@ impl BuiltinKind {
                             return Err(i_for_b_res.unwrap_err());
                         }
 
-                        return Ok(Rc::new(Object::Int(i_for_b_res.unwrap())));
+                        return Ok(Value::new(Object::Int(i_for_b_res.unwrap())));
                     }
 
                     Object::Char(c) => {
-                        return Ok(Rc::new(Object::Int(*c as i64)));
+                        return Ok(Value::new(Object::Int(*c as i64)));
                     }
 
                     Object::Bool(b) => {
                         let i_for_b = if *b { 1 } else { 0 };
-                        return Ok(Rc::new(Object::Int(i_for_b)));
+                        return Ok(Value::new(Object::Int(i_for_b)));
                     }
 
                     _ => {
@@ -574,7 +712,7 @@ impl BuiltinKind {
                     ));
                 }
 
-                return Ok(Rc::new(Object::Bool(args[0].as_ref().is_true())));
+                return Ok(Value::new(Object::Bool(args[0].as_ref().is_true())));
             }
 
             BuiltinKind::Byte => {
@@ -587,7 +725,7 @@ impl BuiltinKind {
 
                 match args[0].as_ref() {
                     Object::Byte(byte) => {
-                        return Ok(Rc::new(Object::Byte(*byte)));
+                        return Ok(Value::new(Object::Byte(*byte)));
                     }
 
                     Object::Int(i) => {
@@ -595,15 +733,15 @@ impl BuiltinKind {
                             return Err(format!("Integer {} cannot be casted to raw", i));
                         }
 
-                        return Ok(Rc::new(Object::Byte(*i as u8)));
+                        return Ok(Value::new(Object::Byte(*i as u8)));
                     }
 
                     Object::Char(c) => {
-                        return Ok(Rc::new(Object::Byte(*c as u8)));
+                        return Ok(Value::new(Object::Byte(*c as u8)));
                     }
 
                     Object::Bool(b) => {
-                        return Ok(Rc::new(Object::Byte(if *b { 1 as u8 } else { 0 as u8 })));
+                        return Ok(Value::new(Object::Byte(if *b { 1 as u8 } else { 0 as u8 })));
                     }
                     _ => {
                         return Err(format!(
@@ -624,7 +762,7 @@ impl BuiltinKind {
 
                 match args[0].as_ref() {
                     Object::Int(i) => {
-                        return Ok(Rc::new(Object::Float(*i as f64)));
+                        return Ok(Value::new(Object::Float(*i as f64)));
                     }
 
                     Object::Str(st) => {
@@ -634,20 +772,20 @@ impl BuiltinKind {
                         }
 
                         let result_f64 = result.unwrap();
-                        return Ok(Rc::new(Object::Float(result_f64)));
+                        return Ok(Value::new(Object::Float(result_f64)));
                     }
 
                     Object::Float(f) => {
-                        return Ok(Rc::new(Object::Float(*f)));
+                        return Ok(Value::new(Object::Float(*f)));
                     }
 
                     Object::Char(c) => {
-                        return Ok(Rc::new(Object::Float(*c as i64 as f64)));
+                        return Ok(Value::new(Object::Float(*c as i64 as f64)));
                     }
 
                     Object::Bool(b) => {
                         let f_for_b = if *b { 1.0 } else { 0.0 };
-                        return Ok(Rc::new(Object::Float(f_for_b)));
+                        return Ok(Value::new(Object::Float(f_for_b)));
                     }
 
                     Object::ByteBuffer(bytes) => {
@@ -656,7 +794,7 @@ impl BuiltinKind {
                             return Err(f_for_b_res.unwrap_err());
                         }
 
-                        return Ok(Rc::new(Object::Float(f_for_b_res.unwrap())));
+                        return Ok(Value::new(Object::Float(f_for_b_res.unwrap())));
                     }
 
                     _ => {
@@ -681,9 +819,9 @@ impl BuiltinKind {
                             return Err(b_array_res.unwrap_err());
                         }
 
-                        return Ok(Rc::new(Object::ByteBuffer(RefCell::new(
+                        return Ok(Value::new(Object::ByteBuffer(Box::new(RefCell::new(
                             b_array_res.unwrap(),
-                        ))));
+                        )))));
                     }
 
                     Object::Float(f) => {
@@ -693,21 +831,23 @@ impl BuiltinKind {
                             return Err(b_array_res.unwrap_err());
                         }
 
-                        return Ok(Rc::new(Object::ByteBuffer(RefCell::new(
+                        return Ok(Value::new(Object::ByteBuffer(Box::new(RefCell::new(
                             b_array_res.unwrap(),
-                        ))));
+                        )))));
                     }
 
                     Object::Char(c) => {
                         let result_arr =
                             buffer::Buffer::from_u8(vec![*c as u8], "todo".to_string(), true);
 
-                        return Ok(Rc::new(Object::ByteBuffer(RefCell::new(result_arr))));
+                        let buffer_obj = Object::ByteBuffer(Box::new(RefCell::new(result_arr)));
+                        return Ok(Value::new(buffer_obj));
                     }
 
                     Object::Str(st) => {
                         let result_arr = buffer::Buffer::from_string(st);
-                        return Ok(Rc::new(Object::ByteBuffer(RefCell::new(result_arr))));
+                        let buffer_obj = Object::ByteBuffer(Box::new(RefCell::new(result_arr)));
+                        return Ok(Value::new(buffer_obj));
                     }
                     _ => {
                         return Err(format!(
@@ -727,7 +867,7 @@ impl BuiltinKind {
 
                 match args[0].as_ref() {
                     Object::Byte(b) => {
-                        return Ok(Rc::new(Object::Char(*b as char)));
+                        return Ok(Value::new(Object::Char(*b as char)));
                     }
                     _ => {
                         return Err(format!(
@@ -761,12 +901,12 @@ impl BuiltinKind {
                 let op_array = Array {
                     name: "todo".to_string(),
                     elements: vec![
-                        Rc::new(Object::Int(exit_code as i64)),
-                        Rc::new(Object::Str(result_str.unwrap())),
+                        Value::new(Object::Int(exit_code as i64)),
+                        Value::new(Object::Str(Box::new(result_str.unwrap()))),
                     ],
                 };
 
-                return Ok(Rc::new(Object::Array(RefCell::new(op_array))));
+                return Ok(Value::new(Object::Array(Box::new(RefCell::new(op_array)))));
             }
 
             BuiltinKind::ExecRaw => {
@@ -789,12 +929,12 @@ impl BuiltinKind {
                 let op_array = Array {
                     name: "todo".to_string(),
                     elements: vec![
-                        Rc::new(Object::Int(exit_code as i64)),
-                        Rc::new(Object::ByteBuffer(RefCell::new(raw_buffer))),
+                        Value::new(Object::Int(exit_code as i64)),
+                        Value::new(Object::ByteBuffer(Box::new(RefCell::new(raw_buffer)))),
                     ],
                 };
 
-                return Ok(Rc::new(Object::Array(RefCell::new(op_array))));
+                return Ok(Value::new(Object::Array(Box::new(RefCell::new(op_array)))));
             }
 
             BuiltinKind::Iter => {
@@ -806,12 +946,12 @@ impl BuiltinKind {
                 }
 
                 let object_to_iter = args[0].as_ref();
-                let iter_res = iter::ObjectIterator::new(Rc::new(object_to_iter.clone()));
+                let iter_res = iter::ObjectIterator::new(Value::new(object_to_iter.clone()));
                 if iter_res.is_err() {
                     return Err(iter_res.unwrap_err());
                 }
 
-                return Ok(Rc::new(Object::Iter(RefCell::new(iter_res.unwrap()))));
+                return Ok(Value::new(Object::Iter(Box::new(RefCell::new(iter_res.unwrap())))));
             }
 
             BuiltinKind::HasNext => {
@@ -825,8 +965,11 @@ impl BuiltinKind {
                 let obj = args[0].as_ref();
                 match obj {
                     Object::Iter(it) => {
-                        let has_next = it.borrow().has_next();
-                        return Ok(Rc::new(Object::Bool(has_next)));
+                        let has_next = it.borrow_mut().wait_has_next(&th.limits);
+                        if has_next.is_err() {
+                            return Err(has_next.unwrap_err());
+                        }
+                        return Ok(Value::new(Object::Bool(has_next.unwrap())));
                     }
                     _ => {
                         return Err(format!(
@@ -848,7 +991,12 @@ impl BuiltinKind {
                 let obj = args[0].as_ref();
                 match obj {
                     Object::Iter(it) => {
-                        let next_obj = it.borrow_mut().next();
+                        let next_res = it.borrow_mut().wait_next(&th.limits);
+                        if next_res.is_err() {
+                            return Err(next_res.unwrap_err());
+                        }
+
+                        let next_obj = next_res.unwrap();
                         if next_obj.is_none() {
                             return Err(format!("next() called on ended iterator",));
                         }
@@ -874,12 +1022,16 @@ impl BuiltinKind {
 
                 match args[0].as_ref() {
                     Object::Float(duration_f_ms) => {
-                        let sleep_fn = platform.sleep;
-                        sleep_fn(duration_f_ms);
+                        let sleep_result = sleep_task(platform, th, *duration_f_ms);
+                        if sleep_result.is_err() {
+                            return Err(sleep_result.unwrap_err());
+                        }
                     }
                     Object::Int(duration_i_ms) => {
-                        let sleep_fn = platform.sleep;
-                        sleep_fn(&(*duration_i_ms as f64));
+                        let sleep_result = sleep_task(platform, th, *duration_i_ms as f64);
+                        if sleep_result.is_err() {
+                            return Err(sleep_result.unwrap_err());
+                        }
                     }
                     _ => {
                         return Err(format!(
@@ -889,7 +1041,7 @@ impl BuiltinKind {
                     }
                 }
 
-                return Ok(Rc::new(Object::Noval));
+                return Ok(Value::new(Object::Noval));
             }
 
             BuiltinKind::SleepSec => {
@@ -902,12 +1054,16 @@ impl BuiltinKind {
 
                 match args[0].as_ref() {
                     Object::Float(duration_f_s) => {
-                        let sleep_fn = platform.sleep;
-                        sleep_fn(&(*duration_f_s * 1000 as f64));
+                        let sleep_result = sleep_task(platform, th, *duration_f_s * 1000 as f64);
+                        if sleep_result.is_err() {
+                            return Err(sleep_result.unwrap_err());
+                        }
                     }
                     Object::Int(duration_i_ms) => {
-                        let sleep_fn = platform.sleep;
-                        sleep_fn(&((*duration_i_ms * 1000) as f64));
+                        let sleep_result = sleep_task(platform, th, (*duration_i_ms * 1000) as f64);
+                        if sleep_result.is_err() {
+                            return Err(sleep_result.unwrap_err());
+                        }
                     }
                     _ => {
                         return Err(format!(
@@ -917,7 +1073,7 @@ impl BuiltinKind {
                     }
                 }
 
-                return Ok(Rc::new(Object::Noval));
+                return Ok(Value::new(Object::Noval));
             }
 
             BuiltinKind::CallFunc => {
@@ -948,6 +1104,8 @@ impl BuiltinKind {
                             platform,
                             gp.clone(),
                             c.clone(),
+                            &th.config,
+                            &th.limits,
                         );
 
                         if sandbox_result.is_err() {
@@ -978,7 +1136,7 @@ impl BuiltinKind {
 
             BuiltinKind::CallAsync => {
 
-                if ! ENABLE_CONCURRENCY {
+                if !th.config.enable_concurrency {
                     return Err(format!("BosonVM has concurrency disabled."));
                 }
 
@@ -1005,31 +1163,16 @@ impl BuiltinKind {
                         let params = params.borrow().elements.clone();
                         // call the async function
                         let thread_params =
-                            ThreadParams::new(ctx.clone(), params, gp.clone(), c.clone());
+                            ThreadParams::new(ctx.clone(), params, gp, c);
 
-                        let thread_create_res = th.create_thread_sandbox(thread_params, platform);
-                        if thread_create_res.is_err() {
-                            return Err(thread_create_res.unwrap_err());
-                        }
-
-                        // wait for the thread to finish:
-                        let thread_exec_res = th.wait_and_return(thread_create_res.unwrap());
-                        if thread_exec_res.is_err() {
-                            return Err(thread_exec_res.unwrap_err());
-                        }
-
-                        // thread has finished execution successfully:
-                        let sandbox_result = thread_exec_res.unwrap().result;
-                        if sandbox_result.is_err() {
-                            let error = sandbox_result.unwrap_err();
-                            return Err(format!(
-                                "{:?}: {} at {}, Instruction: {:?}",
-                                error.t, error.message, error.pos, error.instruction
-                            ));
+                        let name = ctx.as_ref().compiled_fn.name.clone();
+                        let future_res = th.create_future(thread_params, name, platform);
+                        if future_res.is_err() {
+                            return Err(future_res.unwrap_err());
                         }
 
-                        // return the result object
-                        return Ok(sandbox_result.unwrap());
+                        // the call runs in the background, its future is returned:
+                        return Ok(Value::new(Object::Future(Box::new(future_res.unwrap()))));
                     }
                     _ => {
                         return Err(format!(
@@ -1055,22 +1198,38 @@ impl BuiltinKind {
                     return Err(result.unwrap());
                 }
 
-                return Ok(Rc::new(Object::Str(result.unwrap())));
+                return Ok(Value::new(Object::Str(Box::new(result.unwrap()))));
             }
             BuiltinKind::Wait => {
 
-                if ! ENABLE_CONCURRENCY {
+                if !th.config.enable_concurrency {
                     return Err(format!("BosonVM has concurrency disabled."));
                 }
 
-                if args.len() != 1 {
-                    return Err(format!("wait() takes 1 argument, given {}", args.len()));
+                if args.len() != 1 && args.len() != 2 {
+                    return Err(format!("wait() takes 1 or 2 arguments, given {}", args.len()));
+                }
+
+                // wait(th, timeout_ms) returns none when the thread is still running:
+                let mut timeout = None;
+                if args.len() == 2 {
+                    match args[1].as_ref() {
+                        Object::Int(ms) if *ms >= 0 => {
+                            timeout = Some(Duration::from_millis(*ms as u64))
+                        }
+                        _ => {
+                            return Err(format!(
+                                "wait() takes a positive timeout in ms, provided {}",
+                                args[1].describe()
+                            ));
+                        }
+                    }
                 }
 
                 match args[0].as_ref() {
                     Object::Thread(t_block) => {
                         let thread_id = t_block.borrow().handle_id;
-                        let thread_exec_res = th.wait_and_return(thread_id);
+                        let thread_exec_res = th.wait_and_return(thread_id, timeout);
                         if thread_exec_res.is_err() {
                             return Err(format!(
                                 "{} wait error: {}",
@@ -1079,13 +1238,15 @@ impl BuiltinKind {
                             ));
                         }
 
-                        let sandbox_result = thread_exec_res.unwrap().result;
+                        let thread_result = thread_exec_res.unwrap();
+                        if thread_result.is_none() {
+                            return Ok(Value::new(Object::Noval));
+                        }
+
+                        // the error of the thread is thrown with its own kind:
+                        let sandbox_result = thread_result.unwrap().get_result();
                         if sandbox_result.is_err() {
-                            let error = sandbox_result.unwrap_err();
-                            return Err(format!(
-                                "{:?}: {} at {}, Instruction: {:?}",
-                                error.t, error.message, error.pos, error.instruction
-                            ));
+                            return Err(errors::carry_error(sandbox_result.unwrap_err()));
                         }
 
                         return Ok(sandbox_result.unwrap());
@@ -1099,6 +1260,190 @@ impl BuiltinKind {
                 }
             }
 
+            BuiltinKind::IsAlive | BuiltinKind::Cancel => {
+                let name = self.desribe();
+                if args.len() != 1 {
+                    return Err(format!("{}() takes one argument, {} provided", name, args.len()));
+                }
+
+                match args[0].as_ref() {
+                    Object::Thread(t_block) => {
+                        let t_block = t_block.borrow();
+                        if *self == BuiltinKind::IsAlive {
+                            return Ok(Value::new(Object::Bool(th.is_alive(t_block.handle_id))));
+                        }
+
+                        // the thread stops at its next check with a ThreadKillError:
+                        t_block.kill.cancel();
+                        return Ok(Value::new(Object::Noval));
+                    }
+                    _ => {
+                        return Err(format!(
+                            "{}() takes thread as argument, provided {}",
+                            name,
+                            args[0].get_type()
+                        ));
+                    }
+                }
+            }
+
+            BuiltinKind::ThreadId | BuiltinKind::CurrentThread => {
+                if args.len() != 0 {
+                    return Err(format!(
+                        "{}() takes zero arguments, {} provided",
+                        self.desribe(),
+                        args.len()
+                    ));
+                }
+
+                let current = th.current_thread();
+                if *self == BuiltinKind::ThreadId {
+                    return Ok(Value::new(Object::Int(current.handle_id as i64)));
+                }
+                return Ok(Value::new(Object::Thread(Box::new(RefCell::new(current)))));
+            }
+
+            BuiltinKind::CallThread => {
+                if !th.config.enable_concurrency {
+                    return Err(format!("BosonVM has concurrency disabled."));
+                }
+
+                if args.len() != 2 && args.len() != 3 {
+                    return Err(format!(
+                        "call_thread takes 2 or 3 arguments, provided {}.",
+                        args.len()
+                    ));
+                }
+
+                let (ctx, params) = match (args[0].as_ref(), args[1].as_ref()) {
+                    (Object::ClosureContext(ctx), Object::Array(params)) => {
+                        (ctx.clone(), params.borrow().elements.clone())
+                    }
+                    _ => {
+                        return Err(format!(
+                            "call_thread takes closure/func and array as arguments, but got {} {}.",
+                            args[0].get_type(),
+                            args[1].get_type()
+                        ));
+                    }
+                };
+
+                let n_parms_required = ctx.as_ref().compiled_fn.as_ref().num_parameters;
+                if n_parms_required != params.len() {
+                    return Err(format!(
+                        "Function {} requires {} parameters, provided {}",
+                        ctx.as_ref().compiled_fn.name,
+                        n_parms_required,
+                        params.len()
+                    ));
+                }
+
+                // the thread is named after its function unless a name is given:
+                let name = match args.get(2).map(|name| name.as_ref()) {
+                    None => ctx.as_ref().compiled_fn.name.clone(),
+                    Some(Object::Str(name)) => name.as_ref().clone(),
+                    Some(_) => {
+                        return Err(format!(
+                            "call_thread takes a string as the thread name, but got {}.",
+                            args[2].get_type()
+                        ));
+                    }
+                };
+
+                let thread_params = ThreadParams::new(ctx, params, gp, c);
+                let create_result = th.create_thread_sandbox(thread_params, name, platform);
+                if create_result.is_err() {
+                    return Err(create_result.unwrap_err());
+                }
+
+                let thread_obj = Object::Thread(Box::new(RefCell::new(create_result.unwrap())));
+                return Ok(Value::new(thread_obj));
+            }
+
+            BuiltinKind::Pool => {
+                if !th.config.enable_concurrency {
+                    return Err(format!("BosonVM has concurrency disabled."));
+                }
+
+                if args.len() != 1 {
+                    return Err(format!("pool() takes one argument, {} provided", args.len()));
+                }
+
+                match args[0].as_ref() {
+                    Object::Int(size) if *size > 0 => {
+                        let pool_result = th.create_pool(*size as usize, platform, gp, c);
+                        if pool_result.is_err() {
+                            return Err(pool_result.unwrap_err());
+                        }
+                        return Ok(Value::new(Object::Pool(Box::new(pool_result.unwrap()))));
+                    }
+                    _ => {
+                        return Err(format!(
+                            "pool() takes a positive number of workers, provided {}",
+                            args[0].describe()
+                        ));
+                    }
+                }
+            }
+
+            BuiltinKind::ParallelMap => {
+                if !th.config.enable_concurrency {
+                    return Err(format!("BosonVM has concurrency disabled."));
+                }
+
+                if args.len() != 3 {
+                    return Err(format!(
+                        "parallel_map() takes 3 arguments, provided {}.",
+                        args.len()
+                    ));
+                }
+
+                let (ctx, elements, workers) =
+                    match (args[0].as_ref(), args[1].as_ref(), args[2].as_ref()) {
+                        (Object::ClosureContext(ctx), Object::Array(arr), Object::Int(n))
+                            if *n > 0 =>
+                        {
+                            (ctx.clone(), arr.borrow().elements.clone(), *n as usize)
+                        }
+                        _ => {
+                            return Err(format!(
+                                "parallel_map() takes a function, an array and a positive number of workers, but got {} {} {}.",
+                                args[0].get_type(),
+                                args[1].get_type(),
+                                args[2].describe()
+                            ));
+                        }
+                    };
+
+                if ctx.compiled_fn.num_parameters != 1 {
+                    return Err(format!(
+                        "Function {} passed to parallel_map() must take one parameter, it takes {}",
+                        ctx.compiled_fn.name, ctx.compiled_fn.num_parameters
+                    ));
+                }
+
+                // no more workers than elements, every worker takes the next
+                // element when it is done with one:
+                let pool_result = th.create_pool(workers.min(elements.len()).max(1), platform, gp, c);
+                if pool_result.is_err() {
+                    return Err(pool_result.unwrap_err());
+                }
+
+                let pool = pool_result.unwrap();
+                let mut futures = vec![];
+                for element in elements {
+                    let call = ThreadCall::new(ctx.clone(), vec![element]);
+                    let submit_result = pool.submit(call, ctx.compiled_fn.name.clone());
+                    if submit_result.is_err() {
+                        return Err(submit_result.unwrap_err());
+                    }
+                    futures.push(submit_result.unwrap());
+                }
+
+                pool.close();
+                return Future::join_all(&futures, &th.limits);
+            }
+
             BuiltinKind::FStat => {
                 if args.len() != 1 {
                     return Err(format!(
@@ -1110,7 +1455,7 @@ impl BuiltinKind {
                 match args[0].as_ref() {
                     Object::Str(path) => {
                         let platform_finfo = platform.finfo;
-                        let result = platform_finfo(path.clone());
+                        let result = platform_finfo(path.to_string());
                         if result.is_err() {
                             return Err(result.unwrap_err());
                         }
@@ -1137,12 +1482,12 @@ impl BuiltinKind {
                 match (args[0].as_ref(), args[1].as_ref()) {
                     (Object::Str(st), Object::ByteBuffer(buffer)) => {
                         let fwrite_fn = platform.fwrite;
-                        let result = fwrite_fn(st.clone(), &buffer.borrow().data);
+                        let result = fwrite_fn(st.to_string(), &buffer.borrow().data);
                         if result.is_err() {
                             return Err(result.unwrap_err());
                         }
 
-                        return Ok(Rc::new(Object::Int(result.unwrap() as i64)));
+                        return Ok(Value::new(Object::Int(result.unwrap() as i64)));
                     }
                     _ => {
                         return Err(format!(
@@ -1165,12 +1510,12 @@ impl BuiltinKind {
                 match (args[0].as_ref(), args[1].as_ref()) {
                     (Object::Str(st), Object::ByteBuffer(buffer)) => {
                         let fappend_fn = platform.fappend;
-                        let result = fappend_fn(st.clone(), &buffer.borrow().data);
+                        let result = fappend_fn(st.to_string(), &buffer.borrow().data);
                         if result.is_err() {
                             return Err(result.unwrap_err());
                         }
 
-                        return Ok(Rc::new(Object::Int(result.unwrap() as i64)));
+                        return Ok(Value::new(Object::Int(result.unwrap() as i64)));
                     }
                     _ => {
                         return Err(format!(
@@ -1188,7 +1533,23 @@ impl BuiltinKind {
                     start: Option<u64>,
                     n: Option<u64>,
                     p: &Platform,
-                ) -> Result<Rc<Object>, String> {
+                ) -> Result<Value, String> {
+                    // the bytes to read are checked against the heap limit before the
+                    // platform reads them, the size of the file gives the whole read:
+                    if heap::get_limit().is_some() {
+                        let size = match n {
+                            Some(n_bytes) => Some(n_bytes),
+                            None => file_size(p, &path).map(|size| size.saturating_sub(start.unwrap_or(0))),
+                        };
+
+                        if size.is_some() {
+                            let reserved = heap::reserve(size.unwrap() as usize);
+                            if reserved.is_err() {
+                                return Err(reserved.unwrap_err());
+                            }
+                        }
+                    }
+
                     let read_fn = p.fread;
                     let result = read_fn(path, start, n);
                     if result.is_err() {
@@ -1196,27 +1557,30 @@ impl BuiltinKind {
                     }
 
                     let (data, nb) = result.unwrap();
+                    let reserved = heap::reserve(data.capacity());
+                    if reserved.is_err() {
+                        return Err(reserved.unwrap_err());
+                    }
+
                     // return the array:
                     let arr = Array {
                         name: "fread_data".to_string(),
                         elements: vec![
-                            Rc::new(Object::Int(nb as i64)),
-                            Rc::new(Object::ByteBuffer(RefCell::new(buffer::Buffer::from_u8(
-                                data,
-                                "fread_data".to_string(),
-                                false,
+                            Value::new(Object::Int(nb as i64)),
+                            Value::new(Object::ByteBuffer(Box::new(RefCell::new(
+                                buffer::Buffer::from_u8(data, "fread_data".to_string(), false),
                             )))),
                         ],
                     };
 
-                    return Ok(Rc::new(Object::Array(RefCell::new(arr))));
+                    return Ok(Value::new(Object::Array(Box::new(RefCell::new(arr)))));
                 }
 
                 if args.len() == 1 {
                     // only the file path is provided
                     match args[0].as_ref() {
                         Object::Str(st) => {
-                            let result = __read(st.clone(), None, None, &platform);
+                            let result = __read(st.to_string(), None, None, &platform);
                             return result;
                         }
                         _ => {
@@ -1229,7 +1593,7 @@ impl BuiltinKind {
                 } else if args.len() == 2 {
                     match (args[0].as_ref(), args[1].as_ref()) {
                         (Object::Str(st), Object::Int(i)) => {
-                            let result = __read(st.clone(), Some(*i as u64), None, &platform);
+                            let result = __read(st.to_string(), Some(*i as u64), None, &platform);
                             return result;
                         }
                         _ => {
@@ -1243,7 +1607,8 @@ impl BuiltinKind {
                 } else if args.len() == 3 {
                     match (args[0].as_ref(), args[1].as_ref(), args[2].as_ref()) {
                         (Object::Str(st), Object::Int(i), Object::Int(j)) => {
-                            let result = __read(st.clone(), Some(*i as u64), Some(*j as u64), &platform);
+                            let result =
+                                __read(st.to_string(), Some(*i as u64), Some(*j as u64), &platform);
                             return result;
                         }
                         _ => {
@@ -1280,7 +1645,7 @@ impl BuiltinKind {
                             return Err(result.unwrap_err());
                         }
 
-                        return Ok(Rc::new(Object::Int(result.unwrap() as i64)));
+                        return Ok(Value::new(Object::Int(result.unwrap() as i64)));
                     }
                     _ => {
                         return Err(format!(
@@ -1308,7 +1673,7 @@ impl BuiltinKind {
 
                 let data = result.unwrap();
                 let byte_buffer = buffer::Buffer::from_u8(data, "stdout".to_string(), true);
-                return Ok(Rc::new(Object::ByteBuffer(RefCell::new(byte_buffer))));
+                return Ok(Value::new(Object::ByteBuffer(Box::new(RefCell::new(byte_buffer)))));
             }
 
             BuiltinKind::BytecodeEval => {
@@ -1322,9 +1687,13 @@ impl BuiltinKind {
                 let obj = args[0].as_ref();
                 match obj {
                     Object::Str(st) => {
-                        let result = BosonLang::eval_bytecode(st.clone());
+                        let result = BosonLang::eval_bytecode_limited(
+                            st.to_string(),
+                            th.config.clone(),
+                            th.limits.clone(),
+                        );
                         if result.is_none() {
-                            return Ok(Rc::new(Object::Noval));
+                            return Ok(Value::new(Object::Noval));
                         }
 
                         return Ok(result.unwrap());
@@ -1335,10 +1704,413 @@ impl BuiltinKind {
                 }
             }
 
+            BuiltinKind::MemoryUsage => {
+                if args.len() != 0 {
+                    return Err(format!(
+                        "memory_usage() takes zero arguments, {} provided",
+                        args.len()
+                    ));
+                }
+
+                return Ok(Value::new(Object::Int(heap::get_usage() as i64)));
+            }
+
+            BuiltinKind::Gc => {
+                if args.len() != 0 {
+                    return Err(format!("gc() takes zero arguments, {} provided", args.len()));
+                }
+
+                return Ok(Value::new(Object::Int(gc::collect() as i64)));
+            }
+
+            BuiltinKind::Channel => {
+                if args.len() > 1 {
+                    return Err(format!(
+                        "channel() takes at most one argument, {} provided",
+                        args.len()
+                    ));
+                }
+
+                if args.len() == 0 {
+                    return Ok(Channel::new_pair(None));
+                }
+
+                match args[0].as_ref() {
+                    Object::Int(capacity) if *capacity > 0 => {
+                        return Ok(Channel::new_pair(Some(*capacity as usize)));
+                    }
+                    _ => {
+                        return Err(format!(
+                            "channel() takes a positive capacity, provided {}",
+                            args[0].describe()
+                        ));
+                    }
+                }
+            }
+
+            BuiltinKind::SharedMap => {
+                if args.len() != 0 {
+                    return Err(format!(
+                        "shared_map() takes zero arguments, {} provided",
+                        args.len()
+                    ));
+                }
+
+                return Ok(Value::new(Object::Shared(Box::new(SharedObject::new_map()))));
+            }
+
+            BuiltinKind::AtomicInt => {
+                if args.len() > 1 {
+                    return Err(format!(
+                        "atomic_int() takes at most one argument, {} provided",
+                        args.len()
+                    ));
+                }
+
+                let mut initial = 0;
+                if args.len() == 1 {
+                    match args[0].as_ref() {
+                        Object::Int(i) => initial = *i,
+                        _ => {
+                            return Err(format!(
+                                "atomic_int() takes an int, provided {}",
+                                args[0].get_type()
+                            ));
+                        }
+                    }
+                }
+
+                let atomic = SharedObject::new_atomic_int(initial);
+                return Ok(Value::new(Object::Shared(Box::new(atomic))));
+            }
+
+            BuiltinKind::Mutex => {
+                if args.len() != 1 {
+                    return Err(format!("mutex() takes one argument, {} provided", args.len()));
+                }
+
+                let mutex = SharedObject::new_mutex(&args[0]);
+                return Ok(Value::new(Object::Shared(Box::new(mutex))));
+            }
+
+            BuiltinKind::Await | BuiltinKind::IsDone => {
+                let name = self.desribe();
+                if args.len() != 1 {
+                    return Err(format!("{}() takes one argument, {} provided", name, args.len()));
+                }
+
+                match args[0].as_ref() {
+                    Object::Future(future) => {
+                        if *self == BuiltinKind::IsDone {
+                            return Ok(Value::new(Object::Bool(future.is_done())));
+                        }
+                        return future.wait(&th.limits);
+                    }
+                    Object::Task(task) => {
+                        if *self == BuiltinKind::IsDone {
+                            return Ok(Value::new(Object::Bool(task.is_done())));
+                        }
+
+                        // the error of the task is thrown with its own kind:
+                        let result = task.get_result();
+                        if result.is_some() {
+                            let result = result.unwrap();
+                            if result.is_err() {
+                                return Err(errors::carry_error(result.unwrap_err()));
+                            }
+                            return Ok(result.unwrap());
+                        }
+
+                        if th.tasks.current.as_ref() == Some(task.as_ref()) {
+                            return Err("A task cannot await itself".to_string());
+                        }
+
+                        // the other tasks run until this one is done:
+                        if !th.tasks.switch(Switch::Block) {
+                            return Err(format!(
+                                "await() on {} cannot wait here, the task runs once the program yields",
+                                task.describe()
+                            ));
+                        }
+                        return Ok(Value::new(Object::Noval));
+                    }
+                    _ => {
+                        return Err(format!(
+                            "{}() takes a future or a task, provided {}",
+                            name,
+                            args[0].get_type()
+                        ));
+                    }
+                }
+            }
+
+            BuiltinKind::JoinAll | BuiltinKind::Select => {
+                let name = self.desribe();
+                if args.len() != 1 {
+                    return Err(format!("{}() takes one argument, {} provided", name, args.len()));
+                }
+
+                let futures = Future::from_array(&name, &args[0]);
+                if futures.is_err() {
+                    return Err(futures.unwrap_err());
+                }
+
+                if *self == BuiltinKind::JoinAll {
+                    return Future::join_all(&futures.unwrap(), &th.limits);
+                }
+                return Future::select(&futures.unwrap(), &th.limits);
+            }
+
+            BuiltinKind::Spawn => {
+                if args.len() != 2 && args.len() != 3 {
+                    return Err(format!(
+                        "spawn() takes 2 or 3 arguments, provided {}.",
+                        args.len()
+                    ));
+                }
+
+                let (ctx, params) = match (args[0].as_ref(), args[1].as_ref()) {
+                    (Object::ClosureContext(ctx), Object::Array(params)) => {
+                        (ctx.clone(), params.borrow().elements.clone())
+                    }
+                    _ => {
+                        return Err(format!(
+                            "spawn() takes closure/func and array as arguments, but got {} {}.",
+                            args[0].get_type(),
+                            args[1].get_type()
+                        ));
+                    }
+                };
+
+                let n_parms_required = ctx.compiled_fn.num_parameters;
+                if n_parms_required != params.len() {
+                    return Err(format!(
+                        "Function {} requires {} parameters, provided {}",
+                        ctx.compiled_fn.name,
+                        n_parms_required,
+                        params.len()
+                    ));
+                }
+
+                let name = if args.len() == 3 {
+                    match args[2].as_ref() {
+                        Object::Str(name) => name.as_ref().clone(),
+                        _ => {
+                            return Err(format!(
+                                "spawn() takes a string as the name of the task, provided {}",
+                                args[2].get_type()
+                            ));
+                        }
+                    }
+                } else {
+                    ctx.compiled_fn.name.clone()
+                };
+
+                let spawn_result = th.spawn_task(ctx, params, name, platform, gp, c);
+                if spawn_result.is_err() {
+                    return Err(spawn_result.unwrap_err().message);
+                }
+
+                return Ok(Value::new(Object::Task(Box::new(spawn_result.unwrap()))));
+            }
+
+            BuiltinKind::Yield => {
+                if args.len() != 0 {
+                    return Err(format!("yield() takes no arguments, {} provided", args.len()));
+                }
+
+                // outside of the tasks there is nothing to switch to:
+                th.tasks.switch(Switch::Yield);
+                return Ok(Value::new(Object::Noval));
+            }
+
+            BuiltinKind::SetTimeout | BuiltinKind::SetInterval => {
+                let name = self.desribe();
+                if args.len() != 2 && args.len() != 3 {
+                    return Err(format!(
+                        "{}() takes 2 or 3 arguments, provided {}.",
+                        name,
+                        args.len()
+                    ));
+                }
+
+                let ctx = match args[0].as_ref() {
+                    Object::ClosureContext(ctx) => ctx.clone(),
+                    _ => {
+                        return Err(format!(
+                            "{}() takes a closure/func as the first argument, but got {}.",
+                            name,
+                            args[0].get_type()
+                        ));
+                    }
+                };
+
+                let duration_ms = match args[1].as_ref() {
+                    Object::Int(ms) => *ms as f64,
+                    Object::Float(ms) => *ms,
+                    _ => {
+                        return Err(format!(
+                            "{}() expects a float or int, but got {}",
+                            name,
+                            args[1].get_type()
+                        ));
+                    }
+                };
+
+                // an interval of zero would never let the program end:
+                let is_interval = *self == BuiltinKind::SetInterval;
+                if duration_ms < 0.0 || (is_interval && duration_ms <= 0.0) {
+                    return Err(format!(
+                        "{}() takes a positive time, provided {}",
+                        name,
+                        args[1].describe()
+                    ));
+                }
+
+                let params = if args.len() == 3 {
+                    match args[2].as_ref() {
+                        Object::Array(params) => params.borrow().elements.clone(),
+                        _ => {
+                            return Err(format!(
+                                "{}() takes an array of arguments, provided {}",
+                                name,
+                                args[2].get_type()
+                            ));
+                        }
+                    }
+                } else {
+                    vec![]
+                };
+
+                if ctx.compiled_fn.num_parameters != params.len() {
+                    return Err(format!(
+                        "Function {} requires {} parameters, provided {}",
+                        ctx.compiled_fn.name,
+                        ctx.compiled_fn.num_parameters,
+                        params.len()
+                    ));
+                }
+
+                let now = (platform.get_unix_time)();
+                if now.is_err() {
+                    return Err(now.unwrap_err());
+                }
+
+                let interval = if is_interval {
+                    Some(duration_ms / 1000.0)
+                } else {
+                    None
+                };
+
+                let due = now.unwrap() + duration_ms / 1000.0;
+                let id = th.tasks.add_timer(args[0].clone(), params, due, interval);
+                return Ok(Value::new(Object::Int(id as i64)));
+            }
+
+            BuiltinKind::ClearTimer => {
+                if args.len() != 1 {
+                    return Err(format!("clear_timer() takes one argument, {} provided", args.len()));
+                }
+
+                match args[0].as_ref() {
+                    Object::Int(id) => {
+                        let cleared = *id > 0 && th.tasks.clear_timer(*id as u64);
+                        return Ok(Value::new(Object::Bool(cleared)));
+                    }
+                    _ => {
+                        return Err(format!(
+                            "clear_timer() takes the id of a timer, provided {}",
+                            args[0].get_type()
+                        ));
+                    }
+                }
+            }
+
+            BuiltinKind::Process => {
+                return Err("process is not a function, use process.spawn(cmd, args, opts)".to_string());
+            }
+
+            BuiltinKind::Shell | BuiltinKind::ShellRaw => {
+                let name = self.desribe();
+                if args.is_empty() || args.len() > 2 {
+                    return Err(format!("{}() takes 1 or 2 arguments, {} provided", name, args.len()));
+                }
+
+                let template = match args[0].as_ref() {
+                    Object::Str(st) => st.as_ref().clone(),
+                    _ => {
+                        return Err(format!(
+                            "{}() takes the command as a string, provided {}",
+                            name,
+                            args[0].get_type()
+                        ));
+                    }
+                };
+
+                let values = if args.len() == 2 {
+                    args[1].clone()
+                } else {
+                    Value::new(Object::Array(Box::new(RefCell::new(Array {
+                        name: "shell_values".to_string(),
+                        elements: vec![],
+                    }))))
+                };
+
+                let render_result = shell::render(&template, &values);
+                if render_result.is_err() {
+                    return Err(render_result.unwrap_err());
+                }
+
+                let is_raw = *self == BuiltinKind::ShellRaw;
+                let run_result = shell::run(render_result.unwrap(), platform, th, is_raw);
+                if run_result.is_err() {
+                    return Err(run_result.unwrap_err());
+                }
+                return Ok(Value::new(Object::ShellResult(Box::new(run_result.unwrap()))));
+            }
+
             _ => return Err("Trying to invoke invalid builtin".to_string()),
         }
     }
 
+    // the builtins that keep a function to call later on a task of its own:
+    pub fn starts_task(&self) -> bool {
+        match self {
+            BuiltinKind::Spawn | BuiltinKind::SetTimeout | BuiltinKind::SetInterval => true,
+            _ => false,
+        }
+    }
+
+    // the builtins that call a function on another thread:
+    pub fn starts_thread(&self) -> bool {
+        match self {
+            BuiltinKind::CallAsync | BuiltinKind::CallThread | BuiltinKind::ParallelMap => true,
+            _ => false,
+        }
+    }
+
+    // the builtins that read or write outside of the VM, a task lets the
+    // other tasks run after calling them:
+    pub fn is_io(&self) -> bool {
+        match self {
+            BuiltinKind::Print
+            | BuiltinKind::Println
+            | BuiltinKind::Exec
+            | BuiltinKind::ExecRaw
+            | BuiltinKind::ExecShell
+            | BuiltinKind::Shell
+            | BuiltinKind::ShellRaw
+            | BuiltinKind::FStat
+            | BuiltinKind::FWrite
+            | BuiltinKind::FAppend
+            | BuiltinKind::FRead
+            | BuiltinKind::ReadLine
+            | BuiltinKind::SWrite
+            | BuiltinKind::SRead => true,
+            _ => false,
+        }
+    }
+
     pub fn get_by_name(name: &String) -> Option<BuiltinKind> {
         let builtin_size = BuiltinKind::EndMark as usize;
 
@@ -0,0 +1,341 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::isa::InstructionKind;
+use crate::types::array::Array;
+use crate::types::object::Object;
+use crate::types::object::Value;
+use crate::vm::limits::ExecutionLimits;
+use crate::vm::transfer::Transfer;
+
+// a blocked sender or receiver wakes up this often to check the limits of its VM:
+const WAIT_SLICE: Duration = Duration::from_millis(10);
+
+/*
+    A channel carries values between threads, every value is copied when it
+    is sent and created again by the receiver. channel() returns a sender
+    and a receiver, both ends can be copied to any number of threads. A
+    bounded channel blocks the senders while it is full. Once closed,
+    sending fails and the receivers get the values that are left, after
+    that recv fails and iterating over the receiver stops.
+*/
+struct ChannelState {
+    queue: VecDeque<Transfer>,
+    closed: bool,
+}
+
+struct ChannelShared {
+    state: Mutex<ChannelState>,
+    capacity: Option<usize>,
+    // notified when a value is sent or the channel is closed:
+    readable: Condvar,
+    // notified when a value is taken or the channel is closed:
+    writable: Condvar,
+}
+
+#[derive(Clone)]
+pub struct Channel {
+    shared: Arc<ChannelShared>,
+    pub is_sender: bool,
+}
+
+impl Channel {
+    pub fn new(capacity: Option<usize>) -> (Channel, Channel) {
+        let shared = Arc::new(ChannelShared {
+            state: Mutex::new(ChannelState {
+                queue: VecDeque::new(),
+                closed: false,
+            }),
+            capacity: capacity,
+            readable: Condvar::new(),
+            writable: Condvar::new(),
+        });
+
+        let sender = Channel {
+            shared: shared.clone(),
+            is_sender: true,
+        };
+
+        let receiver = Channel {
+            shared: shared,
+            is_sender: false,
+        };
+
+        return (sender, receiver);
+    }
+
+    // a thread that panicked while holding the lock left the queue usable:
+    fn lock(&self) -> MutexGuard<'_, ChannelState> {
+        match self.shared.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn wait<'a>(
+        &self,
+        condvar: &Condvar,
+        guard: MutexGuard<'a, ChannelState>,
+    ) -> MutexGuard<'a, ChannelState> {
+        match condvar.wait_timeout(guard, WAIT_SLICE) {
+            Ok((guard, _)) => guard,
+            Err(poisoned) => poisoned.into_inner().0,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let end = if self.is_sender { "Sender" } else { "Receiver" };
+        match self.shared.capacity {
+            Some(capacity) => format!("{}(capacity={})", end, capacity),
+            None => format!("{}(unbounded)", end),
+        }
+    }
+
+    pub fn get_type(&self) -> String {
+        if self.is_sender {
+            return "sender".to_string();
+        }
+        return "receiver".to_string();
+    }
+
+    pub fn length(&self) -> usize {
+        return self.lock().queue.len();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        return self.lock().closed;
+    }
+
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.shared.readable.notify_all();
+        self.shared.writable.notify_all();
+    }
+
+    pub fn send(&self, value: &Value, limits: &ExecutionLimits) -> Result<(), String> {
+        let transfer = Transfer::pack(&vec![value.clone()]);
+
+        let mut state = self.lock();
+        loop {
+            if state.closed {
+                return Err("send() on a closed channel".to_string());
+            }
+
+            let is_full = match self.shared.capacity {
+                Some(capacity) => state.queue.len() >= capacity,
+                None => false,
+            };
+
+            if !is_full {
+                break;
+            }
+
+            let interruption = limits.interrupted(&InstructionKind::ICallAttr);
+            if interruption.is_some() {
+                return Err(interruption.unwrap().message);
+            }
+
+            state = self.wait(&self.shared.writable, state);
+        }
+
+        state.queue.push_back(transfer);
+        drop(state);
+        self.shared.readable.notify_one();
+        return Ok(());
+    }
+
+    // waits for a value until the timeout, none means wait as long as it takes.
+    // The result is none when the time is up or the channel is closed and empty.
+    pub fn recv(
+        &self,
+        limits: &ExecutionLimits,
+        timeout: Option<Duration>,
+    ) -> Result<Option<Value>, String> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        let mut state = self.lock();
+        loop {
+            let transfer = state.queue.pop_front();
+            if transfer.is_some() {
+                drop(state);
+                self.shared.writable.notify_one();
+                return Ok(transfer.unwrap().unpack().pop());
+            }
+
+            if state.closed {
+                return Ok(None);
+            }
+
+            if deadline.is_some() && Instant::now() >= deadline.unwrap() {
+                return Ok(None);
+            }
+
+            let interruption = limits.interrupted(&InstructionKind::ICallAttr);
+            if interruption.is_some() {
+                return Err(interruption.unwrap().message);
+            }
+
+            state = self.wait(&self.shared.readable, state);
+        }
+    }
+
+    pub fn try_recv(&self) -> Option<Value> {
+        let transfer = self.lock().queue.pop_front();
+        if transfer.is_none() {
+            return None;
+        }
+
+        self.shared.writable.notify_one();
+        return transfer.unwrap().unpack().pop();
+    }
+
+    // true if send or recv would wait now, the other calls never wait:
+    pub fn would_block(&self, name: &str) -> bool {
+        let state = self.lock();
+        if state.closed {
+            return false;
+        }
+
+        match (name, self.is_sender) {
+            ("send", true) => match self.shared.capacity {
+                Some(capacity) => state.queue.len() >= capacity,
+                None => false,
+            },
+            ("recv", false) => state.queue.is_empty(),
+            _ => false,
+        }
+    }
+
+    pub fn attrs(&self) -> Vec<Value> {
+        let mut names = vec!["close", "is_closed", "len"];
+        if self.is_sender {
+            names.push("send");
+        } else {
+            names.extend(vec!["recv", "try_recv", "recv_timeout"]);
+        }
+
+        return names
+            .into_iter()
+            .map(|name| Value::new(Object::Str(Box::new(name.to_string()))))
+            .collect();
+    }
+
+    pub fn resolve_call_attr(
+        &self,
+        keys: &Vec<Value>,
+        args: &Vec<Value>,
+        limits: &ExecutionLimits,
+    ) -> Result<Value, String> {
+        let name = match keys[0].as_ref() {
+            Object::Str(st) => st.as_str(),
+            _ => {
+                return Err(format!(
+                    "Attribute of {} must be a string, got {}",
+                    self.get_type(),
+                    keys[0].get_type()
+                ))
+            }
+        };
+
+        let n_args = match name {
+            "send" | "recv_timeout" => 1,
+            _ => 0,
+        };
+
+        if args.len() != n_args {
+            return Err(format!(
+                "{}() takes {} arguments, provided {}.",
+                name,
+                n_args,
+                args.len()
+            ));
+        }
+
+        match (name, self.is_sender) {
+            ("close", _) => {
+                self.close();
+                return Ok(Value::new(Object::Noval));
+            }
+            ("is_closed", _) => return Ok(Value::new(Object::Bool(self.is_closed()))),
+            ("len", _) => return Ok(Value::new(Object::Int(self.length() as i64))),
+            ("send", true) => {
+                let result = self.send(&args[0], limits);
+                if result.is_err() {
+                    return Err(result.unwrap_err());
+                }
+                return Ok(Value::new(Object::Noval));
+            }
+            ("recv", false) => {
+                let result = self.recv(limits, None);
+                if result.is_err() {
+                    return Err(result.unwrap_err());
+                }
+
+                let value = result.unwrap();
+                if value.is_none() {
+                    return Err("recv() on a closed channel".to_string());
+                }
+                return Ok(value.unwrap());
+            }
+            ("try_recv", false) => {
+                return Ok(self.try_recv().unwrap_or(Value::new(Object::Noval)));
+            }
+            ("recv_timeout", false) => {
+                let timeout = match args[0].as_ref() {
+                    Object::Int(ms) if *ms >= 0 => Duration::from_millis(*ms as u64),
+                    _ => {
+                        return Err(format!(
+                            "recv_timeout() takes a positive int, provided {}",
+                            args[0].describe()
+                        ))
+                    }
+                };
+
+                let result = self.recv(limits, Some(timeout));
+                if result.is_err() {
+                    return Err(result.unwrap_err());
+                }
+                return Ok(result.unwrap().unwrap_or(Value::new(Object::Noval)));
+            }
+            _ => {
+                return Err(format!(
+                    "Attribute {} not found for type {}",
+                    name,
+                    self.get_type()
+                ))
+            }
+        }
+    }
+
+    // channel() and channel(capacity) return an array of the two ends:
+    pub fn new_pair(capacity: Option<usize>) -> Value {
+        let (sender, receiver) = Channel::new(capacity);
+        let ends = Array {
+            name: "channel".to_string(),
+            elements: vec![
+                Value::new(Object::Channel(Box::new(sender))),
+                Value::new(Object::Channel(Box::new(receiver))),
+            ],
+        };
+
+        return Value::new(Object::Array(Box::new(RefCell::new(ends))));
+    }
+}
+
+impl PartialEq for Channel {
+    fn eq(&self, other: &Channel) -> bool {
+        return Arc::ptr_eq(&self.shared, &other.shared) && self.is_sender == other.is_sender;
+    }
+}
+
+impl std::fmt::Debug for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.describe())
+    }
+}
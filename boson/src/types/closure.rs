@@ -1,15 +1,31 @@
+use std::cell::RefCell;
 use std::rc::Rc;
 use std::vec::Vec;
 use std::hash::{Hash, Hasher};
 
 use crate::types::subroutine::Subroutine;
 use crate::types::object::Object;
+use crate::types::object::Value;
 
 
+/*
+    A captured variable, while the function that declared it is still
+    running, the upvalue is open and points at the variable's slot on the
+    data stack. It is closed with the last value when the slot goes away,
+    so every closure sharing the upvalue keeps seeing the same variable.
+*/
+#[derive(Clone, Debug)]
+pub enum UpvalueState {
+    Open(usize),
+    Closed(Value),
+}
+
+pub type Upvalue = Rc<RefCell<UpvalueState>>;
+
 #[derive(Clone, Debug)]
 pub struct ClosureContext {
     pub compiled_fn: Rc<Subroutine>,
-    pub free_objects: Vec<Rc<Object>>,
+    pub free_objects: Vec<Upvalue>,
     pub bytecode_size: usize,
 }
 
@@ -18,9 +34,32 @@ impl ClosureContext {
         return self.compiled_fn.describe();
     }
 
-    pub fn get_objects(&self) -> &Vec<Rc<Object>> {
+    pub fn get_objects(&self) -> &Vec<Upvalue> {
         return &self.free_objects;
     }
+
+    // copies the closure with its upvalues closed over their current values,
+    // used when the closure leaves the VM that owns the data stack.
+    pub fn detach(&self, stack: &Vec<Value>) -> ClosureContext {
+        let mut free_objects = vec![];
+        for upvalue in &self.free_objects {
+            let value = match &*upvalue.borrow() {
+                UpvalueState::Open(slot) => match stack.get(*slot) {
+                    Some(obj) => obj.clone(),
+                    None => Value::new(Object::Noval),
+                },
+                UpvalueState::Closed(obj) => obj.clone(),
+            };
+
+            free_objects.push(Rc::new(RefCell::new(UpvalueState::Closed(value))));
+        }
+
+        return ClosureContext {
+            compiled_fn: self.compiled_fn.clone(),
+            free_objects: free_objects,
+            bytecode_size: self.bytecode_size,
+        };
+    }
 }
 
 impl PartialEq for ClosureContext {
@@ -1,3 +1,6 @@
+use crate::types::object::AttributeResolver;
+use crate::types::object::Object;
+use crate::types::object::Value;
 use crate::vm::errors::StackFrame;
 use crate::vm::errors::VMErrorKind;
 
@@ -31,6 +34,58 @@ impl Exception {
     pub fn describe(&self) -> String {
         return format!("{:?}: {}", self.root_error_type, self.exception_string);
     }
+
+    pub fn get_name(&self) -> String {
+        return self.handle_name.clone();
+    }
+
+    pub fn get_kind(&self) -> VMErrorKind {
+        return self.root_error_type.clone();
+    }
+
+    pub fn get_message(&self) -> String {
+        return self.exception_string.clone();
+    }
+}
+
+impl AttributeResolver for Exception {
+    fn attrs(&self) -> Vec<Value> {
+        return vec![
+            Value::new(Object::Str(Box::new(String::from("kind")))),
+            Value::new(Object::Str(Box::new(String::from("message")))),
+        ];
+    }
+
+    fn resolve_get_attr(&self, keys: &Vec<Value>) -> Result<Value, String> {
+        match keys[0].as_ref() {
+            Object::Str(st) => match st.as_str() {
+                "kind" => {
+                    let kind = format!("{:?}", self.root_error_type);
+                    return Ok(Value::new(Object::Str(Box::new(kind))));
+                }
+                "message" => {
+                    let message = self.exception_string.clone();
+                    return Ok(Value::new(Object::Str(Box::new(message))));
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+
+        return Ok(Value::new(Object::Noval));
+    }
+
+    fn resolve_set_attr(&self, _keys: &Vec<Value>, _value: Value) -> Option<String> {
+        return None;
+    }
+
+    fn resolve_call_attr(
+        &mut self,
+        _keys: &Vec<Value>,
+        _args: &Vec<Value>,
+    ) -> Result<Value, String> {
+        return Err("No function attributes found for type exception".to_string());
+    }
 }
 
 // hash and partial equality:
@@ -0,0 +1,203 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread;
+use std::time::Duration;
+
+use crate::isa::InstructionKind;
+use crate::types::array::Array;
+use crate::types::object::Object;
+use crate::types::object::Value;
+use crate::vm::errors;
+use crate::vm::limits::ExecutionLimits;
+use crate::vm::thread::ThreadReturnType;
+
+// a thread waiting for a future wakes up this often to check the limits of its VM:
+const WAIT_SLICE: Duration = Duration::from_millis(10);
+// select() checks the futures it waits for this often:
+const SELECT_SLICE: Duration = Duration::from_millis(1);
+
+/*
+    The result of an async call. The call runs on a thread of its own and
+    the future is returned at once, the thread stores the result in the
+    future when it finishes. await() waits for it and gives a copy of the
+    result to every caller, or throws the error of the call. A future can
+    be copied to other threads and awaited there.
+*/
+struct FutureShared {
+    result: Mutex<Option<ThreadReturnType>>,
+    // notified when the result is stored:
+    done: Condvar,
+    name: String,
+}
+
+#[derive(Clone)]
+pub struct Future {
+    shared: Arc<FutureShared>,
+}
+
+impl Future {
+    pub fn new(name: String) -> Future {
+        return Future {
+            shared: Arc::new(FutureShared {
+                result: Mutex::new(None),
+                done: Condvar::new(),
+                name: name,
+            }),
+        };
+    }
+
+    // a thread that panicked while holding the lock left the result usable:
+    fn lock(&self) -> MutexGuard<'_, Option<ThreadReturnType>> {
+        match self.shared.result.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let state = if self.is_done() { "done" } else { "pending" };
+        return format!("Future(func={}, {})", self.shared.name, state);
+    }
+
+    pub fn get_type(&self) -> String {
+        return "future".to_string();
+    }
+
+    pub fn is_done(&self) -> bool {
+        return self.lock().is_some();
+    }
+
+    pub fn complete(&self, result: ThreadReturnType) {
+        *self.lock() = Some(result);
+        self.shared.done.notify_all();
+    }
+
+    // the result of a finished call, turned into a value on this thread:
+    fn take_result(result: &ThreadReturnType) -> Result<Value, String> {
+        let sandbox_result = result.clone().get_result();
+        if sandbox_result.is_err() {
+            return Err(errors::carry_error(sandbox_result.unwrap_err()));
+        }
+
+        return Ok(sandbox_result.unwrap());
+    }
+
+    pub fn wait(&self, limits: &ExecutionLimits) -> Result<Value, String> {
+        let mut result = self.lock();
+        loop {
+            if result.is_some() {
+                return Future::take_result(result.as_ref().unwrap());
+            }
+
+            let interruption = limits.interrupted(&InstructionKind::ICall);
+            if interruption.is_some() {
+                return Err(interruption.unwrap().message);
+            }
+
+            result = match self.shared.done.wait_timeout(result, WAIT_SLICE) {
+                Ok((guard, _)) => guard,
+                Err(poisoned) => poisoned.into_inner().0,
+            };
+        }
+    }
+
+    // waits for all the futures and returns their results in the same order:
+    pub fn join_all(futures: &Vec<Future>, limits: &ExecutionLimits) -> Result<Value, String> {
+        let mut results = vec![];
+        for future in futures {
+            let result = future.wait(limits);
+            if result.is_err() {
+                return Err(result.unwrap_err());
+            }
+            results.push(result.unwrap());
+        }
+
+        let array = Array {
+            name: "join_all".to_string(),
+            elements: results,
+        };
+
+        return Ok(Value::new(Object::Array(Box::new(RefCell::new(array)))));
+    }
+
+    // waits for the first future to finish and returns its position and result:
+    pub fn select(futures: &Vec<Future>, limits: &ExecutionLimits) -> Result<Value, String> {
+        loop {
+            for (idx, future) in futures.iter().enumerate() {
+                let finished = future.lock().clone();
+                if finished.is_none() {
+                    continue;
+                }
+
+                let result = Future::take_result(finished.as_ref().unwrap());
+                if result.is_err() {
+                    return Err(result.unwrap_err());
+                }
+
+                let array = Array {
+                    name: "select".to_string(),
+                    elements: vec![Value::new(Object::Int(idx as i64)), result.unwrap()],
+                };
+
+                return Ok(Value::new(Object::Array(Box::new(RefCell::new(array)))));
+            }
+
+            let interruption = limits.interrupted(&InstructionKind::ICall);
+            if interruption.is_some() {
+                return Err(interruption.unwrap().message);
+            }
+
+            // any of the futures can finish, so they are polled:
+            thread::sleep(SELECT_SLICE);
+        }
+    }
+
+    // the futures in an array, for join_all() and select():
+    pub fn from_array(name: &str, value: &Value) -> Result<Vec<Future>, String> {
+        let elements = match value.as_ref() {
+            Object::Array(arr) => arr.borrow().elements.clone(),
+            _ => {
+                return Err(format!(
+                    "{}() takes an array of futures, provided {}",
+                    name,
+                    value.get_type()
+                ))
+            }
+        };
+
+        let mut futures = vec![];
+        for element in &elements {
+            match element.as_ref() {
+                Object::Future(future) => futures.push(future.as_ref().clone()),
+                _ => {
+                    return Err(format!(
+                        "{}() takes an array of futures, found {}",
+                        name,
+                        element.get_type()
+                    ))
+                }
+            }
+        }
+
+        if futures.is_empty() && name == "select" {
+            return Err("select() takes at least one future".to_string());
+        }
+
+        return Ok(futures);
+    }
+}
+
+impl PartialEq for Future {
+    fn eq(&self, other: &Future) -> bool {
+        return Arc::ptr_eq(&self.shared, &other.shared);
+    }
+}
+
+impl std::fmt::Debug for Future {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.describe())
+    }
+}
@@ -0,0 +1,246 @@
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+use std::rc::Weak;
+
+use crate::config::GC_THRESHOLD;
+use crate::types::closure::ClosureContext;
+use crate::types::closure::Upvalue;
+use crate::types::closure::UpvalueState;
+use crate::types::object::Object;
+use crate::types::object::Value;
+
+/*
+    Cycle collector. Arrays and hash tables are copied on write, so a new
+    container can only point at values that already exist, the only way to
+    close a cycle is a closed upvalue that holds a closure capturing it,
+    directly or through containers. Every upvalue created by a capture is
+    tracked, and a collection runs a trial deletion over the graph reachable
+    from them: a node whose reference count is higher than the references
+    it gets from inside the graph is held from outside (the stacks, the
+    globals, the host), it stays alive with everything it reaches. The
+    upvalues that are left are garbage, clearing them breaks the cycles and
+    the reference counts free the rest.
+*/
+thread_local! {
+    static TRACKED: RefCell<Vec<Weak<RefCell<UpvalueState>>>> = const { RefCell::new(Vec::new()) };
+    // upvalues created since the last collection and the count that starts the next one:
+    static CREATED: Cell<usize> = const { Cell::new(0) };
+    static NEXT_COLLECTION: Cell<usize> = const { Cell::new(GC_THRESHOLD) };
+    static THRESHOLD: Cell<usize> = const { Cell::new(GC_THRESHOLD) };
+}
+
+enum Node {
+    Upvalue(Upvalue),
+    Object(Rc<Object>),
+    Closure(Rc<ClosureContext>),
+}
+
+// a reference to a node found while walking the children of another one:
+enum Edge<'a> {
+    Upvalue(&'a Upvalue),
+    Object(&'a Rc<Object>),
+    Closure(&'a Rc<ClosureContext>),
+}
+
+impl<'a> Edge<'a> {
+    fn key(&self) -> usize {
+        match self {
+            Edge::Upvalue(up) => Rc::as_ptr(up) as *const u8 as usize,
+            Edge::Object(obj) => Rc::as_ptr(obj) as *const u8 as usize,
+            Edge::Closure(ctx) => Rc::as_ptr(ctx) as *const u8 as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Edge::Upvalue(up) => Rc::strong_count(up),
+            Edge::Object(obj) => Rc::strong_count(obj),
+            Edge::Closure(ctx) => Rc::strong_count(ctx),
+        }
+    }
+
+    fn to_node(&self) -> Node {
+        match self {
+            Edge::Upvalue(up) => Node::Upvalue(Rc::clone(up)),
+            Edge::Object(obj) => Node::Object(Rc::clone(obj)),
+            Edge::Closure(ctx) => Node::Closure(Rc::clone(ctx)),
+        }
+    }
+}
+
+struct GraphNode {
+    node: Node,
+    // references from outside of the collector and from other nodes:
+    strong: usize,
+    internal: usize,
+    children: Vec<usize>,
+    live: bool,
+}
+
+fn visit_value(value: &Value, visit: &mut dyn FnMut(Edge)) {
+    if let Value::Shared(obj) = value {
+        match obj.as_ref() {
+            Object::Array(_) | Object::HashTable(_) | Object::Iter(_) | Object::ClosureContext(_) => {
+                visit(Edge::Object(obj))
+            }
+            _ => {}
+        }
+    }
+}
+
+fn visit_children(node: &Node, visit: &mut dyn FnMut(Edge)) {
+    match node {
+        Node::Upvalue(up) => {
+            if let UpvalueState::Closed(value) = &*up.borrow() {
+                visit_value(value, visit);
+            }
+        }
+        Node::Closure(ctx) => {
+            for up in &ctx.free_objects {
+                visit(Edge::Upvalue(up));
+            }
+        }
+        Node::Object(obj) => match obj.as_ref() {
+            Object::ClosureContext(ctx) => visit(Edge::Closure(ctx)),
+            Object::Array(arr) => {
+                for value in &arr.borrow().elements {
+                    visit_value(value, visit);
+                }
+            }
+            Object::HashTable(ht) => {
+                for (key, value) in &ht.borrow().entries {
+                    visit_value(key, visit);
+                    visit_value(value, visit);
+                }
+            }
+            Object::Iter(it) => {
+                for value in &it.borrow().elements {
+                    visit_value(value, visit);
+                }
+            }
+            _ => {}
+        },
+    }
+}
+
+pub fn track(upvalue: &Upvalue) {
+    TRACKED.with(|tracked| tracked.borrow_mut().push(Rc::downgrade(upvalue)));
+    CREATED.with(|created| created.set(created.get() + 1));
+}
+
+pub fn set_threshold(threshold: usize) {
+    THRESHOLD.with(|current| current.set(threshold));
+    NEXT_COLLECTION.with(|next| next.set(threshold));
+}
+
+pub fn is_due() -> bool {
+    return CREATED.with(|created| created.get()) >= NEXT_COLLECTION.with(|next| next.get());
+}
+
+// runs a collection and returns the number of objects found in cycles:
+pub fn collect() -> usize {
+    let upvalues: Vec<Upvalue> = TRACKED.with(|tracked| {
+        let mut tracked = tracked.borrow_mut();
+        tracked.retain(|up| up.strong_count() > 0);
+        return tracked.iter().filter_map(|up| up.upgrade()).collect();
+    });
+
+    let mut graph: Vec<GraphNode> = vec![];
+    let mut index: HashMap<usize, usize> = HashMap::new();
+
+    // the tracked upvalues are the roots, not counting the reference held above:
+    for up in &upvalues {
+        let edge = Edge::Upvalue(up);
+        let strong = edge.strong_count() - 1;
+        index.insert(edge.key(), graph.len());
+        graph.push(GraphNode {
+            node: edge.to_node(),
+            strong: strong,
+            internal: 0,
+            children: vec![],
+            live: false,
+        });
+    }
+
+    // the counts of a node are read before the collector holds a reference to it:
+    let mut current = 0;
+    while current < graph.len() {
+        let base = graph.len();
+        let mut children = vec![];
+        let mut found = vec![];
+        visit_children(&graph[current].node, &mut |edge: Edge| {
+            let key = edge.key();
+            let position = match index.get(&key) {
+                Some(pos) => *pos,
+                None => {
+                    let pos = base + found.len();
+                    let strong = edge.strong_count();
+                    index.insert(key, pos);
+                    found.push(GraphNode {
+                        node: edge.to_node(),
+                        strong: strong,
+                        internal: 0,
+                        children: vec![],
+                        live: false,
+                    });
+                    pos
+                }
+            };
+            children.push(position);
+        });
+
+        graph.append(&mut found);
+        for child in &children {
+            graph[*child].internal += 1;
+        }
+
+        graph[current].children = children;
+        current += 1;
+    }
+
+    // everything reachable from a node that is referenced from outside is alive:
+    let mut pending: Vec<usize> = (0..graph.len())
+        .filter(|pos| graph[*pos].strong > graph[*pos].internal)
+        .collect();
+
+    while !pending.is_empty() {
+        let pos = pending.pop().unwrap();
+        if graph[pos].live {
+            continue;
+        }
+
+        graph[pos].live = true;
+        pending.extend(graph[pos].children.iter().filter(|child| !graph[**child].live));
+    }
+
+    let mut garbage = vec![];
+    let mut n_collected = 0;
+    for graph_node in &graph {
+        if graph_node.live {
+            continue;
+        }
+
+        n_collected += 1;
+        if let Node::Upvalue(up) = &graph_node.node {
+            let value = mem::replace(
+                &mut *up.borrow_mut(),
+                UpvalueState::Closed(Value::new(Object::Noval)),
+            );
+            garbage.push(value);
+        }
+    }
+
+    let survivors = upvalues.len().saturating_sub(garbage.len());
+    CREATED.with(|created| created.set(0));
+    NEXT_COLLECTION.with(|next| next.set(THRESHOLD.with(|threshold| threshold.get()).max(survivors)));
+
+    // the references of the collector go first, then the cycles are freed:
+    drop(graph);
+    drop(upvalues);
+    drop(garbage);
+
+    return n_collected;
+}
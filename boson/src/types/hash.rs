@@ -3,17 +3,17 @@ use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::iter::FromIterator;
-use std::rc::Rc;
 use std::vec::Vec;
 
 use crate::types::array::Array;
 use crate::types::object::Object;
+use crate::types::object::Value;
 use crate::types::object::AttributeResolver;
 
 #[derive(Clone, Debug)]
 pub struct HashTable {
     pub name: String,
-    pub entries: HashMap<Rc<Object>, Rc<Object>>,
+    pub entries: HashMap<Value, Value>,
 }
 
 impl HashTable {
@@ -26,15 +26,15 @@ impl HashTable {
         return format!("HashTable({{{}}})", combined_pairs.join(", "));
     }
 
-    pub fn keys(&self) -> Vec<Rc<Object>> {
+    pub fn keys(&self) -> Vec<Value> {
         return Vec::from_iter(self.entries.keys().cloned());
     }
 
-    pub fn set(&mut self, key: Rc<Object>, value: Rc<Object>) {
+    pub fn set(&mut self, key: Value, value: Value) {
         self.entries.insert(key, value);
     }
 
-    pub fn values(&self) -> Vec<Rc<Object>> {
+    pub fn values(&self) -> Vec<Value> {
         return Vec::from_iter(self.entries.values().cloned());
     }
 
@@ -42,7 +42,7 @@ impl HashTable {
         return self.entries.len();
     }
 
-    pub fn get(&self, key: &Rc<Object>) -> Result<Rc<Object>, String> {
+    pub fn get(&self, key: &Value) -> Result<Value, String> {
         let result = self.entries.get(key);
         if result.is_none() {
             return Err(format!("Key {} not found.", key.describe()));
@@ -51,7 +51,7 @@ impl HashTable {
         return Ok(result.unwrap().clone());
     }
 
-    pub fn get_ref(&self, key: &Rc<Object>) -> Result<&Rc<Object>, String> {
+    pub fn get_ref(&self, key: &Value) -> Result<&Value, String> {
         let result = self.entries.get(key);
         if result.is_none() {
             return Err(format!("Key {} not found.", key.describe()));
@@ -61,17 +61,17 @@ impl HashTable {
     }
 
     // attrs:
-    pub fn attrs(&self) -> Vec<Rc<Object>> {
+    pub fn attrs(&self) -> Vec<Value> {
         return vec![
-            Rc::new(Object::Str(String::from("keys"))),
-            Rc::new(Object::Str(String::from("__name__"))),
-            Rc::new(Object::Str(String::from("values"))),
+            Value::new(Object::Str(Box::new(String::from("keys")))),
+            Value::new(Object::Str(Box::new(String::from("__name__")))),
+            Value::new(Object::Str(Box::new(String::from("values")))),
         ];
     }
 
-    pub fn get_attribute(&self, key: &String) -> Result<Rc<Object>, String> {
+    pub fn get_attribute(&self, key: &String) -> Result<Value, String> {
         match key.as_ref() {
-            "__name__" => return Ok(Rc::new(Object::Str(self.name.clone()))),
+            "__name__" => return Ok(Value::new(Object::Str(Box::new(self.name.clone())))),
             _ => {
                 return Err(format!(
                     "Attribute {} not found for type {}",
@@ -84,8 +84,8 @@ impl HashTable {
     pub fn call_attribute(
         &mut self,
         key: &String,
-        args: &Vec<Rc<Object>>,
-    ) -> Result<Rc<Object>, String> {
+        args: &Vec<Value>,
+    ) -> Result<Value, String> {
         match key.as_ref() {
             "keys" => {
                 if args.len() != 0 {
@@ -101,7 +101,7 @@ impl HashTable {
                     name: self.name.clone(),
                 };
 
-                return Ok(Rc::new(Object::Array(RefCell::new(array_obj))));
+                return Ok(Value::new(Object::Array(Box::new(RefCell::new(array_obj)))));
             }
             "values" => {
                 if args.len() != 0 {
@@ -117,7 +117,7 @@ impl HashTable {
                     name: self.name.clone(),
                 };
 
-                return Ok(Rc::new(Object::Array(RefCell::new(array_obj))));
+                return Ok(Value::new(Object::Array(Box::new(RefCell::new(array_obj)))));
             }
             _ => {
                 return Err(format!(
@@ -151,22 +151,22 @@ impl fmt::Display for HashTable {
 
 impl AttributeResolver for HashTable {
 
-    fn attrs(&self) -> Vec<Rc<Object>> {
+    fn attrs(&self) -> Vec<Value> {
         return vec![
-            Rc::new(Object::Str(String::from("keys"))),
-            Rc::new(Object::Str(String::from("__name__"))),
-            Rc::new(Object::Str(String::from("values"))),
+            Value::new(Object::Str(Box::new(String::from("keys")))),
+            Value::new(Object::Str(Box::new(String::from("__name__")))),
+            Value::new(Object::Str(Box::new(String::from("values")))),
         ];
     }
 
-    fn resolve_get_attr(&self, keys: &Vec<Rc<Object>>) -> Result<Rc<Object>, String> {
+    fn resolve_get_attr(&self, keys: &Vec<Value>) -> Result<Value, String> {
         let f_key = &keys[0];
 
         match f_key.as_ref() {
             Object::Str(st) => {
-                match st.as_ref() {
+                match st.as_str() {
                     // base attributes
-                    "__name__" => return Ok(Rc::new(Object::Str(self.name.clone()))),
+                    "__name__" => return Ok(Value::new(Object::Str(Box::new(self.name.clone())))),
                     _ => {}
                 }
             }
@@ -174,19 +174,19 @@ impl AttributeResolver for HashTable {
             _ => {}
         }
 
-        return Ok(Rc::new(Object::Noval));
+        return Ok(Value::new(Object::Noval));
     }
 
-    fn resolve_set_attr(&self, _keys: &Vec<Rc<Object>>, _value: Rc<Object>) -> Option<String> {
+    fn resolve_set_attr(&self, _keys: &Vec<Value>, _value: Value) -> Option<String> {
         return None;
     }
 
-    fn resolve_call_attr(&mut self, keys: &Vec<Rc<Object>>, args: &Vec<Rc<Object>>) -> Result<Rc<Object>, String> {
+    fn resolve_call_attr(&mut self, keys: &Vec<Value>, args: &Vec<Value>) -> Result<Value, String> {
 
         let key = &keys[0];
         match key.as_ref() {
             Object::Str(st) => {
-                match st.as_ref() {
+                match st.as_str() {
                     "keys" => {
                         if args.len() != 0 {
                             return Err(format!(
@@ -201,7 +201,7 @@ impl AttributeResolver for HashTable {
                             name: self.name.clone(),
                         };
         
-                        return Ok(Rc::new(Object::Array(RefCell::new(array_obj))));
+                        return Ok(Value::new(Object::Array(Box::new(RefCell::new(array_obj)))));
                     }
                     "values" => {
                         if args.len() != 0 {
@@ -217,7 +217,7 @@ impl AttributeResolver for HashTable {
                             name: self.name.clone(),
                         };
         
-                        return Ok(Rc::new(Object::Array(RefCell::new(array_obj))));
+                        return Ok(Value::new(Object::Array(Box::new(RefCell::new(array_obj)))));
                     }
                     _ => {
                         return Err(format!(
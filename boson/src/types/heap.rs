@@ -0,0 +1,92 @@
+use std::cell::Cell;
+use std::mem;
+
+use crate::types::array::Array;
+use crate::types::buffer::Buffer;
+use crate::types::hash::HashTable;
+use crate::types::object::Object;
+use crate::types::object::Value;
+
+/*
+    Approximate accounting of the memory held by strings, arrays, hash tables
+    and buffers. The size of an object is counted when its value is created
+    and given back when the last reference to it is dropped, the elements of
+    arrays and hash tables are values of their own. Every thread keeps its
+    own count, the VM of the thread sets the limit.
+*/
+thread_local! {
+    static HEAP_USED: Cell<usize> = const { Cell::new(0) };
+    static HEAP_LIMIT: Cell<Option<usize>> = const { Cell::new(None) };
+    // set when a reservation fails, tells a MemoryError from other errors:
+    static LIMIT_HIT: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn size_of(obj: &Object) -> usize {
+    match obj {
+        Object::Str(st) => mem::size_of::<String>() + st.capacity(),
+        Object::Array(arr) => {
+            let elements = arr.borrow().elements.capacity();
+            return mem::size_of::<Array>() + elements * mem::size_of::<Value>();
+        }
+        Object::HashTable(ht) => {
+            // the entries and the control bytes of the table:
+            let entries = ht.borrow().entries.capacity();
+            return mem::size_of::<HashTable>() + entries * (2 * mem::size_of::<Value>() + 1);
+        }
+        Object::ByteBuffer(buffer) => {
+            return mem::size_of::<Buffer>() + buffer.borrow().data.capacity();
+        }
+        _ => 0,
+    }
+}
+
+pub fn allocated(size: usize) {
+    HEAP_USED.with(|used| used.set(used.get() + size));
+}
+
+pub fn freed(size: usize) {
+    HEAP_USED.with(|used| used.set(used.get().saturating_sub(size)));
+}
+
+pub fn get_usage() -> usize {
+    return HEAP_USED.with(|used| used.get());
+}
+
+pub fn get_limit() -> Option<usize> {
+    return HEAP_LIMIT.with(|limit| limit.get());
+}
+
+pub fn set_limit(limit: Option<usize>) {
+    HEAP_LIMIT.with(|current| current.set(limit));
+}
+
+pub fn is_exceeded() -> bool {
+    let limit = get_limit();
+    return limit.is_some() && get_usage() > limit.unwrap();
+}
+
+// checks that size more bytes fit under the limit before they are allocated:
+pub fn reserve(size: usize) -> Result<(), String> {
+    let limit = get_limit();
+    if limit.is_none() {
+        return Ok(());
+    }
+
+    let used = get_usage();
+    if used.saturating_add(size) > limit.unwrap() {
+        LIMIT_HIT.with(|hit| hit.set(true));
+        return Err(format!(
+            "Heap limit of {} bytes exceeded, {} bytes in use, {} more requested",
+            limit.unwrap(),
+            used,
+            size
+        ));
+    }
+
+    return Ok(());
+}
+
+// tells if the last failed call ran out of memory, the flag is cleared:
+pub fn take_limit_hit() -> bool {
+    return LIMIT_HIT.with(|hit| hit.replace(false));
+}
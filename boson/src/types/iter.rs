@@ -1,7 +1,12 @@
+use crate::types::channel;
 use crate::types::object;
+use crate::vm::limits;
+
+use channel::Channel;
+use limits::ExecutionLimits;
 
 use object::Object;
-use std::rc::Rc;
+use object::Value;
 
 /*
     This is a dummy iterator, not a real one.
@@ -12,17 +17,21 @@ use std::rc::Rc;
 pub struct ObjectIterator {
     pub idx: usize,
     pub size: usize,
-    pub elements: Vec<Rc<Object>>,
+    pub elements: Vec<Value>,
+    // iterating over a receiver takes the values as they arrive, the
+    // elements then hold the one that was received but not taken yet:
+    pub receiver: Option<Channel>,
 }
 
 impl ObjectIterator {
-    pub fn new(obj: Rc<Object>) -> Result<ObjectIterator, String> {
+    pub fn new(obj: Value) -> Result<ObjectIterator, String> {
         match obj.as_ref() {
             Object::Array(arr) => {
                 return Ok(ObjectIterator {
                     idx: 0,
                     size: arr.borrow().elements.len(),
                     elements: arr.borrow().elements.clone(),
+                    receiver: None,
                 });
             }
             Object::HashTable(ht) => {
@@ -32,29 +41,40 @@ impl ObjectIterator {
                     idx: 0,
                     size: length,
                     elements: table.keys(),
+                    receiver: None,
                 });
             }
             Object::Str(st) => {
-                let vec_string: Vec<Rc<Object>> =
-                    st.chars().map(|c| Rc::new(Object::Char(c))).collect();
+                let vec_string: Vec<Value> =
+                    st.chars().map(|c| Value::new(Object::Char(c))).collect();
                 return Ok(ObjectIterator {
                     idx: 0,
                     size: vec_string.len(),
                     elements: vec_string,
+                    receiver: None,
                 });
             }
             Object::ByteBuffer(buffer) => {
-                let byte_vec: Vec<Rc<Object>> = buffer
+                let byte_vec: Vec<Value> = buffer
                     .borrow()
                     .data
                     .iter()
-                    .map(|b| Rc::new(Object::Byte(*b)))
+                    .map(|b| Value::new(Object::Byte(*b)))
                     .collect();
 
                 return Ok(ObjectIterator {
                     idx: 0,
                     size: byte_vec.len(),
                     elements: byte_vec,
+                    receiver: None,
+                });
+            }
+            Object::Channel(ch) if !ch.is_sender => {
+                return Ok(ObjectIterator {
+                    idx: 0,
+                    size: 0,
+                    elements: vec![],
+                    receiver: Some(ch.as_ref().clone()),
                 });
             }
             _ => {
@@ -66,7 +86,21 @@ impl ObjectIterator {
         }
     }
 
-    pub fn next(&mut self) -> Option<Rc<Object>> {
+    pub fn next(&mut self) -> Option<Value> {
+        if self.receiver.is_some() && self.elements.is_empty() {
+            let value = self.receiver.as_ref().unwrap().try_recv();
+            if value.is_none() {
+                return None;
+            }
+            self.elements.push(value.unwrap());
+        }
+
+        if self.receiver.is_some() {
+            self.idx += 1;
+            self.size = self.idx;
+            return self.elements.pop();
+        }
+
         if self.idx >= self.size {
             return None;
         }
@@ -75,6 +109,13 @@ impl ObjectIterator {
         return Some(object);
     }
 
+    // true if the next value has not arrived on the receiver yet:
+    pub fn would_block(&self) -> bool {
+        return self.receiver.is_some()
+            && self.elements.is_empty()
+            && self.receiver.as_ref().unwrap().would_block("recv");
+    }
+
     pub fn get_pos(&self) -> usize {
         return self.idx;
     }
@@ -84,6 +125,40 @@ impl ObjectIterator {
     }
 
     pub fn has_next(&self) -> bool {
+        if self.receiver.is_some() {
+            return !self.elements.is_empty() || !self.receiver.as_ref().unwrap().is_closed();
+        }
         return self.idx < self.size;
     }
+
+    // waits for the next value of a receiver, the other iterators never block.
+    pub fn wait_next(&mut self, limits: &ExecutionLimits) -> Result<Option<Value>, String> {
+        let has_next = self.wait_has_next(limits);
+        if has_next.is_err() {
+            return Err(has_next.unwrap_err());
+        }
+
+        return Ok(self.next());
+    }
+
+    // for a receiver, false once it is closed and every value was taken.
+    pub fn wait_has_next(&mut self, limits: &ExecutionLimits) -> Result<bool, String> {
+        if self.receiver.is_none() || !self.elements.is_empty() {
+            return Ok(self.has_next());
+        }
+
+        let received = self.receiver.as_ref().unwrap().recv(limits, None);
+        if received.is_err() {
+            return Err(received.unwrap_err());
+        }
+
+        let value = received.unwrap();
+        if value.is_none() {
+            return Ok(false);
+        }
+
+        self.elements.push(value.unwrap());
+        self.size = self.idx + 1;
+        return Ok(true);
+    }
 }
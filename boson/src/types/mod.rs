@@ -7,4 +7,13 @@ pub mod builtins;
 pub mod iter;
 pub mod exception;
 pub mod buffer;
-pub mod th;
\ No newline at end of file
+pub mod th;
+pub mod heap;
+pub mod gc;
+pub mod channel;
+pub mod shared;
+pub mod future;
+pub mod pool;
+pub mod task;
+pub mod process;
+pub mod shell;
@@ -2,47 +2,67 @@ use std::cell::RefCell;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::mem;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
 use std::rc::Rc;
 
 use crate::types::array::Array;
 use crate::types::buffer::Buffer;
 use crate::types::builtins::BuiltinKind;
+use crate::types::channel::Channel;
 use crate::types::closure::ClosureContext;
 use crate::types::exception::Exception;
+use crate::types::future::Future;
 use crate::types::hash::HashTable;
+use crate::types::heap;
 use crate::types::iter::ObjectIterator;
+use crate::types::pool::ThreadPool;
+use crate::types::process::Process;
+use crate::types::task::TaskHandle;
+use crate::types::shared::SharedObject;
+use crate::types::shell::ShellResult;
 use crate::types::subroutine::Subroutine;
 use crate::types::th::ThreadBlock;
 
 pub trait AttributeResolver {
-    fn attrs(&self) -> Vec<Rc<Object>>;
-    fn resolve_set_attr(&self, _keys: &Vec<Rc<Object>>, _value: Rc<Object>) -> Option<String>;
-    fn resolve_get_attr(&self, keys: &Vec<Rc<Object>>) -> Result<Rc<Object>, String>;
+    fn attrs(&self) -> Vec<Value>;
+    fn resolve_set_attr(&self, _keys: &Vec<Value>, _value: Value) -> Option<String>;
+    fn resolve_get_attr(&self, keys: &Vec<Value>) -> Result<Value, String>;
     fn resolve_call_attr(
         &mut self,
-        keys: &Vec<Rc<Object>>,
-        args: &Vec<Rc<Object>>,
-    ) -> Result<Rc<Object>, String>;
+        keys: &Vec<Value>,
+        args: &Vec<Value>,
+    ) -> Result<Value, String>;
 }
 
+// large objects are boxed so an object fits in two words, the word sized
+// tag keeps copying a value down to two plain moves.
 #[derive(Clone, Debug, PartialEq)]
+#[repr(u64)]
 pub enum Object {
     Noval,
     Int(i64),
     Bool(bool),
     Char(char),
-    Str(String),
+    Str(Box<String>),
     Byte(u8),
     Float(f64),
     Subroutine(Rc<Subroutine>),
     ClosureContext(Rc<ClosureContext>),
-    Array(RefCell<Array>),
-    ByteBuffer(RefCell<Buffer>),
-    HashTable(RefCell<HashTable>),
+    Array(Box<RefCell<Array>>),
+    ByteBuffer(Box<RefCell<Buffer>>),
+    HashTable(Box<RefCell<HashTable>>),
     Builtins(BuiltinKind),
-    Iter(RefCell<ObjectIterator>),
+    Iter(Box<RefCell<ObjectIterator>>),
     Exception(Rc<Exception>),
-    Thread(RefCell<ThreadBlock>),
+    Thread(Box<RefCell<ThreadBlock>>),
+    Channel(Box<Channel>),
+    Shared(Box<SharedObject>),
+    Future(Box<Future>),
+    Pool(Box<ThreadPool>),
+    Task(Box<TaskHandle>),
+    Process(Box<Process>),
+    ShellResult(Box<ShellResult>),
 }
 
 impl Eq for Object {}
@@ -73,7 +93,7 @@ impl Object {
         match self {
             Object::Int(i) => i.to_string(),
             Object::Char(c) => c.to_string(),
-            Object::Str(st) => st.clone(),
+            Object::Str(st) => st.to_string(),
             Object::Float(f) => f.to_string(),
             Object::Bool(b) => b.to_string(),
             Object::Byte(byte) => byte.to_string(),
@@ -86,6 +106,13 @@ impl Object {
             Object::Exception(exc) => exc.describe(),
             Object::ByteBuffer(buff) => buff.borrow().describe(),
             Object::Thread(th) => th.borrow().describe(),
+            Object::Channel(ch) => ch.describe(),
+            Object::Shared(shared) => shared.describe(),
+            Object::Future(future) => future.describe(),
+            Object::Pool(pool) => pool.describe(),
+            Object::Task(task) => task.describe(),
+            Object::Process(process) => process.describe(),
+            Object::ShellResult(result) => result.describe(),
             _ => String::from("undef"),
         }
     }
@@ -103,6 +130,14 @@ impl Object {
             Object::HashTable(_) => "hashmap".to_string(),
             Object::Iter(_) => "iter".to_string(),
             Object::Exception(_) => "exception".to_string(),
+            Object::Thread(_) => "thread".to_string(),
+            Object::Channel(ch) => ch.get_type(),
+            Object::Shared(shared) => shared.get_type(),
+            Object::Future(future) => future.get_type(),
+            Object::Pool(pool) => pool.get_type(),
+            Object::Task(task) => task.get_type(),
+            Object::Process(process) => process.get_type(),
+            Object::ShellResult(result) => result.get_type(),
             Object::Builtins(_) | Object::Subroutine(_) | Object::ClosureContext(_) => {
                 "func".to_string()
             }
@@ -114,7 +149,7 @@ impl Object {
         match self {
             Object::Bool(val) => val.clone(),
             Object::Noval => false,
-            Object::Str(str) => *str != "",
+            Object::Str(str) => **str != "",
             Object::Int(i) => *i != 0,
             Object::Char(c) => *c != '\0',
             Object::Array(a) => a.borrow().elements.len() != 0,
@@ -126,7 +161,7 @@ impl Object {
         }
     }
 
-    pub fn get_indexed(&self, idx: &Rc<Object>) -> Result<Rc<Object>, String> {
+    pub fn get_indexed(&self, idx: &Value) -> Result<Value, String> {
         match (self, idx.as_ref()) {
             (Object::Array(arr), Object::Int(i)) => {
                 if *i < 0 {
@@ -150,7 +185,7 @@ impl Object {
                 }
 
                 // wrap the result into raw type
-                return Ok(Rc::new(Object::Byte(result.unwrap())));
+                return Ok(Value::new(Object::Byte(result.unwrap())));
             }
 
             (Object::HashTable(ht), _) => {
@@ -171,7 +206,7 @@ impl Object {
                     return Err(format!("String \"{}\" index out of bounds for {}", st, i));
                 }
 
-                return Ok(Rc::new(Object::Char(ch.unwrap())));
+                return Ok(Value::new(Object::Char(ch.unwrap())));
             }
             _ => {
                 return Err(format!(
@@ -183,7 +218,7 @@ impl Object {
         }
     }
 
-    pub fn set_indexed(&mut self, idx: &Rc<Object>, data: Rc<Object>) -> Option<String> {
+    pub fn set_indexed(&mut self, idx: &Value, data: Value) -> Option<String> {
         match (&self, idx.as_ref()) {
             (Object::Array(arr), Object::Int(i)) => {
                 if *i < 0 {
@@ -234,6 +269,107 @@ impl Object {
     }
 }
 
+/*
+    Value is how the VM passes objects around: primitives (none, int, float,
+    bool, char and raw bytes) are stored inline, so creating and copying them
+    never allocates, every other object is shared through a reference count.
+    A value dereferences to its object, so matching on it stays the same.
+    Inline objects own nothing, so they never need to be dropped.
+*/
+pub enum Value {
+    Inline(ManuallyDrop<Object>),
+    Shared(Rc<Object>),
+}
+
+impl Value {
+    pub fn new(obj: Object) -> Value {
+        match obj {
+            Object::Noval
+            | Object::Int(_)
+            | Object::Float(_)
+            | Object::Bool(_)
+            | Object::Char(_)
+            | Object::Byte(_) => Value::Inline(ManuallyDrop::new(obj)),
+            _ => {
+                heap::allocated(heap::size_of(&obj));
+                Value::Shared(Rc::new(obj))
+            }
+        }
+    }
+}
+
+// the size of a shared object is given back with its last reference:
+impl Drop for Value {
+    fn drop(&mut self) {
+        if let Value::Shared(obj) = self {
+            if Rc::strong_count(obj) == 1 {
+                heap::freed(heap::size_of(obj));
+            }
+        }
+    }
+}
+
+// primitives are copied without going through the clone of the object:
+impl Clone for Value {
+    #[inline]
+    fn clone(&self) -> Value {
+        match self {
+            Value::Shared(obj) => Value::Shared(obj.clone()),
+            Value::Inline(obj) => Value::Inline(ManuallyDrop::new(match &**obj {
+                Object::Int(i) => Object::Int(*i),
+                Object::Float(f) => Object::Float(*f),
+                Object::Bool(b) => Object::Bool(*b),
+                Object::Char(c) => Object::Char(*c),
+                Object::Byte(b) => Object::Byte(*b),
+                _ => Object::Noval,
+            })),
+        }
+    }
+}
+
+impl Deref for Value {
+    type Target = Object;
+
+    fn deref(&self) -> &Object {
+        match self {
+            Value::Inline(obj) => obj,
+            Value::Shared(obj) => obj.as_ref(),
+        }
+    }
+}
+
+impl AsRef<Object> for Value {
+    fn as_ref(&self) -> &Object {
+        return self.deref();
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        self.as_ref() == other.as_ref()
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_ref().hash(state);
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.as_ref().fmt(f)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.describe())
+    }
+}
+
 impl fmt::Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.describe())
@@ -241,20 +377,22 @@ impl fmt::Display for Object {
 }
 
 impl AttributeResolver for Object {
-    fn resolve_set_attr(&self, _keys: &Vec<Rc<Object>>, _value: Rc<Object>) -> Option<String> {
+    fn resolve_set_attr(&self, _keys: &Vec<Value>, _value: Value) -> Option<String> {
         // to be implemented
         return None;
     }
 
-    fn resolve_get_attr(&self, keys: &Vec<Rc<Object>>) -> Result<Rc<Object>, String> {
+    fn resolve_get_attr(&self, keys: &Vec<Value>) -> Result<Value, String> {
         let f_key = &keys[0];
 
         match f_key.as_ref() {
             Object::Str(st) => {
-                match st.as_ref() {
+                match st.as_str() {
                     // base attributes
-                    "__address__" => return Ok(Rc::new(Object::Str(format!("{:p}", self)))),
-                    "__size__" => return Ok(Rc::new(Object::Int(mem::size_of::<Self>() as i64))),
+                    "__address__" => {
+                        return Ok(Value::new(Object::Str(Box::new(format!("{:p}", self)))))
+                    }
+                    "__size__" => return Ok(Value::new(Object::Int(mem::size_of::<Self>() as i64))),
                     _ => {}
                 }
 
@@ -262,6 +400,21 @@ impl AttributeResolver for Object {
                     Object::HashTable(ht) => {
                         return ht.borrow().resolve_get_attr(keys);
                     }
+                    Object::Exception(exc) => {
+                        return exc.resolve_get_attr(keys);
+                    }
+                    Object::Thread(th) => {
+                        return th.borrow().get_attribute(keys);
+                    }
+                    Object::Task(task) => {
+                        return task.get_attribute(keys);
+                    }
+                    Object::Process(process) => {
+                        return process.get_attribute(keys);
+                    }
+                    Object::ShellResult(result) => {
+                        return result.get_attribute(keys);
+                    }
                     _ => {
                         return Err(format!(
                             "Object of type {} does not have attribute resolver.",
@@ -274,14 +427,14 @@ impl AttributeResolver for Object {
             _ => {}
         }
 
-        return Ok(Rc::new(Object::Noval));
+        return Ok(Value::new(Object::Noval));
     }
 
     fn resolve_call_attr(
         &mut self,
-        keys: &Vec<Rc<Object>>,
-        args: &Vec<Rc<Object>>,
-    ) -> Result<Rc<Object>, String> {
+        keys: &Vec<Value>,
+        args: &Vec<Value>,
+    ) -> Result<Value, String> {
         match self {
             Object::HashTable(ht) => {
                 return ht.borrow_mut().resolve_call_attr(&keys, &args)
@@ -296,26 +449,43 @@ impl AttributeResolver for Object {
     }
 
     // attributes:
-    fn attrs(&self) -> Vec<Rc<Object>> {
+    fn attrs(&self) -> Vec<Value> {
         let mut main_attrs = vec![
-            Rc::new(Object::Str(String::from("__address__"))),
-            Rc::new(Object::Str(String::from("__size__"))),
+            Value::new(Object::Str(Box::new(String::from("__address__")))),
+            Value::new(Object::Str(Box::new(String::from("__size__")))),
         ];
 
         match self {
             Object::HashTable(ht) => {
                 main_attrs.extend(ht.borrow().attrs());
             }
+            Object::Exception(exc) => {
+                main_attrs.extend(exc.attrs());
+            }
+            Object::Channel(ch) => {
+                main_attrs.extend(ch.attrs());
+            }
+            Object::Shared(shared) => {
+                main_attrs.extend(shared.attrs());
+            }
+            Object::Thread(th) => {
+                main_attrs.extend(th.borrow().attrs());
+            }
+            Object::Pool(pool) => {
+                main_attrs.extend(pool.attrs());
+            }
+            Object::Task(task) => {
+                main_attrs.extend(task.attrs());
+            }
+            Object::Process(process) => {
+                main_attrs.extend(process.attrs());
+            }
+            Object::ShellResult(result) => {
+                main_attrs.extend(result.attrs());
+            }
             _ => {}
         }
 
         return main_attrs;
     }
 }
-
-// make Object capable of sharing between threads.
-// by design, Object will always be thread safe as each thread will
-// get it's own copy of Object.
-// so this unsafe impl is just a cover-up to fool the compiler to make Object
-// sharebale across threads.
-unsafe impl Send for Object {}
@@ -0,0 +1,245 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+
+use crate::types::future::Future;
+use crate::types::object::Object;
+use crate::types::object::Value;
+use crate::vm::thread::ThreadCall;
+
+/*
+    A fixed number of worker threads that run the functions submitted to
+    the pool one after the other. submit(fn, args) queues the call and
+    returns a future of its result. Like threads, the workers start with
+    copies of the globals as they are when the pool is created. The workers
+    stop once the pool is closed, by close() or when the last reference to
+    it is dropped, after running the calls that are still queued.
+*/
+pub struct Job {
+    pub call: ThreadCall,
+    pub future: Future,
+}
+
+struct PoolState {
+    queue: VecDeque<Job>,
+    closed: bool,
+}
+
+struct PoolShared {
+    state: Mutex<PoolState>,
+    // notified when a job is queued or the pool is closed:
+    available: Condvar,
+    size: usize,
+}
+
+impl PoolShared {
+    // a thread that panicked while holding the lock left the queue usable:
+    fn lock(&self) -> MutexGuard<'_, PoolState> {
+        match self.state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn close(&self) {
+        self.lock().closed = true;
+        self.available.notify_all();
+    }
+}
+
+// held by the values of the pool and not by the workers, dropping the
+// last one closes the pool:
+struct PoolOwner {
+    shared: Arc<PoolShared>,
+}
+
+impl Drop for PoolOwner {
+    fn drop(&mut self) {
+        self.shared.close();
+    }
+}
+
+#[derive(Clone)]
+pub struct ThreadPool {
+    owner: Arc<PoolOwner>,
+}
+
+// the end of the queue a worker takes the jobs from:
+pub struct PoolWorker {
+    shared: Arc<PoolShared>,
+}
+
+impl PoolWorker {
+    // waits for the next job, none once the pool is closed and the queue is empty:
+    pub fn next_job(&self) -> Option<Job> {
+        let mut state = self.shared.lock();
+        loop {
+            let job = state.queue.pop_front();
+            if job.is_some() || state.closed {
+                return job;
+            }
+
+            state = match self.shared.available.wait(state) {
+                Ok(guard) => guard,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+        }
+    }
+}
+
+impl ThreadPool {
+    pub fn new(size: usize) -> ThreadPool {
+        let shared = Arc::new(PoolShared {
+            state: Mutex::new(PoolState {
+                queue: VecDeque::new(),
+                closed: false,
+            }),
+            available: Condvar::new(),
+            size: size,
+        });
+
+        return ThreadPool {
+            owner: Arc::new(PoolOwner { shared: shared }),
+        };
+    }
+
+    fn shared(&self) -> &PoolShared {
+        return &self.owner.shared;
+    }
+
+    pub fn worker(&self) -> PoolWorker {
+        return PoolWorker {
+            shared: self.owner.shared.clone(),
+        };
+    }
+
+    pub fn describe(&self) -> String {
+        let state = self.shared().lock();
+        if state.closed {
+            return format!("Pool(workers={}, closed)", self.shared().size);
+        }
+        return format!("Pool(workers={}, pending={})", self.shared().size, state.queue.len());
+    }
+
+    pub fn get_type(&self) -> String {
+        return "pool".to_string();
+    }
+
+    pub fn close(&self) {
+        self.shared().close();
+    }
+
+    pub fn submit(&self, call: ThreadCall, name: String) -> Result<Future, String> {
+        let future = Future::new(name);
+        let mut state = self.shared().lock();
+        if state.closed {
+            return Err("submit() on a closed pool".to_string());
+        }
+
+        state.queue.push_back(Job {
+            call: call,
+            future: future.clone(),
+        });
+
+        drop(state);
+        self.shared().available.notify_one();
+        return Ok(future);
+    }
+
+    pub fn attrs(&self) -> Vec<Value> {
+        return vec!["submit", "size", "pending", "close"]
+            .into_iter()
+            .map(|name| Value::new(Object::Str(Box::new(name.to_string()))))
+            .collect();
+    }
+
+    pub fn resolve_call_attr(
+        &self,
+        keys: &Vec<Value>,
+        args: &Vec<Value>,
+    ) -> Result<Value, String> {
+        let name = match keys[0].as_ref() {
+            Object::Str(st) => st.as_str(),
+            _ => {
+                return Err(format!(
+                    "Attribute of pool must be a string, got {}",
+                    keys[0].get_type()
+                ))
+            }
+        };
+
+        let n_args = match name {
+            "submit" => 2,
+            _ => 0,
+        };
+
+        if args.len() != n_args {
+            return Err(format!(
+                "{}() takes {} arguments, provided {}.",
+                name,
+                n_args,
+                args.len()
+            ));
+        }
+
+        match name {
+            "size" => return Ok(Value::new(Object::Int(self.shared().size as i64))),
+            "pending" => {
+                let pending = self.shared().lock().queue.len();
+                return Ok(Value::new(Object::Int(pending as i64)));
+            }
+            "close" => {
+                self.close();
+                return Ok(Value::new(Object::Noval));
+            }
+            "submit" => {
+                let (ctx, params) = match (args[0].as_ref(), args[1].as_ref()) {
+                    (Object::ClosureContext(ctx), Object::Array(params)) => {
+                        (ctx.clone(), params.borrow().elements.clone())
+                    }
+                    _ => {
+                        return Err(format!(
+                            "submit() takes closure/func and array as arguments, but got {} {}.",
+                            args[0].get_type(),
+                            args[1].get_type()
+                        ))
+                    }
+                };
+
+                let n_parms_required = ctx.compiled_fn.num_parameters;
+                if n_parms_required != params.len() {
+                    return Err(format!(
+                        "Function {} requires {} parameters, provided {}",
+                        ctx.compiled_fn.name,
+                        n_parms_required,
+                        params.len()
+                    ));
+                }
+
+                let name = ctx.compiled_fn.name.clone();
+                let result = self.submit(ThreadCall::new(ctx, params), name);
+                if result.is_err() {
+                    return Err(result.unwrap_err());
+                }
+                return Ok(Value::new(Object::Future(Box::new(result.unwrap()))));
+            }
+            _ => {
+                return Err(format!("Attribute {} not found for type pool", name));
+            }
+        }
+    }
+}
+
+impl PartialEq for ThreadPool {
+    fn eq(&self, other: &ThreadPool) -> bool {
+        return Arc::ptr_eq(&self.owner.shared, &other.owner.shared);
+    }
+}
+
+impl std::fmt::Debug for ThreadPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.describe())
+    }
+}
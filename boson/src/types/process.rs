@@ -0,0 +1,454 @@
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::Weak;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::api::ChildProcess;
+use crate::api::Platform;
+use crate::api::ProcessOptions;
+use crate::api::ProcessStream;
+use crate::isa::InstructionKind;
+use crate::types::buffer::Buffer;
+use crate::types::object::Object;
+use crate::types::object::Value;
+use crate::vm::tasks::Switch;
+use crate::vm::thread::BosonThreads;
+
+// a read or a wait checks the process and the limits of its VM this often:
+const WAIT_SLICE: Duration = Duration::from_millis(10);
+
+/*
+    A process started by process.spawn(cmd, args, opts), the platform runs
+    it with piped stdin, stdout and stderr. The output is read in chunks as
+    it arrives, the exit code is kept once the process is done. With the
+    timeout option, a process still running when the time is up is killed.
+    Waiting for the process or its output lets the other tasks run.
+*/
+struct ProcessState {
+    child: Box<dyn ChildProcess>,
+    exit_code: Option<i32>,
+    deadline: Option<Instant>,
+    // set when the process was killed for running past its timeout:
+    timed_out: bool,
+}
+
+impl ProcessState {
+    // the exit code once the process is done, a process past its deadline is killed:
+    fn poll(&mut self) -> Result<Option<i32>, String> {
+        if self.exit_code.is_some() {
+            return Ok(self.exit_code);
+        }
+
+        if self.deadline.is_some() && Instant::now() >= self.deadline.unwrap() {
+            let kill_result = self.child.kill();
+            if kill_result.is_err() {
+                return Err(kill_result.unwrap_err());
+            }
+            self.deadline = None;
+            self.timed_out = true;
+        }
+
+        let wait_result = self.child.try_wait();
+        if wait_result.is_err() {
+            return Err(wait_result.unwrap_err());
+        }
+
+        self.exit_code = wait_result.unwrap();
+        return Ok(self.exit_code);
+    }
+}
+
+#[derive(Clone)]
+pub struct Process {
+    state: Arc<Mutex<ProcessState>>,
+    pub pid: u32,
+    pub cmd: String,
+}
+
+fn lock_state(state: &Mutex<ProcessState>) -> MutexGuard<'_, ProcessState> {
+    match state.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl Process {
+    // process.spawn(cmd, args, opts), the options are a hash table with
+    // cwd, env, clear_env and timeout (in milliseconds):
+    pub fn spawn(args: &Vec<Value>, platform: &Platform) -> Result<Process, String> {
+        if args.is_empty() || args.len() > 3 {
+            return Err(format!(
+                "spawn() takes 1 to 3 arguments, provided {}.",
+                args.len()
+            ));
+        }
+
+        let cmd = match args[0].as_ref() {
+            Object::Str(cmd) => cmd.as_ref().clone(),
+            _ => {
+                return Err(format!(
+                    "spawn() takes the command as a string, provided {}",
+                    args[0].get_type()
+                ))
+            }
+        };
+
+        let cmd_args: Vec<String> = if args.len() > 1 {
+            match args[1].as_ref() {
+                Object::Array(arr) => arr.borrow().elements.iter().map(|arg| arg.describe()).collect(),
+                _ => {
+                    return Err(format!(
+                        "spawn() takes the arguments as an array, provided {}",
+                        args[1].get_type()
+                    ))
+                }
+            }
+        } else {
+            vec![]
+        };
+
+        let mut opts = ProcessOptions::default();
+        let mut timeout = None;
+        if args.len() > 2 {
+            let entries = match args[2].as_ref() {
+                Object::HashTable(ht) => ht.borrow().entries.clone(),
+                _ => {
+                    return Err(format!(
+                        "spawn() takes the options as a hash table, provided {}",
+                        args[2].get_type()
+                    ))
+                }
+            };
+
+            for (key, value) in entries {
+                match (key.describe().as_str(), value.as_ref()) {
+                    ("cwd", Object::Str(cwd)) => opts.cwd = Some(cwd.as_ref().clone()),
+                    ("env", Object::HashTable(env)) => {
+                        for (name, value) in &env.borrow().entries {
+                            opts.env.push((name.describe(), value.describe()));
+                        }
+                    }
+                    ("clear_env", Object::Bool(clear)) => opts.clear_env = *clear,
+                    ("timeout", Object::Int(ms)) if *ms >= 0 => {
+                        timeout = Some(Duration::from_millis(*ms as u64))
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Invalid option {} of spawn(): {}",
+                            key.describe(),
+                            value.describe()
+                        ))
+                    }
+                }
+            }
+        }
+
+        // names and values of the environment are sorted, so runs are repeatable:
+        opts.env.sort();
+
+        let spawn_fn = platform.spawn_process;
+        let spawn_result = spawn_fn(&cmd, &cmd_args, &opts);
+        if spawn_result.is_err() {
+            return Err(spawn_result.err().unwrap());
+        }
+
+        let child = spawn_result.unwrap();
+        let pid = child.pid();
+        let state = Arc::new(Mutex::new(ProcessState {
+            child: child,
+            exit_code: None,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            timed_out: false,
+        }));
+
+        // kills the process when the time is up, even if nobody waits for it:
+        if timeout.is_some() {
+            let watched: Weak<Mutex<ProcessState>> = Arc::downgrade(&state);
+            let timeout = timeout.unwrap();
+            thread::spawn(move || {
+                thread::sleep(timeout);
+                let state = watched.upgrade();
+                if state.is_some() {
+                    let _ = lock_state(&state.unwrap()).poll();
+                }
+            });
+        }
+
+        return Ok(Process {
+            state: state,
+            pid: pid,
+            cmd: cmd,
+        });
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ProcessState> {
+        return lock_state(&self.state);
+    }
+
+    pub fn describe(&self) -> String {
+        let status = match self.lock().poll() {
+            Ok(Some(code)) => format!("exited={}", code),
+            Ok(None) => "running".to_string(),
+            Err(_) => "unknown".to_string(),
+        };
+        return format!("Process(cmd={}, pid={}, {})", self.cmd, self.pid, status);
+    }
+
+    pub fn get_type(&self) -> String {
+        return "process".to_string();
+    }
+
+    // the next chunk of the stream: none once it is closed, an empty buffer
+    // if nothing arrived before the timeout. Without a timeout, a task lets
+    // the other tasks run until the chunk arrives.
+    fn read(
+        &self,
+        stream: ProcessStream,
+        timeout: Option<Duration>,
+        th: &mut BosonThreads,
+    ) -> Result<Value, String> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let slice = if timeout.is_none() && th.tasks.can_switch {
+            Duration::ZERO
+        } else {
+            WAIT_SLICE
+        };
+
+        loop {
+            let mut state = self.lock();
+            let poll_result = state.poll();
+            if poll_result.is_err() {
+                return Err(poll_result.unwrap_err());
+            }
+
+            let read_result = state.child.read(stream, slice);
+            drop(state);
+            if read_result.is_err() {
+                return Err(read_result.unwrap_err());
+            }
+
+            let chunk = read_result.unwrap();
+            if chunk.is_none() {
+                return Ok(Value::new(Object::Noval));
+            }
+
+            let chunk = chunk.unwrap();
+            if !chunk.is_empty()
+                || (deadline.is_some() && Instant::now() >= deadline.unwrap())
+            {
+                let buffer = Buffer::from_u8(chunk, "process".to_string(), false);
+                return Ok(Value::new(Object::ByteBuffer(Box::new(RefCell::new(buffer)))));
+            }
+
+            if timeout.is_none() && th.tasks.switch(Switch::Block) {
+                return Ok(Value::new(Object::Noval));
+            }
+
+            let interruption = th.limits.interrupted(&InstructionKind::ICallAttr);
+            if interruption.is_some() {
+                return Err(interruption.unwrap().message);
+            }
+        }
+    }
+
+    // the exit code, none if the process is still running when the timeout is up:
+    fn wait(&self, timeout: Option<Duration>, th: &mut BosonThreads) -> Result<Value, String> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            let poll_result = self.lock().poll();
+            if poll_result.is_err() {
+                return Err(poll_result.unwrap_err());
+            }
+
+            let exit_code = poll_result.unwrap();
+            if exit_code.is_some() {
+                return Ok(Value::new(Object::Int(exit_code.unwrap() as i64)));
+            }
+
+            if timeout.is_none() && th.tasks.switch(Switch::Block) {
+                return Ok(Value::new(Object::Noval));
+            }
+
+            if deadline.is_some() && Instant::now() >= deadline.unwrap() {
+                return Ok(Value::new(Object::Noval));
+            }
+
+            let interruption = th.limits.interrupted(&InstructionKind::ICallAttr);
+            if interruption.is_some() {
+                return Err(interruption.unwrap().message);
+            }
+
+            thread::sleep(WAIT_SLICE);
+        }
+    }
+
+    pub fn attrs(&self) -> Vec<Value> {
+        return vec![
+            "pid",
+            "cmd",
+            "read_stdout",
+            "read_stderr",
+            "write",
+            "close_stdin",
+            "wait",
+            "kill",
+            "is_running",
+            "exit_code",
+            "timed_out",
+        ]
+        .into_iter()
+        .map(|name| Value::new(Object::Str(Box::new(name.to_string()))))
+        .collect();
+    }
+
+    pub fn get_attribute(&self, keys: &Vec<Value>) -> Result<Value, String> {
+        match keys[0].as_ref() {
+            Object::Str(st) => match st.as_str() {
+                "pid" => return Ok(Value::new(Object::Int(self.pid as i64))),
+                "cmd" => return Ok(Value::new(Object::Str(Box::new(self.cmd.clone())))),
+                _ => {}
+            },
+            _ => {}
+        }
+
+        return Err(format!(
+            "Attribute {} not found for type process",
+            keys[0].describe()
+        ));
+    }
+
+    pub fn resolve_call_attr(
+        &self,
+        keys: &Vec<Value>,
+        args: &Vec<Value>,
+        th: &mut BosonThreads,
+    ) -> Result<Value, String> {
+        let name = match keys[0].as_ref() {
+            Object::Str(st) => st.as_str(),
+            _ => {
+                return Err(format!(
+                    "Attribute of process must be a string, got {}",
+                    keys[0].get_type()
+                ))
+            }
+        };
+
+        // the reads and the wait take an optional timeout in milliseconds:
+        let (min_args, max_args) = match name {
+            "write" => (1, 1),
+            "read_stdout" | "read_stderr" | "wait" => (0, 1),
+            _ => (0, 0),
+        };
+
+        if args.len() < min_args || args.len() > max_args {
+            return Err(format!(
+                "{}() takes {} arguments, provided {}.",
+                name,
+                max_args,
+                args.len()
+            ));
+        }
+
+        let timeout = if max_args == 1 && name != "write" && args.len() == 1 {
+            match args[0].as_ref() {
+                Object::Int(ms) if *ms >= 0 => Some(Duration::from_millis(*ms as u64)),
+                _ => {
+                    return Err(format!(
+                        "{}() takes a positive int, provided {}",
+                        name,
+                        args[0].describe()
+                    ))
+                }
+            }
+        } else {
+            None
+        };
+
+        match name {
+            "read_stdout" => return self.read(ProcessStream::Stdout, timeout, th),
+            "read_stderr" => return self.read(ProcessStream::Stderr, timeout, th),
+            "wait" => return self.wait(timeout, th),
+            "write" => {
+                let data = match args[0].as_ref() {
+                    Object::Str(st) => st.as_bytes().to_vec(),
+                    Object::ByteBuffer(buffer) => buffer.borrow().data.clone(),
+                    _ => {
+                        return Err(format!(
+                            "write() takes a string or bytes, provided {}",
+                            args[0].get_type()
+                        ))
+                    }
+                };
+
+                let result = self.lock().child.write(&data);
+                if result.is_err() {
+                    return Err(result.unwrap_err());
+                }
+                return Ok(Value::new(Object::Int(result.unwrap() as i64)));
+            }
+            "close_stdin" => {
+                self.lock().child.close_stdin();
+                return Ok(Value::new(Object::Noval));
+            }
+            "kill" => {
+                let result = self.lock().child.kill();
+                if result.is_err() {
+                    return Err(result.unwrap_err());
+                }
+                return Ok(Value::new(Object::Noval));
+            }
+            "is_running" | "exit_code" => {
+                let poll_result = self.lock().poll();
+                if poll_result.is_err() {
+                    return Err(poll_result.unwrap_err());
+                }
+
+                let exit_code = poll_result.unwrap();
+                if name == "is_running" {
+                    return Ok(Value::new(Object::Bool(exit_code.is_none())));
+                }
+                return Ok(exit_code
+                    .map(|code| Value::new(Object::Int(code as i64)))
+                    .unwrap_or(Value::new(Object::Noval)));
+            }
+            "timed_out" => return Ok(Value::new(Object::Bool(self.lock().timed_out))),
+            _ => {
+                return Err(format!("Attribute {} not found for type process", name));
+            }
+        }
+    }
+
+    // process.spawn(), the functions of the process builtin:
+    pub fn resolve_module_call(
+        keys: &Vec<Value>,
+        args: &Vec<Value>,
+        platform: &Platform,
+    ) -> Result<Value, String> {
+        match keys[0].describe().as_str() {
+            "spawn" => {
+                let result = Process::spawn(args, platform);
+                if result.is_err() {
+                    return Err(result.unwrap_err());
+                }
+                return Ok(Value::new(Object::Process(Box::new(result.unwrap()))));
+            }
+            name => return Err(format!("Attribute {} not found for process", name)),
+        }
+    }
+}
+
+impl PartialEq for Process {
+    fn eq(&self, other: &Process) -> bool {
+        return Arc::ptr_eq(&self.state, &other.state);
+    }
+}
+
+impl std::fmt::Debug for Process {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Process(cmd={}, pid={})", self.cmd, self.pid)
+    }
+}
@@ -0,0 +1,494 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicI64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::TryLockError;
+use std::thread;
+use std::thread::ThreadId;
+use std::time::Duration;
+
+use crate::api::Platform;
+use crate::compiler::symtab::ConstantPool;
+use crate::isa::InstructionKind;
+use crate::types::array::Array;
+use crate::types::builtins::BuiltinKind;
+use crate::types::object::Object;
+use crate::types::object::Value;
+use crate::vm::global::GlobalPool;
+use crate::vm::limits::ExecutionLimits;
+use crate::vm::thread::BosonThreads;
+use crate::vm::transfer::Transfer;
+
+// a thread waiting for a lock held by another one checks its limits this often:
+const LOCK_SLICE: Duration = Duration::from_millis(1);
+
+/*
+    Objects shared by all the threads that hold them, copying a shared
+    object to another thread copies the reference and not the contents.
+    shared_map() is a hash table with scalar keys, atomic_int() an integer
+    updated in one step and mutex(value) a value that is only used by one
+    thread at a time through lock(fn). The values stored in a map or a
+    mutex are copied in and out like the values sent through a channel.
+*/
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum SharedKey {
+    Int(i64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Byte(u8),
+}
+
+impl SharedKey {
+    fn from_value(value: &Value) -> Result<SharedKey, String> {
+        match value.as_ref() {
+            Object::Int(i) => Ok(SharedKey::Int(*i)),
+            Object::Bool(b) => Ok(SharedKey::Bool(*b)),
+            Object::Char(c) => Ok(SharedKey::Char(*c)),
+            Object::Str(st) => Ok(SharedKey::Str(st.as_ref().clone())),
+            Object::Byte(b) => Ok(SharedKey::Byte(*b)),
+            _ => Err(format!(
+                "Keys of a shared map must be int, bool, char, string or raw, got {}",
+                value.get_type()
+            )),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        let obj = match self {
+            SharedKey::Int(i) => Object::Int(*i),
+            SharedKey::Bool(b) => Object::Bool(*b),
+            SharedKey::Char(c) => Object::Char(*c),
+            SharedKey::Str(st) => Object::Str(Box::new(st.clone())),
+            SharedKey::Byte(b) => Object::Byte(*b),
+        };
+
+        return Value::new(obj);
+    }
+}
+
+// a mutex that knows the thread holding it, so locking it twice from the
+// same thread fails instead of waiting forever:
+struct Guarded<T> {
+    value: Mutex<T>,
+    owner: Mutex<Option<ThreadId>>,
+}
+
+struct Held<'a, T> {
+    guard: MutexGuard<'a, T>,
+    owner: &'a Mutex<Option<ThreadId>>,
+}
+
+impl<'a, T> Drop for Held<'a, T> {
+    fn drop(&mut self) {
+        *lock_owner(self.owner) = None;
+    }
+}
+
+fn lock_owner(owner: &Mutex<Option<ThreadId>>) -> MutexGuard<'_, Option<ThreadId>> {
+    match owner.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+impl<T> Guarded<T> {
+    fn new(value: T) -> Guarded<T> {
+        return Guarded {
+            value: Mutex::new(value),
+            owner: Mutex::new(None),
+        };
+    }
+
+    fn is_locked(&self) -> bool {
+        return lock_owner(&self.owner).is_some();
+    }
+
+    fn acquire(&self, limits: &ExecutionLimits) -> Result<Held<'_, T>, String> {
+        loop {
+            let guard = match self.value.try_lock() {
+                Ok(guard) => Some(guard),
+                Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+                Err(TryLockError::WouldBlock) => None,
+            };
+
+            if guard.is_some() {
+                *lock_owner(&self.owner) = Some(thread::current().id());
+                return Ok(Held {
+                    guard: guard.unwrap(),
+                    owner: &self.owner,
+                });
+            }
+
+            if *lock_owner(&self.owner) == Some(thread::current().id()) {
+                return Err("Lock is already held by this thread".to_string());
+            }
+
+            let interruption = limits.interrupted(&InstructionKind::ICallAttr);
+            if interruption.is_some() {
+                return Err(interruption.unwrap().message);
+            }
+
+            thread::sleep(LOCK_SLICE);
+        }
+    }
+}
+
+#[derive(Clone)]
+enum SharedKind {
+    Map(Arc<Guarded<HashMap<SharedKey, Transfer>>>),
+    AtomicInt(Arc<AtomicI64>),
+    Mutex(Arc<Guarded<Transfer>>),
+}
+
+#[derive(Clone)]
+pub struct SharedObject {
+    kind: SharedKind,
+}
+
+fn pack_one(value: &Value) -> Transfer {
+    return Transfer::pack(&vec![value.clone()]);
+}
+
+fn unpack_one(transfer: &Transfer) -> Value {
+    return transfer.clone().unpack().pop().unwrap();
+}
+
+fn get_int(name: &str, value: &Value) -> Result<i64, String> {
+    match value.as_ref() {
+        Object::Int(i) => Ok(*i),
+        _ => Err(format!("{}() takes an int, provided {}", name, value.get_type())),
+    }
+}
+
+// runs fn(value) on the thread that called the method, like call_func:
+fn call_function(
+    func: &Value,
+    value: Value,
+    platform: &Platform,
+    gp: &mut GlobalPool,
+    c: &mut ConstantPool,
+    th: &mut BosonThreads,
+) -> Result<Value, String> {
+    let params = Array {
+        name: "params".to_string(),
+        elements: vec![value],
+    };
+
+    let args = vec![
+        func.clone(),
+        Value::new(Object::Array(Box::new(RefCell::new(params)))),
+    ];
+
+    return BuiltinKind::CallFunc.exec(args, platform, gp, c, th);
+}
+
+impl SharedObject {
+    pub fn new_map() -> SharedObject {
+        return SharedObject {
+            kind: SharedKind::Map(Arc::new(Guarded::new(HashMap::new()))),
+        };
+    }
+
+    pub fn new_atomic_int(value: i64) -> SharedObject {
+        return SharedObject {
+            kind: SharedKind::AtomicInt(Arc::new(AtomicI64::new(value))),
+        };
+    }
+
+    pub fn new_mutex(value: &Value) -> SharedObject {
+        return SharedObject {
+            kind: SharedKind::Mutex(Arc::new(Guarded::new(pack_one(value)))),
+        };
+    }
+
+    pub fn describe(&self) -> String {
+        match &self.kind {
+            SharedKind::Map(map) => {
+                let length = match map.value.try_lock() {
+                    Ok(guard) => guard.len(),
+                    Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner().len(),
+                    Err(TryLockError::WouldBlock) => return "SharedMap(locked)".to_string(),
+                };
+                return format!("SharedMap(len={})", length);
+            }
+            SharedKind::AtomicInt(atomic) => {
+                format!("AtomicInt({})", atomic.load(Ordering::SeqCst))
+            }
+            SharedKind::Mutex(mutex) => {
+                if mutex.is_locked() {
+                    return "Mutex(locked)".to_string();
+                }
+                return "Mutex(unlocked)".to_string();
+            }
+        }
+    }
+
+    pub fn get_type(&self) -> String {
+        match &self.kind {
+            SharedKind::Map(_) => "shared_map".to_string(),
+            SharedKind::AtomicInt(_) => "atomic_int".to_string(),
+            SharedKind::Mutex(_) => "mutex".to_string(),
+        }
+    }
+
+    pub fn attrs(&self) -> Vec<Value> {
+        let names = match &self.kind {
+            SharedKind::Map(_) => vec![
+                "get", "set", "remove", "contains", "keys", "len", "update",
+            ],
+            SharedKind::AtomicInt(_) => vec![
+                "get", "set", "add", "incr", "decr", "swap", "compare_and_swap",
+            ],
+            SharedKind::Mutex(_) => vec!["get", "set", "lock", "is_locked"],
+        };
+
+        return names
+            .into_iter()
+            .map(|name| Value::new(Object::Str(Box::new(name.to_string()))))
+            .collect();
+    }
+
+    fn n_args(&self, name: &str) -> Option<usize> {
+        match (&self.kind, name) {
+            (SharedKind::Map(_), "keys" | "len") => Some(0),
+            (SharedKind::Map(_), "get" | "remove" | "contains") => Some(1),
+            (SharedKind::Map(_), "set" | "update") => Some(2),
+            (SharedKind::AtomicInt(_), "get" | "incr" | "decr") => Some(0),
+            (SharedKind::AtomicInt(_), "set" | "add" | "swap") => Some(1),
+            (SharedKind::AtomicInt(_), "compare_and_swap") => Some(2),
+            (SharedKind::Mutex(_), "get" | "is_locked") => Some(0),
+            (SharedKind::Mutex(_), "set" | "lock") => Some(1),
+            _ => None,
+        }
+    }
+
+    pub fn resolve_call_attr(
+        &self,
+        keys: &Vec<Value>,
+        args: &Vec<Value>,
+        platform: &Platform,
+        gp: &mut GlobalPool,
+        c: &mut ConstantPool,
+        th: &mut BosonThreads,
+    ) -> Result<Value, String> {
+        let name = match keys[0].as_ref() {
+            Object::Str(st) => st.as_str(),
+            _ => {
+                return Err(format!(
+                    "Attribute of {} must be a string, got {}",
+                    self.get_type(),
+                    keys[0].get_type()
+                ))
+            }
+        };
+
+        let n_args = self.n_args(name);
+        if n_args.is_none() {
+            return Err(format!(
+                "Attribute {} not found for type {}",
+                name,
+                self.get_type()
+            ));
+        }
+
+        if args.len() != n_args.unwrap() {
+            return Err(format!(
+                "{}() takes {} arguments, provided {}.",
+                name,
+                n_args.unwrap(),
+                args.len()
+            ));
+        }
+
+        match &self.kind {
+            SharedKind::Map(map) => {
+                return SharedObject::call_map(map, name, args, platform, gp, c, th)
+            }
+            SharedKind::AtomicInt(atomic) => {
+                return SharedObject::call_atomic_int(atomic, name, args)
+            }
+            SharedKind::Mutex(mutex) => {
+                return SharedObject::call_mutex(mutex, name, args, platform, gp, c, th)
+            }
+        }
+    }
+
+    fn call_map(
+        map: &Guarded<HashMap<SharedKey, Transfer>>,
+        name: &str,
+        args: &Vec<Value>,
+        platform: &Platform,
+        gp: &mut GlobalPool,
+        c: &mut ConstantPool,
+        th: &mut BosonThreads,
+    ) -> Result<Value, String> {
+        let mut key = None;
+        if name != "keys" && name != "len" {
+            let key_result = SharedKey::from_value(&args[0]);
+            if key_result.is_err() {
+                return Err(key_result.unwrap_err());
+            }
+            key = key_result.ok();
+        }
+
+        let held = map.acquire(&th.limits);
+        if held.is_err() {
+            return Err(held.err().unwrap());
+        }
+
+        let mut held = held.ok().unwrap();
+        let entries = &mut *held.guard;
+
+        match name {
+            "get" => {
+                let value = entries.get(key.as_ref().unwrap());
+                return Ok(value.map(unpack_one).unwrap_or(Value::new(Object::Noval)));
+            }
+            "set" => {
+                entries.insert(key.unwrap(), pack_one(&args[1]));
+                return Ok(Value::new(Object::Noval));
+            }
+            "remove" => {
+                let value = entries.remove(key.as_ref().unwrap());
+                return Ok(value.map(|v| unpack_one(&v)).unwrap_or(Value::new(Object::Noval)));
+            }
+            "contains" => {
+                return Ok(Value::new(Object::Bool(entries.contains_key(key.as_ref().unwrap()))));
+            }
+            "len" => return Ok(Value::new(Object::Int(entries.len() as i64))),
+            "keys" => {
+                let keys = Array {
+                    name: "keys".to_string(),
+                    elements: entries.keys().map(|key| key.to_value()).collect(),
+                };
+                return Ok(Value::new(Object::Array(Box::new(RefCell::new(keys)))));
+            }
+            // update(key, fn) stores fn(value) while the map is locked, the
+            // value is none when the key is missing:
+            _ => {
+                let key = key.unwrap();
+                let current = entries.get(&key).map(unpack_one).unwrap_or(Value::new(Object::Noval));
+
+                let result = call_function(&args[1], current, platform, gp, c, th);
+                if result.is_err() {
+                    return Err(result.unwrap_err());
+                }
+
+                let value = result.unwrap();
+                entries.insert(key, pack_one(&value));
+                return Ok(value);
+            }
+        }
+    }
+
+    fn call_atomic_int(atomic: &AtomicI64, name: &str, args: &Vec<Value>) -> Result<Value, String> {
+        let value = match name {
+            "get" => atomic.load(Ordering::SeqCst),
+            "incr" => atomic.fetch_add(1, Ordering::SeqCst).wrapping_add(1),
+            "decr" => atomic.fetch_sub(1, Ordering::SeqCst).wrapping_sub(1),
+            "compare_and_swap" => {
+                let expected = get_int(name, &args[0]);
+                if expected.is_err() {
+                    return Err(expected.unwrap_err());
+                }
+
+                let new = get_int(name, &args[1]);
+                if new.is_err() {
+                    return Err(new.unwrap_err());
+                }
+
+                let swapped = atomic.compare_exchange(
+                    expected.unwrap(),
+                    new.unwrap(),
+                    Ordering::SeqCst,
+                    Ordering::SeqCst,
+                );
+                return Ok(Value::new(Object::Bool(swapped.is_ok())));
+            }
+            _ => {
+                let operand = get_int(name, &args[0]);
+                if operand.is_err() {
+                    return Err(operand.unwrap_err());
+                }
+
+                let operand = operand.unwrap();
+                match name {
+                    // set returns the new value, swap the one it replaced:
+                    "set" => {
+                        atomic.store(operand, Ordering::SeqCst);
+                        operand
+                    }
+                    "swap" => atomic.swap(operand, Ordering::SeqCst),
+                    _ => atomic.fetch_add(operand, Ordering::SeqCst).wrapping_add(operand),
+                }
+            }
+        };
+
+        return Ok(Value::new(Object::Int(value)));
+    }
+
+    fn call_mutex(
+        mutex: &Guarded<Transfer>,
+        name: &str,
+        args: &Vec<Value>,
+        platform: &Platform,
+        gp: &mut GlobalPool,
+        c: &mut ConstantPool,
+        th: &mut BosonThreads,
+    ) -> Result<Value, String> {
+        if name == "is_locked" {
+            return Ok(Value::new(Object::Bool(mutex.is_locked())));
+        }
+
+        let held = mutex.acquire(&th.limits);
+        if held.is_err() {
+            return Err(held.err().unwrap());
+        }
+
+        let mut held = held.ok().unwrap();
+        match name {
+            "get" => return Ok(unpack_one(&held.guard)),
+            "set" => {
+                *held.guard = pack_one(&args[0]);
+                return Ok(Value::new(Object::Noval));
+            }
+            // lock(fn) runs fn(value) while no other thread can use the mutex,
+            // what fn returns becomes the new value:
+            _ => {
+                let current = unpack_one(&held.guard);
+                let result = call_function(&args[0], current, platform, gp, c, th);
+                if result.is_err() {
+                    return Err(result.unwrap_err());
+                }
+
+                let value = result.unwrap();
+                *held.guard = pack_one(&value);
+                return Ok(value);
+            }
+        }
+    }
+
+    fn address(&self) -> usize {
+        match &self.kind {
+            SharedKind::Map(map) => Arc::as_ptr(map) as *const u8 as usize,
+            SharedKind::AtomicInt(atomic) => Arc::as_ptr(atomic) as *const u8 as usize,
+            SharedKind::Mutex(mutex) => Arc::as_ptr(mutex) as *const u8 as usize,
+        }
+    }
+}
+
+impl PartialEq for SharedObject {
+    fn eq(&self, other: &SharedObject) -> bool {
+        return self.address() == other.address();
+    }
+}
+
+impl std::fmt::Debug for SharedObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.describe())
+    }
+}
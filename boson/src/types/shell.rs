@@ -0,0 +1,302 @@
+use std::cell::RefCell;
+use std::thread;
+use std::time::Duration;
+
+use crate::api::Platform;
+use crate::api::ProcessOptions;
+use crate::api::ProcessStream;
+use crate::isa::InstructionKind;
+use crate::types::buffer::Buffer;
+use crate::types::object::Object;
+use crate::types::object::Value;
+use crate::vm::thread::BosonThreads;
+
+// the output is read and the limits of the VM are checked this often:
+const READ_SLICE: Duration = Duration::from_millis(10);
+
+/*
+    Templated shell commands: sh("cat {} | grep -c {}", [file, pattern])
+    and $$ "cat {file} | grep -c {pattern}" put every value into the command
+    shell-quoted, so a value is always one word for the shell, whatever it
+    contains. {} takes the next value of an array, {name} the value of a
+    hash table, {{ and }} are the braces themselves. The command runs with
+    the shell of the platform and gives a ShellResult with the exit code,
+    stdout and stderr.
+*/
+
+// the characters a word can have without quotes:
+fn is_plain(ch: char) -> bool {
+    return ch.is_ascii_alphanumeric() || "_-./=:,+@%".contains(ch);
+}
+
+// the value as a single word of a POSIX shell:
+pub fn quote(value: &Value) -> String {
+    let word = match value.as_ref() {
+        Object::Str(st) => st.as_ref().clone(),
+        Object::ByteBuffer(buffer) => String::from_utf8_lossy(&buffer.borrow().data).to_string(),
+        _ => value.describe(),
+    };
+
+    if !word.is_empty() && word.chars().all(is_plain) {
+        return word;
+    }
+
+    return format!("'{}'", word.replace('\'', "'\\''"));
+}
+
+// the names of the {name} placeholders of a template, none if it has none:
+pub fn placeholders(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = vec![];
+    let mut chars = template.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if (ch == '{' || ch == '}') && chars.peek() == Some(&ch) {
+            chars.next();
+            continue;
+        }
+
+        if ch != '{' {
+            continue;
+        }
+
+        let mut name = String::new();
+        while let Some(next) = chars.peek() {
+            if *next == '}' || !(next.is_ascii_alphanumeric() || *next == '_') {
+                break;
+            }
+            name.push(*next);
+            chars.next();
+        }
+
+        let is_name = !name.is_empty() && !name.starts_with(|ch: char| ch.is_ascii_digit());
+        if is_name && chars.peek() == Some(&'}') && !names.contains(&name) {
+            names.push(name);
+        }
+    }
+
+    return names;
+}
+
+// the command of the template with the quoted values:
+pub fn render(template: &str, values: &Value) -> Result<String, String> {
+    let mut rendered = String::new();
+    let mut next_position = 0;
+    let mut chars = template.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if (ch == '{' || ch == '}') && chars.peek() == Some(&ch) {
+            chars.next();
+            rendered.push(ch);
+            continue;
+        }
+
+        if ch == '}' {
+            return Err(format!("Unmatched }} in the shell template {}", template));
+        }
+
+        if ch != '{' {
+            rendered.push(ch);
+            continue;
+        }
+
+        let mut name = String::new();
+        let mut closed = false;
+        for next in chars.by_ref() {
+            if next == '}' {
+                closed = true;
+                break;
+            }
+            name.push(next);
+        }
+
+        if !closed {
+            return Err(format!("Unmatched {{ in the shell template {}", template));
+        }
+
+        let value = match values.as_ref() {
+            Object::Array(arr) if name.is_empty() => {
+                let value = arr.borrow().elements.get(next_position).cloned();
+                next_position += 1;
+                value
+            }
+            Object::HashTable(ht) if !name.is_empty() => {
+                let key = Value::new(Object::Str(Box::new(name.clone())));
+                ht.borrow().entries.get(&key).cloned()
+            }
+            _ => {
+                return Err(format!(
+                    "The placeholder {{{}}} needs {} of values, provided {}",
+                    name,
+                    if name.is_empty() { "an array" } else { "a hash table" },
+                    values.get_type()
+                ))
+            }
+        };
+
+        if value.is_none() {
+            return Err(format!("No value for the placeholder {{{}}} of {}", name, template));
+        }
+
+        rendered.push_str(&quote(&value.unwrap()));
+    }
+
+    return Ok(rendered);
+}
+
+// runs the command with the shell of the platform until it exits:
+pub fn run(
+    command: String,
+    platform: &Platform,
+    th: &mut BosonThreads,
+    is_raw: bool,
+) -> Result<ShellResult, String> {
+    let shell_fn = platform.sys_shell;
+    let mut shell: Vec<String> = shell_fn().split_whitespace().map(|s| s.to_string()).collect();
+    if shell.is_empty() {
+        return Err("The platform has no shell".to_string());
+    }
+
+    let shell_cmd = shell.remove(0);
+    shell.push(command.clone());
+
+    let spawn_fn = platform.spawn_process;
+    let spawn_result = spawn_fn(&shell_cmd, &shell, &ProcessOptions::default());
+    if spawn_result.is_err() {
+        return Err(spawn_result.err().unwrap());
+    }
+
+    let mut child = spawn_result.unwrap();
+    child.close_stdin();
+
+    // both streams are drained together, so a full pipe cannot stall the
+    // command. A round that got nothing waits for the next one.
+    let streams = [ProcessStream::Stdout, ProcessStream::Stderr];
+    let mut outputs: [Vec<u8>; 2] = [vec![], vec![]];
+    let mut open = [true, true];
+    let mut idle = false;
+    while open[0] || open[1] {
+        let mut waited = false;
+        let mut got_data = false;
+        for idx in 0..2 {
+            if !open[idx] {
+                continue;
+            }
+
+            let timeout = if idle && !waited { READ_SLICE } else { Duration::ZERO };
+            waited = true;
+            let read_result = child.read(streams[idx], timeout);
+            if read_result.is_err() {
+                return Err(read_result.unwrap_err());
+            }
+
+            match read_result.unwrap() {
+                Some(chunk) => {
+                    got_data = got_data || !chunk.is_empty();
+                    outputs[idx].extend(chunk);
+                }
+                None => open[idx] = false,
+            }
+        }
+
+        idle = !got_data;
+        let interruption = th.limits.interrupted(&InstructionKind::ICall);
+        if interruption.is_some() {
+            let _ = child.kill();
+            return Err(interruption.unwrap().message);
+        }
+    }
+
+    let code = loop {
+        let wait_result = child.try_wait();
+        if wait_result.is_err() {
+            return Err(wait_result.unwrap_err());
+        }
+
+        let exit_code = wait_result.unwrap();
+        if exit_code.is_some() {
+            break exit_code.unwrap();
+        }
+
+        let interruption = th.limits.interrupted(&InstructionKind::ICall);
+        if interruption.is_some() {
+            let _ = child.kill();
+            return Err(interruption.unwrap().message);
+        }
+        thread::sleep(READ_SLICE);
+    };
+
+    // the output of sh() is text, sh_raw() keeps the bytes:
+    if !is_raw {
+        for output in &outputs {
+            let text = std::str::from_utf8(output);
+            if text.is_err() {
+                return Err(format!("{}", text.unwrap_err()));
+            }
+        }
+    }
+
+    let [stdout, stderr] = outputs;
+    return Ok(ShellResult {
+        cmd: command,
+        code: code as i64,
+        stdout: stdout,
+        stderr: stderr,
+        is_raw: is_raw,
+    });
+}
+
+// the exit code, stdout and stderr of a shell command:
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShellResult {
+    pub cmd: String,
+    pub code: i64,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+    is_raw: bool,
+}
+
+impl ShellResult {
+    fn output_value(&self, data: &Vec<u8>) -> Value {
+        if self.is_raw {
+            let buffer = Buffer::from_u8(data.clone(), "shell".to_string(), true);
+            return Value::new(Object::ByteBuffer(Box::new(RefCell::new(buffer))));
+        }
+        return Value::new(Object::Str(Box::new(String::from_utf8_lossy(data).to_string())));
+    }
+
+    pub fn describe(&self) -> String {
+        return format!(
+            "ShellResult(code={}, stdout={}, stderr={})",
+            self.code,
+            self.output_value(&self.stdout).describe(),
+            self.output_value(&self.stderr).describe()
+        );
+    }
+
+    pub fn get_type(&self) -> String {
+        return "shell_result".to_string();
+    }
+
+    pub fn attrs(&self) -> Vec<Value> {
+        return vec!["cmd", "code", "stdout", "stderr"]
+            .into_iter()
+            .map(|name| Value::new(Object::Str(Box::new(name.to_string()))))
+            .collect();
+    }
+
+    pub fn get_attribute(&self, keys: &Vec<Value>) -> Result<Value, String> {
+        match keys[0].as_ref() {
+            Object::Str(st) => match st.as_str() {
+                "cmd" => return Ok(Value::new(Object::Str(Box::new(self.cmd.clone())))),
+                "code" => return Ok(Value::new(Object::Int(self.code))),
+                "stdout" => return Ok(self.output_value(&self.stdout)),
+                "stderr" => return Ok(self.output_value(&self.stderr)),
+                _ => {}
+            },
+            _ => {}
+        }
+
+        return Err(format!(
+            "Attribute {} not found for type shell_result",
+            keys[0].describe()
+        ));
+    }
+}
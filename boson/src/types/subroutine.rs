@@ -2,6 +2,8 @@ use std::fmt;
 use std::hash::{Hash, Hasher};
 
 use crate::compiler::CompiledInstructions;
+use crate::isa::DecodedInstructions;
+use crate::isa::InstructionPacker;
 
 #[derive(Clone, Debug)]
 pub struct Subroutine {
@@ -10,9 +12,30 @@ pub struct Subroutine {
     pub num_locals: usize,
     pub num_parameters: usize,
     pub is_local_scope: bool,
+    // the bytecode decoded once, executed by the VM:
+    pub instructions: DecodedInstructions,
 }
 
 impl Subroutine {
+    pub fn new(
+        name: String,
+        bytecode: CompiledInstructions,
+        num_locals: usize,
+        num_parameters: usize,
+        is_local_scope: bool,
+    ) -> Subroutine {
+        let instructions = InstructionPacker::decode_instructions(&bytecode);
+
+        return Subroutine {
+            name: name,
+            bytecode: bytecode,
+            num_locals: num_locals,
+            num_parameters: num_parameters,
+            is_local_scope: is_local_scope,
+            instructions: instructions,
+        };
+    }
+
     pub fn get_name(&self) -> &String {
         return &self.name;
     }
@@ -21,6 +44,10 @@ impl Subroutine {
         return &self.bytecode;
     }
 
+    pub fn get_instructions(&self) -> &DecodedInstructions {
+        return &self.instructions;
+    }
+
     pub fn get_n_locals(&self) -> usize {
         return self.num_locals;
     }
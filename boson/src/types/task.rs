@@ -0,0 +1,87 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::types::object::Object;
+use crate::types::object::Value;
+use crate::vm::errors::VMError;
+
+/*
+    The handle of a task started by spawn(fn, args). Tasks are coroutines
+    run one at a time by the VM that spawned them, they share its globals
+    and switch only at yield points, so a task never leaves the VM.
+    await(task) gives the result of the function or throws its error with
+    its own kind.
+*/
+#[derive(Clone)]
+pub struct TaskHandle {
+    pub id: u64,
+    pub name: String,
+    result: Rc<RefCell<Option<Result<Value, VMError>>>>,
+}
+
+impl TaskHandle {
+    pub fn new(id: u64, name: String) -> TaskHandle {
+        return TaskHandle {
+            id: id,
+            name: name,
+            result: Rc::new(RefCell::new(None)),
+        };
+    }
+
+    pub fn describe(&self) -> String {
+        let state = if self.is_done() { "done" } else { "pending" };
+        return format!("Task(name={}, id={}, {})", self.name, self.id, state);
+    }
+
+    pub fn get_type(&self) -> String {
+        return "task".to_string();
+    }
+
+    pub fn is_done(&self) -> bool {
+        return self.result.borrow().is_some();
+    }
+
+    pub fn complete(&self, result: Result<Value, VMError>) {
+        *self.result.borrow_mut() = Some(result);
+    }
+
+    // none while the task is still running:
+    pub fn get_result(&self) -> Option<Result<Value, VMError>> {
+        return self.result.borrow().clone();
+    }
+
+    pub fn attrs(&self) -> Vec<Value> {
+        return vec![
+            Value::new(Object::Str(Box::new(String::from("id")))),
+            Value::new(Object::Str(Box::new(String::from("name")))),
+        ];
+    }
+
+    pub fn get_attribute(&self, keys: &Vec<Value>) -> Result<Value, String> {
+        match keys[0].as_ref() {
+            Object::Str(st) => match st.as_str() {
+                "id" => return Ok(Value::new(Object::Int(self.id as i64))),
+                "name" => return Ok(Value::new(Object::Str(Box::new(self.name.clone())))),
+                _ => {}
+            },
+            _ => {}
+        }
+
+        return Err(format!(
+            "Attribute {} not found for type task",
+            keys[0].describe()
+        ));
+    }
+}
+
+impl PartialEq for TaskHandle {
+    fn eq(&self, other: &TaskHandle) -> bool {
+        return Rc::ptr_eq(&self.result, &other.result);
+    }
+}
+
+impl std::fmt::Debug for TaskHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.describe())
+    }
+}
@@ -1,6 +1,10 @@
 use std::hash::Hash;
 use std::hash::Hasher;
 
+use crate::types::object::Object;
+use crate::types::object::Value;
+use crate::vm::limits::CancelHandle;
+
 #[derive(Debug, Clone)]
 pub struct ThreadBlock {
     // handle_id is a unique 64 bit ID for a thread.
@@ -8,26 +12,55 @@ pub struct ThreadBlock {
     // inside the current VM scope.
     pub handle_id: u64,
     pub name: String,
+    // set by cancel(th), checked by the VM running the thread:
+    pub kill: CancelHandle,
 }
 
 impl ThreadBlock {
-    pub fn new(handle_id: u64, name: String) -> ThreadBlock {
-        return ThreadBlock { handle_id, name };
+    pub fn new(handle_id: u64, name: String, kill: CancelHandle) -> ThreadBlock {
+        return ThreadBlock {
+            handle_id,
+            name,
+            kill,
+        };
     }
 
     pub fn describe(&self) -> String {
-        return format!("Thread(func={}, id={})", self.name, self.handle_id);
+        return format!("Thread(name={}, id={})", self.name, self.handle_id);
+    }
+
+    pub fn attrs(&self) -> Vec<Value> {
+        return vec![
+            Value::new(Object::Str(Box::new(String::from("id")))),
+            Value::new(Object::Str(Box::new(String::from("name")))),
+        ];
+    }
+
+    pub fn get_attribute(&self, keys: &Vec<Value>) -> Result<Value, String> {
+        match keys[0].as_ref() {
+            Object::Str(st) => match st.as_str() {
+                "id" => return Ok(Value::new(Object::Int(self.handle_id as i64))),
+                "name" => return Ok(Value::new(Object::Str(Box::new(self.name.clone())))),
+                _ => {}
+            },
+            _ => {}
+        }
+
+        return Err(format!(
+            "Attribute {} not found for type thread",
+            keys[0].describe()
+        ));
     }
 }
 
 impl PartialEq for ThreadBlock {
     fn eq(&self, other: &ThreadBlock) -> bool {
-        self.name == other.name
+        self.handle_id == other.handle_id
     }
 }
 
 impl Hash for ThreadBlock {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.name.hash(state);
+        self.handle_id.hash(state);
     }
 }
@@ -1,5 +1,5 @@
-use std::rc::Rc;
 
+use crate::types::heap;
 use crate::types::object;
 use crate::vm::errors;
 
@@ -7,12 +7,13 @@ use errors::ISAError;
 use errors::ISAErrorKind;
 
 use object::Object;
+use object::Value;
 
 pub struct Arithmetic {}
 pub struct Bitwise {}
 
 impl Arithmetic {
-    fn new_overflow_err(v_1: &Rc<Object>, v_2: &Rc<Object>, operation: String) -> ISAError {
+    fn new_overflow_err(v_1: &Value, v_2: &Value, operation: String) -> ISAError {
         return ISAError::new(
             format!(
                 "Operation {} between {} and {} results in arithmetic overflow",
@@ -24,31 +25,36 @@ impl Arithmetic {
         );
     }
 
-    pub fn add(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn add(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Int(lval), Object::Int(rval)) => {
                 let result = (*lval).checked_add(*rval);
                 if result.is_none() {
                     return Err(Arithmetic::new_overflow_err(left, right, "add".to_string()));
                 }
-                return Ok(Rc::new(Object::Int(result.unwrap())));
+                return Ok(Value::new(Object::Int(result.unwrap())));
             }
             (Object::Int(lval), Object::Float(rval)) => {
                 let result = lval.clone() as f64 + rval;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Float(lval), Object::Int(rval)) => {
                 let result = lval + rval.clone() as f64;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Float(lval), Object::Float(rval)) => {
                 let result = lval + rval;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Str(lval), Object::Str(rval)) => {
+                let reserved = heap::reserve(lval.len() + rval.len());
+                if reserved.is_err() {
+                    return Err(ISAError::new(reserved.unwrap_err(), ISAErrorKind::MemoryError));
+                }
+
                 let mut result = lval.clone();
                 result.push_str(rval);
-                return Ok(Rc::new(Object::Str(result)));
+                return Ok(Value::new(Object::Str(result)));
             }
             _ => {
                 // throw a panic
@@ -66,26 +72,26 @@ impl Arithmetic {
         }
     }
 
-    pub fn sub(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn sub(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Int(lval), Object::Int(rval)) => {
                 let result = (*lval).checked_sub(*rval);
                 if result.is_none() {
                     return Err(Arithmetic::new_overflow_err(left, right, "add".to_string()));
                 }
-                return Ok(Rc::new(Object::Int(result.unwrap())));
+                return Ok(Value::new(Object::Int(result.unwrap())));
             }
             (Object::Int(lval), Object::Float(rval)) => {
                 let result = lval.clone() as f64 - rval;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Float(lval), Object::Int(rval)) => {
                 let result = lval - rval.clone() as f64;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Float(lval), Object::Float(rval)) => {
                 let result = lval - rval;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             _ => {
                 // throw a panic
@@ -103,26 +109,26 @@ impl Arithmetic {
         }
     }
 
-    pub fn mul(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn mul(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Int(lval), Object::Int(rval)) => {
                 let result = (*lval).checked_mul(*rval);
                 if result.is_none() {
                     return Err(Arithmetic::new_overflow_err(left, right, "add".to_string()));
                 }
-                return Ok(Rc::new(Object::Int(result.unwrap())));
+                return Ok(Value::new(Object::Int(result.unwrap())));
             }
             (Object::Int(lval), Object::Float(rval)) => {
                 let result = lval.clone() as f64 * rval;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Float(lval), Object::Int(rval)) => {
                 let result = lval * rval.clone() as f64;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Float(lval), Object::Float(rval)) => {
                 let result = lval * rval;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             _ => {
                 // throw a panic
@@ -140,7 +146,7 @@ impl Arithmetic {
         }
     }
 
-    pub fn div(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn div(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Int(lval), Object::Int(rval)) => {
                 if *rval == 0 {
@@ -151,7 +157,7 @@ impl Arithmetic {
                 }
 
                 let result = *lval as f64 / *rval as f64;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Int(lval), Object::Float(rval)) => {
                 if *rval == 0.0 {
@@ -162,7 +168,7 @@ impl Arithmetic {
                 }
 
                 let result = lval.clone() as f64 / rval;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Float(lval), Object::Int(rval)) => {
                 if *rval == 0 {
@@ -173,7 +179,7 @@ impl Arithmetic {
                 }
 
                 let result = lval / rval.clone() as f64;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Float(lval), Object::Float(rval)) => {
                 if *rval == 0.0 {
@@ -183,7 +189,7 @@ impl Arithmetic {
                     ));
                 }
                 let result = lval / rval;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             _ => {
                 // throw a panic
@@ -201,7 +207,7 @@ impl Arithmetic {
         }
     }
 
-    pub fn modulus(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn modulus(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Int(lval), Object::Int(rval)) => {
                 if *rval == 0 {
@@ -212,7 +218,7 @@ impl Arithmetic {
                 }
 
                 let result = lval % rval;
-                return Ok(Rc::new(Object::Int(result)));
+                return Ok(Value::new(Object::Int(result)));
             }
             (Object::Int(lval), Object::Float(rval)) => {
                 if *rval == 0.0 {
@@ -223,7 +229,7 @@ impl Arithmetic {
                 }
 
                 let result = lval.clone() as f64 % rval;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Float(lval), Object::Int(rval)) => {
                 if *rval == 0 {
@@ -234,7 +240,7 @@ impl Arithmetic {
                 }
 
                 let result = lval * rval.clone() as f64;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             (Object::Float(lval), Object::Float(rval)) => {
                 if *rval == 0.0 {
@@ -244,7 +250,7 @@ impl Arithmetic {
                     ));
                 }
                 let result = lval % rval;
-                return Ok(Rc::new(Object::Float(result)));
+                return Ok(Value::new(Object::Float(result)));
             }
             _ => {
                 // throw a panic
@@ -264,11 +270,11 @@ impl Arithmetic {
 }
 
 impl Bitwise {
-    pub fn and(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn and(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Int(lval), Object::Int(rval)) => {
                 let result = lval & rval;
-                return Ok(Rc::new(Object::Int(result)));
+                return Ok(Value::new(Object::Int(result)));
             }
             _ => {
                 let l_type = left.get_type();
@@ -284,11 +290,11 @@ impl Bitwise {
         }
     }
 
-    pub fn or(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn or(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Int(lval), Object::Int(rval)) => {
                 let result = lval | rval;
-                return Ok(Rc::new(Object::Int(result)));
+                return Ok(Value::new(Object::Int(result)));
             }
             _ => {
                 let l_type = left.get_type();
@@ -304,11 +310,11 @@ impl Bitwise {
         }
     }
 
-    pub fn not(obj: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn not(obj: &Value) -> Result<Value, ISAError> {
         match obj.as_ref() {
             Object::Int(val) => {
                 let result = !val;
-                return Ok(Rc::new(Object::Int(result)));
+                return Ok(Value::new(Object::Int(result)));
             }
             _ => {
                 let val_type = obj.get_type();
@@ -324,46 +330,46 @@ impl Bitwise {
 pub struct Logical {}
 
 impl Logical {
-    pub fn or(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn or(left: &Value, right: &Value) -> Result<Value, ISAError> {
         let result = left.is_true() || right.is_true();
-        return Ok(Rc::new(Object::Bool(result)));
+        return Ok(Value::new(Object::Bool(result)));
     }
 
-    pub fn and(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn and(left: &Value, right: &Value) -> Result<Value, ISAError> {
         let result = left.is_true() && right.is_true();
-        return Ok(Rc::new(Object::Bool(result)));
+        return Ok(Value::new(Object::Bool(result)));
     }
 
-    pub fn not(obj: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn not(obj: &Value) -> Result<Value, ISAError> {
         let result = !obj.is_true();
-        return Ok(Rc::new(Object::Bool(result)));
+        return Ok(Value::new(Object::Bool(result)));
     }
 }
 
 pub struct Comparision {}
 
 impl Comparision {
-    pub fn gt(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn gt(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Char(lval), Object::Char(rval)) => {
                 let result = lval > rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Int(rval)) => {
                 let result = lval > rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Float(rval)) => {
                 let result = lval > rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Int(rval)) => {
                 let result = *lval > (*rval as f64);
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Float(rval)) => {
                 let result = (*lval as f64) > *rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             _ => {
                 let l_type = left.get_type();
@@ -380,27 +386,27 @@ impl Comparision {
         }
     }
 
-    pub fn gte(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn gte(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Char(lval), Object::Char(rval)) => {
                 let result = lval >= rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Int(rval)) => {
                 let result = lval >= rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Float(rval)) => {
                 let result = lval >= rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Int(rval)) => {
                 let result = *lval >= (*rval as f64);
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Float(rval)) => {
                 let result = (*lval as f64) >= *rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             _ => {
                 let l_type = left.get_type();
@@ -417,27 +423,27 @@ impl Comparision {
         }
     }
 
-    pub fn lt(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn lt(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Char(lval), Object::Char(rval)) => {
                 let result = lval < rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Int(rval)) => {
                 let result = lval < rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Float(rval)) => {
                 let result = lval < rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Int(rval)) => {
                 let result = *lval < (*rval as f64);
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Float(rval)) => {
                 let result = (*lval as f64) < *rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             _ => {
                 let l_type = left.get_type();
@@ -454,27 +460,27 @@ impl Comparision {
         }
     }
 
-    pub fn lte(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn lte(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Char(lval), Object::Char(rval)) => {
                 let result = lval <= rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Int(rval)) => {
                 let result = lval <= rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Float(rval)) => {
                 let result = lval <= rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Int(rval)) => {
                 let result = *lval <= (*rval as f64);
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Float(rval)) => {
                 let result = (*lval as f64) <= *rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             _ => {
                 let l_type = left.get_type();
@@ -491,31 +497,36 @@ impl Comparision {
         }
     }
 
-    pub fn eq(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn eq(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Char(lval), Object::Char(rval)) => {
                 let result = lval == rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Int(rval)) => {
                 let result = lval == rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Float(rval)) => {
                 let result = lval == rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Str(lval), Object::Str(rval)) => {
                 let result = lval == rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Int(rval)) => {
                 let result = *lval == (*rval as f64);
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Float(rval)) => {
                 let result = (*lval as f64) == *rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
+            }
+            // none is only equal to itself:
+            (Object::Noval, _) | (_, Object::Noval) => {
+                let result = left.as_ref() == right.as_ref();
+                return Ok(Value::new(Object::Bool(result)));
             }
             _ => {
                 let l_type = left.get_type();
@@ -532,31 +543,36 @@ impl Comparision {
         }
     }
 
-    pub fn neq(left: &Rc<Object>, right: &Rc<Object>) -> Result<Rc<Object>, ISAError> {
+    pub fn neq(left: &Value, right: &Value) -> Result<Value, ISAError> {
         match (left.as_ref(), right.as_ref()) {
             (Object::Char(lval), Object::Char(rval)) => {
                 let result = lval != rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Int(rval)) => {
                 let result = lval != rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Float(rval)) => {
                 let result = lval != rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Str(lval), Object::Str(rval)) => {
                 let result = lval != rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Float(lval), Object::Int(rval)) => {
                 let result = *lval != (*rval as f64);
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
             }
             (Object::Int(lval), Object::Float(rval)) => {
                 let result = (*lval as f64) != *rval;
-                return Ok(Rc::new(Object::Bool(result)));
+                return Ok(Value::new(Object::Bool(result)));
+            }
+            // none is only equal to itself:
+            (Object::Noval, _) | (_, Object::Noval) => {
+                let result = left.as_ref() != right.as_ref();
+                return Ok(Value::new(Object::Bool(result)));
             }
             _ => {
                 let l_type = left.get_type();
@@ -1,18 +1,20 @@
 use crate::api;
 use crate::compiler::symtab::ConstantPool;
-use crate::config;
 use crate::isa;
 use crate::types::array;
 use crate::types::builtins;
+use crate::types::closure;
 use crate::types::hash;
 use crate::types::iter;
 use crate::types::object;
-use crate::types::th;
+use crate::types::process;
 use crate::vm::alu;
 use crate::vm::errors;
 use crate::vm::frames;
 use crate::vm::global;
+use crate::vm::limits;
 use crate::vm::stack;
+use crate::vm::tasks;
 use crate::vm::thread;
 
 use std::cell::Ref;
@@ -28,7 +30,9 @@ use alu::Logical;
 use api::Platform;
 use array::Array;
 use builtins::BuiltinKind;
-use config::ENABLE_CONCURRENCY;
+use closure::ClosureContext;
+use closure::Upvalue;
+use closure::UpvalueState;
 use errors::ISAError;
 use errors::ISAErrorKind;
 use errors::VMError;
@@ -38,9 +42,12 @@ use global::GlobalPool;
 use hash::HashTable;
 use isa::InstructionKind;
 use iter::ObjectIterator;
+use limits::ExecutionLimits;
 use object::Object;
+use object::Value;
+use process::Process;
+use tasks::Switch;
 use stack::DataStack;
-use th::ThreadBlock;
 use object::AttributeResolver;
 
 pub struct Controls {}
@@ -105,7 +112,21 @@ impl Controls {
             return Some(free_obj_result.unwrap_err());
         }
 
-        let free_object = free_obj_result.unwrap();
+        let free_object = match &*free_obj_result.unwrap().borrow() {
+            UpvalueState::Open(slot) => match ds.stack.get(*slot) {
+                Some(obj) => obj.clone(),
+                None => {
+                    return Some(VMError::new(
+                        format!("Free variable points to invalid slot {}", slot),
+                        VMErrorKind::UnknownFreeVariable,
+                        Some(InstructionKind::ILoadFree),
+                        0,
+                    ))
+                }
+            },
+            UpvalueState::Closed(obj) => obj.clone(),
+        };
+
         // push it to the stack:
         let push_result = ds.push_object(free_object, InstructionKind::ILoadFree);
 
@@ -116,7 +137,45 @@ impl Controls {
         return None;
     }
 
-    pub fn push_objects(objs: Vec<Rc<Object>>, ds: &mut DataStack) -> Option<VMError> {
+    pub fn store_free(
+        ds: &mut DataStack,
+        frame: &mut RefMut<ExecutionFrame>,
+        idx: usize,
+    ) -> Option<VMError> {
+        let free_obj_result = frame.get_free(idx, InstructionKind::IStoreFree);
+        if free_obj_result.is_err() {
+            return Some(free_obj_result.unwrap_err());
+        }
+
+        let popped_result = ds.pop_object(InstructionKind::IStoreFree);
+        if popped_result.is_err() {
+            return Some(popped_result.unwrap_err());
+        }
+
+        let upvalue = free_obj_result.unwrap();
+        let mut state = upvalue.borrow_mut();
+        match &mut *state {
+            UpvalueState::Open(slot) => {
+                if *slot >= ds.stack.len() {
+                    return Some(VMError::new(
+                        format!("Free variable points to invalid slot {}", slot),
+                        VMErrorKind::UnknownFreeVariable,
+                        Some(InstructionKind::IStoreFree),
+                        0,
+                    ));
+                }
+
+                ds.stack[*slot] = popped_result.unwrap();
+            }
+            UpvalueState::Closed(obj) => {
+                *obj = popped_result.unwrap();
+            }
+        }
+
+        return None;
+    }
+
+    pub fn push_objects(objs: Vec<Value>, ds: &mut DataStack) -> Option<VMError> {
         let objs_len = objs.len() as i64;
         if ds.stack_pointer + objs_len >= ds.max_size as i64 {
             return Some(VMError::new(
@@ -174,6 +233,15 @@ impl Controls {
         return Ok(push_result.unwrap());
     }
 
+    pub fn release_local(ds: &mut DataStack, pos: usize, f: &mut RefMut<ExecutionFrame>) {
+        let bp = f.get_bp();
+        if bp + pos < ds.stack.len() {
+            // closures created in the block keep the last value:
+            f.close_upvalue(bp + pos, ds.stack[bp + pos].clone());
+            ds.stack[bp + pos] = Value::new(Object::Noval);
+        }
+    }
+
     pub fn load_global(gp: &GlobalPool, ds: &mut DataStack, pos: usize) -> Result<i64, VMError> {
         let object = gp.get(pos);
         if object.is_some() {
@@ -202,7 +270,7 @@ impl Controls {
     pub fn get_binary_operands(
         ds: &mut DataStack,
         inst: &InstructionKind,
-    ) -> Result<(Rc<Object>, Rc<Object>), VMError> {
+    ) -> Result<(Value, Value), VMError> {
         let right_pop = ds.pop_object(inst.clone());
         if right_pop.is_err() {
             return Err(right_pop.unwrap_err());
@@ -265,11 +333,219 @@ impl Controls {
         return None;
     }
 
+    // the comparison of a ICmpNotJump, integers and floats are compared in
+    // place, other types go through the ALU.
+    fn compare(
+        cmp: &InstructionKind,
+        left: &Value,
+        right: &Value,
+    ) -> Result<bool, ISAError> {
+        match (left.as_ref(), right.as_ref()) {
+            (Object::Int(lval), Object::Int(rval)) => match cmp {
+                InstructionKind::ILLt => return Ok(lval < rval),
+                InstructionKind::ILLTe => return Ok(lval <= rval),
+                InstructionKind::ILGt => return Ok(lval > rval),
+                InstructionKind::ILGte => return Ok(lval >= rval),
+                InstructionKind::ILEq => return Ok(lval == rval),
+                InstructionKind::ILNe => return Ok(lval != rval),
+                _ => {}
+            },
+            (Object::Float(lval), Object::Float(rval)) => match cmp {
+                InstructionKind::ILLt => return Ok(lval < rval),
+                InstructionKind::ILLTe => return Ok(lval <= rval),
+                InstructionKind::ILGt => return Ok(lval > rval),
+                InstructionKind::ILGte => return Ok(lval >= rval),
+                InstructionKind::ILEq => return Ok(lval == rval),
+                InstructionKind::ILNe => return Ok(lval != rval),
+                _ => {}
+            },
+            _ => {}
+        }
+
+        let result = match cmp {
+            InstructionKind::ILLt => Comparision::lt(left, right),
+            InstructionKind::ILLTe => Comparision::lte(left, right),
+            InstructionKind::ILGt => Comparision::gt(left, right),
+            InstructionKind::ILGte => Comparision::gte(left, right),
+            InstructionKind::ILEq => Comparision::eq(left, right),
+            InstructionKind::ILNe => Comparision::neq(left, right),
+            _ => Err(ISAError::new(
+                format!("{} is not a comparison", cmp.as_string()),
+                ISAErrorKind::InvalidOperation,
+            )),
+        };
+
+        if result.is_err() {
+            return Err(result.unwrap_err());
+        }
+
+        return Ok(result.unwrap().is_true());
+    }
+
+    pub fn compare_not_jump(
+        cf: &mut RefMut<ExecutionFrame>,
+        ds: &mut DataStack,
+        pos: usize,
+        cmp: &InstructionKind,
+    ) -> Result<bool, VMError> {
+        let operands_result = Controls::get_binary_operands(ds, cmp);
+        if operands_result.is_err() {
+            return Err(operands_result.unwrap_err());
+        }
+
+        let (left, right) = operands_result.unwrap();
+        let result = Controls::compare(cmp, &left, &right);
+        if result.is_err() {
+            return Err(VMError::new_from_isa_error(
+                &result.unwrap_err(),
+                cmp.clone(),
+            ));
+        }
+
+        if !result.unwrap() {
+            let jmp_result = Controls::jump(cf, pos);
+            if jmp_result.is_err() {
+                return Err(jmp_result.unwrap_err());
+            }
+
+            return Ok(true);
+        }
+
+        return Ok(false);
+    }
+
+    // the add of the fused instructions: ints and floats are added here,
+    // the other types and an overflow go through the ALU.
+    #[inline]
+    fn add_values(left: &Value, right: &Value) -> Result<Value, ISAError> {
+        match (left.as_ref(), right.as_ref()) {
+            (Object::Int(lval), Object::Int(rval)) => {
+                let result = lval.checked_add(*rval);
+                if result.is_some() {
+                    return Ok(Value::new(Object::Int(result.unwrap())));
+                }
+            }
+            (Object::Float(lval), Object::Float(rval)) => {
+                return Ok(Value::new(Object::Float(lval + rval)));
+            }
+            _ => {}
+        }
+
+        return Arithmetic::add(left, right);
+    }
+
+    // x = x + constant for a local, the errors are the ones of IAdd.
+    pub fn incr_local(
+        ds: &mut DataStack,
+        cp: &ConstantPool,
+        f: &RefMut<ExecutionFrame>,
+        pos: usize,
+        const_pos: usize,
+    ) -> Option<VMError> {
+        let slot = f.get_bp() + pos;
+        if slot >= ds.stack.len() {
+            return Some(VMError::new(
+                "Stack overflow!".to_string(),
+                VMErrorKind::DataStackOverflow,
+                Some(InstructionKind::IIncrLocal),
+                0,
+            ));
+        }
+
+        let constant = cp.get_object(const_pos).unwrap();
+        let result = Controls::add_values(&ds.stack[slot], &constant);
+        if result.is_err() {
+            return Some(VMError::new_from_isa_error(
+                &result.unwrap_err(),
+                InstructionKind::IAdd,
+            ));
+        }
+
+        ds.stack[slot] = result.unwrap();
+        return None;
+    }
+
+    // x = x + constant for a global:
+    pub fn incr_global(
+        gp: &mut GlobalPool,
+        cp: &ConstantPool,
+        pos: usize,
+        const_pos: usize,
+    ) -> Option<VMError> {
+        let object = gp.get(pos);
+        if object.is_none() {
+            return Some(VMError::new(
+                format!("Index {} exceeds global pool size {}", pos, gp.max_size),
+                VMErrorKind::GlobalPoolSizeExceeded,
+                None,
+                0,
+            ));
+        }
+
+        let constant = cp.get_object(const_pos).unwrap();
+        let result = Controls::add_values(&object.unwrap(), &constant);
+        if result.is_err() {
+            return Some(VMError::new_from_isa_error(
+                &result.unwrap_err(),
+                InstructionKind::IAdd,
+            ));
+        }
+
+        return gp.set_object(result.unwrap(), pos);
+    }
+
+    // adds the variable to the top of the stack, like a load and IAdd:
+    pub fn add_variable(ds: &mut DataStack, right: Option<Value>) -> Option<VMError> {
+        if right.is_none() {
+            return Some(VMError::new(
+                "Variable out of bounds".to_string(),
+                VMErrorKind::IllegalOperation,
+                Some(InstructionKind::IAdd),
+                0,
+            ));
+        }
+
+        let left = ds.pop_object(InstructionKind::IAdd);
+        if left.is_err() {
+            return Some(left.unwrap_err());
+        }
+
+        let result = Controls::add_values(&left.unwrap(), &right.unwrap());
+        if result.is_err() {
+            return Some(VMError::new_from_isa_error(
+                &result.unwrap_err(),
+                InstructionKind::IAdd,
+            ));
+        }
+
+        let push_result = ds.push_object(result.unwrap(), InstructionKind::IAdd);
+        if push_result.is_err() {
+            return Some(push_result.unwrap_err());
+        }
+
+        return None;
+    }
+
+    // the functions among the values with their captured variables closed
+    // over the current values of the frames on the stack, for functions that
+    // run on a task or on another thread:
+    fn detach_closures(values: Vec<Value>, stack: &Vec<Value>) -> Vec<Value> {
+        return values
+            .into_iter()
+            .map(|value| match value.as_ref() {
+                Object::ClosureContext(ctx) => {
+                    Value::new(Object::ClosureContext(Rc::new(ctx.detach(stack))))
+                }
+                _ => value,
+            })
+            .collect();
+    }
+
     fn pop_n(
         ds: &mut DataStack,
         n: usize,
         inst: &InstructionKind,
-    ) -> Result<Vec<Rc<Object>>, VMError> {
+    ) -> Result<Vec<Value>, VMError> {
         let mut objs = vec![];
 
         for _ in 0..n {
@@ -330,7 +606,7 @@ impl Controls {
         }
 
         // push to the stack
-        let obj = Rc::new(Object::Builtins(builtin_kind.unwrap()));
+        let obj = Value::new(Object::Builtins(builtin_kind.unwrap()));
         let push_res = ds.push_object(obj, InstructionKind::ILoadBuiltIn);
         if push_res.is_err() {
             return Err(push_res.unwrap_err());
@@ -344,7 +620,7 @@ impl Controls {
         frame: &Ref<ExecutionFrame>,
         has_val: bool,
     ) -> Option<VMError> {
-        let mut returned_obj: Rc<Object> = Rc::new(Object::Noval);
+        let mut returned_obj: Value = Value::new(Object::Noval);
         if has_val {
             let returned_obj_res = ds.pop_object(InstructionKind::IRetVal);
             if returned_obj_res.is_err() {
@@ -355,6 +631,7 @@ impl Controls {
         }
 
         let local_boundary = frame.get_bp();
+        frame.close_upvalues(&ds.stack);
         // clear off the stack till this point:
 
         ds.stack.truncate(local_boundary);
@@ -395,17 +672,33 @@ impl Controls {
 
                 let mut args = popped_args.unwrap();
                 args.reverse();
+
+                // a task or a thread runs on stacks of its own, its function reads
+                // the captured variables of the frames on this stack by their values:
+                if func.starts_task() || func.starts_thread() {
+                    args = Controls::detach_closures(args, &ds.stack);
+                }
+
                 // call the builtin:
                 let exec_result = func.exec(args, platform, global_pool, constants, threads);
                 if exec_result.is_err() {
-                    return Err(VMError::new(
+                    // a builtin that waits stops when the run is interrupted:
+                    let interruption = threads.limits.interrupted(inst);
+                    if interruption.is_some() {
+                        return Err(interruption.unwrap());
+                    }
+
+                    return Err(VMError::new_from_builtin_error(
                         exec_result.unwrap_err(),
-                        VMErrorKind::BuiltinFunctionError,
                         Some(inst.clone()),
-                        0,
                     ));
                 }
 
+                // the other tasks run while this one waits for its IO:
+                if func.is_io() {
+                    threads.tasks.switch(Switch::Yield);
+                }
+
                 let result_obj = exec_result.unwrap();
                 let push_res = ds.push_object(result_obj, inst.clone());
                 if push_res.is_err() {
@@ -430,29 +723,13 @@ impl Controls {
                     ));
                 }
 
-                let frame_bp = if ds.stack_pointer <= 0 {
-                    0
-                } else {
-                    ds.stack.len() - n_args
-                };
-
-                // allocate the stack for local variables and frame:
-                let new_frame = ExecutionFrame::new(Rc::new(closure.clone()), frame_bp);
-
-                let n_locals = closure.compiled_fn.num_locals;
-                let n_params = closure.compiled_fn.num_parameters;
-                let mut local_space = vec![];
-                local_space.resize(n_locals - n_params, Rc::new(Object::Noval));
-
-                // push the local space on to the stack
-                let push_res = ds.push_objects(InstructionKind::ICall, local_space);
-                if push_res.is_err() {
-                    return Err(push_res.unwrap_err());
+                let frame_bp = ds.stack.len() - n_args;
+                let new_frame = Controls::create_frame(ds, closure, frame_bp);
+                if new_frame.is_err() {
+                    return Err(new_frame.unwrap_err());
                 }
 
-                // set the new stack pointer:
-                ds.stack_pointer = (new_frame.base_pointer + n_locals) as i64;
-                return Ok(Some(RefCell::new(new_frame)));
+                return Ok(Some(new_frame.unwrap()));
             }
             _ => {
                 return Err(VMError::new(
@@ -465,6 +742,88 @@ impl Controls {
         }
     }
 
+    // creates the frame of the closure, the arguments are already on the
+    // stack starting at frame_bp.
+    fn create_frame(
+        ds: &mut DataStack,
+        closure: &ClosureContext,
+        frame_bp: usize,
+    ) -> Result<RefCell<ExecutionFrame>, VMError> {
+        // allocate the stack for local variables and frame:
+        let new_frame = ExecutionFrame::new(Rc::new(closure.clone()), frame_bp);
+
+        let n_locals = closure.compiled_fn.num_locals;
+        let n_params = closure.compiled_fn.num_parameters;
+        let mut local_space = vec![];
+        local_space.resize(n_locals - n_params, Value::new(Object::Noval));
+
+        // push the local space on to the stack
+        let push_res = ds.push_objects(InstructionKind::ICall, local_space);
+        if push_res.is_err() {
+            return Err(push_res.unwrap_err());
+        }
+
+        // set the new stack pointer:
+        ds.stack_pointer = (new_frame.base_pointer + n_locals) as i64 - 1;
+        return Ok(RefCell::new(new_frame));
+    }
+
+    // call in tail position: the arguments are moved to the base of the
+    // current frame and the new frame takes its place. Returns None if the
+    // callee is not a closure, such calls are made as regular calls.
+    pub fn execute_tail_call(
+        inst: &InstructionKind,
+        ds: &mut DataStack,
+        n_args: usize,
+        frame: &RefMut<ExecutionFrame>,
+    ) -> Result<Option<RefCell<ExecutionFrame>>, VMError> {
+        let top_res = ds.get_top_ref(inst.clone());
+        if top_res.is_err() {
+            return Err(top_res.unwrap_err());
+        }
+
+        let top = top_res.unwrap();
+        let closure = match top.as_ref() {
+            Object::ClosureContext(ctx) => ctx.clone(),
+            _ => return Ok(None),
+        };
+
+        let subroutine = closure.compiled_fn.as_ref();
+        if subroutine.num_parameters != n_args {
+            return Err(VMError::new(
+                format!(
+                    "Function {} expects {} arguments, given {}",
+                    subroutine.name, subroutine.num_parameters, n_args
+                ),
+                VMErrorKind::FunctionArgumentsError,
+                Some(inst.clone()),
+                0,
+            ));
+        }
+
+        let _ = ds.pop_object(inst.clone());
+
+        // the locals of the caller are dropped, closures capturing them
+        // keep their own copy:
+        frame.close_upvalues(&ds.stack);
+
+        let frame_bp = frame.get_bp();
+        let args_start = ds.stack.len() - n_args;
+        for idx in 0..n_args {
+            ds.stack[frame_bp + idx] = ds.stack[args_start + idx].clone();
+        }
+
+        ds.stack.truncate(frame_bp + n_args);
+        ds.stack_pointer = (frame_bp + n_args) as i64 - 1;
+
+        let new_frame = Controls::create_frame(ds, closure.as_ref(), frame_bp);
+        if new_frame.is_err() {
+            return Err(new_frame.unwrap_err());
+        }
+
+        return Ok(Some(new_frame.unwrap()));
+    }
+
     pub fn execute_unary_op(inst: &InstructionKind, ds: &mut DataStack) -> Option<VMError> {
         let pop_result = ds.pop_object(inst.clone());
         if pop_result.is_err() {
@@ -517,7 +876,7 @@ impl Controls {
             elements: popped,
         };
 
-        let array_obj = Rc::new(Object::Array(RefCell::new(array)));
+        let array_obj = Value::new(Object::Array(Box::new(RefCell::new(array))));
 
         // push the array on to the stack:
         let push_res = ds.push_object(array_obj, inst.clone());
@@ -556,7 +915,7 @@ impl Controls {
             entries: hash_table,
         };
 
-        let ht_obj = Rc::new(Object::HashTable(RefCell::new(ht)));
+        let ht_obj = Value::new(Object::HashTable(Box::new(RefCell::new(ht))));
         let push_res = ds.push_object(ht_obj, inst.clone());
 
         if push_res.is_err() {
@@ -569,16 +928,22 @@ impl Controls {
     pub fn create_closure(
         ds: &mut DataStack,
         constants: &ConstantPool,
+        frame: &mut RefMut<ExecutionFrame>,
         n_free: usize,
         func_idx: usize,
     ) -> Option<VMError> {
-        // pop off the free objects
-        let popped_res = Controls::pop_n(ds, n_free, &InstructionKind::IClosure);
-        if popped_res.is_err() {
-            return Some(popped_res.unwrap_err());
+        // take the upvalues captured for this closure:
+        let captures_res = frame.take_captures(n_free);
+        if captures_res.is_none() {
+            return Some(VMError::new(
+                format!("Closure expects {} captured variables", n_free),
+                VMErrorKind::UnknownFreeVariable,
+                Some(InstructionKind::IClosure),
+                0,
+            ));
         }
-        // get free objects:
-        let free_objects = popped_res.unwrap();
+
+        let free_objects: Vec<Upvalue> = captures_res.unwrap();
 
         // retrive  the function from constant pool:
         let function_res = constants.get_object(func_idx);
@@ -655,7 +1020,7 @@ impl Controls {
             ));
         }
 
-        let iter_object = Rc::new(Object::Iter(RefCell::new(iter_res.unwrap())));
+        let iter_object = Value::new(Object::Iter(Box::new(RefCell::new(iter_res.unwrap()))));
         let push_res = ds.push_object(iter_object, InstructionKind::IIter);
         if push_res.is_err() {
             return Some(push_res.unwrap_err());
@@ -664,11 +1029,25 @@ impl Controls {
         return None;
     }
 
+    // true if the iterator on top of the stack waits for a value of a receiver:
+    pub fn iter_would_block(ds: &mut DataStack) -> bool {
+        let top_ref_res = ds.get_top_ref(InstructionKind::IIterNext);
+        if top_ref_res.is_err() {
+            return false;
+        }
+
+        match top_ref_res.unwrap().as_ref() {
+            Object::Iter(iter) => return iter.borrow().would_block(),
+            _ => return false,
+        }
+    }
+
     pub fn jump_next_iter(
         ds: &mut DataStack,
         jmp_pos: usize,
         frame: &mut RefMut<ExecutionFrame>,
         enumerate: bool,
+        limits: &ExecutionLimits,
     ) -> Result<bool, VMError> {
         let top_ref_res = ds.get_top_ref(InstructionKind::IIterNext);
         if top_ref_res.is_err() {
@@ -686,8 +1065,22 @@ impl Controls {
                     current_pos = iterator.get_pos();
                 }
 
-                let obj = iterator.next();
+                let next_res = iterator.wait_next(limits);
+                if next_res.is_err() {
+                    let interruption = limits.interrupted(&InstructionKind::IIterNext);
+                    if interruption.is_some() {
+                        return Err(interruption.unwrap());
+                    }
+
+                    return Err(VMError::new(
+                        next_res.unwrap_err(),
+                        VMErrorKind::IterationError,
+                        Some(InstructionKind::IIterNext),
+                        0,
+                    ));
+                }
 
+                let obj = next_res.unwrap();
                 if obj.is_none() {
                     // pop the end
                     drop(iterator);
@@ -709,7 +1102,7 @@ impl Controls {
 
                     if enumerate {
                         push_res = ds.push_object(
-                            Rc::new(Object::Int(current_pos as i64)),
+                            Value::new(Object::Int(current_pos as i64)),
                             InstructionKind::IIterNext,
                         );
                         if push_res.is_err() {
@@ -758,7 +1151,7 @@ impl Controls {
         }
 
         // push the object back to stack:
-        let push_result = ds.push_object(Rc::new(new_object), InstructionKind::ISetIndex);
+        let push_result = ds.push_object(Value::new(new_object), InstructionKind::ISetIndex);
         if push_result.is_err() {
             return Some(push_result.unwrap_err());
         }
@@ -773,9 +1166,9 @@ impl Controls {
         constants: &mut ConstantPool,
         platform: &Platform,
         threads: &mut thread::BosonThreads,
-        join: bool,
+        is_async: bool,
     ) -> Option<VMError> {
-        if !ENABLE_CONCURRENCY {
+        if !threads.config.enable_concurrency {
             return Some(VMError::new(
                 "BosonVM has concurrency disabled.".to_string(),
                 VMErrorKind::IllegalOperation,
@@ -818,56 +1211,48 @@ impl Controls {
 
                 // wrap parameters in a thread-type:
                 let thread_params = thread::ThreadParams::new(
-                    ctx.clone(),
+                    Rc::new(ctx.detach(&ds.stack)),
                     args,
-                    global_pool.clone(),
-                    constants.clone(),
+                    global_pool,
+                    constants,
                 );
 
-                let create_result = threads.create_thread_sandbox(thread_params, platform);
-                if create_result.is_err() {
-                    return Some(VMError::new(
-                        create_result.unwrap_err(),
-                        VMErrorKind::ThreadCreateError,
-                        Some(inst.clone()),
-                        0,
-                    ));
-                }
-
-                let th = create_result.unwrap();
-
-                if !join {
-                    // create a thread ID object
-                    let thread_obj = ThreadBlock::new(th, subroutine.name.clone());
-                    let push_result = ds.push_object(
-                        Rc::new(Object::Thread(RefCell::new(thread_obj))),
-                        inst.clone(),
-                    );
-                    if push_result.is_err() {
-                        return Some(push_result.unwrap_err());
+                // an async call returns a future of the result at once:
+                let thread_obj = if is_async {
+                    let create_result =
+                        threads.create_future(thread_params, subroutine.name.clone(), platform);
+                    if create_result.is_err() {
+                        return Some(VMError::new(
+                            create_result.unwrap_err(),
+                            VMErrorKind::ThreadCreateError,
+                            Some(inst.clone()),
+                            0,
+                        ));
                     }
+
+                    Object::Future(Box::new(create_result.unwrap()))
                 } else {
-                    let thread_result = threads.wait_and_return(th);
-                    if thread_result.is_err() {
+                    let create_result = threads.create_thread_sandbox(
+                        thread_params,
+                        subroutine.name.clone(),
+                        platform,
+                    );
+                    if create_result.is_err() {
                         return Some(VMError::new(
-                            thread_result.unwrap_err(),
-                            VMErrorKind::ThreadWaitError,
+                            create_result.unwrap_err(),
+                            VMErrorKind::ThreadCreateError,
                             Some(inst.clone()),
                             0,
                         ));
                     }
 
-                    // unwrap the resut object
-                    let sandbox_result = thread_result.unwrap().result;
-                    if sandbox_result.is_err() {
-                        return Some(sandbox_result.unwrap_err());
-                    }
+                    // create a thread ID object
+                    Object::Thread(Box::new(RefCell::new(create_result.unwrap())))
+                };
 
-                    // push the result to the stack:
-                    let push_res = ds.push_object(sandbox_result.unwrap(), inst.clone());
-                    if push_res.is_err() {
-                        return Some(push_res.unwrap_err());
-                    }
+                let push_result = ds.push_object(Value::new(thread_obj), inst.clone());
+                if push_result.is_err() {
+                    return Some(push_result.unwrap_err());
                 }
             }
             _ => {
@@ -908,19 +1293,17 @@ impl Controls {
             Object::Str(_) => {
                 // split it to args:
                 let shell_fn = platform.sys_shell;
-                let mut args: Vec<Rc<Object>> = shell_fn()
+                let mut args: Vec<Value> = shell_fn()
                     .split_whitespace()
-                    .map(|s| Rc::new(Object::Str(s.to_string())))
+                    .map(|s| Value::new(Object::Str(Box::new(s.to_string()))))
                     .collect();
 
                 args.push(popped_obj);
                 let exec_result = builtin.exec(args, platform, gp, c, th);
                 if exec_result.is_err() {
-                    return Some(VMError::new(
+                    return Some(VMError::new_from_builtin_error(
                         exec_result.unwrap_err(),
-                        VMErrorKind::BuiltinFunctionError,
                         Some(inst.clone()),
-                        0,
                     ));
                 }
 
@@ -988,6 +1371,10 @@ impl Controls {
         inst: &InstructionKind,
         n_attrs: usize,
         n_params: usize,
+        platform: &Platform,
+        gp: &mut GlobalPool,
+        c: &mut ConstantPool,
+        th: &mut thread::BosonThreads,
     ) -> Option<VMError> {
         // pop N objects, which act as attributes
         let pop_res = Controls::pop_n(ds, n_attrs, inst);
@@ -1037,6 +1424,91 @@ impl Controls {
                    return Some(push_result.unwrap_err());
                }
            }
+            Object::Channel(ch) => {
+                // a task lets the other tasks run until the channel is ready:
+                if th.tasks.can_switch && ch.would_block(&attrs[0].describe()) {
+                    th.tasks.switch(Switch::Block);
+                    return None;
+                }
+
+                // a function sent over the channel can be received on another thread:
+                let params = Controls::detach_closures(params, &ds.stack);
+
+                let call_result = ch.resolve_call_attr(&attrs, &params, &th.limits);
+                if call_result.is_err() {
+                    let interruption = th.limits.interrupted(inst);
+                    if interruption.is_some() {
+                        return interruption;
+                    }
+
+                    return Some(VMError::new(
+                        call_result.unwrap_err(),
+                        VMErrorKind::AttributeError,
+                        Some(inst.clone()),
+                        0,
+                    ));
+                }
+
+                let push_result = ds.push_object(call_result.unwrap(), inst.clone());
+                if push_result.is_err() {
+                    return Some(push_result.unwrap_err());
+                }
+            }
+            Object::Builtins(BuiltinKind::Process) | Object::Process(_) => {
+                let call_result = match parent_obj.as_ref() {
+                    Object::Process(process) => process.resolve_call_attr(&attrs, &params, th),
+                    _ => Process::resolve_module_call(&attrs, &params, platform),
+                };
+                if call_result.is_err() {
+                    let interruption = th.limits.interrupted(inst);
+                    if interruption.is_some() {
+                        return interruption;
+                    }
+
+                    return Some(VMError::new(
+                        call_result.unwrap_err(),
+                        VMErrorKind::AttributeError,
+                        Some(inst.clone()),
+                        0,
+                    ));
+                }
+
+                let push_result = ds.push_object(call_result.unwrap(), inst.clone());
+                if push_result.is_err() {
+                    return Some(push_result.unwrap_err());
+                }
+            }
+            Object::Shared(_) | Object::Pool(_) => {
+                // the functions run in a sandbox or on another thread, they read
+                // the captured variables of the frames on this stack by their values:
+                let params = Controls::detach_closures(params, &ds.stack);
+
+                let call_result = match parent_obj.as_ref() {
+                    Object::Shared(shared) => {
+                        shared.resolve_call_attr(&attrs, &params, platform, gp, c, th)
+                    }
+                    Object::Pool(pool) => pool.resolve_call_attr(&attrs, &params),
+                    _ => unreachable!(),
+                };
+                if call_result.is_err() {
+                    let interruption = th.limits.interrupted(inst);
+                    if interruption.is_some() {
+                        return interruption;
+                    }
+
+                    return Some(VMError::new(
+                        call_result.unwrap_err(),
+                        VMErrorKind::AttributeError,
+                        Some(inst.clone()),
+                        0,
+                    ));
+                }
+
+                let push_result = ds.push_object(call_result.unwrap(), inst.clone());
+                if push_result.is_err() {
+                    return Some(push_result.unwrap_err());
+                }
+            }
             _ => {
                 return Some(VMError::new(
                     format!(
@@ -1,10 +1,25 @@
 use crate::isa;
+use crate::types::heap;
 use crate::vm::frames::ExecutionFrame;
 
+use std::cell::RefCell;
 use std::rc::Rc;
 
 use isa::InstructionKind;
 
+thread_local! {
+    // the error of a thread, an async call or a task that a builtin waited
+    // for, it is thrown with its own kind instead of a builtin error:
+    static CARRIED_ERROR: RefCell<Option<VMError>> = const { RefCell::new(None) };
+}
+
+// keeps the error for the VM, the builtin fails with its message:
+pub fn carry_error(error: VMError) -> String {
+    let message = error.message.clone();
+    CARRIED_ERROR.with(|carried| *carried.borrow_mut() = Some(error));
+    return message;
+}
+
 // VM runtime Error
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub enum VMErrorKind {
@@ -35,6 +50,11 @@ pub enum VMErrorKind {
     ThreadCreateError,
     ThreadWaitError,
     AttributeError,
+    InstructionBudgetExceeded,
+    DeadlineExceeded,
+    Cancelled,
+    MemoryError,
+    UserException,
 }
 
 #[derive(Debug, Clone)]
@@ -60,6 +80,33 @@ impl VMError {
         };
     }
 
+    // builtins report errors as strings, the heap tells if one ran out of memory
+    // and the error of a thread, an async call or a task keeps its own kind:
+    pub fn new_from_builtin_error(message: String, instruction: Option<InstructionKind>) -> VMError {
+        let carried = CARRIED_ERROR.with(|error| error.borrow_mut().take());
+        if carried.is_some() {
+            return carried.unwrap();
+        }
+
+        let kind = if heap::take_limit_hit() {
+            VMErrorKind::MemoryError
+        } else {
+            VMErrorKind::BuiltinFunctionError
+        };
+
+        return VMError::new(message, kind, instruction, 0);
+    }
+
+    // the limits of the run stop it even inside a try block:
+    pub fn is_catchable(&self) -> bool {
+        match self.t {
+            VMErrorKind::InstructionBudgetExceeded
+            | VMErrorKind::DeadlineExceeded
+            | VMErrorKind::Cancelled => return false,
+            _ => return true,
+        }
+    }
+
     pub fn new_from_isa_error(isa_error: &ISAError, inst: InstructionKind) -> VMError {
         match isa_error.t {
             ISAErrorKind::TypeError => {
@@ -86,6 +133,16 @@ impl VMError {
                     pos: 0,
                 };
             }
+            ISAErrorKind::MemoryError => {
+                // the failed reservation is reported here, not by a builtin:
+                heap::take_limit_hit();
+                return VMError {
+                    message: isa_error.message.clone(),
+                    t: VMErrorKind::MemoryError,
+                    instruction: Some(inst),
+                    pos: 0,
+                };
+            }
             _ => {
                 return VMError {
                     message: isa_error.message.clone(),
@@ -104,6 +161,7 @@ pub enum ISAErrorKind {
     OverflowError,
     TypeError,
     InvalidOperation,
+    MemoryError,
 }
 
 #[derive(Debug)]
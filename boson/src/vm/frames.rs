@@ -1,27 +1,32 @@
 use crate::compiler;
 use crate::isa;
 use crate::types::closure;
+use crate::types::gc;
 use crate::types::object;
 use crate::types::subroutine;
 use crate::vm::errors;
 
+use std::cell::RefCell;
 use std::rc::Rc;
 
 use closure::ClosureContext;
+use closure::Upvalue;
+use closure::UpvalueState;
 use compiler::CompiledBytecode;
 use errors::VMError;
 use errors::VMErrorKind;
 use isa::InstructionKind;
-use isa::InstructionPacker;
-use isa::Operands;
 use object::Object;
+use object::Value;
 use subroutine::Subroutine;
 
-
+// an open try block, errors raised inside it continue at the catch block:
 #[derive(Debug, Clone)]
 pub struct ExceptionHandler {
-    exception_handler: Rc<ClosureContext>,
-    finally_handler: Option<Rc<ClosureContext>>
+    pub catch_pos: usize,
+    // data stack length and number of open blocks when the try block started:
+    pub stack_len: usize,
+    pub block_depth: usize,
 }
 
 pub type ExceptionHandleStack = Vec<ExceptionHandler>;
@@ -33,6 +38,15 @@ pub struct ExecutionFrame {
     pub base_pointer: usize,
     pub bytecode_size: usize,
     pub handlers: ExceptionHandleStack,
+    // data stack length at the start of each open block:
+    pub blocks: Vec<usize>,
+    // open upvalues pointing at the locals of this frame:
+    pub upvalues: Vec<Upvalue>,
+    // upvalues collected for the next closure:
+    pub captures: Vec<Upvalue>,
+    // closures registered by defer, run in reverse order on return:
+    pub defers: Vec<Value>,
+    pub is_deferred: bool,
 }
 
 impl ExecutionFrame {
@@ -44,21 +58,26 @@ impl ExecutionFrame {
             instruction_pointer: 0,
             base_pointer: base_pointer,
             bytecode_size: bytecode_size,
-            handlers: vec![]
+            handlers: vec![],
+            blocks: vec![],
+            upvalues: vec![],
+            captures: vec![],
+            defers: vec![],
+            is_deferred: false,
         };
     }
 
-    pub fn new_closure(func: Rc<Subroutine>, free_objects: Vec<Rc<Object>>) -> Rc<Object> {
-        let b_size = func.as_ref().bytecode.len();
+    pub fn new_closure(func: Rc<Subroutine>, free_objects: Vec<Upvalue>) -> Value {
+        let b_size = func.as_ref().instructions.len();
 
-        return Rc::new(Object::ClosureContext(Rc::new(ClosureContext {
+        return Value::new(Object::ClosureContext(Rc::new(ClosureContext {
             compiled_fn: func,
             bytecode_size: b_size,
             free_objects: free_objects,
         })));
     }
 
-    pub fn get_free(&mut self, idx: usize, inst: InstructionKind) -> Result<Rc<Object>, VMError> {
+    pub fn get_free(&mut self, idx: usize, inst: InstructionKind) -> Result<Upvalue, VMError> {
 
         let free_object = self.context.free_objects.get(idx);
         if free_object.is_some() {
@@ -79,22 +98,100 @@ impl ExecutionFrame {
         n_locals: usize,
         n_params: usize,
     ) -> ExecutionFrame {
+        let subroutine = Subroutine::new(
+            fn_name,
+            bytecode.instructions.clone(),
+            n_locals,
+            n_params,
+            false,
+        );
+
         let closure = ClosureContext {
-            compiled_fn: Rc::new(Subroutine {
-                name: fn_name,
-                bytecode: bytecode.instructions.clone(),
-                num_locals: n_locals,
-                num_parameters: n_params,
-                is_local_scope: false,
-            }),
+            bytecode_size: subroutine.instructions.len(),
+            compiled_fn: Rc::new(subroutine),
             free_objects: vec![],
-            bytecode_size: bytecode.instructions.len(),
         };
 
         let frame = ExecutionFrame::new(Rc::new(closure), 0);
         return frame;
     }
 
+    // captures the local at the given slot, closures capturing the same
+    // slot share the upvalue.
+    pub fn capture_local(&mut self, slot: usize) {
+        for upvalue in &self.upvalues {
+            let is_same = match &*upvalue.borrow() {
+                UpvalueState::Open(pos) => *pos == slot,
+                UpvalueState::Closed(_) => false,
+            };
+
+            if is_same {
+                self.captures.push(upvalue.clone());
+                return;
+            }
+        }
+
+        let upvalue = Rc::new(RefCell::new(UpvalueState::Open(slot)));
+        gc::track(&upvalue);
+        self.upvalues.push(upvalue.clone());
+        self.captures.push(upvalue);
+    }
+
+    pub fn capture_free(&mut self, idx: usize) -> Option<VMError> {
+        let free_res = self.get_free(idx, InstructionKind::ICaptureFree);
+        if free_res.is_err() {
+            return Some(free_res.unwrap_err());
+        }
+
+        self.captures.push(free_res.unwrap());
+        return None;
+    }
+
+    pub fn take_captures(&mut self, n_free: usize) -> Option<Vec<Upvalue>> {
+        if self.captures.len() < n_free {
+            return None;
+        }
+
+        let start = self.captures.len() - n_free;
+        return Some(self.captures.split_off(start));
+    }
+
+    // closes the upvalue of the slot, it is called when the slot is released.
+    pub fn close_upvalue(&mut self, slot: usize, value: Value) {
+        let mut idx = 0;
+        while idx < self.upvalues.len() {
+            let is_same = match &*self.upvalues[idx].borrow() {
+                UpvalueState::Open(pos) => *pos == slot,
+                UpvalueState::Closed(_) => false,
+            };
+
+            if is_same {
+                let upvalue = self.upvalues.remove(idx);
+                *upvalue.borrow_mut() = UpvalueState::Closed(value);
+                return;
+            }
+
+            idx += 1;
+        }
+    }
+
+    // closes all the open upvalues before the frame is dropped.
+    pub fn close_upvalues(&self, stack: &Vec<Value>) {
+        for upvalue in &self.upvalues {
+            let slot = match &*upvalue.borrow() {
+                UpvalueState::Open(pos) => *pos,
+                UpvalueState::Closed(_) => continue,
+            };
+
+            let value = match stack.get(slot) {
+                Some(obj) => obj.clone(),
+                None => Value::new(Object::Noval),
+            };
+
+            *upvalue.borrow_mut() = UpvalueState::Closed(value);
+        }
+    }
+
     pub fn get_ip(&self) -> usize {
         return self.instruction_pointer;
     }
@@ -113,34 +210,75 @@ impl ExecutionFrame {
         return None;
     }
 
-    pub fn read_current_instruction(&self) -> (InstructionKind, Operands, usize) {
-        let bytecode = self.context.compiled_fn.as_ref().get_bytecode();
-        let curr_instr_raw = bytecode[self.instruction_pointer];
-        let decoded_opcode: InstructionKind = unsafe { ::std::mem::transmute(curr_instr_raw) };
-
-        let (operands, next_offset) = InstructionPacker::decode_instruction(
-            &decoded_opcode,
-            &bytecode[self.instruction_pointer + 1..],
-        );
-
-        return (decoded_opcode, operands, next_offset);
+    pub fn read_current_instruction(&self) -> (InstructionKind, [usize; 2]) {
+        let instructions = self.context.compiled_fn.as_ref().get_instructions();
+        let instruction = &instructions[self.instruction_pointer];
+        return (instruction.kind.clone(), instruction.operands);
     }
 
-    pub fn farword_ip(&mut self, pos: usize) {
-        self.instruction_pointer += pos + 1;
+    pub fn farword_ip(&mut self) {
+        self.instruction_pointer += 1;
     }
 
     pub fn has_instructions(&self) -> bool {
         self.instruction_pointer < self.bytecode_size
     }
 
+    pub fn enter_block(&mut self, ds_len: usize) {
+        self.blocks.push(ds_len);
+    }
+
+    pub fn exit_block(&mut self) -> Option<usize> {
+        let block_start = self.blocks.pop();
+        // leaving a try block, by its end or by break and continue, drops its handler:
+        while self.handlers.len() != 0
+            && self.handlers[self.handlers.len() - 1].block_depth > self.blocks.len()
+        {
+            self.handlers.pop();
+        }
+
+        return block_start;
+    }
+
+    pub fn push_handler(&mut self, catch_pos: usize, stack_len: usize) {
+        self.handlers.push(ExceptionHandler {
+            catch_pos: catch_pos,
+            stack_len: stack_len,
+            block_depth: self.blocks.len(),
+        });
+    }
+
+    // closes the open upvalues of the locals from the slot onwards, the
+    // locals are dropped when an error leaves their block.
+    pub fn close_upvalues_from(&mut self, slot: usize, stack: &Vec<Value>) {
+        let mut idx = 0;
+        while idx < self.upvalues.len() {
+            let pos = match &*self.upvalues[idx].borrow() {
+                UpvalueState::Open(pos) if *pos >= slot => *pos,
+                _ => {
+                    idx += 1;
+                    continue;
+                }
+            };
+
+            let value = match stack.get(pos) {
+                Some(obj) => obj.clone(),
+                None => Value::new(Object::Noval),
+            };
+
+            let upvalue = self.upvalues.remove(idx);
+            *upvalue.borrow_mut() = UpvalueState::Closed(value);
+        }
+    }
+
     pub fn get_bp(&self) -> usize {
         return self.base_pointer;
     }
 
     pub fn disassemble_ip(&self) -> String {
-        let (inst, operands, _) = self.read_current_instruction();
-        let encoded_string = inst.disasm_instruction(&operands);
+        let (inst, operands) = self.read_current_instruction();
+        let n_operands = inst.get_encoding_width().len();
+        let encoded_string = inst.disasm_instruction(&operands[..n_operands].to_vec());
         return encoded_string;
     }
 
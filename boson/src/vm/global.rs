@@ -1,4 +1,3 @@
-use std::rc::Rc;
 use std::vec::Vec;
 
 use crate::types;
@@ -7,41 +6,36 @@ use crate::vm::errors;
 use errors::VMError;
 use errors::VMErrorKind;
 use types::object::Object;
-
-use crate::config::GLOBAL_POOL_SIZE;
+use types::object::Value;
 
 #[derive(Clone)]
 pub struct GlobalPool {
-    pub pool: Vec<Rc<Object>>,
+    pub pool: Vec<Value>,
     pub max_size: usize,
 }
 
-// make GlobalPool capable of sharing between threads.
-// by design, GlobalPool will always be thread safe as each thread will
-// get it's own copy of global pool.
-// so this unsafe impl is just a cover-up to fool the compiler to make global pool
-// sharebale across threads.
-unsafe impl Send for GlobalPool {}
-
 impl GlobalPool {
-    pub fn new() -> GlobalPool {
-        let mut pool = Vec::with_capacity(GLOBAL_POOL_SIZE);
-        pool.resize(GLOBAL_POOL_SIZE, Rc::new(Object::Noval));
+    // the pool grows on demand up to max_size globals, the globals
+    // that were never set are none.
+    pub fn new(max_size: usize) -> GlobalPool {
         return GlobalPool {
-            pool: pool,
-            max_size: GLOBAL_POOL_SIZE,
+            pool: vec![],
+            max_size: max_size,
         };
     }
 
-    pub fn get(&self, idx: usize) -> Option<Rc<Object>> {
-        return self.pool.get(idx).cloned();
-    }
+    pub fn get(&self, idx: usize) -> Option<Value> {
+        if idx >= self.max_size {
+            return None;
+        }
 
-    pub fn get_ref(&self, idx: usize) -> Option<&Rc<Object>> {
-        return self.pool.get(idx);
+        match self.pool.get(idx) {
+            Some(obj) => return Some(obj.clone()),
+            None => return Some(Value::new(Object::Noval)),
+        }
     }
 
-    pub fn set_object(&mut self, object: Rc<Object>, idx: usize) -> Option<VMError> {
+    pub fn set_object(&mut self, object: Value, idx: usize) -> Option<VMError> {
         if idx >= self.max_size {
             return Some(VMError::new(
                 format!("Index {} exceeds global pool size {}", idx, self.max_size),
@@ -51,11 +45,15 @@ impl GlobalPool {
             ));
         }
 
+        if idx >= self.pool.len() {
+            self.pool.resize(idx + 1, Value::new(Object::Noval));
+        }
+
         self.pool[idx] = object;
         return None;
     }
 
     pub fn set_none(&mut self, idx: usize) -> Option<VMError> {
-        return self.set_object(Rc::new(Object::Noval), idx);
+        return self.set_object(Value::new(Object::Noval), idx);
     }
 }
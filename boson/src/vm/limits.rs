@@ -0,0 +1,186 @@
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::config::VMConfig;
+use crate::isa::InstructionKind;
+use crate::types::gc;
+use crate::types::heap;
+use crate::vm::errors;
+
+use errors::VMError;
+use errors::VMErrorKind;
+
+// the limits are checked once every CHECK_INTERVAL instructions:
+const CHECK_INTERVAL: u64 = 1024;
+
+/*
+    CancelHandle lets the host abort a running VM from another thread, the
+    handle is shared with all the threads spawned by the VM. Once cancelled,
+    every VM that holds the handle stops at its next check until it is reset.
+*/
+#[derive(Debug, Clone)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    pub fn new() -> CancelHandle {
+        return CancelHandle {
+            cancelled: Arc::new(AtomicBool::new(false)),
+        };
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        return self.cancelled.load(Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExecutionLimits {
+    pub max_instructions: Option<u64>,
+    pub deadline: Option<Instant>,
+    pub cancel: CancelHandle,
+    // cancellation of the thread that runs the VM, set by cancel(th). It
+    // raises a ThreadKillError until the thread catches one:
+    pub kill: CancelHandle,
+    // instructions executed in the slices that were already checked:
+    executed: u64,
+    // length of the current slice and the instructions left in it:
+    slice: u64,
+    countdown: u64,
+}
+
+impl ExecutionLimits {
+    // the deadline starts counting when the limits are created.
+    pub fn new(config: &VMConfig, cancel: CancelHandle) -> ExecutionLimits {
+        let deadline = config
+            .timeout_ms
+            .map(|timeout| Instant::now() + Duration::from_millis(timeout));
+
+        return ExecutionLimits {
+            max_instructions: config.max_instructions,
+            deadline: deadline,
+            cancel: cancel,
+            kill: CancelHandle::new(),
+            executed: 0,
+            slice: 0,
+            countdown: 0,
+        };
+    }
+
+    pub fn get_executed(&self) -> u64 {
+        return self.executed + self.slice - self.countdown;
+    }
+
+    // tells an instruction that blocks, like a channel receive, to stop waiting:
+    pub fn interrupted(&self, inst: &InstructionKind) -> Option<VMError> {
+        if self.cancel.is_cancelled() {
+            return Some(VMError::new(
+                "Execution was cancelled".to_string(),
+                VMErrorKind::Cancelled,
+                Some(inst.clone()),
+                0,
+            ));
+        }
+
+        if self.deadline.is_some() && Instant::now() >= self.deadline.unwrap() {
+            return Some(VMError::new(
+                "Execution exceeded the deadline".to_string(),
+                VMErrorKind::DeadlineExceeded,
+                Some(inst.clone()),
+                0,
+            ));
+        }
+
+        if self.kill.is_cancelled() {
+            return Some(VMError::new(
+                "Thread was cancelled".to_string(),
+                VMErrorKind::ThreadKillError,
+                Some(inst.clone()),
+                0,
+            ));
+        }
+
+        return None;
+    }
+
+    // the limits of a new thread, it has a cancellation of its own:
+    pub fn for_thread(&self, kill: &CancelHandle) -> ExecutionLimits {
+        let mut limits = self.clone();
+        limits.kill = kill.clone();
+        return limits;
+    }
+
+    // called before every instruction, the limits are checked once the
+    // current slice is used up.
+    #[inline]
+    pub fn tick(&mut self, inst: &InstructionKind) -> Option<VMError> {
+        if self.countdown == 0 {
+            return self.check(inst);
+        }
+
+        self.countdown -= 1;
+        return None;
+    }
+
+    #[inline(never)]
+    fn check(&mut self, inst: &InstructionKind) -> Option<VMError> {
+        self.executed += self.slice;
+        self.slice = 0;
+
+        let interruption = self.interrupted(inst);
+        if interruption.is_some() {
+            return interruption;
+        }
+
+        if gc::is_due() {
+            gc::collect();
+        }
+
+        // values that grew past the limit without a reservation, like
+        // arrays and tables built element by element:
+        if heap::is_exceeded() {
+            return Some(VMError::new(
+                format!(
+                    "Heap limit of {} bytes exceeded, {} bytes in use",
+                    heap::get_limit().unwrap(),
+                    heap::get_usage()
+                ),
+                VMErrorKind::MemoryError,
+                Some(inst.clone()),
+                0,
+            ));
+        }
+
+        let mut slice = CHECK_INTERVAL;
+        if self.max_instructions.is_some() {
+            let max_instructions = self.max_instructions.unwrap();
+            if self.executed >= max_instructions {
+                return Some(VMError::new(
+                    format!("Execution exceeded the budget of {} instructions", max_instructions),
+                    VMErrorKind::InstructionBudgetExceeded,
+                    Some(inst.clone()),
+                    0,
+                ));
+            }
+
+            slice = slice.min(max_instructions - self.executed);
+        }
+
+        // the current instruction is the first one of the new slice:
+        self.slice = slice;
+        self.countdown = slice - 1;
+        return None;
+    }
+}
@@ -3,8 +3,11 @@ pub mod controls;
 pub mod errors;
 pub mod frames;
 pub mod global;
+pub mod limits;
 pub mod stack;
+pub mod tasks;
 pub mod thread;
+pub mod transfer;
 
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -14,17 +17,33 @@ use errors::VMError;
 use errors::VMErrorKind;
 use frames::ExecutionFrame;
 use global::GlobalPool;
+use limits::ExecutionLimits;
 use stack::CallStack;
 use stack::DataStack;
+use tasks::Switch;
+use tasks::Task;
 
 use crate::api::Platform;
 use crate::compiler::symtab::ConstantPool;
 use crate::compiler::CompiledBytecode;
+use crate::config::VMConfig;
 use crate::isa::InstructionKind;
 use crate::types::closure;
+use crate::types::exception;
 use crate::types::object;
+use crate::types::gc;
+use crate::types::heap;
+use crate::types::task;
 
+use exception::Exception;
 use object::Object;
+use object::Value;
+use task::TaskHandle;
+
+// while no task is ready, the VM checks the blocked tasks and its limits
+// this often, in milliseconds:
+const BLOCKED_SLICE_MS: f64 = 1.0;
+const SLEEP_SLICE_MS: f64 = 10.0;
 
 pub struct BosonVM {
     pub constants: ConstantPool,
@@ -35,30 +54,34 @@ pub struct BosonVM {
 }
 
 impl BosonVM {
-    pub fn new(bytecode: &CompiledBytecode) -> BosonVM {
+    pub fn new(bytecode: &CompiledBytecode, config: &VMConfig) -> BosonVM {
         let main_frame = ExecutionFrame::new_from_bytecode(bytecode, "main".to_string(), 0, 0);
 
-        let mut call_stack = CallStack::new();
-        let data_stack = DataStack::new();
+        let mut call_stack = CallStack::new(config.frame_stack_size);
+        let data_stack = DataStack::new(config.data_stack_size);
 
         let _ = call_stack.push_frame(RefCell::new(main_frame));
 
-        let globals = GlobalPool::new();
+        let globals = GlobalPool::new(config.global_pool_size);
 
         return BosonVM {
             constants: bytecode.constant_pool.clone(),
             call_stack: call_stack,
             data_stack: data_stack,
             globals: globals,
-            threads: thread::BosonThreads::new_empty(),
+            threads: thread::BosonThreads::new_empty(config.clone()),
         };
     }
 
-    pub fn new_state(bytecode: &CompiledBytecode, globals: GlobalPool) -> BosonVM {
+    pub fn new_state(
+        bytecode: &CompiledBytecode,
+        globals: GlobalPool,
+        config: &VMConfig,
+    ) -> BosonVM {
         let main_frame = ExecutionFrame::new_from_bytecode(bytecode, "main".to_string(), 0, 0);
 
-        let mut call_stack = CallStack::new();
-        let data_stack = DataStack::new();
+        let mut call_stack = CallStack::new(config.frame_stack_size);
+        let data_stack = DataStack::new(config.data_stack_size);
 
         let _ = call_stack.push_frame(RefCell::new(main_frame));
 
@@ -67,20 +90,24 @@ impl BosonVM {
             call_stack: call_stack,
             data_stack: data_stack,
             globals: globals,
-            threads: thread::BosonThreads::new_empty(),
+            threads: thread::BosonThreads::new_empty(config.clone()),
         };
     }
 
-    pub fn new_empty_from_state(globals: GlobalPool, constants: ConstantPool) -> BosonVM {
-        let call_stack = CallStack::new();
-        let data_stack = DataStack::new();
+    pub fn new_empty_from_state(
+        globals: GlobalPool,
+        constants: ConstantPool,
+        config: &VMConfig,
+    ) -> BosonVM {
+        let call_stack = CallStack::new(config.frame_stack_size);
+        let data_stack = DataStack::new(config.data_stack_size);
 
         return BosonVM {
             constants: constants,
             call_stack: call_stack,
             data_stack: data_stack,
             globals: globals,
-            threads: thread::BosonThreads::new_empty(),
+            threads: thread::BosonThreads::new_empty(config.clone()),
         };
     }
 
@@ -98,16 +125,376 @@ impl BosonVM {
         platform: &Platform,
         pop_last: bool,
         break_on_ret: bool,
-    ) -> Result<Rc<Object>, VMError> {
-        while self.call_stack.top_ref().has_instructions() {
+    ) -> Result<Value, VMError> {
+        // with break_on_ret, evaluation stops once the current frame returns:
+        let stop_depth = if break_on_ret {
+            self.call_stack.get_top() - 1
+        } else {
+            -1
+        };
+
+        // the heap and the cycle collector are per thread, so every VM sets its own limits:
+        heap::set_limit(self.threads.config.max_heap_bytes);
+        gc::set_threshold(self.threads.config.gc_threshold);
+
+        // the outermost run also runs the tasks:
+        let result = if stop_depth == -1 {
+            self.run_tasks(platform)
+        } else {
+            self.run_catching(platform, stop_depth)
+        };
+        if result.is_err() {
+            self.unwind_deferred(platform, stop_depth);
+            return Err(result.unwrap_err());
+        }
+
+        if pop_last {
+            let popped_result = self.data_stack.pop_object(InstructionKind::IBlockEnd);
+            if popped_result.is_err() {
+                return Ok(Value::new(Object::Noval));
+            }
+            return Ok(popped_result.unwrap());
+        }
+
+        return Ok(Value::new(Object::Noval));
+    }
+
+    // pushes a frame for the deferred closure, the closure takes no arguments.
+    fn call_deferred(&mut self, platform: &Platform, deferred: Value) -> Option<VMError> {
+        let push_res = self
+            .data_stack
+            .push_object(deferred, InstructionKind::IDefer);
+        if push_res.is_err() {
+            return Some(push_res.unwrap_err());
+        }
+
+        let result = Controls::execute_call(
+            &InstructionKind::IDefer,
+            &mut self.data_stack,
+            0,
+            &mut self.globals,
+            &mut self.constants,
+            platform,
+            &mut self.threads,
+        );
+
+        if result.is_err() {
+            return Some(result.unwrap_err());
+        }
+
+        let new_frame = result.unwrap();
+        if new_frame.is_some() {
+            let frame = new_frame.unwrap();
+            frame.borrow_mut().is_deferred = true;
+            return self.push_new_frame(frame);
+        }
+
+        return None;
+    }
+
+    // runs the deferred closures of the frames left on the call stack by
+    // an error, errors raised by the deferred code itself are dropped.
+    fn unwind_deferred(&mut self, platform: &Platform, stop_depth: i64) {
+        let has_deferred = self
+            .call_stack
+            .stack
+            .iter()
+            .any(|frame| frame.borrow().defers.len() != 0);
+        if !has_deferred {
+            return;
+        }
+
+        self.unwind_frames(platform, stop_depth);
+    }
+
+    // pops the frames above stop_depth, running their deferred closures.
+    fn unwind_frames(&mut self, platform: &Platform, stop_depth: i64) {
+        while self.call_stack.get_top() > stop_depth {
+            let deferred = self.call_stack.top().defers.pop();
+            if deferred.is_none() {
+                let popped = self.call_stack.pop_frame();
+                if popped.is_ok() {
+                    let frame = popped.unwrap();
+                    frame.borrow().close_upvalues(&self.data_stack.stack);
+                    self.data_stack.truncate(frame.borrow().get_bp());
+                }
+                continue;
+            }
+
+            let depth = self.call_stack.get_top();
+            let error = self.call_deferred(platform, deferred.unwrap());
+            if error.is_some() {
+                continue;
+            }
+
+            let result = self.run_catching(platform, depth);
+            if result.is_err() {
+                self.unwind_frames(platform, depth);
+            }
+        }
+    }
+
+    // runs the frames above stop_depth, an error raised inside a try block
+    // of one of them continues at the catch block.
+    fn run_catching(&mut self, platform: &Platform, stop_depth: i64) -> Result<(), VMError> {
+        loop {
+            let result = self.run(platform, stop_depth);
+            if result.is_ok() {
+                return result;
+            }
+
+            let error = result.unwrap_err();
+            if !self.catch_error(platform, &error, stop_depth) {
+                return Err(error);
+            }
+        }
+    }
+
+    // runs the program and the tasks it spawns until all of them are done,
+    // the running task hands over to the next one at its yield points:
+    fn run_tasks(&mut self, platform: &Platform) -> Result<(), VMError> {
+        // the stacks of the program once it is done, while the tasks finish:
+        let mut program: Option<(CallStack, DataStack)> = None;
+        // tasks blocked in a row, once all of them are blocked the VM waits:
+        let mut n_blocked = 0;
+
+        loop {
+            let result = self.run_catching(platform, -1);
+            let switch = self.threads.tasks.request.take();
+            let current = self.threads.tasks.current.take();
+
+            if result.is_err() {
+                let error = result.unwrap_err();
+                // errors of the program and of the limits stop all the tasks:
+                if current.is_none() || !error.is_catchable() {
+                    return Err(self.stop_tasks(platform, current, program, error));
+                }
+
+                // the error of a task is thrown by await():
+                self.unwind_deferred(platform, -1);
+                current.unwrap().complete(Err(error.clone()));
+                n_blocked = 0;
+            } else if switch.is_some() {
+                let switch = switch.unwrap();
+                n_blocked = if switch == Switch::Block { n_blocked + 1 } else { 0 };
+                let wake_at = match switch {
+                    Switch::Sleep(time) => Some(time),
+                    _ => None,
+                };
+
+                let (call_stack, data_stack) = self.take_stacks();
+                self.threads
+                    .tasks
+                    .push(Task::new(current, call_stack, data_stack, wake_at));
+            } else if current.is_some() {
+                let value = self.data_stack.pop_object(InstructionKind::IRetVal);
+                current
+                    .unwrap()
+                    .complete(Ok(value.unwrap_or(Value::new(Object::Noval))));
+                n_blocked = 0;
+            } else {
+                if !self.threads.tasks.has_tasks() && !self.threads.tasks.has_timers() {
+                    return Ok(());
+                }
+
+                program = Some(self.take_stacks());
+                n_blocked = 0;
+            }
+
+            let next = self.next_task(platform, &mut n_blocked);
+            if next.is_err() {
+                let error = next.err().unwrap();
+                return Err(self.stop_tasks(platform, None, program, error));
+            }
+
+            let next = next.unwrap();
+            if next.is_none() {
+                // only the program can be left, it is done:
+                let (call_stack, data_stack) = program.unwrap();
+                self.call_stack = call_stack;
+                self.data_stack = data_stack;
+                return Ok(());
+            }
+
+            let task = next.unwrap();
+            self.call_stack = task.call_stack;
+            self.data_stack = task.data_stack;
+            self.threads.tasks.current = task.handle;
+        }
+    }
+
+    // the task to run next, the VM waits while all the tasks sleep or are
+    // blocked and no timer is due. None once no tasks and no timers are left.
+    fn next_task(
+        &mut self,
+        platform: &Platform,
+        n_blocked: &mut usize,
+    ) -> Result<Option<Task>, VMError> {
+        loop {
+            let now = (platform.get_unix_time)().unwrap_or(0.0);
+            let error = self.fire_timers(platform, now);
+            if error.is_some() {
+                return Err(error.unwrap());
+            }
+
+            if !self.threads.tasks.has_tasks() {
+                if !self.threads.tasks.has_timers() {
+                    return Ok(None);
+                }
+                // nothing is blocked, the VM waits for the next timer:
+                *n_blocked = 0;
+            }
+
+            if *n_blocked <= self.threads.tasks.n_queued() {
+                let task = self.threads.tasks.next_ready(now);
+                if task.is_some() {
+                    return Ok(task);
+                }
+            }
+
+            let interruption = self.threads.limits.interrupted(&InstructionKind::ICall);
+            if interruption.is_some() {
+                return Err(interruption.unwrap());
+            }
+
+            let next_wake = self.threads.tasks.next_wake();
+            let wait_ms = if *n_blocked == 0 && next_wake.is_some() {
+                ((next_wake.unwrap() - now) * 1000.0).clamp(0.0, SLEEP_SLICE_MS)
+            } else {
+                BLOCKED_SLICE_MS
+            };
+
+            (platform.sleep)(&wait_ms);
+            *n_blocked = 0;
+        }
+    }
+
+    // queues a task for every timer that is due:
+    fn fire_timers(&mut self, platform: &Platform, now: f64) -> Option<VMError> {
+        for timer in self.threads.tasks.take_due_timers(now) {
+            let closure = match timer.func.as_ref() {
+                Object::ClosureContext(ctx) => ctx.clone(),
+                _ => continue,
+            };
+
+            let name = closure.compiled_fn.name.clone();
+            let spawn_result = self.threads.spawn_task(
+                closure,
+                timer.args,
+                name,
+                platform,
+                &mut self.globals,
+                &mut self.constants,
+            );
+            if spawn_result.is_err() {
+                return Some(spawn_result.unwrap_err());
+            }
+        }
+
+        return None;
+    }
+
+    // an error that stops the VM: the deferred closures of the failed task
+    // run, and the program gets its stacks back to unwind its own.
+    fn stop_tasks(
+        &mut self,
+        platform: &Platform,
+        current: Option<TaskHandle>,
+        program: Option<(CallStack, DataStack)>,
+        error: VMError,
+    ) -> VMError {
+        if current.is_some() {
+            self.unwind_deferred(platform, -1);
+            current.unwrap().complete(Err(error.clone()));
+        }
+
+        let waiting = self.threads.tasks.take_program();
+        if waiting.is_some() {
+            let task = waiting.unwrap();
+            self.call_stack = task.call_stack;
+            self.data_stack = task.data_stack;
+        } else if program.is_some() {
+            let (call_stack, data_stack) = program.unwrap();
+            self.call_stack = call_stack;
+            self.data_stack = data_stack;
+        }
+
+        self.threads.tasks.clear();
+        return error;
+    }
+
+    // the stacks of the running task, replaced by empty ones:
+    fn take_stacks(&mut self) -> (CallStack, DataStack) {
+        let config = &self.threads.config;
+        let call_stack = std::mem::replace(
+            &mut self.call_stack,
+            CallStack::new(config.frame_stack_size),
+        );
+        let data_stack = std::mem::replace(
+            &mut self.data_stack,
+            DataStack::new(config.data_stack_size),
+        );
+        return (call_stack, data_stack);
+    }
+
+    fn catch_error(&mut self, platform: &Platform, error: &VMError, stop_depth: i64) -> bool {
+        if !error.is_catchable() {
+            return false;
+        }
+
+        // the innermost frame with an open try block:
+        let mut depth = self.call_stack.get_top();
+        while depth > stop_depth && self.call_stack.stack[depth as usize].borrow().handlers.len() == 0
+        {
+            depth -= 1;
+        }
+
+        if depth <= stop_depth {
+            return false;
+        }
+
+        // a cancelled thread that catches the error goes on running:
+        if error.t == VMErrorKind::ThreadKillError {
+            self.threads.limits.kill.reset();
+        }
+
+        self.unwind_frames(platform, depth);
+
+        // drop the blocks and the temporaries of the try block:
+        let mut frame = self.call_stack.top();
+        let handler = frame.handlers.pop().unwrap();
+        frame.blocks.truncate(handler.block_depth - 1);
+        frame.close_upvalues_from(handler.stack_len, &self.data_stack.stack);
+        let _ = frame.set_ip(handler.catch_pos);
+        std::mem::drop(frame);
+        self.data_stack.truncate(handler.stack_len);
+
+        // the catch block starts with the exception on the stack:
+        let exception = Exception::new(
+            format!("{:?}", error.t),
+            error.message.clone(),
+            error.t.clone(),
+            vec![],
+        );
+        let exception_obj = Value::new(Object::Exception(Rc::new(exception)));
+        let push_res = self.data_stack.push_object(exception_obj, InstructionKind::IRaise);
+        return push_res.is_ok();
+    }
+
+    fn run(&mut self, platform: &Platform, stop_depth: i64) -> Result<(), VMError> {
+        while self.call_stack.get_top() > stop_depth && self.call_stack.top_ref().has_instructions() {
             let mut frame = self.call_stack.top();
 
-            let (inst, operands, next) = frame.read_current_instruction();
+            let (inst, operands) = frame.read_current_instruction();
+            let limit_error = self.threads.limits.tick(&inst);
+            if limit_error.is_some() {
+                return Err(limit_error.unwrap());
+            }
 
             match inst {
                 // illegal and NoOp
                 InstructionKind::INoOp => {
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::IIllegal => {
@@ -119,8 +506,33 @@ impl BosonVM {
                     ));
                 }
 
-                InstructionKind::IBlockStart | InstructionKind::IBlockEnd => {
-                    frame.farword_ip(next);
+                InstructionKind::IBlockStart => {
+                    frame.enter_block(self.data_stack.stack.len());
+                    frame.farword_ip();
+                }
+
+                InstructionKind::IBlockEnd => {
+                    // drop the temporaries left by the block:
+                    let block_start = frame.exit_block();
+                    if block_start.is_some() {
+                        self.data_stack.truncate(block_start.unwrap());
+                    }
+
+                    frame.farword_ip();
+                }
+
+                InstructionKind::IReleaseLocal => {
+                    Controls::release_local(&mut self.data_stack, operands[0], &mut frame);
+                    frame.farword_ip();
+                }
+
+                InstructionKind::IReleaseGlobal => {
+                    let error = self.globals.set_none(operands[0]);
+                    if error.is_some() {
+                        return Err(error.unwrap());
+                    }
+
+                    frame.farword_ip();
                 }
 
                 // jump and not jump
@@ -141,7 +553,7 @@ impl BosonVM {
 
                     let has_jumped = result.unwrap();
                     if !has_jumped {
-                        frame.farword_ip(next);
+                        frame.farword_ip();
                     }
                 }
 
@@ -155,7 +567,7 @@ impl BosonVM {
                         return Err(result.unwrap_err());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::IStoreGlobal => {
@@ -167,7 +579,7 @@ impl BosonVM {
                         return Err(result.unwrap_err());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::ILoadGlobal => {
@@ -179,7 +591,7 @@ impl BosonVM {
                         return Err(result.unwrap_err());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::ILoadFree => {
@@ -190,7 +602,31 @@ impl BosonVM {
                         return Err(error.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
+                }
+
+                InstructionKind::IStoreFree => {
+                    let error = Controls::store_free(&mut self.data_stack, &mut frame, operands[0]);
+                    if error.is_some() {
+                        return Err(error.unwrap());
+                    }
+
+                    frame.farword_ip();
+                }
+
+                InstructionKind::ICaptureLocal => {
+                    let slot = frame.get_bp() + operands[0];
+                    frame.capture_local(slot);
+                    frame.farword_ip();
+                }
+
+                InstructionKind::ICaptureFree => {
+                    let error = frame.capture_free(operands[0]);
+                    if error.is_some() {
+                        return Err(error.unwrap());
+                    }
+
+                    frame.farword_ip();
                 }
 
                 InstructionKind::ILoadLocal => {
@@ -201,7 +637,7 @@ impl BosonVM {
                         return Err(result.unwrap_err());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::IStoreLocal => {
@@ -212,7 +648,7 @@ impl BosonVM {
                         return Err(result.unwrap_err());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::IAssertFail => {
@@ -221,7 +657,7 @@ impl BosonVM {
                         return Err(error.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::IGetIndex => {
@@ -230,7 +666,7 @@ impl BosonVM {
                         return Err(error.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::ISetIndex => {
@@ -239,7 +675,7 @@ impl BosonVM {
                         return Err(error.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 // Binary operations:
@@ -263,7 +699,76 @@ impl BosonVM {
                         return Err(error.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
+                }
+
+                // superinstructions:
+                InstructionKind::IIncrLocal => {
+                    let error = Controls::incr_local(
+                        &mut self.data_stack,
+                        &self.constants,
+                        &frame,
+                        operands[0],
+                        operands[1],
+                    );
+                    if error.is_some() {
+                        return Err(error.unwrap());
+                    }
+
+                    frame.farword_ip();
+                }
+
+                InstructionKind::IIncrGlobal => {
+                    let error = Controls::incr_global(
+                        &mut self.globals,
+                        &self.constants,
+                        operands[0],
+                        operands[1],
+                    );
+                    if error.is_some() {
+                        return Err(error.unwrap());
+                    }
+
+                    frame.farword_ip();
+                }
+
+                InstructionKind::IAddLocal => {
+                    let slot = frame.get_bp() + operands[0];
+                    let local = self.data_stack.stack.get(slot).cloned();
+                    let error = Controls::add_variable(&mut self.data_stack, local);
+                    if error.is_some() {
+                        return Err(error.unwrap());
+                    }
+
+                    frame.farword_ip();
+                }
+
+                InstructionKind::IAddGlobal => {
+                    let global = self.globals.get(operands[0]);
+                    let error = Controls::add_variable(&mut self.data_stack, global);
+                    if error.is_some() {
+                        return Err(error.unwrap());
+                    }
+
+                    frame.farword_ip();
+                }
+
+                InstructionKind::ICmpNotJump => {
+                    let cmp: InstructionKind = unsafe { ::std::mem::transmute(operands[1] as u8) };
+                    let result = Controls::compare_not_jump(
+                        &mut frame,
+                        &mut self.data_stack,
+                        operands[0],
+                        &cmp,
+                    );
+                    if result.is_err() {
+                        return Err(result.unwrap_err());
+                    }
+
+                    let has_jumped = result.unwrap();
+                    if !has_jumped {
+                        frame.farword_ip();
+                    }
                 }
 
                 // unary operators:
@@ -273,7 +778,7 @@ impl BosonVM {
                         return Err(error.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 // built-ins
@@ -284,13 +789,51 @@ impl BosonVM {
                         return Err(result.unwrap_err());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 // function call:
-                InstructionKind::ICall => {
+                InstructionKind::ICall | InstructionKind::ITailCall => {
                     let args_len = operands[0];
 
+                    // a tail call replaces the current frame, unless the frame
+                    // still has deferred closures to run on return:
+                    if inst == InstructionKind::ITailCall
+                        && frame.defers.len() == 0
+                        && !frame.is_deferred
+                    {
+                        let result = Controls::execute_tail_call(
+                            &inst,
+                            &mut self.data_stack,
+                            args_len,
+                            &frame,
+                        );
+                        if result.is_err() {
+                            return Err(result.unwrap_err());
+                        }
+
+                        let new_frame = result.unwrap();
+                        if new_frame.is_some() {
+                            std::mem::drop(frame);
+                            let popped = self.call_stack.pop_frame();
+                            if popped.is_err() {
+                                return Err(popped.unwrap_err());
+                            }
+
+                            let error = self.push_new_frame(new_frame.unwrap());
+                            if error.is_some() {
+                                return Err(error.unwrap());
+                            }
+                            continue;
+                        }
+                    }
+
+                    let stack_len = self.data_stack.stack.len();
+                    let kept =
+                        self.threads
+                            .tasks
+                            .before_call(stop_depth == -1, &self.data_stack, args_len + 1);
+
                     let result = Controls::execute_call(
                         &inst,
                         &mut self.data_stack,
@@ -301,6 +844,7 @@ impl BosonVM {
                         &mut self.threads
                     );
 
+                    let switch = self.threads.tasks.after_call(&mut self.data_stack, kept, stack_len);
                     if result.is_err() {
                         return Err(result.unwrap_err());
                     }
@@ -309,13 +853,23 @@ impl BosonVM {
                     if new_frame.is_some() {
                         // the previous frame should point to the
                         // next instruction after call
-                        frame.farword_ip(next);
+                        frame.farword_ip();
                         // Looking for better way to handle this:
                         std::mem::drop(frame);
                         // -------------------------------------
-                        self.push_new_frame(new_frame.unwrap());
+                        let error = self.push_new_frame(new_frame.unwrap());
+                        if error.is_some() {
+                            return Err(error.unwrap());
+                        }
                     } else {
-                        frame.farword_ip(next);
+                        // a blocked call is made again on the next turn of the task:
+                        if switch != Some(Switch::Block) {
+                            frame.farword_ip();
+                        }
+
+                        if switch.is_some() {
+                            return Ok(());
+                        }
                     }
                 }
 
@@ -336,7 +890,7 @@ impl BosonVM {
                         return Err(result.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
 
@@ -357,7 +911,7 @@ impl BosonVM {
                         return Err(result.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 // build Array and Hash:
@@ -368,7 +922,7 @@ impl BosonVM {
                         return Err(result.unwrap_err());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::IHash => {
@@ -378,13 +932,14 @@ impl BosonVM {
                         return Err(result.unwrap_err());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::IClosure => {
                     let error = Controls::create_closure(
                         &mut self.data_stack,
                         &self.constants,
+                        &mut frame,
                         operands[1],
                         operands[0],
                     );
@@ -393,11 +948,22 @@ impl BosonVM {
                         return Err(error.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::IRet => {
+                    // run the deferred closures first, the return is executed
+                    // again once each of them is done:
+                    let deferred = frame.defers.pop();
                     std::mem::drop(frame);
+                    if deferred.is_some() {
+                        let error = self.call_deferred(platform, deferred.unwrap());
+                        if error.is_some() {
+                            return Err(error.unwrap());
+                        }
+                        continue;
+                    }
+
                     let current_frame_res = self.call_stack.pop_frame();
                     if current_frame_res.is_err() {
                         return Err(current_frame_res.unwrap_err());
@@ -405,9 +971,10 @@ impl BosonVM {
 
                     // execute return: This function cleans up the subroutine's data
                     // on stack
+                    let current_frame = current_frame_res.unwrap();
                     let error = Controls::execute_return(
                         &mut self.data_stack,
-                        &current_frame_res.unwrap().borrow(),
+                        &current_frame.borrow(),
                         false,
                     );
 
@@ -415,13 +982,25 @@ impl BosonVM {
                         return Err(error.unwrap());
                     }
 
-                    if break_on_ret {
-                        break;
+                    // deferred closures do not return a value to the caller:
+                    if current_frame.borrow().is_deferred {
+                        let _ = self.data_stack.pop_object(inst);
                     }
                 }
 
                 InstructionKind::IRetVal => {
+                    // run the deferred closures first, the return is executed
+                    // again once each of them is done:
+                    let deferred = frame.defers.pop();
                     std::mem::drop(frame);
+                    if deferred.is_some() {
+                        let error = self.call_deferred(platform, deferred.unwrap());
+                        if error.is_some() {
+                            return Err(error.unwrap());
+                        }
+                        continue;
+                    }
+
                     let current_frame_res = self.call_stack.pop_frame();
                     if current_frame_res.is_err() {
                         return Err(current_frame_res.unwrap_err());
@@ -429,9 +1008,10 @@ impl BosonVM {
 
                     // execute return: This function cleans up the subroutine's data
                     // on stack
+                    let current_frame = current_frame_res.unwrap();
                     let error = Controls::execute_return(
                         &mut self.data_stack,
-                        &current_frame_res.unwrap().borrow(),
+                        &current_frame.borrow(),
                         true,
                     );
 
@@ -439,45 +1019,122 @@ impl BosonVM {
                         return Err(error.unwrap());
                     }
 
-                    if break_on_ret {
-                        break;
+                    // deferred closures do not return a value to the caller:
+                    if current_frame.borrow().is_deferred {
+                        let _ = self.data_stack.pop_object(inst);
                     }
                 }
 
+                InstructionKind::IPushExcHandle => {
+                    frame.push_handler(operands[0], self.data_stack.stack.len());
+                    frame.farword_ip();
+                }
+
+                InstructionKind::IPopExcHandle => {
+                    frame.handlers.pop();
+                    frame.farword_ip();
+                }
+
+                InstructionKind::IRaise => {
+                    let popped = self.data_stack.pop_object(InstructionKind::IRaise);
+                    if popped.is_err() {
+                        return Err(popped.unwrap_err());
+                    }
+
+                    // exceptions are raised again as they are, other values
+                    // are raised as user exceptions:
+                    let thrown = popped.unwrap();
+                    let error = match thrown.as_ref() {
+                        Object::Exception(exc) => {
+                            VMError::new(exc.get_message(), exc.get_kind(), Some(inst), 0)
+                        }
+                        _ => VMError::new(
+                            thrown.describe(),
+                            VMErrorKind::UserException,
+                            Some(inst),
+                            0,
+                        ),
+                    };
+
+                    return Err(error);
+                }
+
+                InstructionKind::IDefer => {
+                    let popped = self.data_stack.pop_object(InstructionKind::IDefer);
+                    if popped.is_err() {
+                        return Err(popped.unwrap_err());
+                    }
+
+                    frame.defers.push(popped.unwrap());
+                    frame.farword_ip();
+                }
+
                 InstructionKind::IIter => {
                     let error = Controls::create_iter(&mut self.data_stack);
                     if error.is_some() {
                         return Err(error.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::IIterNext => {
+                    // a task waiting for the next value of a receiver lets the
+                    // other tasks run, the instruction is run again on its next turn:
+                    if stop_depth == -1
+                        && self.threads.tasks.is_active()
+                        && Controls::iter_would_block(&mut self.data_stack)
+                    {
+                        self.threads.tasks.request = Some(Switch::Block);
+                        return Ok(());
+                    }
+
                     let jmp_pos = operands[0];
                     let result =
-                        Controls::jump_next_iter(&mut self.data_stack, jmp_pos, &mut frame, false);
+                        Controls::jump_next_iter(
+                            &mut self.data_stack,
+                            jmp_pos,
+                            &mut frame,
+                            false,
+                            &self.threads.limits,
+                        );
                     if result.is_err() {
                         return Err(result.unwrap_err());
                     }
 
                     let has_jumped = result.unwrap();
                     if !has_jumped {
-                        frame.farword_ip(next);
+                        frame.farword_ip();
                     }
                 }
 
                 InstructionKind::IEnumNext => {
+                    // a task waiting for the next value of a receiver lets the
+                    // other tasks run, the instruction is run again on its next turn:
+                    if stop_depth == -1
+                        && self.threads.tasks.is_active()
+                        && Controls::iter_would_block(&mut self.data_stack)
+                    {
+                        self.threads.tasks.request = Some(Switch::Block);
+                        return Ok(());
+                    }
+
                     let jmp_pos = operands[0];
                     let result =
-                        Controls::jump_next_iter(&mut self.data_stack, jmp_pos, &mut frame, true);
+                        Controls::jump_next_iter(
+                            &mut self.data_stack,
+                            jmp_pos,
+                            &mut frame,
+                            true,
+                            &self.threads.limits,
+                        );
                     if result.is_err() {
                         return Err(result.unwrap_err());
                     }
 
                     let has_jumped = result.unwrap();
                     if !has_jumped {
-                        frame.farword_ip(next);
+                        frame.farword_ip();
                     }
                 }
 
@@ -496,7 +1153,7 @@ impl BosonVM {
                         return Err(result.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::IShellRaw => {
@@ -514,7 +1171,7 @@ impl BosonVM {
                         return Err(result.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::IGetAttr => {
@@ -529,24 +1186,42 @@ impl BosonVM {
                         return Err(result.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    frame.farword_ip();
                 }
 
                 InstructionKind::ICallAttr => {
                     let n_attrs = operands[0];
                     let n_params = operands[1];
 
+                    let stack_len = self.data_stack.stack.len();
+                    let kept = self.threads.tasks.before_call(
+                        stop_depth == -1,
+                        &self.data_stack,
+                        n_attrs + n_params + 1,
+                    );
+
                     let result = Controls::call_attr(
                         &mut self.data_stack,
                         &inst,
-                        n_attrs, n_params
+                        n_attrs, n_params,
+                        platform,
+                        &mut self.globals,
+                        &mut self.constants,
+                        &mut self.threads
                     );
 
+                    let switch = self.threads.tasks.after_call(&mut self.data_stack, kept, stack_len);
                     if result.is_some() {
                         return Err(result.unwrap());
                     }
 
-                    frame.farword_ip(next);
+                    if switch != Some(Switch::Block) {
+                        frame.farword_ip();
+                    }
+
+                    if switch.is_some() {
+                        return Ok(());
+                    }
                 }
 
                 _ => {
@@ -560,15 +1235,7 @@ impl BosonVM {
             }
         }
 
-        if pop_last {
-            let popped_result = self.data_stack.pop_object(InstructionKind::IBlockEnd);
-            if popped_result.is_err() {
-                return Ok(Rc::new(Object::Noval));
-            }
-            return Ok(popped_result.unwrap());
-        }
-
-        return Ok(Rc::new(Object::Noval));
+        return Ok(());
     }
 
     pub fn dump_globals(&self) -> String {
@@ -590,16 +1257,19 @@ impl BosonVM {
 
     pub fn execute_sandbox(
         closure: Rc<closure::ClosureContext>,
-        params: Vec<Rc<Object>>,
+        params: Vec<Value>,
         platform: &Platform,
         globals: GlobalPool,
         constants: ConstantPool,
-    ) -> Result<Rc<Object>, VMError> {
+        config: &VMConfig,
+        limits: &ExecutionLimits,
+    ) -> Result<Value, VMError> {
         // create an execution frame for that closure:
 
         // new empty from state will create a VM with an empty call stack.
-        let mut vm_instance = BosonVM::new_empty_from_state(globals, constants);
-        let closure_rc = Rc::new(Object::ClosureContext(closure));
+        let mut vm_instance = BosonVM::new_empty_from_state(globals, constants, config);
+        vm_instance.threads.limits = limits.clone();
+        let closure_rc = Value::new(Object::ClosureContext(closure));
 
         // push the arguments on top of the stack:
         let params_len = params.len();
@@ -647,7 +1317,7 @@ impl BosonVM {
             .data_stack
             .pop_object(InstructionKind::IBlockEnd);
         if popped_result.is_err() {
-            return Ok(Rc::new(Object::Noval));
+            return Ok(Value::new(Object::Noval));
         }
 
         return Ok(popped_result.unwrap());
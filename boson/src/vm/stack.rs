@@ -3,19 +3,14 @@ use crate::types::object;
 use crate::vm::errors;
 use crate::vm::frames;
 
-use crate::config::DATA_STACK_SIZE;
-use crate::config::FRAME_STACK_SIZE;
-use crate::config::USE_STATIC_DATA_STACK;
-
 use errors::VMError;
 use errors::VMErrorKind;
 use frames::ExecutionFrame;
 use isa::InstructionKind;
-use object::Object;
+use object::Value;
 use std::cell::Ref;
 use std::cell::RefCell;
 use std::cell::RefMut;
-use std::rc::Rc;
 
 // aliasing I to I
 type I = InstructionKind;
@@ -24,7 +19,7 @@ type I = InstructionKind;
 type E = RefCell<ExecutionFrame>;
 
 // aliasing O:
-type O = Rc<Object>;
+type O = Value;
 
 pub struct CallStack {
     pub stack: Vec<E>,
@@ -33,11 +28,11 @@ pub struct CallStack {
 }
 
 impl CallStack {
-    pub fn new() -> CallStack {
+    pub fn new(max_size: usize) -> CallStack {
         return CallStack {
             stack: vec![],
             stack_pointer: -1,
-            max_size: FRAME_STACK_SIZE,
+            max_size: max_size,
         };
     }
 
@@ -101,17 +96,12 @@ pub struct DataStack {
 }
 
 impl DataStack {
-    pub fn new() -> DataStack {
-
-        let mut stack = vec![];
-        if USE_STATIC_DATA_STACK {
-            stack.resize(DATA_STACK_SIZE, Object::Noval);
-        }
-
+    // the stack grows on demand up to max_size values:
+    pub fn new(max_size: usize) -> DataStack {
         return DataStack {
             stack: vec![],
             stack_pointer: -1,
-            max_size: DATA_STACK_SIZE,
+            max_size: max_size,
         };
     }
 }
@@ -177,4 +167,12 @@ impl DataStack {
 
         return Ok(self.stack.get(self.stack_pointer as usize).unwrap());
     }
+
+    // drops everything above the given length.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.stack.len() {
+            self.stack.truncate(len);
+            self.stack_pointer = len as i64 - 1;
+        }
+    }
 }
@@ -0,0 +1,270 @@
+use std::collections::VecDeque;
+
+use crate::types::object::Value;
+use crate::types::task::TaskHandle;
+use crate::vm::stack::CallStack;
+use crate::vm::stack::DataStack;
+
+/*
+    Green threads. spawn(fn, args) starts a task with a call stack and a
+    data stack of its own, the VM runs one task at a time on the same
+    globals and switches to the next one only at a yield point: yield(),
+    sleep_ms() and sleep_sec(), the IO builtins, waiting for a task and
+    channel operations that cannot complete yet. The tasks take their turns
+    in the order they were queued, so a run does not depend on the OS.
+    The program itself is a task without a handle, once it is done the VM
+    runs the tasks that are left before it returns.
+
+    Timers are kept by the scheduler as well: set_timeout(fn, ms) and
+    set_interval(fn, ms) start a task that calls the function once the
+    clock of the platform reaches the time, the VM runs until no timers
+    and no tasks are left.
+*/
+
+// why the running task stopped at a yield point:
+#[derive(Debug, Clone, PartialEq)]
+pub enum Switch {
+    // let the other tasks run, go on after the call:
+    Yield,
+    // go on after the call once the clock reaches the time, in seconds:
+    Sleep(f64),
+    // the call cannot complete yet, it is made again on the next turn:
+    Block,
+}
+
+pub struct Task {
+    // none for the program:
+    pub handle: Option<TaskHandle>,
+    pub call_stack: CallStack,
+    pub data_stack: DataStack,
+    wake_at: Option<f64>,
+}
+
+impl Task {
+    pub fn new(
+        handle: Option<TaskHandle>,
+        call_stack: CallStack,
+        data_stack: DataStack,
+        wake_at: Option<f64>,
+    ) -> Task {
+        return Task {
+            handle: handle,
+            call_stack: call_stack,
+            data_stack: data_stack,
+            wake_at: wake_at,
+        };
+    }
+}
+
+#[derive(Clone)]
+pub struct Timer {
+    pub id: u64,
+    // the clock time the timer fires at, in seconds:
+    due: f64,
+    // the time between two calls of an interval, in seconds:
+    interval: Option<f64>,
+    pub func: Value,
+    pub args: Vec<Value>,
+}
+
+pub struct Scheduler {
+    // the tasks waiting for their turn, in the order they run:
+    queue: VecDeque<Task>,
+    // the running task, none while the program runs:
+    pub current: Option<TaskHandle>,
+    // set by a builtin that stops the running task:
+    pub request: Option<Switch>,
+    // set by the VM before a call that can stop at a yield point:
+    pub can_switch: bool,
+    next_id: u64,
+    // the timers waiting to fire, in the order they were set:
+    timers: Vec<Timer>,
+    next_timer_id: u64,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        return Scheduler {
+            queue: VecDeque::new(),
+            current: None,
+            request: None,
+            can_switch: false,
+            next_id: 1,
+            timers: vec![],
+            next_timer_id: 1,
+        };
+    }
+
+    // true once a task was spawned or a timer set, until all of them are done:
+    pub fn is_active(&self) -> bool {
+        return self.current.is_some() || !self.queue.is_empty() || !self.timers.is_empty();
+    }
+
+    pub fn has_tasks(&self) -> bool {
+        return !self.queue.is_empty();
+    }
+
+    pub fn has_timers(&self) -> bool {
+        return !self.timers.is_empty();
+    }
+
+    pub fn add_timer(&mut self, func: Value, args: Vec<Value>, due: f64, interval: Option<f64>) -> u64 {
+        let id = self.next_timer_id;
+        self.next_timer_id += 1;
+        self.timers.push(Timer {
+            id: id,
+            due: due,
+            interval: interval,
+            func: func,
+            args: args,
+        });
+        return id;
+    }
+
+    // false if the timer fired already or was cleared:
+    pub fn clear_timer(&mut self, id: u64) -> bool {
+        let len = self.timers.len();
+        self.timers.retain(|timer| timer.id != id);
+        return self.timers.len() != len;
+    }
+
+    // the timers due at now, in the order they are due. An interval is set
+    // again for its next time, a timeout is removed.
+    pub fn take_due_timers(&mut self, now: f64) -> Vec<Timer> {
+        let mut due: Vec<Timer> = vec![];
+        let mut idx = 0;
+        while idx < self.timers.len() {
+            if self.timers[idx].due > now {
+                idx += 1;
+                continue;
+            }
+
+            let timer = &mut self.timers[idx];
+            if timer.interval.is_none() {
+                due.push(self.timers.remove(idx));
+                continue;
+            }
+
+            // an interval that fell behind the clock skips the calls it missed:
+            let interval = timer.interval.unwrap();
+            due.push(timer.clone());
+            timer.due = if timer.due + interval > now {
+                timer.due + interval
+            } else {
+                now + interval
+            };
+            idx += 1;
+        }
+
+        due.sort_by(|a, b| a.due.total_cmp(&b.due).then(a.id.cmp(&b.id)));
+        return due;
+    }
+
+    pub fn n_queued(&self) -> usize {
+        return self.queue.len();
+    }
+
+    pub fn new_handle(&mut self, name: String) -> TaskHandle {
+        let handle = TaskHandle::new(self.next_id, name);
+        self.next_id += 1;
+        return handle;
+    }
+
+    pub fn push(&mut self, task: Task) {
+        self.queue.push_back(task);
+    }
+
+    // stops the running task after the current call, if it can switch:
+    pub fn switch(&mut self, switch: Switch) -> bool {
+        if !self.can_switch {
+            return false;
+        }
+
+        if self.request.is_none() {
+            self.request = Some(switch);
+        }
+        return true;
+    }
+
+    // before a call: only the calls made by the outermost run of a VM with
+    // tasks can switch. For them, the function and the arguments on top of
+    // the stack are kept, a blocked call is made again with them.
+    #[inline]
+    pub fn before_call(
+        &mut self,
+        outermost: bool,
+        ds: &DataStack,
+        n_values: usize,
+    ) -> Option<Vec<Value>> {
+        if !outermost || !self.is_active() {
+            return None;
+        }
+
+        self.can_switch = true;
+        let start = ds.stack.len().saturating_sub(n_values);
+        return Some(ds.stack[start..].to_vec());
+    }
+
+    // after a call that can switch: the switch the running task stops for,
+    // if any. A blocked call is put back on the stack as it was before.
+    pub fn after_call(
+        &mut self,
+        ds: &mut DataStack,
+        kept: Option<Vec<Value>>,
+        stack_len: usize,
+    ) -> Option<Switch> {
+        if kept.is_none() {
+            return None;
+        }
+
+        self.can_switch = false;
+        if self.request == Some(Switch::Block) {
+            let kept = kept.unwrap();
+            ds.truncate(stack_len - kept.len());
+            ds.stack.extend(kept);
+            ds.stack_pointer = ds.stack.len() as i64 - 1;
+        }
+
+        return self.request.clone();
+    }
+
+    // the first task in the queue that does not sleep past now:
+    pub fn next_ready(&mut self, now: f64) -> Option<Task> {
+        let position = self
+            .queue
+            .iter()
+            .position(|task| task.wake_at.is_none() || task.wake_at.unwrap() <= now);
+        if position.is_none() {
+            return None;
+        }
+
+        return self.queue.remove(position.unwrap());
+    }
+
+    // the time the first sleeping task wakes up or the first timer fires at:
+    pub fn next_wake(&self) -> Option<f64> {
+        return self
+            .queue
+            .iter()
+            .filter_map(|task| task.wake_at)
+            .chain(self.timers.iter().map(|timer| timer.due))
+            .min_by(|a, b| a.total_cmp(b));
+    }
+
+    // the program waiting for its turn, taken out of the queue:
+    pub fn take_program(&mut self) -> Option<Task> {
+        let position = self.queue.iter().position(|task| task.handle.is_none());
+        if position.is_none() {
+            return None;
+        }
+
+        return self.queue.remove(position.unwrap());
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.timers.clear();
+        self.current = None;
+        self.request = None;
+    }
+}
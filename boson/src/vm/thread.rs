@@ -1,111 +1,250 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
 use std::thread;
+use std::time::Duration;
+use std::time::Instant;
 
 use crate::api;
 use crate::compiler::symtab;
+use crate::config::VMConfig;
+use crate::isa::InstructionKind;
 use crate::types::closure;
+use crate::types::future;
 use crate::types::object;
+use crate::types::pool;
+use crate::types::task;
+use crate::types::th;
 use crate::vm;
 use crate::vm::global;
+use crate::vm::controls;
+use crate::vm::limits;
+use crate::vm::stack;
+use crate::vm::tasks;
+use crate::vm::transfer;
 
 use api::BosonLang;
 use api::Platform;
 use api::PlatformKind;
 use closure::ClosureContext;
+use controls::Controls;
+use future::Future;
 use global::GlobalPool;
+use limits::CancelHandle;
+use limits::ExecutionLimits;
 use object::Object;
+use object::Value;
+use pool::ThreadPool;
+use stack::CallStack;
+use stack::DataStack;
 use symtab::ConstantPool;
+use task::TaskHandle;
+use tasks::Scheduler;
+use tasks::Task;
+use th::ThreadBlock;
+use transfer::Transfer;
 use vm::BosonVM;
 
 use crate::vm::errors;
 use errors::VMError;
 
+// the result of a thread, copied out of it when the thread finishes:
 #[derive(Debug, Clone)]
 pub struct ThreadReturnType {
-    pub result: Result<Rc<Object>, VMError>,
+    result: Result<Transfer, VMError>,
 }
 
 impl ThreadReturnType {
-    pub fn new(result: Result<Rc<Object>, VMError>) -> ThreadReturnType {
-        return ThreadReturnType { result: result };
+    pub fn new(result: Result<Value, VMError>) -> ThreadReturnType {
+        return ThreadReturnType {
+            result: result.map(|value| Transfer::pack(&vec![value])),
+        };
     }
-}
 
-unsafe impl Send for ThreadReturnType {}
+    // the values are created on the thread that waits for the result:
+    pub fn get_result(self) -> Result<Value, VMError> {
+        return self.result.map(|transfer| transfer.unpack().pop().unwrap());
+    }
+}
 
+/*
+    Everything a new thread starts with: the function, its arguments and
+    copies of the globals and the constants. All of them are packed
+    together, so the functions they share are copied once.
+*/
 pub struct ThreadParams {
-    closure: Rc<ClosureContext>,
-    params: Vec<Rc<Object>>,
-    globals: GlobalPool,
-    constants: ConstantPool,
+    transfer: Transfer,
+    n_params: usize,
+    n_globals: usize,
+    max_globals: usize,
 }
 
 impl ThreadParams {
     pub fn new(
         closure: Rc<ClosureContext>,
-        params: Vec<Rc<Object>>,
-        globals: GlobalPool,
-        constants: ConstantPool,
+        params: Vec<Value>,
+        globals: &GlobalPool,
+        constants: &ConstantPool,
     ) -> ThreadParams {
+        let mut values = vec![Value::new(Object::ClosureContext(closure))];
+        values.extend(params.iter().cloned());
+        values.extend(globals.pool.iter().cloned());
+        values.extend(constants.objects.iter().cloned());
+
         return ThreadParams {
-            closure: closure,
-            params: params,
-            globals: globals,
-            constants: constants,
+            transfer: Transfer::pack(&values),
+            n_params: params.len(),
+            n_globals: globals.pool.len(),
+            max_globals: globals.max_size,
+        };
+    }
+
+    // turns the parameters back into values on the new thread:
+    fn unpack(self) -> (Rc<ClosureContext>, Vec<Value>, GlobalPool, ConstantPool) {
+        let mut values = self.transfer.unpack();
+        let constants = values.split_off(1 + self.n_params + self.n_globals);
+        let globals = values.split_off(1 + self.n_params);
+        let params = values.split_off(1);
+
+        let closure = match values[0].as_ref() {
+            Object::ClosureContext(ctx) => ctx.clone(),
+            _ => unreachable!(),
+        };
+
+        let mut global_pool = GlobalPool::new(self.max_globals);
+        global_pool.pool = globals;
+        return (closure, params, global_pool, ConstantPool::new_from_objects(constants));
+    }
+}
+
+/*
+    A function and its arguments, packed without the globals and the
+    constants. The jobs of a pool carry only this, its workers unpack the
+    globals and the constants once when they start.
+*/
+pub struct ThreadCall {
+    transfer: Transfer,
+}
+
+impl ThreadCall {
+    pub fn new(closure: Rc<ClosureContext>, params: Vec<Value>) -> ThreadCall {
+        let mut values = vec![Value::new(Object::ClosureContext(closure))];
+        values.extend(params);
+        return ThreadCall {
+            transfer: Transfer::pack(&values),
+        };
+    }
+
+    fn unpack(self) -> (Rc<ClosureContext>, Vec<Value>) {
+        let mut values = self.transfer.unpack();
+        let params = values.split_off(1);
+        let closure = match values[0].as_ref() {
+            Object::ClosureContext(ctx) => ctx.clone(),
+            _ => unreachable!(),
         };
+
+        return (closure, params);
     }
 }
 
-unsafe impl Send for ThreadParams {}
+// IDs are unique across all the threads of the process, 0 is the main thread:
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+// a thread waiting for another one checks the limits of its VM this often:
+const WAIT_SLICE: Duration = Duration::from_millis(1);
+
+thread_local! {
+    // the ID and the name of the thread, none on the main thread:
+    static CURRENT_THREAD: RefCell<Option<(u64, String)>> = const { RefCell::new(None) };
+}
 
 pub struct BosonThreads {
     pub thread_map: HashMap<u64, thread::JoinHandle<ThreadReturnType>>,
-    // this counter will increment each time a new thread is created.
-    // so it will have a new ID.
+    // the number of threads created by this VM, the IDs are given by
+    // NEXT_THREAD_ID.
     pub current_count: u64,
+    // limits of the VM, every new thread runs with the same config.
+    pub config: VMConfig,
+    // instruction budget, deadline and cancellation of the VM, carried
+    // over to every new thread.
+    pub limits: ExecutionLimits,
+    // the green threads of the VM, they run on this thread:
+    pub tasks: Scheduler,
 }
 
 impl BosonThreads {
-    pub fn new_empty() -> BosonThreads {
+    pub fn new_empty(config: VMConfig) -> BosonThreads {
         return BosonThreads {
             thread_map: HashMap::new(),
             current_count: 0,
+            limits: ExecutionLimits::new(&config, CancelHandle::new()),
+            config: config,
+            tasks: Scheduler::new(),
         };
     }
 
-    pub fn wait_and_return(&mut self, thread_id: u64) -> Result<ThreadReturnType, String> {
+    // waits for the thread to finish until the timeout, none means wait as
+    // long as it takes. The result is none when the time is up, the thread
+    // can be waited for again. The limits of the VM interrupt the wait.
+    pub fn wait_and_return(
+        &mut self,
+        thread_id: u64,
+        timeout: Option<Duration>,
+    ) -> Result<Option<ThreadReturnType>, String> {
         if !self.thread_map.contains_key(&thread_id) {
             return Err(format!(
-                "Cannot kill thread with ID {}, thread does not exist anymore.",
+                "Cannot join on a thread {} which does not exist anymore.",
                 thread_id
             ));
         }
 
-        // kill the thread:
-        let handle_ref = self.thread_map.remove(&thread_id);
-        if handle_ref.is_none() {
-            return Err(format!(
-                "Cannot joing on a thread {} which does not exist.",
-                thread_id
-            ));
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        while !self.thread_map[&thread_id].is_finished() {
+            if deadline.is_some() && Instant::now() >= deadline.unwrap() {
+                return Ok(None);
+            }
+
+            let interruption = self.limits.interrupted(&InstructionKind::ICall);
+            if interruption.is_some() {
+                return Err(interruption.unwrap().message);
+            }
+
+            thread::sleep(WAIT_SLICE);
         }
 
-        let join_result = handle_ref.unwrap().join();
+        let handle = self.thread_map.remove(&thread_id).unwrap();
+        let join_result = handle.join();
         if join_result.is_err() {
             return Err("Failed to join thread.".to_string());
         }
 
-        return Ok(join_result.unwrap());
+        return Ok(Some(join_result.unwrap()));
     }
 
-    pub fn create_thread_sandbox(
-        &mut self,
-        thread_params: ThreadParams,
-        platform: &Platform,
-    ) -> Result<u64, String> {
-        let new_platform = match platform.platform_type {
-            PlatformKind::Native => BosonLang::prepare_native_platform(),
+    // a thread is alive until it finishes, the current thread is always alive:
+    pub fn is_alive(&self, thread_id: u64) -> bool {
+        if thread_id == self.current_thread().handle_id {
+            return true;
+        }
+
+        let handle = self.thread_map.get(&thread_id);
+        return handle.is_some() && !handle.unwrap().is_finished();
+    }
+
+    // the thread running this VM, the main thread has the ID 0:
+    pub fn current_thread(&self) -> ThreadBlock {
+        let (thread_id, name) = CURRENT_THREAD.with(|current| {
+            return current.borrow().clone().unwrap_or((0, "main".to_string()));
+        });
+
+        return ThreadBlock::new(thread_id, name, self.limits.kill.clone());
+    }
+
+    fn prepare_platform(platform: &Platform) -> Result<Platform, String> {
+        match platform.platform_type {
+            PlatformKind::Native => return Ok(BosonLang::prepare_native_platform()),
             _ => {
                 // TODO: re-iterate this section after web assembly support.
                 return Err(format!(
@@ -113,24 +252,231 @@ impl BosonThreads {
                     platform.platform_type
                 ));
             }
-        };
+        }
+    }
 
-        let handle = thread::spawn(move || {
-            let result = BosonVM::execute_sandbox(
-                thread_params.closure,
-                thread_params.params,
-                &new_platform,
-                thread_params.globals,
-                thread_params.constants,
-            );
+    // runs the function of the thread on the calling thread:
+    fn run_sandbox(
+        thread_params: ThreadParams,
+        platform: &Platform,
+        config: &VMConfig,
+        limits: &ExecutionLimits,
+    ) -> ThreadReturnType {
+        let (closure, params, globals, constants) = thread_params.unpack();
+        return BosonThreads::run_call(
+            closure, params, globals, constants, platform, config, limits,
+        );
+    }
 
-            return ThreadReturnType::new(result);
+    fn run_call(
+        closure: Rc<ClosureContext>,
+        params: Vec<Value>,
+        globals: GlobalPool,
+        constants: ConstantPool,
+        platform: &Platform,
+        config: &VMConfig,
+        limits: &ExecutionLimits,
+    ) -> ThreadReturnType {
+        let result = BosonVM::execute_sandbox(
+            closure, params, platform, globals, constants, config, limits,
+        );
+
+        return ThreadReturnType::new(result);
+    }
+
+    // runs the function of the thread on a new thread that knows its ID and name:
+    fn spawn_sandbox<F>(
+        &mut self,
+        thread_params: ThreadParams,
+        name: String,
+        platform: &Platform,
+        kill: &CancelHandle,
+        on_done: F,
+    ) -> Result<(u64, thread::JoinHandle<ThreadReturnType>), String>
+    where
+        F: FnOnce(&ThreadReturnType) + Send + 'static,
+    {
+        let platform_result = BosonThreads::prepare_platform(platform);
+        if platform_result.is_err() {
+            return Err(platform_result.unwrap_err());
+        }
+
+        let new_platform = platform_result.unwrap();
+        let config = self.config.clone();
+        let limits = self.limits.for_thread(kill);
+        let thread_id = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+        let spawn_result = thread::Builder::new().name(name.clone()).spawn(move || {
+            CURRENT_THREAD.with(|current| *current.borrow_mut() = Some((thread_id, name)));
+            let result = BosonThreads::run_sandbox(thread_params, &new_platform, &config, &limits);
+            on_done(&result);
+            return result;
         });
 
+        if spawn_result.is_err() {
+            return Err(format!("Failed to spawn thread: {}", spawn_result.unwrap_err()));
+        }
+
+        self.current_count += 1;
+        return Ok((thread_id, spawn_result.unwrap()));
+    }
+
+    pub fn create_thread_sandbox(
+        &mut self,
+        thread_params: ThreadParams,
+        name: String,
+        platform: &Platform,
+    ) -> Result<ThreadBlock, String> {
+        let kill = CancelHandle::new();
+        let spawn_result = self.spawn_sandbox(thread_params, name.clone(), platform, &kill, |_| {});
+        if spawn_result.is_err() {
+            return Err(spawn_result.unwrap_err());
+        }
+
         // register the thread handle
-        let thread_id = self.current_count;
+        let (thread_id, handle) = spawn_result.unwrap();
         self.thread_map.insert(thread_id, handle);
-        self.current_count += 1;
-        return Ok(thread_id);
+        return Ok(ThreadBlock::new(thread_id, name, kill));
+    }
+
+    // an async call: the thread is not registered, it hands its result
+    // over to the future and nobody joins it.
+    pub fn create_future(
+        &mut self,
+        thread_params: ThreadParams,
+        name: String,
+        platform: &Platform,
+    ) -> Result<Future, String> {
+        let future = Future::new(name.clone());
+        let thread_future = future.clone();
+        let spawn_result = self.spawn_sandbox(
+            thread_params,
+            name,
+            platform,
+            &CancelHandle::new(),
+            move |result| thread_future.complete(result.clone()),
+        );
+
+        if spawn_result.is_err() {
+            return Err(spawn_result.unwrap_err());
+        }
+
+        return Ok(future);
+    }
+
+    // queues a task that calls the function on stacks of its own, it runs
+    // when the running task stops at a yield point:
+    pub fn spawn_task(
+        &mut self,
+        closure: Rc<ClosureContext>,
+        params: Vec<Value>,
+        name: String,
+        platform: &Platform,
+        globals: &mut GlobalPool,
+        constants: &mut ConstantPool,
+    ) -> Result<TaskHandle, VMError> {
+        let mut call_stack = CallStack::new(self.config.frame_stack_size);
+        let mut data_stack = DataStack::new(self.config.data_stack_size);
+
+        let n_params = params.len();
+        let mut values = params;
+        values.push(Value::new(Object::ClosureContext(closure)));
+        let error = Controls::push_objects(values, &mut data_stack);
+        if error.is_some() {
+            return Err(error.unwrap());
+        }
+
+        let frame_result = Controls::execute_call(
+            &InstructionKind::ICall,
+            &mut data_stack,
+            n_params,
+            globals,
+            constants,
+            platform,
+            self,
+        );
+        if frame_result.is_err() {
+            return Err(frame_result.unwrap_err());
+        }
+
+        let push_result = call_stack.push_frame(frame_result.unwrap().unwrap());
+        if push_result.is_err() {
+            return Err(push_result.unwrap_err());
+        }
+
+        let handle = self.tasks.new_handle(name);
+        self.tasks
+            .push(Task::new(Some(handle.clone()), call_stack, data_stack, None));
+        return Ok(handle);
+    }
+
+    // starts the workers of a pool, every worker runs the jobs one after the
+    // other until the pool is closed. The globals and the constants are
+    // packed once, every worker starts with a copy of them and the jobs it
+    // runs share it:
+    pub fn create_pool(
+        &mut self,
+        size: usize,
+        platform: &Platform,
+        globals: &GlobalPool,
+        constants: &ConstantPool,
+    ) -> Result<ThreadPool, String> {
+        let mut values = globals.pool.clone();
+        values.extend(constants.objects.iter().cloned());
+        let env = Transfer::pack(&values);
+        let n_globals = globals.pool.len();
+        let max_globals = globals.max_size;
+
+        let pool = ThreadPool::new(size);
+        for idx in 0..size {
+            let platform_result = BosonThreads::prepare_platform(platform);
+            if platform_result.is_err() {
+                pool.close();
+                return Err(platform_result.unwrap_err());
+            }
+
+            let new_platform = platform_result.unwrap();
+            let config = self.config.clone();
+            let limits = self.limits.for_thread(&CancelHandle::new());
+            let worker = pool.worker();
+            let thread_id = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+            let name = format!("pool-worker-{}", idx);
+            let env = env.clone();
+            let spawn_result = thread::Builder::new().name(name.clone()).spawn(move || {
+                CURRENT_THREAD.with(|current| *current.borrow_mut() = Some((thread_id, name)));
+                let mut values = env.unpack();
+                let constants = ConstantPool::new_from_objects(values.split_off(n_globals));
+                let mut globals = GlobalPool::new(max_globals);
+                globals.pool = values;
+
+                loop {
+                    let job = worker.next_job();
+                    if job.is_none() {
+                        return;
+                    }
+
+                    let job = job.unwrap();
+                    let (closure, params) = job.call.unpack();
+                    let result = BosonThreads::run_call(
+                        closure,
+                        params,
+                        globals.clone(),
+                        constants.clone(),
+                        &new_platform,
+                        &config,
+                        &limits,
+                    );
+                    job.future.complete(result);
+                }
+            });
+
+            if spawn_result.is_err() {
+                pool.close();
+                return Err(format!("Failed to spawn thread: {}", spawn_result.unwrap_err()));
+            }
+
+            self.current_count += 1;
+        }
+
+        return Ok(pool);
     }
 }
@@ -0,0 +1,282 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::types::array::Array;
+use crate::types::buffer::Buffer;
+use crate::types::builtins::BuiltinKind;
+use crate::types::channel::Channel;
+use crate::types::closure::ClosureContext;
+use crate::types::closure::UpvalueState;
+use crate::types::exception::Exception;
+use crate::types::future::Future;
+use crate::types::gc;
+use crate::types::hash::HashTable;
+use crate::types::iter::ObjectIterator;
+use crate::types::object::Object;
+use crate::types::object::Value;
+use crate::types::pool::ThreadPool;
+use crate::types::process::Process;
+use crate::types::shell::ShellResult;
+use crate::types::shared::SharedObject;
+use crate::types::subroutine::Subroutine;
+use crate::types::th::ThreadBlock;
+use crate::vm::errors::VMErrorKind;
+
+/*
+    Values are reference counted without synchronisation, so they never
+    cross a thread boundary as they are. A Transfer is a deep copy of a set
+    of values made only of owned data, it is built on the sending thread,
+    moved to the other one and turned back into values there. Functions and
+    captured variables are stored once and referenced by their position,
+    so the sharing between closures and the cycles through captured
+    variables are the same on both sides.
+*/
+#[derive(Debug, Clone)]
+enum Sendable {
+    Noval,
+    Int(i64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+    Byte(u8),
+    Float(f64),
+    Subroutine(usize),
+    // the function and the captured variables:
+    Closure(usize, Vec<usize>),
+    Array(String, Vec<Sendable>),
+    ByteBuffer(Buffer),
+    HashTable(String, Vec<(Sendable, Sendable)>),
+    Builtins(BuiltinKind),
+    Iter(usize, usize, Vec<Sendable>, Option<Channel>),
+    Exception(String, String, VMErrorKind),
+    Thread(ThreadBlock),
+    // both threads hold the same channel, shared object, future or pool:
+    Channel(Channel),
+    Shared(SharedObject),
+    Future(Future),
+    Pool(ThreadPool),
+    Process(Process),
+    ShellResult(ShellResult),
+}
+
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    values: Vec<Sendable>,
+    functions: Vec<Subroutine>,
+    upvalues: Vec<Sendable>,
+}
+
+struct Packer {
+    functions: Vec<Subroutine>,
+    function_index: HashMap<usize, usize>,
+    upvalues: Vec<Sendable>,
+    upvalue_index: HashMap<usize, usize>,
+}
+
+impl Packer {
+    fn pack_function(&mut self, func: &Rc<Subroutine>) -> usize {
+        let key = Rc::as_ptr(func) as usize;
+        let existing = self.function_index.get(&key);
+        if existing.is_some() {
+            return *existing.unwrap();
+        }
+
+        self.functions.push(func.as_ref().clone());
+        self.function_index.insert(key, self.functions.len() - 1);
+        return self.functions.len() - 1;
+    }
+
+    fn pack_closure(&mut self, ctx: &ClosureContext) -> Sendable {
+        let func = self.pack_function(&ctx.compiled_fn);
+
+        let mut upvalues = vec![];
+        for upvalue in &ctx.free_objects {
+            let key = Rc::as_ptr(upvalue) as usize;
+            let existing = self.upvalue_index.get(&key);
+            if existing.is_some() {
+                upvalues.push(*existing.unwrap());
+                continue;
+            }
+
+            // the slot is taken before the value is packed, a closure that
+            // captures itself finds it:
+            let pos = self.upvalues.len();
+            self.upvalues.push(Sendable::Noval);
+            self.upvalue_index.insert(key, pos);
+
+            // the callers close the variables of the running frames first,
+            // one still open here has no stack to be read from:
+            let packed = match &*upvalue.borrow() {
+                UpvalueState::Closed(value) => self.pack(value),
+                UpvalueState::Open(_) => Sendable::Noval,
+            };
+
+            self.upvalues[pos] = packed;
+            upvalues.push(pos);
+        }
+
+        return Sendable::Closure(func, upvalues);
+    }
+
+    fn pack_values(&mut self, values: &Vec<Value>) -> Vec<Sendable> {
+        return values.iter().map(|value| self.pack(value)).collect();
+    }
+
+    fn pack(&mut self, value: &Value) -> Sendable {
+        match value.as_ref() {
+            Object::Noval => Sendable::Noval,
+            Object::Int(i) => Sendable::Int(*i),
+            Object::Bool(b) => Sendable::Bool(*b),
+            Object::Char(c) => Sendable::Char(*c),
+            Object::Str(st) => Sendable::Str(st.as_ref().clone()),
+            Object::Byte(b) => Sendable::Byte(*b),
+            Object::Float(f) => Sendable::Float(*f),
+            Object::Subroutine(func) => Sendable::Subroutine(self.pack_function(func)),
+            Object::ClosureContext(ctx) => self.pack_closure(ctx),
+            Object::Array(arr) => {
+                let arr = arr.borrow();
+                return Sendable::Array(arr.name.clone(), self.pack_values(&arr.elements));
+            }
+            Object::ByteBuffer(buffer) => Sendable::ByteBuffer(buffer.borrow().clone()),
+            Object::HashTable(ht) => {
+                let ht = ht.borrow();
+                let mut entries = vec![];
+                for (key, value) in &ht.entries {
+                    entries.push((self.pack(key), self.pack(value)));
+                }
+                return Sendable::HashTable(ht.name.clone(), entries);
+            }
+            Object::Builtins(kind) => Sendable::Builtins(*kind),
+            Object::Iter(it) => {
+                let it = it.borrow();
+                return Sendable::Iter(
+                    it.idx,
+                    it.size,
+                    self.pack_values(&it.elements),
+                    it.receiver.clone(),
+                );
+            }
+            // the stack trace refers to the frames of the sending thread:
+            Object::Exception(exc) => Sendable::Exception(
+                exc.get_name(),
+                exc.get_message(),
+                exc.get_kind(),
+            ),
+            Object::Thread(th) => Sendable::Thread(th.borrow().clone()),
+            Object::Channel(ch) => Sendable::Channel(ch.as_ref().clone()),
+            Object::Shared(shared) => Sendable::Shared(shared.as_ref().clone()),
+            Object::Future(future) => Sendable::Future(future.as_ref().clone()),
+            Object::Pool(pool) => Sendable::Pool(pool.as_ref().clone()),
+            // a task runs on the VM that spawned it and is left out:
+            Object::Task(_) => Sendable::Noval,
+            Object::Process(process) => Sendable::Process(process.as_ref().clone()),
+            Object::ShellResult(result) => Sendable::ShellResult(result.as_ref().clone()),
+        }
+    }
+}
+
+struct Unpacker {
+    functions: Vec<Rc<Subroutine>>,
+    upvalues: Vec<Rc<RefCell<UpvalueState>>>,
+}
+
+impl Unpacker {
+    fn unpack_values(&self, values: Vec<Sendable>) -> Vec<Value> {
+        return values.into_iter().map(|value| self.unpack(value)).collect();
+    }
+
+    fn unpack(&self, value: Sendable) -> Value {
+        let obj = match value {
+            Sendable::Noval => Object::Noval,
+            Sendable::Int(i) => Object::Int(i),
+            Sendable::Bool(b) => Object::Bool(b),
+            Sendable::Char(c) => Object::Char(c),
+            Sendable::Str(st) => Object::Str(Box::new(st)),
+            Sendable::Byte(b) => Object::Byte(b),
+            Sendable::Float(f) => Object::Float(f),
+            Sendable::Subroutine(func) => Object::Subroutine(self.functions[func].clone()),
+            Sendable::Closure(func, upvalues) => Object::ClosureContext(Rc::new(ClosureContext {
+                compiled_fn: self.functions[func].clone(),
+                free_objects: upvalues.iter().map(|pos| self.upvalues[*pos].clone()).collect(),
+                bytecode_size: self.functions[func].instructions.len(),
+            })),
+            Sendable::Array(name, elements) => Object::Array(Box::new(RefCell::new(Array {
+                name: name,
+                elements: self.unpack_values(elements),
+            }))),
+            Sendable::ByteBuffer(buffer) => Object::ByteBuffer(Box::new(RefCell::new(buffer))),
+            Sendable::HashTable(name, entries) => {
+                let mut table = HashMap::new();
+                for (key, value) in entries {
+                    table.insert(self.unpack(key), self.unpack(value));
+                }
+
+                Object::HashTable(Box::new(RefCell::new(HashTable {
+                    name: name,
+                    entries: table,
+                })))
+            }
+            Sendable::Builtins(kind) => Object::Builtins(kind),
+            Sendable::Iter(idx, size, elements, receiver) => {
+                Object::Iter(Box::new(RefCell::new(ObjectIterator {
+                    idx: idx,
+                    size: size,
+                    elements: self.unpack_values(elements),
+                    receiver: receiver,
+                })))
+            }
+            Sendable::Exception(name, message, kind) => {
+                Object::Exception(Rc::new(Exception::new(name, message, kind, vec![])))
+            }
+            Sendable::Thread(th) => Object::Thread(Box::new(RefCell::new(th))),
+            Sendable::Channel(ch) => Object::Channel(Box::new(ch)),
+            Sendable::Shared(shared) => Object::Shared(Box::new(shared)),
+            Sendable::Future(future) => Object::Future(Box::new(future)),
+            Sendable::Pool(pool) => Object::Pool(Box::new(pool)),
+            Sendable::Process(process) => Object::Process(Box::new(process)),
+            Sendable::ShellResult(result) => Object::ShellResult(Box::new(result)),
+        };
+
+        return Value::new(obj);
+    }
+}
+
+impl Transfer {
+    pub fn pack(values: &Vec<Value>) -> Transfer {
+        let mut packer = Packer {
+            functions: vec![],
+            function_index: HashMap::new(),
+            upvalues: vec![],
+            upvalue_index: HashMap::new(),
+        };
+
+        let values = packer.pack_values(values);
+        return Transfer {
+            values: values,
+            functions: packer.functions,
+            upvalues: packer.upvalues,
+        };
+    }
+
+    pub fn unpack(self) -> Vec<Value> {
+        let mut unpacker = Unpacker {
+            functions: self.functions.into_iter().map(Rc::new).collect(),
+            upvalues: vec![],
+        };
+
+        // the captured variables are created first, closures refer to them:
+        for _ in 0..self.upvalues.len() {
+            let upvalue = Rc::new(RefCell::new(UpvalueState::Closed(Value::new(Object::Noval))));
+            gc::track(&upvalue);
+            unpacker.upvalues.push(upvalue);
+        }
+
+        for (pos, value) in self.upvalues.into_iter().enumerate() {
+            let value = unpacker.unpack(value);
+            *unpacker.upvalues[pos].borrow_mut() = UpvalueState::Closed(value);
+        }
+
+        return unpacker.unpack_values(self.values);
+    }
+}